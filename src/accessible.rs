@@ -0,0 +1,367 @@
+//! Linear text for screen readers: every fact is a sentence, with no tree
+//! glyphs, bars or alignment that only make sense visually.
+
+use crate::analytics::VoiceStats;
+use crate::buckets::{count_bucket, CountStyle};
+use crate::errors::MyError;
+use crate::humanize::fmt_duration;
+use crate::numbers::percent_value;
+use crate::render::{format_thousands, ResultsView};
+use crate::totals::totals;
+use crate::{Conversation, ConversationType};
+use std::cmp::Reverse;
+use std::io::Write;
+use std::time::Duration;
+
+pub fn render_accessible(view: &ResultsView, out: &mut dyn Write) -> Result<(), MyError> {
+    let number = |n: usize| match view.thousands_separators {
+        true => format_thousands(n as u64),
+        false => n.to_string(),
+    };
+    let messages = |n: usize| match view.counts {
+        CountStyle::Exact => number(n),
+        CountStyle::Bucketed => count_bucket(n as u64),
+    };
+    let report = view.report;
+
+    if let Some(ref partial) = report.partial {
+        writeln!(out, "{}.", partial)?;
+    }
+    if let Some(ref window) = view.display_window {
+        writeln!(
+            out,
+            "Charts are limited to the display window {}; message counts cover the whole package.",
+            window
+        )?;
+    }
+    if !view.quiet && view.filter_stages.len() > 1 {
+        let stages: Vec<String> = view
+            .filter_stages
+            .iter()
+            .map(|stage| format!("{} {}", number(stage.remaining), stage.stage.label()))
+            .collect();
+        writeln!(out, "Conversations: {}.", stages.join(", "))?;
+    }
+
+    let total: usize = report
+        .conversations
+        .iter()
+        .map(Conversation::message_count)
+        .sum();
+    for (rank, &conversation) in view.conversations.iter().enumerate() {
+        let title = match (conversation, conversation.effective_kind(view.group_dms_as)) {
+            // Unknown servers hold a single channel, which is all there is to name
+            (Conversation::Guild { channels, .. }, _) if conversation.is_unknown_guild() => {
+                let channel = channels.first().map_or("", |channel| channel.name.as_str());
+                format!("Channel {} of an unknown server", channel)
+            }
+            (_, ConversationType::Dm) => format!("Direct message {}", conversation.name()),
+            (_, ConversationType::GroupDm) => {
+                format!("Group direct message {}", conversation.name())
+            }
+            (_, ConversationType::Guild) => format!("Server {}", conversation.name()),
+        };
+        writeln!(
+            out,
+            "Rank {}: {}, {} messages, {} of all messages.",
+            rank + 1,
+            title,
+            messages(conversation.message_count()),
+            percent(conversation.message_count(), total, view.counts)
+        )?;
+        if let Some(note) = report.note(conversation.id()) {
+            writeln!(out, "Note: {}.", note)?;
+        }
+        if let Some(discrepancy) = report.discrepancy(conversation.id()) {
+            writeln!(
+                out,
+                "About {} more messages were sent here historically, likely deleted or missing.",
+                number(discrepancy.missing() as usize)
+            )?;
+        }
+
+        if let (Conversation::Guild { channels, .. }, false) =
+            (conversation, conversation.is_unknown_guild())
+        {
+            for channel in channels {
+                let previous = match report.previous_names(&channel.id, &channel.name)[..] {
+                    [] => String::new(),
+                    ref names => format!(", previously {}", names.join(" and ")),
+                };
+                writeln!(
+                    out,
+                    "Channel {}{}, {} messages.",
+                    channel.name,
+                    previous,
+                    messages(channel.message_count)
+                )?;
+            }
+        }
+    }
+
+    if view.summary && !view.quiet {
+        let totals = totals(view);
+        match totals.shown_messages == totals.total_messages {
+            true => writeln!(
+                out,
+                "In total, {} messages.",
+                messages(totals.total_messages as usize)
+            )?,
+            false => writeln!(
+                out,
+                "Showing {} of {} messages.",
+                messages(totals.shown_messages as usize),
+                messages(totals.total_messages as usize)
+            )?,
+        }
+        writeln!(
+            out,
+            "{} direct messages and group direct messages, {} servers, {} channels.",
+            number(totals.dms),
+            number(totals.guilds),
+            number(totals.channels)
+        )?;
+        if let Some(largest) = totals.largest {
+            writeln!(
+                out,
+                "The largest conversation is {}, with {} messages.",
+                largest.name,
+                messages(largest.message_count as usize)
+            )?;
+        }
+    }
+
+    if let (false, Some(concentration)) = (view.quiet, &view.concentration) {
+        let total = messages(concentration.total_messages as usize);
+        writeln!(out, "{}", concentration.spoken(&total))?;
+    }
+
+    if let Some(ref breadth) = report.breadth_by_year {
+        for year in breadth.iter().filter(|year| {
+            view.display_window
+                .is_none_or(|w| w.overlaps_year(year.year))
+        }) {
+            writeln!(
+                out,
+                "In {}: {} servers, {} channels and {} direct messages active.",
+                year.year,
+                number(year.servers),
+                number(year.channels),
+                number(year.dms)
+            )?;
+        }
+    }
+
+    if let Some(ref timeline) = view.threshold_timeline {
+        for year in timeline.shown_years(view.display_window) {
+            writeln!(
+                out,
+                "In {}: {} conversations with at least {} messages.",
+                year.year,
+                number(year.conversations),
+                number(timeline.threshold)
+            )?;
+        }
+    }
+
+    if let Some(ref voice) = report.voice {
+        write_voice(voice, view, out)?;
+    }
+
+    if let Some(ref reference_counts) = report.reference_counts {
+        writeln!(
+            out,
+            "Counted {} messages. Discord reports {} from {}, a difference of {} percent: {}.",
+            messages(reference_counts.counted as usize),
+            messages(reference_counts.reported.count as usize),
+            reference_counts.reported.source,
+            view.counts.percent(
+                reference_counts.difference(),
+                reference_counts.reported.count
+            ),
+            reference_counts.explanation()
+        )?;
+    }
+
+    if !report.discrepancies.is_empty() {
+        let missing: u64 = report.discrepancies.iter().map(|d| d.missing()).sum();
+        writeln!(
+            out,
+            "Estimated undercount: about {} messages across {} conversations are missing from the package.",
+            messages(missing as usize),
+            number(report.discrepancies.len())
+        )?;
+    }
+
+    if view.excluded_system {
+        let excluded: usize = report
+            .conversations
+            .iter()
+            .map(Conversation::excluded_system)
+            .sum();
+        writeln!(
+            out,
+            "Excluded {} system messages from the counts.",
+            number(excluded)
+        )?;
+    }
+
+    let empty: usize = report
+        .conversations
+        .iter()
+        .map(Conversation::empty_messages)
+        .sum();
+    if view.excluded_empty {
+        writeln!(
+            out,
+            "Excluded {} empty messages from the counts.",
+            number(empty)
+        )?;
+    }
+    if view.empty_message_stats {
+        let counted: usize = report
+            .conversations
+            .iter()
+            .map(Conversation::message_count)
+            .sum();
+        let read = counted + if view.excluded_empty { empty } else { 0 };
+        writeln!(
+            out,
+            "{} messages have neither contents nor attachments, {} of all messages.",
+            number(empty),
+            percent(empty, read, view.counts)
+        )?;
+    }
+
+    if view.excluded_bots {
+        writeln!(out, "Excluded {} DMs with bots.", number(report.bot_dms()))?;
+    }
+    let departed = report.departed_guilds();
+    if view.excluded_departed && departed > 0 {
+        writeln!(
+            out,
+            "Hid {} servers you're no longer in; --include-departed lists them.",
+            number(departed)
+        )?;
+    }
+
+    Ok(())
+}
+
+fn write_voice(voice: &VoiceStats, view: &ResultsView, out: &mut dyn Write) -> Result<(), MyError> {
+    if voice.sessions == 0 {
+        return Ok(());
+    }
+
+    writeln!(
+        out,
+        "Voice activity: {} in total.",
+        fmt_duration(Duration::from_secs(voice.total_seconds), 2)
+    )?;
+    let mut guilds: Vec<(&String, &u64)> = voice.seconds_by_guild.iter().collect();
+    guilds.sort_by_key(|&(id, seconds)| (Reverse(*seconds), id));
+    for (guild_id, seconds) in guilds {
+        let name = view
+            .report
+            .guild_name(guild_id)
+            .map_or_else(|| format!("Guild {}", guild_id), str::to_string);
+        writeln!(
+            out,
+            "Voice in {}: {}.",
+            name,
+            fmt_duration(Duration::from_secs(*seconds), 2)
+        )?;
+    }
+    Ok(())
+}
+
+fn percent(part: usize, total: usize, counts: CountStyle) -> String {
+    let decimals = match counts {
+        CountStyle::Exact => 1,
+        CountStyle::Bucketed => 0,
+    };
+    format!(
+        "{} percent",
+        percent_value(part as u64, total as u64, decimals)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::concentration::concentration;
+    use crate::render::OutputFormat;
+    use crate::testing::{channel, dm, guild, rendered, report, view};
+
+    fn accessible(view: &mut crate::render::ResultsView) -> String {
+        view.accessible = true;
+        rendered(OutputFormat::Text, view)
+    }
+
+    #[test]
+    fn every_conversation_is_a_sentence() {
+        let mut report = report(vec![
+            guild(
+                Some("g1"),
+                "Rustaceans",
+                vec![channel("c1", "general", 6000), channel("c2", "help", 2000)],
+            ),
+            dm("d1", "Alice", 1500),
+            guild(None, "Unknown server", vec![channel("c3", "lost", 500)]),
+        ]);
+        report
+            .notes
+            .insert("d1".to_string(), "my sister".to_string());
+
+        assert_eq!(
+            accessible(&mut view(&report)),
+            "Rank 1: Server Rustaceans, 8000 messages, 80.0 percent of all messages.\n\
+             Channel general, 6000 messages.\n\
+             Channel help, 2000 messages.\n\
+             Rank 2: Direct message Alice, 1500 messages, 15.0 percent of all messages.\n\
+             Note: my sister.\n\
+             Rank 3: Channel lost of an unknown server, 500 messages, 5.0 percent of all messages.\n"
+        );
+    }
+
+    #[test]
+    fn summary_and_concentration_spell_out_percentages() {
+        let report = report(vec![dm("1", "Alice", 6000), dm("2", "Bob", 4000)]);
+        let mut view = view(&report);
+        view.quiet = false;
+        view.summary = true;
+        view.concentration = concentration([6000, 4000], &"50,90".parse().unwrap());
+
+        let text = accessible(&mut view);
+        assert_eq!(
+            text.lines().skip(2).collect::<Vec<_>>(),
+            [
+                "In total, 10000 messages.",
+                "2 direct messages and group direct messages, 0 servers, 0 channels.",
+                "The largest conversation is Alice, with 6000 messages.",
+                "Half of your 10000 messages are in just 1 conversation; 90 percent in 2.",
+            ]
+        );
+        assert!(!text.contains('%'), "{}", text);
+    }
+
+    #[test]
+    fn separators_and_buckets_apply_to_counts() {
+        let report = report(vec![dm("1", "Alice", 41203)]);
+        let mut view = view(&report);
+        view.thousands_separators = true;
+        assert_eq!(
+            accessible(&mut view),
+            "Rank 1: Direct message Alice, 41,203 messages, 100.0 percent of all messages.\n"
+        );
+
+        view.thousands_separators = false;
+        view.counts = crate::buckets::CountStyle::Bucketed;
+        let text = accessible(&mut view);
+        assert!(
+            text.ends_with(", 100 percent of all messages.\n"),
+            "{}",
+            text
+        );
+        assert!(!text.contains("41203"), "{}", text);
+    }
+}
@@ -0,0 +1,413 @@
+use crate::analytics::{
+    find_discrepancies, load_analytics_tally, load_channel_names, load_reported_count,
+    load_voice_stats, ReferenceCounts,
+};
+use crate::budget::{CancelFlag, StopReason};
+use crate::coverage::mixed_exports;
+use crate::errors::MyError;
+use crate::extraction::{ExtractionWatch, SETTLE_INTERVAL, SETTLE_TIMEOUT};
+use crate::file_operations::ProcessOptions;
+use crate::file_operations::{
+    load_account_id, load_mappings, prepare_data_root, process_conversations,
+};
+use crate::humanize::fmt_duration;
+use crate::index::build_index;
+use crate::index_cache::cached_index;
+use crate::inventory::{self, folder_channels, indexed_channels};
+use crate::report::Report;
+use crate::resolve::{MappingResolver, NameResolver};
+use crate::warnings::Warning;
+use crate::Conversation;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Phases of a run, in the order they happen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Extracting,
+    LoadingMappings,
+    LoadingAnalytics,
+    IndexingChannels,
+    /// Another instance is indexing the same package for the cache
+    WaitingForLock,
+    #[cfg(feature = "zip")]
+    VerifyingMirror,
+    ProcessingChannels,
+    Comparing,
+    Rendering,
+}
+
+impl Stage {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Extracting => "Extracting archive",
+            Self::LoadingMappings => "Loading name mappings",
+            Self::LoadingAnalytics => "Loading analytics",
+            Self::IndexingChannels => "Indexing channels",
+            Self::WaitingForLock => "Waiting for another instance to finish indexing",
+            #[cfg(feature = "zip")]
+            Self::VerifyingMirror => "Verifying against the original archive",
+            Self::ProcessingChannels => "Processing channels",
+            Self::Comparing => "Comparing against Discord's figures",
+            Self::Rendering => "Rendering report",
+        }
+    }
+}
+
+/// Progress events sent while a package is analysed.
+#[derive(Debug)]
+pub enum Progress {
+    /// Sent when a new stage begins
+    Stage(Stage),
+    /// Sent before every channel folder and once all of them are processed,
+    /// with the messages counted so far
+    Channels {
+        processed: usize,
+        total: usize,
+        messages: usize,
+    },
+    /// A complete conversation, sent when `stream_conversations` is set:
+    /// DMs as soon as they are counted, guilds once every channel is
+    Conversation(Box<Conversation>),
+}
+
+/// Wall-clock time spent in each stage, fed by the same stage transitions
+/// the progress display shows.
+#[derive(Debug, Default)]
+pub struct Timings {
+    finished: Vec<(Stage, Duration)>,
+    current: Option<(Stage, Instant)>,
+}
+
+impl Timings {
+    /// End the current stage, if any, and start timing `stage`.
+    pub fn enter(&mut self, stage: Stage) {
+        self.finish();
+        self.current = Some((stage, Instant::now()));
+    }
+
+    pub fn finish(&mut self) {
+        if let Some((stage, started)) = self.current.take() {
+            self.finished.push((stage, started.elapsed()));
+        }
+    }
+
+    pub fn print(&self) {
+        eprintln!("Timing:");
+        for (stage, duration) in &self.finished {
+            eprintln!(
+                "    {:<36} {:>9}",
+                stage.label(),
+                fmt_duration(*duration, 2)
+            );
+        }
+        let total: Duration = self.finished.iter().map(|(_, duration)| *duration).sum();
+        eprintln!("    {:<36} {:>9}", "Total", fmt_duration(total, 2));
+    }
+}
+
+pub type ProgressSender = Sender<Progress>;
+pub type ProgressReceiver = Receiver<Progress>;
+pub type CancelHandle = CancelFlag;
+
+/// Everything needed to turn a package into a `Report`.
+#[derive(Debug, Clone)]
+pub struct AnalysisOptions {
+    pub input_path: PathBuf,
+    pub process: ProcessOptions,
+    /// Minimum number of missing messages for a conversation to be flagged
+    pub discrepancy_threshold: u64,
+    pub voice_stats: bool,
+    /// Compare messages/index.json, the channel folders and the counts
+    pub coverage: bool,
+    /// Collect the names channels had in the analytics
+    pub historical_names: bool,
+    /// Reuse the channel index of an unchanged package from earlier runs
+    pub cache_index: bool,
+    /// Wait while another instance holds the cache entry instead of failing
+    pub wait_for_locks: bool,
+    /// Where ZIP archives are extracted instead of the system's temp directory
+    #[cfg(feature = "zip")]
+    pub temp_dir: Option<PathBuf>,
+    /// Original archive the input folder is a copy of, checked before counting
+    #[cfg(feature = "zip")]
+    pub verify_against: Option<PathBuf>,
+    #[cfg(feature = "net")]
+    pub resolve_online: bool,
+    #[cfg(feature = "net")]
+    pub resolve_limit: usize,
+}
+
+/// Analyse a package on a worker thread. Progress streams over the returned
+/// receiver until the worker finishes; cancelling stops it between channels
+/// and makes it return `MyError::Cancelled`.
+pub fn spawn_analysis(
+    mut options: AnalysisOptions,
+) -> (
+    JoinHandle<Result<Report, MyError>>,
+    ProgressReceiver,
+    CancelHandle,
+) {
+    let (sender, receiver) = mpsc::channel();
+    let cancel = CancelFlag::default();
+    options.process.progress = Some(sender);
+    options.process.cancel = cancel.clone();
+
+    let handle = thread::spawn(move || {
+        let report = analyze(&options)?;
+        // A budget stop is a result in its own right, a cancellation is not
+        match report.partial {
+            Some(ref partial) if matches!(partial.stopped_by, StopReason::Cancelled) => {
+                Err(MyError::Cancelled)
+            }
+            _ => Ok(report),
+        }
+    });
+
+    (handle, receiver, cancel)
+}
+
+/// Analyse a package on the current thread.
+pub fn analyze(options: &AnalysisOptions) -> Result<Report, MyError> {
+    let mut process_options = options.process.clone();
+    let enter = |stage: Stage| {
+        if let Some(ref progress) = options.process.progress {
+            let _ = progress.send(Progress::Stage(stage));
+        }
+    };
+
+    // Prepare data root
+    if options.input_path.is_file() {
+        enter(Stage::Extracting);
+    }
+    #[cfg(feature = "zip")]
+    let temp_location = options.temp_dir.as_deref();
+    #[cfg(not(feature = "zip"))]
+    let temp_location = None;
+    let data_root = prepare_data_root(&options.input_path, temp_location)?;
+
+    // Load mappings
+    enter(Stage::LoadingMappings);
+    let account_id = load_account_id(&data_root)?;
+    process_options.account_id = account_id.clone();
+    let (channel_mapping, guild_mapping, mapping_warnings) = load_mappings(&data_root)?;
+    process_options.indexed_guilds = guild_mapping
+        .as_ref()
+        .map(|mapping| mapping.keys().cloned().collect());
+    let mut resolver = name_resolver(channel_mapping, guild_mapping, options)?;
+
+    // Load analytics first so counting knows where their coverage starts
+    enter(Stage::LoadingAnalytics);
+    let tally = load_analytics_tally(&data_root)?;
+    process_options.analytics_since = tally.as_ref().and_then(|tally| tally.since);
+
+    // Read every channel.json before any messages; a folder the user is
+    // still extracting into may change between the two passes
+    enter(Stage::IndexingChannels);
+    let mut watch = match options.input_path.is_file() {
+        true => None,
+        false => ExtractionWatch::start(&data_root),
+    };
+    let mut waited = false;
+    let mut index = match options.cache_index {
+        true => cached_index(
+            &options.input_path,
+            &data_root,
+            watch.as_ref(),
+            options.wait_for_locks,
+            || {
+                waited = true;
+                enter(Stage::WaitingForLock);
+            },
+        )?,
+        false => build_index(&data_root)?,
+    };
+    if waited {
+        enter(Stage::IndexingChannels);
+    }
+    if let Some(ref mut watch) = watch {
+        if watch.is_filling() && watch.settle(SETTLE_INTERVAL, SETTLE_TIMEOUT) {
+            index = build_index(&data_root)?;
+        }
+    }
+
+    #[cfg(feature = "zip")]
+    let mirror_warnings = match options.verify_against {
+        Some(_) if options.input_path.is_file() => {
+            return Err(MyError::InvalidInputPath(format!(
+                "{} is an archive; --verify-against checks extracted folders",
+                options.input_path.display()
+            )))
+        }
+        Some(ref original) => {
+            enter(Stage::VerifyingMirror);
+            crate::mirror::verify_mirror(&data_root.path, original)?
+        }
+        None => Vec::new(),
+    };
+    #[cfg(not(feature = "zip"))]
+    let mirror_warnings = Vec::new();
+
+    // Process conversations
+    enter(Stage::ProcessingChannels);
+    let files: Vec<PathBuf> = match watch {
+        Some(_) => index
+            .channels
+            .iter()
+            .map(|channel| channel.messages_file.clone())
+            .collect(),
+        None => Vec::new(),
+    };
+    let outcome = process_conversations(index.channels, resolver.as_mut(), &process_options)?;
+    let extracting = watch.and_then(|watch| watch.finish(&files, &outcome.channel_errors));
+    resolver.finish();
+    let conversations = outcome.conversations;
+    let mut warnings = index.warnings;
+    warnings.extend(extracting);
+    warnings.extend(mapping_warnings);
+    warnings.extend(mirror_warnings);
+    warnings.extend(outcome.warnings);
+    warnings.extend(mixed_exports(&outcome.last_messages));
+    warnings.extend(
+        outcome
+            .channel_errors
+            .into_iter()
+            .map(|error| Warning::SkippedChannel {
+                channel_id: error.channel_id,
+                reason: error.kind.to_string(),
+            }),
+    );
+
+    // Compare against Discord's own figures, if the package has them; a
+    // partial count would only produce meaningless discrepancies
+    enter(Stage::Comparing);
+    let counted = conversations.iter().map(|c| c.message_count() as u64).sum();
+    let (reference_counts, discrepancies) = match (&outcome.partial, &tally) {
+        (Some(_), _) => (None, Vec::new()),
+        (None, tally) => (
+            load_reported_count(&data_root, tally.as_ref())?
+                .map(|reported| ReferenceCounts { counted, reported }),
+            tally.as_ref().map_or_else(Vec::new, |tally| {
+                find_discrepancies(
+                    &conversations,
+                    tally,
+                    &outcome.counted_since_analytics,
+                    &outcome.conversation_of_channel,
+                    options.discrepancy_threshold,
+                )
+            }),
+        ),
+    };
+
+    // Without analytics there is nothing to enrich the channels with
+    let previous_names = match options.historical_names {
+        true => load_channel_names(&data_root)?.unwrap_or_default(),
+        false => HashMap::new(),
+    };
+
+    let coverage = match options.coverage {
+        true => {
+            let with_messages: HashSet<String> = outcome
+                .messages_by_channel
+                .iter()
+                .filter(|&(_, &count)| count > 0)
+                .map(|(id, _)| id.clone())
+                .collect();
+            Some(inventory::coverage(
+                indexed_channels(&data_root)?.as_ref(),
+                &folder_channels(&data_root)?,
+                Some(&with_messages),
+            ))
+        }
+        false => None,
+    };
+
+    // A package without analytics still gets an (empty) voice section note
+    let voice = match options.voice_stats {
+        true => Some(load_voice_stats(&data_root)?.unwrap_or_default()),
+        false => None,
+    };
+
+    Ok(Report {
+        conversations,
+        reference_counts,
+        discrepancies,
+        analytics_since: process_options.analytics_since,
+        warnings,
+        partial: outcome.partial,
+        voice,
+        breadth_by_year: outcome.breadth,
+        messages_by_year: outcome.messages_by_year.unwrap_or_default(),
+        peak_days: outcome.peak_days.unwrap_or_default(),
+        sessions: outcome.sessions,
+        first_message: outcome.first_message,
+        last_message: outcome.last_message,
+        previous_names,
+        account_id,
+        vintage: Some(index.vintage),
+        notes: HashMap::new(),
+        processing_stats: Some(outcome.stats),
+        coverage,
+    })
+}
+
+fn name_resolver(
+    channel_mapping: Option<HashMap<String, String>>,
+    guild_mapping: Option<HashMap<String, String>>,
+    options: &AnalysisOptions,
+) -> Result<Box<dyn NameResolver>, MyError> {
+    let resolver: Box<dyn NameResolver> =
+        Box::new(MappingResolver::new(channel_mapping, guild_mapping));
+
+    #[cfg(feature = "net")]
+    if options.resolve_online {
+        let cache_path = crate::paths::cache_file("names.json")?;
+        return Ok(Box::new(crate::resolve::OnlineResolver::new(
+            resolver,
+            cache_path,
+            options.resolve_limit,
+        )));
+    }
+    #[cfg(not(feature = "net"))]
+    let _ = options;
+
+    Ok(resolver)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Package;
+    use serde_json::json;
+
+    #[test]
+    fn cancelling_stops_the_worker() {
+        let package = Package::new();
+        for id in 0..500 {
+            package.channel(&id.to_string(), json!({"id": id.to_string()}), 20);
+        }
+
+        let (handle, progress, cancel) = spawn_analysis(package.options());
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut cancelled_after = None;
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match progress.recv_timeout(timeout) {
+                Ok(Progress::Channels { processed: 1, .. }) => {
+                    cancel.cancel();
+                    cancelled_after = Some(Instant::now());
+                }
+                Ok(_) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                Err(mpsc::RecvTimeoutError::Timeout) => panic!("the worker kept running"),
+            }
+        }
+
+        let stopped_in = cancelled_after.expect("a run cancelled mid-way").elapsed();
+        assert!(stopped_in < Duration::from_secs(5));
+        assert!(matches!(handle.join().unwrap(), Err(MyError::Cancelled)));
+    }
+}
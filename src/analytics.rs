@@ -0,0 +1,422 @@
+use crate::errors::MyError;
+use crate::file_operations::DataRoot;
+use crate::metrics::Metric;
+use crate::Conversation;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+/// Fields in `account/user.json` that have carried a lifetime message total
+/// in some package vintages.
+const ACCOUNT_COUNT_FIELDS: &[&str] = &["message_count", "messages_sent", "total_messages"];
+
+/// Analytics event types that are emitted once per message sent.
+const MESSAGE_SENT_EVENTS: &[&str] = &["send_message", "message_sent"];
+
+/// Analytics event types that end a voice session and carry its duration.
+const VOICE_SESSION_EVENTS: &[&str] = &["leave_voice_channel", "voice_disconnect"];
+
+/// Sessions longer than this are assumed to be corrupt and discarded.
+const MAX_VOICE_SESSION_SECONDS: u64 = 24 * 60 * 60;
+
+/// Folders below `activity/` that contain NDJSON analytics event dumps.
+const ACTIVITY_FOLDERS: &[&str] = &["analytics", "reporting", "tns", "modeling"];
+
+pub const REFERENCE_METRIC: Metric = Metric {
+    name: "reference_counts",
+    definition: "The counted total next to Discord's own figure, taken from account/user.json \
+        or else from the message-sent events in the analytics. The difference is \
+        |reported - counted| in percent of the reported figure.",
+    affected_by: "Every option that changes message_count changes the counted side.",
+    edge_cases: "Left out for partial runs and for packages with neither source. A lower \
+        count suggests deleted channels, a higher one incomplete analytics.",
+};
+
+pub const DISCREPANCY_METRIC: Metric = Metric {
+    name: "discrepancies",
+    definition: "Conversations where the analytics record more sent messages than the package \
+        holds since the analytics start: analytics_sent - counted, kept when above \
+        --discrepancy-threshold. The undercount line sums them.",
+    affected_by: "--discrepancy-threshold; --exclude-system and --dedupe-messages lower the \
+        counted side.",
+    edge_cases: "Only messages from the first analytics event on are compared. Left out for \
+        partial runs and packages without analytics. Events of channels missing from the \
+        package cannot be attributed and are ignored.",
+};
+
+pub const VOICE_METRIC: Metric = Metric {
+    name: "voice",
+    definition: "Time spent in voice channels: the durations of voice sessions recorded in \
+        the analytics, in total and per guild.",
+    affected_by: "Collected with --voice-stats; no other option changes it.",
+    edge_cases: "Sessions longer than 24 hours are assumed corrupt and discarded. A package \
+        without analytics reports no voice activity.",
+};
+
+/// Discord's own figure for the number of messages sent by the account.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportedCount {
+    pub count: u64,
+    pub source: String,
+}
+
+/// Comparison of the counted total against Discord's own figure.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReferenceCounts {
+    pub counted: u64,
+    pub reported: ReportedCount,
+}
+
+impl ReferenceCounts {
+    /// Absolute difference between both figures, to be put in relation to
+    /// the reported count.
+    pub fn difference(&self) -> u64 {
+        self.reported.count.abs_diff(self.counted)
+    }
+
+    /// Most likely explanation for the direction of the discrepancy.
+    pub fn explanation(&self) -> &'static str {
+        if self.counted < self.reported.count {
+            "likely deleted channels"
+        } else if self.counted > self.reported.count {
+            "analytics are likely incomplete"
+        } else {
+            "counts match"
+        }
+    }
+}
+
+/// Message-sent events from the analytics, tallied per channel.
+#[derive(Debug, Default, Clone)]
+pub struct AnalyticsTally {
+    pub total: u64,
+    pub sent_by_channel: HashMap<String, u64>,
+    /// Earliest event seen; analytics don't reach back to the account's start
+    pub since: Option<DateTime<Utc>>,
+}
+
+/// A conversation where Discord recorded noticeably more sent messages than
+/// the package contains.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Discrepancy {
+    pub conversation_id: String,
+    /// Messages sent according to the analytics
+    pub analytics_sent: u64,
+    /// Messages in the package within the analytics' time window
+    pub counted: u64,
+}
+
+impl Discrepancy {
+    pub fn missing(&self) -> u64 {
+        self.analytics_sent.saturating_sub(self.counted)
+    }
+}
+
+/// Time spent in voice channels according to the analytics.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct VoiceStats {
+    pub total_seconds: u64,
+    /// Voice time per guild id; calls outside guilds only count towards the total
+    pub seconds_by_guild: HashMap<String, u64>,
+    pub sessions: usize,
+    /// Sessions discarded for exceeding the sanity cap
+    pub discarded_sessions: usize,
+}
+
+/// Locate Discord's own message total, preferring the account data over the
+/// analytics tally. Returns `None` if neither is present.
+pub fn load_reported_count(
+    data_root: &DataRoot,
+    tally: Option<&AnalyticsTally>,
+) -> Result<Option<ReportedCount>, MyError> {
+    if let Some(count) = account_message_count(&data_root.path.join("account").join("user.json"))? {
+        return Ok(Some(ReportedCount {
+            count,
+            source: "account/user.json".to_string(),
+        }));
+    }
+
+    Ok(tally.map(|tally| ReportedCount {
+        count: tally.total,
+        source: "activity analytics".to_string(),
+    }))
+}
+
+/// Tally message-sent events from the analytics dumps. Returns `None` if the
+/// package contains no analytics.
+pub fn load_analytics_tally(data_root: &DataRoot) -> Result<Option<AnalyticsTally>, MyError> {
+    let files = analytics_files(&data_root.path.join("activity"))?;
+    if files.is_empty() {
+        return Ok(None);
+    }
+
+    let mut tally = AnalyticsTally::default();
+    let mut found = false;
+    for file in &files {
+        found |= tally_sent_events(file, &mut tally)?;
+    }
+
+    Ok(found.then_some(tally))
+}
+
+/// Sum the durations of voice sessions in the analytics dumps. Returns `None`
+/// if the package contains no analytics.
+pub fn load_voice_stats(data_root: &DataRoot) -> Result<Option<VoiceStats>, MyError> {
+    let files = analytics_files(&data_root.path.join("activity"))?;
+    if files.is_empty() {
+        return Ok(None);
+    }
+
+    let mut stats = VoiceStats::default();
+    for file in &files {
+        tally_voice_sessions(file, &mut stats)?;
+    }
+
+    Ok(Some(stats))
+}
+
+/// Distinct channel names in the analytics, most recently seen first, by
+/// channel ID. Returns `None` if the package contains no analytics.
+pub fn load_channel_names(
+    data_root: &DataRoot,
+) -> Result<Option<HashMap<String, Vec<String>>>, MyError> {
+    let files = analytics_files(&data_root.path.join("activity"))?;
+    if files.is_empty() {
+        return Ok(None);
+    }
+
+    let mut seen: HashMap<String, HashMap<String, Option<DateTime<Utc>>>> = HashMap::new();
+    for file in &files {
+        tally_channel_names(file, &mut seen)?;
+    }
+
+    Ok(Some(
+        seen.into_iter()
+            .map(|(channel_id, names)| {
+                let mut names: Vec<(String, Option<DateTime<Utc>>)> = names.into_iter().collect();
+                names.sort_by(|(a_name, a_seen), (b_name, b_seen)| {
+                    b_seen.cmp(a_seen).then_with(|| a_name.cmp(b_name))
+                });
+                (
+                    channel_id,
+                    names.into_iter().map(|(name, _)| name).collect(),
+                )
+            })
+            .collect(),
+    ))
+}
+
+/// Compare each conversation's count within the analytics window against the
+/// analytics, keeping those where more than `threshold` messages are missing.
+/// Channels are attributed to conversations through `conversation_of_channel`.
+pub fn find_discrepancies(
+    conversations: &[Conversation],
+    tally: &AnalyticsTally,
+    counted_since: &HashMap<String, usize>,
+    conversation_of_channel: &HashMap<String, String>,
+    threshold: u64,
+) -> Vec<Discrepancy> {
+    let mut analytics_sent: HashMap<&str, u64> = HashMap::new();
+    for (channel_id, sent) in &tally.sent_by_channel {
+        if let Some(conversation_id) = conversation_of_channel.get(channel_id) {
+            *analytics_sent.entry(conversation_id).or_default() += sent;
+        }
+    }
+    let mut counted: HashMap<&str, u64> = HashMap::new();
+    for (channel_id, count) in counted_since {
+        if let Some(conversation_id) = conversation_of_channel.get(channel_id) {
+            *counted.entry(conversation_id).or_default() += *count as u64;
+        }
+    }
+
+    conversations
+        .iter()
+        .filter_map(|conversation| {
+            let id = conversation.id();
+            let discrepancy = Discrepancy {
+                conversation_id: id.to_string(),
+                analytics_sent: analytics_sent.get(id).copied().unwrap_or_default(),
+                counted: counted.get(id).copied().unwrap_or_default(),
+            };
+            (discrepancy.missing() > threshold).then_some(discrepancy)
+        })
+        .collect()
+}
+
+fn account_message_count(path: &Path) -> Result<Option<u64>, MyError> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let file = File::open(path)?;
+    let user: Value = match serde_json::from_reader(BufReader::new(file)) {
+        Ok(user) => user,
+        // An unreadable account file only means there is nothing to compare against
+        Err(_) => return Ok(None),
+    };
+
+    Ok(ACCOUNT_COUNT_FIELDS
+        .iter()
+        .find_map(|field| user.get(*field).and_then(as_count)))
+}
+
+fn analytics_files(activity_folder: &Path) -> Result<Vec<PathBuf>, MyError> {
+    let mut files = Vec::new();
+
+    for folder in ACTIVITY_FOLDERS {
+        let folder = activity_folder.join(folder);
+        if !folder.is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(folder)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                files.push(path);
+            }
+        }
+        // The analytics folders mirror each other, so the first one found is enough
+        if !files.is_empty() {
+            break;
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Add the message-sent events of an NDJSON file to the tally.
+/// Returns whether the file contained any events at all.
+fn tally_sent_events(path: &Path, tally: &mut AnalyticsTally) -> Result<bool, MyError> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut found = false;
+
+    for line in reader.lines() {
+        let line = line?;
+        found = true;
+
+        // Skip the JSON parse for the vast majority of unrelated events
+        if !MESSAGE_SENT_EVENTS.iter().any(|event| line.contains(event)) {
+            continue;
+        }
+
+        if let Ok(event) = serde_json::from_str::<Value>(&line) {
+            let event_type = event.get("event_type").and_then(|v| v.as_str());
+            if !event_type.is_some_and(|t| MESSAGE_SENT_EVENTS.contains(&t)) {
+                continue;
+            }
+
+            tally.total += 1;
+            if let Some(channel_id) = event.get("channel_id").and_then(as_id) {
+                *tally.sent_by_channel.entry(channel_id).or_default() += 1;
+            }
+            if let Some(timestamp) = event.get("timestamp").and_then(as_timestamp) {
+                tally.since = Some(tally.since.map_or(timestamp, |since| since.min(timestamp)));
+            }
+        }
+    }
+
+    Ok(found)
+}
+
+fn tally_voice_sessions(path: &Path, stats: &mut VoiceStats) -> Result<(), MyError> {
+    let reader = BufReader::new(File::open(path)?);
+
+    for line in reader.lines() {
+        let line = line?;
+        if !VOICE_SESSION_EVENTS
+            .iter()
+            .any(|event| line.contains(event))
+        {
+            continue;
+        }
+
+        let Ok(event) = serde_json::from_str::<Value>(&line) else {
+            continue;
+        };
+        let event_type = event.get("event_type").and_then(|v| v.as_str());
+        if !event_type.is_some_and(|t| VOICE_SESSION_EVENTS.contains(&t)) {
+            continue;
+        }
+        // Durations are recorded in milliseconds
+        let Some(seconds) = event.get("duration").and_then(as_count).map(|ms| ms / 1000) else {
+            continue;
+        };
+
+        if seconds > MAX_VOICE_SESSION_SECONDS {
+            stats.discarded_sessions += 1;
+            continue;
+        }
+
+        stats.sessions += 1;
+        stats.total_seconds += seconds;
+        if let Some(guild_id) = event.get("guild_id").and_then(as_id) {
+            *stats.seconds_by_guild.entry(guild_id).or_default() += seconds;
+        }
+    }
+
+    Ok(())
+}
+
+/// Record when each channel name was last seen in an NDJSON file.
+fn tally_channel_names(
+    path: &Path,
+    seen: &mut HashMap<String, HashMap<String, Option<DateTime<Utc>>>>,
+) -> Result<(), MyError> {
+    let reader = BufReader::new(File::open(path)?);
+
+    for line in reader.lines() {
+        let line = line?;
+        if !line.contains("channel_name") {
+            continue;
+        }
+
+        let Ok(event) = serde_json::from_str::<Value>(&line) else {
+            continue;
+        };
+        let (Some(channel_id), Some(name)) = (
+            event.get("channel_id").and_then(as_id),
+            event.get("channel_name").and_then(|v| v.as_str()),
+        ) else {
+            continue;
+        };
+        let name = name.trim().trim_matches('"');
+        if name.is_empty() {
+            continue;
+        }
+        let timestamp = event.get("timestamp").and_then(as_timestamp);
+        let last_seen = seen
+            .entry(channel_id)
+            .or_default()
+            .entry(name.to_string())
+            .or_default();
+        *last_seen = (*last_seen).max(timestamp);
+    }
+
+    Ok(())
+}
+
+fn as_id(value: &Value) -> Option<String> {
+    match value {
+        Value::String(id) => Some(id.trim_matches('"').to_string()),
+        Value::Number(id) => Some(id.to_string()),
+        _ => None,
+    }
+}
+
+/// Analytics timestamps are RFC 3339 strings, sometimes wrapped in literal quotes.
+fn as_timestamp(value: &Value) -> Option<DateTime<Utc>> {
+    let timestamp = value.as_str()?.trim_matches('"');
+    DateTime::parse_from_rfc3339(timestamp)
+        .ok()
+        .map(|timestamp| timestamp.with_timezone(&Utc))
+}
+
+fn as_count(value: &Value) -> Option<u64> {
+    value
+        .as_u64()
+        .or_else(|| value.as_str().and_then(|s| s.parse().ok()))
+}
@@ -0,0 +1,159 @@
+//! `--format bars`: a horizontal bar per conversation, scaled to the largest
+//! one, so relative sizes can be seen at a glance. The chart fills the
+//! terminal's width, or 80 columns when there is no terminal to measure.
+
+use crate::color::TOP_CONVERSATIONS;
+use crate::errors::MyError;
+use crate::render::{display_name, ResultsView};
+use crate::width::truncate;
+use crate::Conversation;
+use std::io::Write;
+use unicode_width::UnicodeWidthStr;
+
+const FALLBACK_WIDTH: usize = 80;
+/// Share of the width names may take before they are cut short.
+const NAME_SHARE: usize = 3;
+const MIN_BAR_WIDTH: usize = 10;
+const CONVERSATION_BAR: char = '█';
+const CHANNEL_BAR: char = '▓';
+/// Indent of channel rows below their guild.
+const CHANNEL_INDENT: &str = "  ";
+
+struct Row {
+    name: String,
+    count: usize,
+    bar: char,
+    top: bool,
+}
+
+pub fn render_bars(view: &ResultsView, out: &mut dyn Write) -> Result<(), MyError> {
+    let rows = rows(view);
+    let width = crossterm::terminal::size()
+        .map_or(FALLBACK_WIDTH, |(columns, _)| usize::from(columns))
+        .max(MIN_BAR_WIDTH * 2);
+    let count = |n: usize| view.counts.grouped(n as u64);
+
+    let count_width = rows
+        .iter()
+        .map(|row| count(row.count).width())
+        .max()
+        .unwrap_or(0);
+    let name_width = rows
+        .iter()
+        .map(|row| row.name.width())
+        .max()
+        .unwrap_or(0)
+        .min(width / NAME_SHARE);
+    let bar_width = width
+        .saturating_sub(name_width + count_width + 2)
+        .max(MIN_BAR_WIDTH);
+    let largest = rows.iter().map(|row| row.count).max().unwrap_or(0);
+
+    for row in &rows {
+        let name = fit(&row.name, name_width);
+        let name = match row.top {
+            true => view.palette.top(&name),
+            false => name,
+        };
+        let cells = bar_cells(row.count, largest, bar_width);
+        writeln!(
+            out,
+            "{} {}{} {:>width$}",
+            name,
+            String::from(row.bar).repeat(cells),
+            " ".repeat(bar_width - cells),
+            view.palette.count(&count(row.count)),
+            width = count_width
+        )?;
+    }
+    Ok(())
+}
+
+/// Conversations in display order, followed by their channels with --channels.
+fn rows(view: &ResultsView) -> Vec<Row> {
+    let mut rows = Vec::new();
+    for (index, &conversation) in view.conversations.iter().enumerate() {
+        rows.push(Row {
+            name: display_name(conversation),
+            count: conversation.message_count(),
+            bar: CONVERSATION_BAR,
+            top: index < TOP_CONVERSATIONS,
+        });
+        if let (true, false, Conversation::Guild { channels, .. }) =
+            (view.channels, conversation.is_unknown_guild(), conversation)
+        {
+            rows.extend(channels.iter().map(|channel| Row {
+                name: format!("{}#{}", CHANNEL_INDENT, channel.name),
+                count: channel.message_count,
+                bar: CHANNEL_BAR,
+                top: false,
+            }));
+        }
+    }
+    rows
+}
+
+/// Cells of a bar for `count`, rounded, and at least one for any message.
+/// Everything is an empty bar when the largest count is zero.
+fn bar_cells(count: usize, largest: usize, bar_width: usize) -> usize {
+    if largest == 0 || count == 0 {
+        return 0;
+    }
+    ((count * bar_width + largest / 2) / largest).clamp(1, bar_width)
+}
+
+/// `name` padded or cut short with `…` to exactly `width` terminal cells.
+fn fit(name: &str, width: usize) -> String {
+    let fitted = truncate(name, width);
+    let padding = width.saturating_sub(fitted.width());
+    format!("{}{}", fitted, " ".repeat(padding))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::OutputFormat;
+    use crate::testing::{dm, rendered, report, view};
+
+    #[test]
+    fn no_bars_when_the_largest_count_is_zero() {
+        assert_eq!(bar_cells(0, 0, 40), 0);
+        // A count above a zero maximum cannot happen, but must not divide by zero
+        assert_eq!(bar_cells(5, 0, 40), 0);
+
+        let report = report(vec![dm("1", "Alice", 0), dm("2", "Bob", 0)]);
+        let bars = rendered(OutputFormat::Bars, &view(&report));
+        assert_eq!(bars.lines().count(), 2);
+        assert!(!bars.contains(CONVERSATION_BAR), "{}", bars);
+    }
+
+    #[test]
+    fn the_largest_count_fills_the_width() {
+        assert_eq!(bar_cells(7, 7, 40), 40);
+        assert_eq!(bar_cells(1, 1, MIN_BAR_WIDTH), MIN_BAR_WIDTH);
+        // Just short of the largest rounds to the full width, never beyond it
+        assert_eq!(bar_cells(999, 1000, 40), 40);
+        assert_eq!(bar_cells(987, 1000, 40), 39);
+        assert!((1..=1000).all(|count| bar_cells(count, 1000, 40) <= 40));
+    }
+
+    #[test]
+    fn cells_round_half_up_and_any_message_shows() {
+        assert_eq!(bar_cells(1, 2, 41), 21);
+        assert_eq!(bar_cells(1, 4, 10), 3);
+        assert_eq!(bar_cells(1, 1000, 40), 1);
+        assert_eq!(bar_cells(0, 1000, 40), 0);
+    }
+
+    #[test]
+    fn bars_are_scaled_to_the_largest() {
+        let report = report(vec![dm("1", "Alice", 2), dm("2", "Bob", 1)]);
+        let bars = rendered(OutputFormat::Bars, &view(&report));
+        let cells: Vec<usize> = bars
+            .lines()
+            .map(|line| line.matches(CONVERSATION_BAR).count())
+            .collect();
+        assert!(cells[0] >= MIN_BAR_WIDTH, "{}", bars);
+        assert_eq!(cells[1], cells[0].div_ceil(2), "{}", bars);
+    }
+}
@@ -0,0 +1,101 @@
+use crate::errors::MyError;
+use crate::metrics::Metric;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+
+pub const METRIC: Metric = Metric {
+    name: "breadth_by_year",
+    definition: "Per calendar year, the distinct servers, guild channels and DMs with at least \
+        one counted message in that year.",
+    affected_by: "Collected with --breadth; --timezone decides where years begin; \
+        --display-window limits the years shown. --exclude-system and --dedupe-messages \
+        remove messages first.",
+    edge_cases: "Channels of unknown servers count as channels but not as servers. Messages \
+        without a usable timestamp belong to no year.",
+};
+
+/// Distinct places posted in during one calendar year.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct YearBreadth {
+    pub year: i32,
+    pub servers: usize,
+    pub channels: usize,
+    pub dms: usize,
+}
+
+#[derive(Default)]
+struct YearIds {
+    servers: HashSet<u64>,
+    channels: HashSet<u64>,
+    dms: HashSet<u64>,
+}
+
+/// Collects per-year sets of snowflakes while channels are processed.
+#[derive(Default)]
+pub struct BreadthTally {
+    by_year: BTreeMap<i32, YearIds>,
+}
+
+impl BreadthTally {
+    /// Record a guild channel active in `years`. Channels of unidentifiable
+    /// servers only count as channels.
+    pub fn add_guild_channel(
+        &mut self,
+        guild_id: Option<&str>,
+        channel_id: &str,
+        years: &BTreeMap<i32, usize>,
+    ) {
+        let guild_id = guild_id.and_then(|id| id.parse().ok());
+        let Ok(channel_id) = channel_id.parse() else {
+            return;
+        };
+        for &year in years.keys() {
+            let ids = self.by_year.entry(year).or_default();
+            ids.channels.insert(channel_id);
+            if let Some(guild_id) = guild_id {
+                ids.servers.insert(guild_id);
+            }
+        }
+    }
+
+    pub fn add_dm(&mut self, channel_id: &str, years: &BTreeMap<i32, usize>) {
+        let Ok(channel_id) = channel_id.parse() else {
+            return;
+        };
+        for &year in years.keys() {
+            self.by_year.entry(year).or_default().dms.insert(channel_id);
+        }
+    }
+
+    /// Years in ascending order; only years with at least one message appear.
+    pub fn finish(self) -> Vec<YearBreadth> {
+        self.by_year
+            .into_iter()
+            .map(|(year, ids)| YearBreadth {
+                year,
+                servers: ids.servers.len(),
+                channels: ids.channels.len(),
+                dms: ids.dms.len(),
+            })
+            .collect()
+    }
+}
+
+pub fn write_breadth_table(breadth: &[YearBreadth], out: &mut dyn Write) -> Result<(), MyError> {
+    writeln!(out, "Breadth by year:")?;
+    writeln!(
+        out,
+        "{:<6}{:>9}{:>10}{:>7}",
+        "Year", "Servers", "Channels", "DMs"
+    )?;
+    for year in breadth {
+        writeln!(
+            out,
+            "{:<6}{:>9}{:>10}{:>7}",
+            year.year, year.servers, year.channels, year.dms
+        )?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
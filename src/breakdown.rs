@@ -0,0 +1,173 @@
+use crate::errors::MyError;
+use crate::output::Output;
+use chrono::{Datelike, Days, IsoWeek, NaiveDate};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+/// Shades used for the heatmap, from no activity to the busiest week.
+const HEATMAP_SHADES: &[char] = &[' ', '░', '▒', '▓', '█'];
+
+/// Message count of a single ISO 8601 week.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WeekCount {
+    pub week: IsoWeek,
+    pub count: usize,
+}
+
+impl WeekCount {
+    /// Label in ISO 8601 week notation, e.g. `2023-W41`.
+    pub fn label(&self) -> String {
+        format!("{}-W{:02}", self.week.year(), self.week.week())
+    }
+}
+
+/// Bucket dates into ISO weeks (starting Monday, week 1 containing January 4),
+/// filling weeks without messages between the first and last active week
+/// with zero.
+pub fn weekly_counts<I: IntoIterator<Item = NaiveDate>>(dates: I) -> Vec<WeekCount> {
+    let mut by_monday: BTreeMap<NaiveDate, usize> = BTreeMap::new();
+    for date in dates {
+        let Some(monday) =
+            date.checked_sub_days(Days::new(date.weekday().num_days_from_monday().into()))
+        else {
+            continue;
+        };
+        *by_monday.entry(monday).or_default() += 1;
+    }
+
+    let (Some(&first), Some(&last)) = (by_monday.keys().next(), by_monday.keys().next_back())
+    else {
+        return Vec::new();
+    };
+
+    let mut weeks = Vec::new();
+    let mut monday = first;
+    while monday <= last {
+        weeks.push(WeekCount {
+            week: monday.iso_week(),
+            count: by_monday.get(&monday).copied().unwrap_or(0),
+        });
+        let Some(next) = monday.checked_add_days(Days::new(7)) else {
+            break;
+        };
+        monday = next;
+    }
+    weeks
+}
+
+pub fn print_weekly_rows(weeks: &[WeekCount]) {
+    for week in weeks {
+        println!("{}  {}", week.label(), week.count);
+    }
+}
+
+/// Print the weeks as a single-row heatmap, one column per week.
+pub fn print_weekly_heatmap(weeks: &[WeekCount]) {
+    let (Some(first), Some(last)) = (weeks.first(), weeks.last()) else {
+        return;
+    };
+
+    let max = weeks.iter().map(|week| week.count).max().unwrap_or(0);
+    let row: String = weeks
+        .iter()
+        .map(|week| {
+            if week.count == 0 || max == 0 {
+                HEATMAP_SHADES[0]
+            } else {
+                HEATMAP_SHADES[(week.count * (HEATMAP_SHADES.len() - 1)).div_ceil(max)]
+            }
+        })
+        .collect();
+
+    println!("{} {} {}", first.label(), row, last.label());
+}
+
+/// Write the weeks as CSV to `path`, which is only replaced with `force`.
+pub fn write_weekly_csv(weeks: &[WeekCount], path: &Path, force: bool) -> Result<(), MyError> {
+    let mut out = Output::open(Some(path), force)?;
+    writeln!(out, "week,messages")?;
+    for week in weeks {
+        writeln!(out, "{},{}", week.label(), week.count)?;
+    }
+    out.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::fs;
+
+    #[test]
+    fn dates_at_the_calendar_limits_do_not_overflow() {
+        assert!(weekly_counts([NaiveDate::MIN]).len() <= 1);
+        assert_eq!(weekly_counts([NaiveDate::MAX]).len(), 1);
+    }
+
+    #[test]
+    fn quiet_weeks_between_active_ones_are_zero() {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        // A Sunday, the Monday after it and a Wednesday two weeks on
+        let weeks = weekly_counts([date(2023, 1, 1), date(2023, 1, 2), date(2023, 1, 18)]);
+        let labels: Vec<(String, usize)> = weeks
+            .iter()
+            .map(|week| (week.label(), week.count))
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                ("2022-W52".to_string(), 1),
+                ("2023-W01".to_string(), 1),
+                ("2023-W02".to_string(), 0),
+                ("2023-W03".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn weekly_csv_only_replaces_a_file_with_force() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("weeks.csv");
+        let date = NaiveDate::from_ymd_opt(2023, 1, 2).unwrap();
+        let weeks = weekly_counts([date, date]);
+
+        fs::write(&path, "mine").unwrap();
+        assert!(matches!(
+            write_weekly_csv(&weeks, &path, false),
+            Err(MyError::OutputExists(_))
+        ));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "mine");
+
+        write_weekly_csv(&weeks, &path, true).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "week,messages\n2023-W01,2\n"
+        );
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    proptest! {
+        /// Dates within a few years of each other anywhere in the range
+        /// `Message::datetime` lets through, a day either side for zones.
+        #[test]
+        fn weeks_are_consecutive_and_hold_every_date(
+            start in 719_162i32..3_652_060,
+            offsets in proptest::collection::vec(0i32..2_000, 0..50),
+        ) {
+            let dates: Vec<NaiveDate> = offsets
+                .iter()
+                .map(|&offset| NaiveDate::from_num_days_from_ce_opt(start + offset).unwrap())
+                .collect();
+            let weeks = weekly_counts(dates.iter().copied());
+
+            prop_assert_eq!(weeks.iter().map(|week| week.count).sum::<usize>(), dates.len());
+            for pair in weeks.windows(2) {
+                let monday = |week: IsoWeek| {
+                    NaiveDate::from_isoywd_opt(week.year(), week.week(), chrono::Weekday::Mon).unwrap()
+                };
+                prop_assert_eq!(monday(pair[1].week) - monday(pair[0].week), chrono::Duration::days(7));
+            }
+        }
+    }
+}
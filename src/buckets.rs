@@ -0,0 +1,170 @@
+//! Coarse count ranges for reports that are shared, so exact figures are not
+//! revealed. The scale steps through 1, 2.5 and 5 of every power of ten:
+//! 1–10, 10–25, 25–50, 50–100, 100–250, …, 10k–25k, … Each range includes
+//! its lower bound and excludes its upper one; zero stays zero.
+
+use crate::metrics::Metric;
+use crate::numbers::percent_value;
+use crate::render::format_thousands;
+
+pub const METRIC: Metric = Metric {
+    name: "count_bucket",
+    definition: "The range of 1, 2.5 and 5 times a power of ten a message count falls into, \
+        e.g. 10k–25k for 12,345. Each range includes its lower bound and excludes its upper one.",
+    affected_by: "Shown instead of exact counts with --bucket-counts; sorting still uses the \
+        exact counts. Percentages lose their decimals.",
+    edge_cases: "Zero stays 0, and every count from 1 to 9 is 1–10.",
+};
+
+/// How message counts are displayed. Sorting always uses the exact values.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CountStyle {
+    #[default]
+    Exact,
+    Bucketed,
+}
+
+impl CountStyle {
+    pub fn format(self, n: u64) -> String {
+        match self {
+            Self::Exact => n.to_string(),
+            Self::Bucketed => count_bucket(n),
+        }
+    }
+
+    /// Like `format`, with thousands separators for exact counts.
+    pub fn grouped(self, n: u64) -> String {
+        match self {
+            Self::Exact => format_thousands(n),
+            Self::Bucketed => count_bucket(n),
+        }
+    }
+
+    /// Percentages get whole numbers once counts are bucketed, since their
+    /// decimals would reveal the exact figures again.
+    pub fn percent(self, numerator: u64, denominator: u64) -> String {
+        match self {
+            Self::Exact => percent_value(numerator, denominator, 2),
+            Self::Bucketed => percent_value(numerator, denominator, 0),
+        }
+    }
+
+    /// A share with its sign as listings show it, e.g. "12.4%": one decimal,
+    /// or none once counts are bucketed. A nonzero count never reads as zero
+    /// but as "<0.1%", or "<1%".
+    pub fn share(self, numerator: u64, denominator: u64) -> String {
+        let (decimals, smallest) = match self {
+            Self::Exact => (1, "0.1"),
+            Self::Bucketed => (0, "1"),
+        };
+        let value = percent_value(numerator, denominator, decimals);
+        match numerator > 0 && value.chars().all(|c| c == '0' || c == '.') {
+            true => format!("<{}%", smallest),
+            false => format!("{}%", value),
+        }
+    }
+}
+
+/// The range of the bucket scale `n` falls into, e.g. "10k–25k".
+pub fn count_bucket(n: u64) -> String {
+    if n == 0 {
+        return "0".to_string();
+    }
+    if n < 10 {
+        return format!("1–{}", compact(10));
+    }
+
+    // Wide enough that the last power of ten cannot overflow
+    let n = u128::from(n);
+    let mut power: u128 = 10;
+    loop {
+        for (lower, upper) in [
+            (power, power * 5 / 2),
+            (power * 5 / 2, power * 5),
+            (power * 5, power * 10),
+        ] {
+            if n < upper {
+                return format!("{}–{}", compact(lower), compact(upper));
+            }
+        }
+        power *= 10;
+    }
+}
+
+fn compact(n: u128) -> String {
+    let (unit, suffix) = match n {
+        n if n >= 1_000_000_000_000 => (1_000_000_000_000, "T"),
+        n if n >= 1_000_000_000 => (1_000_000_000, "G"),
+        n if n >= 1_000_000 => (1_000_000, "M"),
+        n if n >= 1_000 => (1_000, "k"),
+        _ => (1, ""),
+    };
+    // Every bound of the scale is a whole or half multiple of its unit
+    let (whole, half) = (n / unit, (n % unit) * 2 / unit);
+    match half {
+        0 => format!("{}{}", whole, suffix),
+        _ => format!("{}.5{}", whole, suffix),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::OutputFormat;
+    use crate::testing::{dm, rendered, report, view};
+
+    #[test]
+    fn ranges_include_their_lower_bound_only() {
+        let cases = [
+            (0, "0"),
+            (1, "1–10"),
+            (9, "1–10"),
+            (10, "10–25"),
+            (24, "10–25"),
+            (25, "25–50"),
+            (49, "25–50"),
+            (50, "50–100"),
+            (99, "50–100"),
+            (100, "100–250"),
+            (2_499, "1k–2.5k"),
+            (2_500, "2.5k–5k"),
+            (9_999, "5k–10k"),
+            (10_000, "10k–25k"),
+            (12_345, "10k–25k"),
+            (999_999, "500k–1M"),
+            (1_000_000, "1M–2.5M"),
+            (2_500_000_000, "2.5G–5G"),
+            (5_000_000_000_000, "5T–10T"),
+        ];
+        for (n, bucket) in cases {
+            assert_eq!(count_bucket(n), bucket, "{}", n);
+        }
+    }
+
+    #[test]
+    fn the_largest_counts_still_get_a_range() {
+        assert_eq!(count_bucket(u64::MAX), "10000000T–25000000T");
+    }
+
+    #[test]
+    fn shares_never_read_as_zero() {
+        assert_eq!(CountStyle::Exact.share(1, 10_000), "<0.1%");
+        assert_eq!(CountStyle::Bucketed.share(1, 1_000), "<1%");
+        assert_eq!(CountStyle::Bucketed.share(0, 1_000), "0%");
+        assert_eq!(CountStyle::Bucketed.percent(1, 3), "33");
+    }
+
+    #[test]
+    fn json_carries_ranges_instead_of_counts() {
+        let report = report(vec![dm("1", "Alice", 12_345)]);
+        let mut view = view(&report);
+        view.counts = CountStyle::Bucketed;
+
+        let json: serde_json::Value =
+            serde_json::from_str(&rendered(OutputFormat::Json, &view)).unwrap();
+        let conversation = &json["conversations"][0];
+        assert_eq!(conversation["count_bucket"], "10k–25k");
+        assert!(conversation.get("message_count").is_none());
+        assert!(json["meta"].get("total_messages").is_none());
+    }
+}
@@ -0,0 +1,99 @@
+use crate::humanize::fmt_duration;
+use crate::numbers::fmt_percent;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Cooperative cancellation shared between the processing loop and anything
+/// that may want it to stop early. Checked between channels.
+#[derive(Debug, Clone, Default)]
+pub struct CancelFlag(Arc<AtomicBool>);
+
+impl CancelFlag {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Upper bounds on how much work a run may do.
+#[derive(Debug, Clone, Default)]
+pub struct Budget {
+    pub max_duration: Option<Duration>,
+    pub max_messages: Option<usize>,
+}
+
+/// The budget that was exhausted.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetLimit {
+    MaxSeconds(u64),
+    MaxMessages(usize),
+}
+
+impl Budget {
+    /// Returns the exhausted limit, if any, given the run's start and the
+    /// number of messages counted so far.
+    pub fn exceeded(&self, started: Instant, counted: usize) -> Option<BudgetLimit> {
+        if let Some(max_duration) = self.max_duration {
+            if started.elapsed() >= max_duration {
+                return Some(BudgetLimit::MaxSeconds(max_duration.as_secs()));
+            }
+        }
+        if let Some(max_messages) = self.max_messages {
+            if counted >= max_messages {
+                return Some(BudgetLimit::MaxMessages(max_messages));
+            }
+        }
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    Budget(BudgetLimit),
+    Cancelled,
+}
+
+/// Marks a report whose processing stopped before every channel was counted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartialRun {
+    pub stopped_by: StopReason,
+    pub channels_processed: usize,
+    pub channels_total: usize,
+}
+
+impl fmt::Display for PartialRun {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self.stopped_by {
+            StopReason::Budget(BudgetLimit::MaxSeconds(seconds)) => {
+                format!(
+                    "the {} time budget was exhausted",
+                    fmt_duration(Duration::from_secs(seconds), 2)
+                )
+            }
+            StopReason::Budget(BudgetLimit::MaxMessages(messages)) => {
+                format!("the {} message budget was exhausted", messages)
+            }
+            StopReason::Cancelled => "processing was cancelled".to_string(),
+        };
+        write!(
+            f,
+            "PARTIAL RESULT: {}; {} of {} channels ({}) processed",
+            reason,
+            self.channels_processed,
+            self.channels_total,
+            fmt_percent(
+                self.channels_processed as u64,
+                self.channels_total as u64,
+                1
+            )
+        )
+    }
+}
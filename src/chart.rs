@@ -0,0 +1,149 @@
+//! A PNG bar chart of the conversations with the most messages. Text is
+//! drawn with a TrueType font read at runtime, so names in any script render
+//! as long as the font covers them.
+
+use crate::errors::MyError;
+use crate::render::{display_name, ResultsView};
+use crate::Conversation;
+use plotters::prelude::*;
+use plotters::style::{register_font, FontStyle};
+use std::fs;
+use std::path::Path;
+
+/// Fonts tried, in order, when no `--chart-font` is given.
+const SYSTEM_FONTS: &[&str] = &[
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/System/Library/Fonts/Supplemental/Arial Unicode.ttf",
+    "/Library/Fonts/Arial Unicode.ttf",
+    "C:\\Windows\\Fonts\\arial.ttf",
+];
+
+/// Characters of a conversation name shown on the axis.
+const NAME_LIMIT: usize = 28;
+
+const SIZE: (u32, u32) = (1200, 800);
+
+/// Draw the first `top` conversations of `view` as horizontal bars to the
+/// PNG file at `path`, largest at the top.
+pub fn write_chart(
+    view: &ResultsView,
+    path: &Path,
+    font: Option<&Path>,
+    top: usize,
+) -> Result<(), MyError> {
+    load_font(font)?;
+
+    let shown = &view.conversations[..view.conversations.len().min(top)];
+    let total: usize = view
+        .report
+        .conversations
+        .iter()
+        .map(Conversation::message_count)
+        .sum();
+    let largest = shown
+        .iter()
+        .map(|conversation| conversation.message_count())
+        .max()
+        .unwrap_or(0);
+    let names: Vec<String> = shown
+        .iter()
+        .map(|conversation| truncate(&display_name(conversation)))
+        .collect();
+    let rows = shown.len().max(1) as u32;
+
+    let root = BitMapBackend::new(path, SIZE).into_drawing_area();
+    root.fill(&WHITE).map_err(chart_error)?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption(
+            format!(
+                "Top {} of {} conversations, {} messages in total",
+                shown.len(),
+                view.conversations.len(),
+                view.counts.grouped(total as u64)
+            ),
+            ("sans-serif", 28),
+        )
+        .margin(20)
+        .x_label_area_size(40)
+        .y_label_area_size(280)
+        .build_cartesian_2d(
+            0..(largest as u64 * 21 / 20).max(1),
+            (0..rows - 1).into_segmented(),
+        )
+        .map_err(chart_error)?;
+
+    chart
+        .configure_mesh()
+        .disable_y_mesh()
+        .y_labels(rows as usize)
+        .y_label_formatter(&|value| match value {
+            SegmentValue::CenterOf(row) => rows
+                .checked_sub(row + 1)
+                .and_then(|index| names.get(index as usize))
+                .cloned()
+                .unwrap_or_default(),
+            _ => String::new(),
+        })
+        .x_desc("Messages")
+        .label_style(("sans-serif", 16))
+        .draw()
+        .map_err(chart_error)?;
+
+    chart
+        .draw_series(shown.iter().enumerate().map(|(index, conversation)| {
+            let row = rows - 1 - index as u32;
+            let mut bar = Rectangle::new(
+                [
+                    (0, SegmentValue::Exact(row)),
+                    (
+                        conversation.message_count() as u64,
+                        SegmentValue::Exact(row + 1),
+                    ),
+                ],
+                RGBColor(0x58, 0x65, 0xf2).filled(),
+            );
+            bar.set_margin(4, 4, 0, 0);
+            bar
+        }))
+        .map_err(chart_error)?;
+
+    root.present().map_err(chart_error)?;
+    Ok(())
+}
+
+/// Register the font every text of the chart is drawn with. Plotters keeps
+/// fonts for the rest of the process, so the bytes are leaked on purpose.
+fn load_font(font: Option<&Path>) -> Result<(), MyError> {
+    let path = match font {
+        Some(path) => path,
+        None => SYSTEM_FONTS
+            .iter()
+            .map(Path::new)
+            .find(|path| path.is_file())
+            .ok_or_else(|| {
+                MyError::Chart("no usable font found; pass one with --chart-font".to_string())
+            })?,
+    };
+    let bytes = fs::read(path).map_err(|error| {
+        MyError::Chart(format!("cannot read font {} ({})", path.display(), error))
+    })?;
+    register_font(
+        "sans-serif",
+        FontStyle::Normal,
+        Box::leak(bytes.into_boxed_slice()),
+    )
+    .map_err(|_| MyError::Chart(format!("{} is not a TrueType font", path.display())))
+}
+
+fn truncate(name: &str) -> String {
+    match name.char_indices().nth(NAME_LIMIT) {
+        Some((end, _)) => format!("{}…", &name[..end]),
+        None => name.to_string(),
+    }
+}
+
+fn chart_error(error: impl std::fmt::Display) -> MyError {
+    MyError::Chart(error.to_string())
+}
@@ -0,0 +1,12 @@
+//! `--copy`: the rendered results on the system clipboard, ready to paste
+//! into a chat without selecting terminal output.
+
+use arboard::Clipboard;
+
+/// Put `text` on the clipboard. Fails where there is none, e.g. on a
+/// headless system.
+pub fn copy(text: &str) -> Result<(), arboard::Error> {
+    // On X11 the contents are served by this process; dropping the
+    // clipboard hands them to a clipboard manager if one is running
+    Clipboard::new()?.set_text(text)
+}
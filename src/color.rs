@@ -0,0 +1,145 @@
+//! Colors of the human-readable renderers. Machine formats never get any;
+//! text and tables only when `--color` allows it for stdout. Builds without
+//! the `color` feature have no `--color` and always print plain text.
+
+#[cfg(feature = "color")]
+use clap::ValueEnum;
+use comfy_table::{Attribute, Cell, Color};
+#[cfg(feature = "color")]
+use std::env;
+
+const RESET: &str = "\x1b[0m";
+
+/// Conversations highlighted at the top of the listing.
+pub const TOP_CONVERSATIONS: usize = 3;
+
+/// When stdout gets colors.
+#[cfg(feature = "color")]
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorMode {
+    /// Only on a terminal, and not when NO_COLOR is set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+#[cfg(feature = "color")]
+impl ColorMode {
+    /// Whether output to a terminal, or not, gets colors.
+    pub fn enabled(self, terminal: bool) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Never => false,
+            // https://no-color.org: set and not empty
+            Self::Auto => terminal && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()),
+        }
+    }
+}
+
+/// What a piece of output is, which decides how it is painted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Guild,
+    Channel,
+    Count,
+    /// The largest conversations, whatever their kind
+    Top,
+    /// Tips and other asides below the results
+    Dim,
+}
+
+impl Style {
+    /// SGR sequence of the style.
+    fn sgr(self) -> &'static str {
+        match self {
+            Self::Guild => "\x1b[36m",
+            Self::Channel => "\x1b[32m",
+            Self::Count => "\x1b[1m",
+            Self::Top => "\x1b[1;33m",
+            Self::Dim => "\x1b[2m",
+        }
+    }
+
+    /// The same style for a table cell, which the table has to measure
+    /// without escape sequences.
+    fn cell(self, cell: Cell) -> Cell {
+        match self {
+            Self::Guild => cell.fg(Color::Cyan),
+            Self::Channel => cell.fg(Color::Green),
+            Self::Count => cell.add_attribute(Attribute::Bold),
+            Self::Top => cell.fg(Color::Yellow).add_attribute(Attribute::Bold),
+            Self::Dim => cell.add_attribute(Attribute::Dim),
+        }
+    }
+}
+
+/// Paints names and counts, or leaves them plain when colors are off.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Palette {
+    pub enabled: bool,
+}
+
+impl Palette {
+    pub fn guild(self, name: &str) -> String {
+        self.paint(Style::Guild, name)
+    }
+
+    pub fn channel(self, name: &str) -> String {
+        self.paint(Style::Channel, name)
+    }
+
+    pub fn count(self, count: &str) -> String {
+        self.paint(Style::Count, count)
+    }
+
+    pub fn top(self, name: &str) -> String {
+        self.paint(Style::Top, name)
+    }
+
+    pub fn dim(self, text: &str) -> String {
+        self.paint(Style::Dim, text)
+    }
+
+    fn paint(self, style: Style, text: &str) -> String {
+        match self.enabled {
+            true => format!("{}{}{}", style.sgr(), text, RESET),
+            false => text.to_string(),
+        }
+    }
+
+    /// A table cell holding `text`, styled like `paint` would.
+    pub fn cell(self, style: Style, text: impl ToString) -> Cell {
+        let cell = Cell::new(text);
+        match self.enabled {
+            true => style.cell(cell),
+            false => cell,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_text_when_colors_are_off() {
+        assert_eq!(Palette { enabled: false }.dim("Tip: --all"), "Tip: --all");
+        assert_eq!(
+            Palette { enabled: true }.dim("Tip: --all"),
+            "\x1b[2mTip: --all\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn plain_cells_when_colors_are_off() {
+        let cell = |enabled| Palette { enabled }.cell(Style::Top, "Alice");
+        assert_eq!(cell(false), Cell::new("Alice"));
+        assert_eq!(
+            cell(true),
+            Cell::new("Alice")
+                .fg(Color::Yellow)
+                .add_attribute(Attribute::Bold)
+        );
+    }
+}
@@ -0,0 +1,266 @@
+//! How few conversations hold most of the messages, e.g. "Half of your 412k
+//! messages are in just 4 conversations; 80% in 17; 95% in 58."
+
+use crate::metrics::Metric;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::str::FromStr;
+
+pub const METRIC: Metric = Metric {
+    name: "concentration",
+    definition: "For each level in percent, the fewest conversations that together hold at \
+        least that share of all messages: conversations are sorted by count, largest first, \
+        and added up until the running total reaches the level.",
+    affected_by: "--concentration-levels sets the levels; --conversation-type, --participant \
+        and --min-messages decide which conversations take part. --limit does not: hidden \
+        conversations still count.",
+    edge_cases: "Only the counts matter, so ties give the same answer in any order. \
+        Conversations without messages are ignored, nothing is shown without any messages, \
+        and a single conversation is reported as holding everything.",
+};
+
+/// Shares of all messages to find the smallest set of conversations for,
+/// in percent and ascending. Parsed from a list such as `50,80,95`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConcentrationLevels(Vec<u8>);
+
+impl FromStr for ConcentrationLevels {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut levels = s
+            .split(',')
+            .map(|level| {
+                let level = level.trim().trim_end_matches('%');
+                match level.parse::<u8>() {
+                    Ok(percent @ 1..=100) => Ok(percent),
+                    _ => Err(format!("'{}' is not a percentage from 1 to 100", level)),
+                }
+            })
+            .collect::<Result<Vec<u8>, String>>()?;
+        levels.sort_unstable();
+        levels.dedup();
+        Ok(Self(levels))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConcentrationLevel {
+    pub percent: u8,
+    /// Fewest conversations holding at least `percent` of all messages
+    pub conversations: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Concentration {
+    pub total_messages: u64,
+    /// Conversations with at least one message
+    pub conversations: usize,
+    pub levels: Vec<ConcentrationLevel>,
+}
+
+impl Concentration {
+    /// The summary sentence, given the total as it should be displayed.
+    pub fn sentence(&self, total: &str) -> String {
+        self.worded(total, "%")
+    }
+
+    /// The summary sentence with percentages spelled out, for screen readers.
+    pub fn spoken(&self, total: &str) -> String {
+        self.worded(total, " percent")
+    }
+
+    fn worded(&self, total: &str, percent_sign: &str) -> String {
+        if self.conversations == 1 {
+            return format!(
+                "All of your {} messages are in a single conversation.",
+                total
+            );
+        }
+
+        let mut parts = Vec::with_capacity(self.levels.len());
+        for (index, level) in self.levels.iter().enumerate() {
+            parts.push(match index {
+                0 => {
+                    let share = match level.percent {
+                        50 => "Half".to_string(),
+                        100 => "All".to_string(),
+                        percent => format!("{}{}", percent, percent_sign),
+                    };
+                    let noun = match level.conversations {
+                        1 => "conversation",
+                        _ => "conversations",
+                    };
+                    format!(
+                        "{} of your {} messages are in just {} {}",
+                        share, total, level.conversations, noun
+                    )
+                }
+                _ => format!(
+                    "{}{} in {}",
+                    level.percent, percent_sign, level.conversations
+                ),
+            });
+        }
+        format!("{}.", parts.join("; "))
+    }
+}
+
+/// How many of the largest conversations it takes to reach each level. Only
+/// the counts matter, so however ties are ordered the answer is the same.
+/// `None` when there are no messages at all.
+pub fn concentration(
+    counts: impl IntoIterator<Item = usize>,
+    levels: &ConcentrationLevels,
+) -> Option<Concentration> {
+    let mut counts: Vec<u64> = counts
+        .into_iter()
+        .filter(|&count| count > 0)
+        .map(|count| count as u64)
+        .collect();
+    counts.sort_unstable_by_key(|&count| Reverse(count));
+    let total: u64 = counts.iter().sum();
+    if total == 0 {
+        return None;
+    }
+
+    let mut levels = levels.0.iter().peekable();
+    let mut result = Vec::new();
+    let mut cumulative = 0u128;
+    for (index, count) in counts.iter().enumerate() {
+        cumulative += u128::from(*count);
+        // Compared on integers, so a share just short of a level never rounds up to it
+        while let Some(&&percent) = levels.peek() {
+            if cumulative * 100 < u128::from(percent) * u128::from(total) {
+                break;
+            }
+            result.push(ConcentrationLevel {
+                percent,
+                conversations: index + 1,
+            });
+            levels.next();
+        }
+    }
+
+    Some(Concentration {
+        total_messages: total,
+        conversations: counts.len(),
+        levels: result,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::OutputFormat;
+    use crate::testing::{dm, rendered, report, view};
+
+    fn levels(s: &str) -> ConcentrationLevels {
+        s.parse().unwrap()
+    }
+
+    /// Conversations needed for each level, in level order.
+    fn needed(counts: &[usize], s: &str) -> Vec<(u8, usize)> {
+        concentration(counts.iter().copied(), &levels(s))
+            .unwrap()
+            .levels
+            .iter()
+            .map(|level| (level.percent, level.conversations))
+            .collect()
+    }
+
+    #[test]
+    fn levels_parse_sorted_and_deduplicated() {
+        assert_eq!(
+            levels("95, 50%,80,50"),
+            ConcentrationLevels(vec![50, 80, 95])
+        );
+        assert_eq!(levels("100"), ConcentrationLevels(vec![100]));
+        for invalid in ["0", "101", "fifty", "50,,80", "-5"] {
+            assert!(
+                invalid.parse::<ConcentrationLevels>().is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn a_share_exactly_at_a_level_reaches_it() {
+        assert_eq!(
+            needed(&[50, 30, 20], "50,80,100"),
+            [(50, 1), (80, 2), (100, 3)]
+        );
+        // Just short of half takes a second conversation
+        assert_eq!(needed(&[49, 30, 21], "50"), [(50, 2)]);
+    }
+
+    #[test]
+    fn uniform_counts_need_that_share_of_conversations() {
+        assert_eq!(
+            needed(&[1; 100], "50,80,95"),
+            [(50, 50), (80, 80), (95, 95)]
+        );
+    }
+
+    #[test]
+    fn ties_give_the_same_answer_in_any_order() {
+        assert_eq!(
+            needed(&[5, 10, 5, 10], "50,80"),
+            needed(&[10, 5, 10, 5], "50,80")
+        );
+    }
+
+    #[test]
+    fn one_conversation_and_no_messages() {
+        let single = concentration([0, 7, 0], &levels("50,80,95")).unwrap();
+        assert_eq!(single.conversations, 1);
+        assert!(single.levels.iter().all(|level| level.conversations == 1));
+        assert_eq!(
+            single.sentence("7"),
+            "All of your 7 messages are in a single conversation."
+        );
+
+        assert!(concentration([0, 0], &levels("50")).is_none());
+        assert!(concentration([], &levels("50")).is_none());
+    }
+
+    #[test]
+    fn sentence_names_the_first_level_in_full() {
+        let result = concentration([60, 30, 10], &levels("50,80,95")).unwrap();
+        assert_eq!(
+            result.sentence("100"),
+            "Half of your 100 messages are in just 1 conversation; 80% in 2; 95% in 3."
+        );
+        assert_eq!(
+            result.spoken("100"),
+            "Half of your 100 messages are in just 1 conversation; 80 percent in 2; 95 percent in 3."
+        );
+        let result = concentration([60, 30, 10], &levels("90")).unwrap();
+        assert_eq!(
+            result.sentence("100"),
+            "90% of your 100 messages are in just 2 conversations."
+        );
+    }
+
+    #[test]
+    fn json_carries_each_level() {
+        let report = report(vec![dm("1", "Alice", 3), dm("2", "Bob", 1)]);
+        let mut view = view(&report);
+        view.concentration = concentration([3, 1], &levels("50,100"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&rendered(OutputFormat::Json, &view)).unwrap();
+        assert_eq!(
+            json["concentration"],
+            serde_json::json!({
+                "total_messages": 4,
+                "conversations": 2,
+                "levels": [
+                    {"percent": 50, "conversations": 1},
+                    {"percent": 100, "conversations": 2},
+                ],
+            })
+        );
+    }
+}
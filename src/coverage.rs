@@ -0,0 +1,131 @@
+//! Looks for channel folders that came from a different export than the
+//! rest of the package, e.g. a newer export's folders copied into an older
+//! extraction. No channel of an export holds messages from after it was
+//! created, so the newest messages of most channels end before that day,
+//! and folders from a newer export stand out by ending months after them.
+//! File times are not compared, as copying or extracting a package resets
+//! them. Only gaps of months are reported, so a package of one export whose
+//! channels simply went quiet at different times does not warn.
+
+use crate::warnings::Warning;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+
+/// How far a channel has to be from the rest of the package to be reported.
+const ANOMALY_DAYS: i64 = 90;
+
+/// Compare each channel's newest message, by channel ID, against the window
+/// the newest messages of most channels fall in. Channels ending after a
+/// gap of more than `ANOMALY_DAYS` in which no channel ends are reported, as
+/// long as most channels end before it.
+pub fn mixed_exports(last_messages: &HashMap<String, DateTime<Utc>>) -> Vec<Warning> {
+    let mut ends: Vec<(DateTime<Utc>, &str)> = last_messages
+        .iter()
+        .map(|(id, &last)| (last, id.as_str()))
+        .collect();
+    ends.sort_unstable();
+    let margin = Duration::days(ANOMALY_DAYS);
+    let majority = ends.len() / 2 + 1;
+    let Some(split) = (majority..ends.len()).find(|&i| ends[i].0 - ends[i - 1].0 > margin) else {
+        return Vec::new();
+    };
+
+    let mut newer: Vec<String> = ends[split..]
+        .iter()
+        .map(|&(_, id)| id.to_string())
+        .collect();
+    newer.sort();
+    vec![Warning::MessagesAfterExport {
+        channel_ids: newer,
+        others_end: ends[split - 1].0.format("%Y-%m-%d").to_string(),
+    }]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Package;
+    use serde_json::json;
+
+    /// A package with a DM per entry, whose newest message is on that day.
+    fn package(ends: &[(&str, &str)]) -> Package {
+        let package = Package::new();
+        for &(id, day) in ends {
+            let messages = json!([
+                {"ID": "1", "Timestamp": "2019-01-01 12:00:00"},
+                {"ID": "2", "Timestamp": format!("{} 12:00:00", day)},
+            ]);
+            package
+                .file(
+                    &format!("messages/c{}/channel.json", id),
+                    &json!({"id": id, "type": 1, "recipients": ["99"]}).to_string(),
+                )
+                .file(
+                    &format!("messages/c{}/messages.json", id),
+                    &messages.to_string(),
+                );
+        }
+        package
+    }
+
+    fn mixed(package: &Package) -> Vec<Warning> {
+        package
+            .analyze()
+            .warnings
+            .into_iter()
+            .filter(|warning| matches!(warning, Warning::MessagesAfterExport { .. }))
+            .collect()
+    }
+
+    #[test]
+    fn folders_of_a_newer_export_are_named() {
+        let blended = package(&[
+            ("1", "2021-03-01"),
+            ("2", "2021-02-20"),
+            ("3", "2020-11-02"),
+            ("4", "2022-05-01"),
+            ("5", "2022-04-28"),
+        ]);
+        assert_eq!(
+            mixed(&blended),
+            [Warning::MessagesAfterExport {
+                channel_ids: vec!["4".to_string(), "5".to_string()],
+                others_end: "2021-03-01".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn a_single_export_does_not_warn() {
+        // Channels that went quiet long ago are no sign of another export
+        let single = package(&[
+            ("1", "2019-06-01"),
+            ("2", "2021-02-20"),
+            ("3", "2021-03-01"),
+            ("4", "2020-01-15"),
+        ]);
+        assert_eq!(mixed(&single), []);
+    }
+
+    #[test]
+    fn most_channels_have_to_end_before_the_gap() {
+        let day = |day: &str| format!("{}T00:00:00Z", day).parse().unwrap();
+        let ends = |days: &[&str]| -> HashMap<String, DateTime<Utc>> {
+            days.iter()
+                .enumerate()
+                .map(|(i, &d)| (i.to_string(), day(d)))
+                .collect()
+        };
+        assert_eq!(mixed_exports(&ends(&["2020-01-01", "2021-01-01"])), []);
+        assert_eq!(
+            mixed_exports(&ends(&["2020-01-01", "2020-01-02", "2021-01-01"])).len(),
+            1
+        );
+        // Exactly the margin apart is not yet an anomaly
+        assert_eq!(
+            mixed_exports(&ends(&["2020-01-01", "2020-01-01", "2020-03-31"])),
+            []
+        );
+        assert_eq!(mixed_exports(&HashMap::new()), []);
+    }
+}
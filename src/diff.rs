@@ -0,0 +1,188 @@
+//! `diff`: how the counts of two reports emitted with `--emit internal`
+//! differ, conversation by conversation, so two counts of a package can be
+//! compared without counting either of them again.
+
+use crate::errors::MyError;
+use crate::render::format_thousands;
+use crate::report::Report;
+use crate::wire;
+use crate::Conversation;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// A conversation whose count differs between the two reports.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ConversationDiff {
+    pub name: String,
+    /// Messages in the old report; 0 when it lacks the conversation
+    pub old: usize,
+    /// Messages in the new report; 0 when it lacks the conversation
+    pub new: usize,
+}
+
+impl ConversationDiff {
+    fn change(&self) -> i64 {
+        self.new as i64 - self.old as i64
+    }
+}
+
+/// Compare the reports at `old` and `new`, each a file or `-` for stdin.
+pub fn run(old: &Path, new: &Path) -> Result<(), MyError> {
+    if old == Path::new("-") && new == Path::new("-") {
+        return Err(MyError::InvalidWireInput(
+            "only one of the two reports can be read from stdin".to_string(),
+        ));
+    }
+    let old = wire::read_report_at(old)?;
+    let new = wire::read_report_at(new)?;
+    write_diff(&old, &new, &mut io::stdout().lock())
+}
+
+/// Conversations whose counts differ, the largest change first. Conversations
+/// are matched by ID and named as in the new report where it has them.
+pub fn diff_reports(old: &Report, new: &Report) -> Vec<ConversationDiff> {
+    let mut counts: BTreeMap<&str, ConversationDiff> = BTreeMap::new();
+    for conversation in &old.conversations {
+        counts.insert(
+            conversation.id(),
+            ConversationDiff {
+                name: conversation.name().to_string(),
+                old: conversation.message_count(),
+                new: 0,
+            },
+        );
+    }
+    for conversation in &new.conversations {
+        let entry = counts
+            .entry(conversation.id())
+            .or_insert_with(|| ConversationDiff {
+                name: String::new(),
+                old: 0,
+                new: 0,
+            });
+        entry.name = conversation.name().to_string();
+        entry.new = conversation.message_count();
+    }
+
+    let mut changed: Vec<ConversationDiff> = counts
+        .into_values()
+        .filter(|diff| diff.old != diff.new)
+        .collect();
+    changed.sort_by(|a, b| {
+        Reverse(a.change().unsigned_abs())
+            .cmp(&Reverse(b.change().unsigned_abs()))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    changed
+}
+
+fn write_diff(old: &Report, new: &Report, out: &mut dyn Write) -> Result<(), MyError> {
+    let total = |report: &Report| -> usize {
+        report
+            .conversations
+            .iter()
+            .map(Conversation::message_count)
+            .sum()
+    };
+    let (old_total, new_total) = (total(old), total(new));
+    writeln!(
+        out,
+        "Messages: {} -> {} ({})",
+        format_thousands(old_total as u64),
+        format_thousands(new_total as u64),
+        signed(new_total as i64 - old_total as i64)
+    )?;
+
+    let changed = diff_reports(old, new);
+    if changed.is_empty() {
+        writeln!(out, "No conversation's count changed.")?;
+        return Ok(());
+    }
+    writeln!(out, "Changed conversations: {}", changed.len())?;
+    writeln!(out)?;
+    for diff in &changed {
+        writeln!(
+            out,
+            "{}: {} -> {} ({})",
+            diff.name,
+            format_thousands(diff.old as u64),
+            format_thousands(diff.new as u64),
+            signed(diff.change())
+        )?;
+    }
+    Ok(())
+}
+
+/// A change with its sign, `+0` when there is none.
+fn signed(change: i64) -> String {
+    match change {
+        ..0 => format!("-{}", format_thousands(change.unsigned_abs())),
+        _ => format!("+{}", format_thousands(change as u64)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn report(dms: &[(&str, &str, usize)]) -> Report {
+        let conversations: Vec<_> = dms
+            .iter()
+            .map(|&(id, name, count)| {
+                json!({"kind": "dm", "id": id, "name": name, "message_count": count})
+            })
+            .collect();
+        serde_json::from_value(json!({
+            "conversations": conversations,
+            "reference_counts": null,
+        }))
+        .unwrap()
+    }
+
+    fn diff(name: &str, old: usize, new: usize) -> ConversationDiff {
+        ConversationDiff {
+            name: name.to_string(),
+            old,
+            new,
+        }
+    }
+
+    #[test]
+    fn lists_changes_largest_first() {
+        let old = report(&[("1", "Alice", 10), ("2", "Bob", 5), ("3", "Carol", 7)]);
+        let new = report(&[("1", "Alice", 12), ("3", "Carol", 7), ("4", "Dave", 9)]);
+        assert_eq!(
+            diff_reports(&old, &new),
+            [diff("Dave", 0, 9), diff("Bob", 5, 0), diff("Alice", 10, 12)]
+        );
+    }
+
+    #[test]
+    fn matches_by_id_and_takes_the_new_name() {
+        let old = report(&[("1", "alice_old", 1)]);
+        let new = report(&[("1", "Alice", 2)]);
+        assert_eq!(diff_reports(&old, &new), [diff("Alice", 1, 2)]);
+    }
+
+    #[test]
+    fn writes_totals_with_signs() {
+        let old = report(&[("1", "Alice", 1500)]);
+        let new = report(&[("1", "Alice", 500)]);
+        let mut out = Vec::new();
+        write_diff(&old, &new, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Messages: 1,500 -> 500 (-1,000)\nChanged conversations: 1\n\n\
+             Alice: 1,500 -> 500 (-1,000)\n"
+        );
+
+        let mut out = Vec::new();
+        write_diff(&old, &old, &mut out).unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .ends_with("(+0)\nNo conversation's count changed.\n"));
+    }
+}
@@ -0,0 +1,220 @@
+//! `--format discord`: the results for pasting into Discord itself. The
+//! listing is split into `ansi` code blocks of at most 2000 characters,
+//! Discord's message limit, so every block can be sent as a message of its
+//! own. Discord draws the largest conversations in bold inside such blocks.
+
+use crate::buckets::CountStyle;
+use crate::color::TOP_CONVERSATIONS;
+use crate::errors::MyError;
+use crate::humanize::fmt_compact;
+use crate::render::{display_name, ResultsView};
+use crate::Conversation;
+use std::io::Write;
+
+/// Characters Discord accepts in a single message.
+pub const MESSAGE_LIMIT: usize = 2000;
+
+const OPEN_FENCE: &str = "```ansi\n";
+const CLOSE_FENCE: &str = "```";
+/// Last line of every block but the final one
+const CONTINUED: &str = "…continued";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+pub fn render_discord(view: &ResultsView, out: &mut dyn Write) -> Result<(), MyError> {
+    for (i, block) in blocks(&lines(view), MESSAGE_LIMIT).iter().enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+        writeln!(out, "{}", block)?;
+    }
+    Ok(())
+}
+
+/// A line of the listing, without its styling so it can be cut short.
+struct Line {
+    text: String,
+    bold: bool,
+}
+
+impl Line {
+    fn plain(text: String) -> Self {
+        Self { text, bold: false }
+    }
+}
+
+/// The listing, one entry per line: a total, then conversations by rank
+/// with the channels of guilds indented below them.
+fn lines(view: &ResultsView) -> Vec<Line> {
+    let count = |n: usize| match view.counts {
+        CountStyle::Exact => fmt_compact(n as u64),
+        CountStyle::Bucketed => view.counts.format(n as u64),
+    };
+    let total: usize = view
+        .conversations
+        .iter()
+        .map(|conversation| conversation.message_count())
+        .sum();
+
+    let mut lines = vec![
+        Line::plain(format!(
+            "Discord messages: {} in {} conversations",
+            count(total),
+            view.conversations.len()
+        )),
+        Line::plain(String::new()),
+    ];
+    for (rank, &conversation) in view.conversations.iter().enumerate() {
+        lines.push(Line {
+            text: format!(
+                "{}. {} — {}",
+                rank + 1,
+                fenced_safe(&display_name(conversation)),
+                count(conversation.message_count())
+            ),
+            bold: rank < TOP_CONVERSATIONS,
+        });
+        if let (false, Conversation::Guild { channels, .. }) =
+            (conversation.is_unknown_guild(), conversation)
+        {
+            lines.extend(channels.iter().map(|channel| {
+                Line::plain(format!(
+                    "    #{} — {}",
+                    fenced_safe(&channel.name),
+                    count(channel.message_count)
+                ))
+            }));
+        }
+    }
+    lines
+}
+
+/// Group `lines` into code blocks of at most `limit` characters, fences and
+/// continuation marker included. Lines are never split; one that could not
+/// fit even an empty block is cut short with `…` before it is styled, so a
+/// bold line keeps its reset.
+fn blocks(lines: &[Line], limit: usize) -> Vec<String> {
+    let overhead =
+        OPEN_FENCE.chars().count() + CONTINUED.chars().count() + 1 + CLOSE_FENCE.chars().count();
+    // Every line is followed by its line break
+    let room = limit - overhead;
+
+    let mut blocks = Vec::new();
+    let mut body = String::new();
+    let mut body_length = 0;
+    let styling = BOLD.chars().count() + RESET.chars().count();
+    for line in lines {
+        let line = match line.bold {
+            true => format!(
+                "{}{}{}",
+                BOLD,
+                shortened(&line.text, room - 1 - styling),
+                RESET
+            ),
+            false => shortened(&line.text, room - 1),
+        };
+        let length = line.chars().count() + 1;
+        if body_length + length > room && body_length > 0 {
+            blocks.push(format!(
+                "{}{}{}\n{}",
+                OPEN_FENCE, body, CONTINUED, CLOSE_FENCE
+            ));
+            body.clear();
+            body_length = 0;
+        }
+        body.push_str(&line);
+        body.push('\n');
+        body_length += length;
+    }
+    blocks.push(format!("{}{}{}", OPEN_FENCE, body, CLOSE_FENCE));
+    blocks
+}
+
+fn shortened(line: &str, limit: usize) -> String {
+    match line.chars().count() > limit {
+        true => line.chars().take(limit - 1).chain(['…']).collect(),
+        false => line.to_string(),
+    }
+}
+
+/// Backticks in a name would end the code block early.
+fn fenced_safe(name: &str) -> String {
+    name.replace('`', "'")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn room() -> usize {
+        MESSAGE_LIMIT
+            - OPEN_FENCE.chars().count()
+            - CONTINUED.chars().count()
+            - 1
+            - CLOSE_FENCE.chars().count()
+    }
+
+    /// Lines of `width` characters each, which with their line breaks take
+    /// up `total` characters.
+    fn filling(total: usize, width: usize) -> Vec<Line> {
+        let mut lines = Vec::new();
+        let mut left = total;
+        while left > 0 {
+            let length = left.min(width + 1);
+            lines.push(Line::plain("x".repeat(length - 1)));
+            left -= length;
+        }
+        lines
+    }
+
+    fn lengths(blocks: &[String]) -> Vec<usize> {
+        blocks.iter().map(|block| block.chars().count()).collect()
+    }
+
+    #[test]
+    fn a_listing_exactly_filling_a_block_stays_in_one() {
+        let blocks = blocks(&filling(room(), 99), MESSAGE_LIMIT);
+        assert_eq!(blocks.len(), 1);
+        assert!(!blocks[0].contains(CONTINUED));
+        assert!(lengths(&blocks)[0] <= MESSAGE_LIMIT);
+    }
+
+    #[test]
+    fn one_character_over_starts_another_block_at_the_limit() {
+        let mut lines = filling(room(), 99);
+        lines.push(Line::plain(String::new()));
+        let blocks = blocks(&lines, MESSAGE_LIMIT);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(lengths(&blocks)[0], MESSAGE_LIMIT);
+        assert!(blocks[0].ends_with(&format!("{}\n{}", CONTINUED, CLOSE_FENCE)));
+        assert!(!blocks[1].contains(CONTINUED));
+    }
+
+    #[test]
+    fn a_line_longer_than_a_block_is_cut_short() {
+        let blocks = blocks(
+            &[Line::plain("x".repeat(MESSAGE_LIMIT + 10))],
+            MESSAGE_LIMIT,
+        );
+        assert_eq!(blocks.len(), 1);
+        assert!(lengths(&blocks)[0] <= MESSAGE_LIMIT);
+        assert!(blocks[0].contains("x…\n"));
+    }
+
+    #[test]
+    fn a_bold_line_cut_short_keeps_its_reset() {
+        let line = Line {
+            text: "x".repeat(MESSAGE_LIMIT + 10),
+            bold: true,
+        };
+        let blocks = blocks(&[line, Line::plain("after".to_string())], MESSAGE_LIMIT);
+        assert!(lengths(&blocks)
+            .iter()
+            .all(|&length| length <= MESSAGE_LIMIT));
+        assert!(blocks[0].contains(&format!("x…{}\n", RESET)));
+        assert_eq!(
+            blocks[0].matches(BOLD).count(),
+            blocks[0].matches(RESET).count()
+        );
+    }
+}
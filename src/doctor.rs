@@ -0,0 +1,74 @@
+use crate::errors::MyError;
+use crate::file_operations::{prepare_data_root, ProcessOptions};
+use crate::footprint;
+use crate::index::{build_index, ChannelProbe};
+use crate::inventory::{self, folder_channels, indexed_channels, write_condensed};
+use crate::messages::count_messages;
+use std::cmp::Reverse;
+use std::io;
+use std::path::Path;
+
+/// Number of channels checked for duplicated messages.
+const DUPLICATE_SAMPLE_SIZE: usize = 8;
+
+/// Diagnose common problems with a data package without counting all of it.
+pub fn run(input_path: &Path) -> Result<(), MyError> {
+    let footprint = footprint::measure(input_path)?;
+    footprint::write_footprint(&footprint, &mut io::stdout().lock())?;
+
+    let data_root = prepare_data_root(input_path, None)?;
+    let index = build_index(&data_root)?;
+
+    println!("Package vintage: {}", index.vintage);
+    println!("Found {} channel folders", index.channels.len());
+    // Counting is what the doctor avoids, so empty channels are not looked for
+    let coverage = inventory::coverage(
+        indexed_channels(&data_root)?.as_ref(),
+        &folder_channels(&data_root)?,
+        None,
+    );
+    write_condensed(&coverage, &mut io::stdout().lock())?;
+    check_duplicates(&index.channels)?;
+
+    Ok(())
+}
+
+/// Check the largest message files for duplicated IDs, since that is where
+/// duplicates inflate counts the most.
+fn check_duplicates(channels: &[ChannelProbe]) -> Result<(), MyError> {
+    let mut largest: Vec<&ChannelProbe> = channels.iter().collect();
+    largest.sort_unstable_by_key(|channel| Reverse(channel.messages_size));
+    largest.truncate(DUPLICATE_SAMPLE_SIZE);
+
+    println!(
+        "Checked the {} largest channels for duplicated messages:",
+        largest.len()
+    );
+
+    let mut found = false;
+    let options = ProcessOptions {
+        dedupe_messages: true,
+        ..Default::default()
+    };
+    for channel in largest {
+        let counts = count_messages(&channel.messages_file, &options)?;
+        if counts.duplicates > 0 {
+            found = true;
+            let channel = channel
+                .messages_file
+                .parent()
+                .and_then(|folder| folder.file_name())
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            println!("    {}: {} duplicated messages", channel, counts.duplicates);
+        }
+    }
+
+    if found {
+        println!("Duplicates found; rerun with --dedupe-messages to count each message once.");
+    } else {
+        println!("    No duplicated messages found.");
+    }
+
+    Ok(())
+}
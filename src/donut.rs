@@ -0,0 +1,271 @@
+//! `--chart-svg`: a donut chart of how all messages split between the
+//! largest conversations, with everything else as one "Other" slice. The SVG
+//! is written by hand, so it needs no charting library or font: viewers draw
+//! the legend with their own. Names are taken as shown, so `--redact`
+//! anonymizes them here too.
+
+use crate::errors::MyError;
+use crate::numbers::apportion;
+use crate::render::{display_name, ResultsView};
+use crate::Conversation;
+use std::f64::consts::{FRAC_PI_2, TAU};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+const WIDTH: u32 = 860;
+/// Grows when the legend needs more room
+const MIN_HEIGHT: u32 = 480;
+const CENTER: (f64, f64) = (240.0, 240.0);
+const OUTER_RADIUS: f64 = 200.0;
+const INNER_RADIUS: f64 = 115.0;
+const LEGEND_X: u32 = 480;
+const LEGEND_TOP: u32 = 40;
+const LEGEND_ROW: u32 = 30;
+/// Characters of a conversation name shown in the legend.
+const NAME_LIMIT: usize = 32;
+
+/// Slice colors in order; "Other" is always grey.
+const COLORS: &[&str] = &[
+    "#5865f2", "#eb459e", "#57f287", "#fee75c", "#ed4245", "#3ba55c", "#faa61a", "#00b0f4",
+    "#9b59b6", "#1abc9c",
+];
+const OTHER_COLOR: &str = "#99aab5";
+const OTHER_LABEL: &str = "Other";
+
+struct Slice {
+    label: String,
+    count: u64,
+    color: &'static str,
+}
+
+/// Draw the `top` conversations of `view` with the most messages, and the
+/// rest of all counted messages as "Other", to the SVG file at `path`.
+pub fn write_donut(view: &ResultsView, path: &Path, top: usize) -> Result<(), MyError> {
+    let mut largest: Vec<&Conversation> = view.conversations.clone();
+    largest.sort_by_key(|conversation| std::cmp::Reverse(conversation.message_count()));
+    largest.truncate(top);
+
+    let total: u64 = view
+        .report
+        .conversations
+        .iter()
+        .map(|conversation| conversation.message_count() as u64)
+        .sum();
+    let mut slices: Vec<Slice> = largest
+        .iter()
+        .zip(COLORS.iter().cycle())
+        .map(|(conversation, &color)| Slice {
+            label: display_name(conversation),
+            count: conversation.message_count() as u64,
+            color,
+        })
+        .collect();
+    let other = total.saturating_sub(slices.iter().map(|slice| slice.count).sum());
+    if other > 0 {
+        slices.push(Slice {
+            label: OTHER_LABEL.to_string(),
+            count: other,
+            color: OTHER_COLOR,
+        });
+    }
+
+    fs::write(path, svg(&slices, total))?;
+    Ok(())
+}
+
+fn svg(slices: &[Slice], total: u64) -> String {
+    let height = MIN_HEIGHT.max(2 * LEGEND_TOP + slices.len() as u32 * LEGEND_ROW);
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="sans-serif" font-size="16">"#,
+        w = WIDTH,
+        h = height
+    );
+    let _ = writeln!(
+        svg,
+        r##"<rect width="100%" height="100%" fill="#ffffff"/>"##
+    );
+
+    if total == 0 {
+        let _ = writeln!(svg, "{}", ring(OTHER_COLOR));
+        let _ = writeln!(
+            svg,
+            r#"<text x="{}" y="{}" text-anchor="middle">No messages</text>"#,
+            CENTER.0, CENTER.1
+        );
+        svg.push_str("</svg>\n");
+        return svg;
+    }
+
+    // Tenths of a percent, adding up to exactly 100.0
+    let counts: Vec<u64> = slices.iter().map(|slice| slice.count).collect();
+    let tenths = apportion(&counts, 1000);
+
+    let mut start = 0u64;
+    for slice in slices.iter().filter(|slice| slice.count > 0) {
+        let end = start + slice.count;
+        let shape = match slice.count == total {
+            true => ring(slice.color),
+            false => sector(
+                start as f64 / total as f64,
+                end as f64 / total as f64,
+                slice.color,
+            ),
+        };
+        let _ = writeln!(svg, "{}", shape);
+        start = end;
+    }
+
+    for (row, (slice, tenths)) in (0u32..).zip(slices.iter().zip(tenths)) {
+        let y = LEGEND_TOP + row * LEGEND_ROW;
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{}" y="{}" width="18" height="18" fill="{}"/>"#,
+            LEGEND_X, y, slice.color
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text x="{}" y="{}">{} — {}.{}%</text>"#,
+            LEGEND_X + 28,
+            y + 15,
+            escape(&shortened(&slice.label)),
+            tenths / 10,
+            tenths % 10
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// The part of the donut from `from` to `to`, as fractions of a full turn
+/// clockwise from the top.
+fn sector(from: f64, to: f64, color: &str) -> String {
+    let point = |radius: f64, fraction: f64| {
+        let angle = fraction * TAU - FRAC_PI_2;
+        (
+            CENTER.0 + radius * angle.cos(),
+            CENTER.1 + radius * angle.sin(),
+        )
+    };
+    let large = u8::from(to - from > 0.5);
+    let (outer_start, outer_end) = (point(OUTER_RADIUS, from), point(OUTER_RADIUS, to));
+    let (inner_end, inner_start) = (point(INNER_RADIUS, to), point(INNER_RADIUS, from));
+    format!(
+        r#"<path d="M {:.2} {:.2} A {r} {r} 0 {large} 1 {:.2} {:.2} L {:.2} {:.2} A {ir} {ir} 0 {large} 0 {:.2} {:.2} Z" fill="{color}"/>"#,
+        outer_start.0,
+        outer_start.1,
+        outer_end.0,
+        outer_end.1,
+        inner_end.0,
+        inner_end.1,
+        inner_start.0,
+        inner_start.1,
+        r = OUTER_RADIUS,
+        ir = INNER_RADIUS,
+        large = large,
+        color = color
+    )
+}
+
+/// A whole donut, which a single arc cannot draw.
+fn ring(color: &str) -> String {
+    format!(
+        r#"<circle cx="{}" cy="{}" r="{}" fill="none" stroke="{}" stroke-width="{}"/>"#,
+        CENTER.0,
+        CENTER.1,
+        (OUTER_RADIUS + INNER_RADIUS) / 2.0,
+        color,
+        OUTER_RADIUS - INNER_RADIUS
+    )
+}
+
+fn shortened(name: &str) -> String {
+    match name.chars().count() > NAME_LIMIT {
+        true => name.chars().take(NAME_LIMIT - 1).chain(['…']).collect(),
+        false => name.to_string(),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{dm, report, view};
+    use proptest::prelude::*;
+
+    /// The legend's percentages, in tenths, in legend order.
+    fn legend(svg: &str) -> Vec<u64> {
+        svg.lines()
+            .filter_map(|line| line.strip_suffix("%</text>"))
+            .map(|line| {
+                let (_, percent) = line.rsplit_once(" — ").unwrap();
+                percent.replace('.', "").parse().unwrap()
+            })
+            .collect()
+    }
+
+    fn slices(counts: &[u64]) -> Vec<Slice> {
+        counts
+            .iter()
+            .enumerate()
+            .map(|(index, &count)| Slice {
+                label: format!("Conversation {}", index),
+                count,
+                color: COLORS[index % COLORS.len()],
+            })
+            .collect()
+    }
+
+    proptest! {
+        #[test]
+        fn legend_percentages_add_up_to_a_hundred(
+            counts in prop::collection::vec(0u64..1_000_000, 1..12)
+                .prop_filter("some messages", |counts| counts.iter().any(|&count| count > 0)),
+        ) {
+            let total = counts.iter().sum();
+            let percents = legend(&svg(&slices(&counts), total));
+            prop_assert_eq!(percents.len(), counts.len());
+            prop_assert_eq!(percents.iter().sum::<u64>(), 1000);
+        }
+    }
+
+    #[test]
+    fn thirds_still_add_up_to_a_hundred() {
+        assert_eq!(legend(&svg(&slices(&[1, 1, 1]), 3)), [334, 333, 333]);
+    }
+
+    #[test]
+    fn the_rest_of_all_messages_is_other() {
+        let report = report(vec![
+            dm("1", "Alice", 50),
+            dm("2", "Bob", 30),
+            dm("3", "Carol", 20),
+        ]);
+        let mut view = view(&report);
+        // Hidden conversations still count towards "Other"
+        view.conversations.truncate(2);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("chart.svg");
+        write_donut(&view, &path, 1).unwrap();
+
+        let svg = fs::read_to_string(path).unwrap();
+        assert!(svg.contains("Alice — 50.0%"), "{}", svg);
+        assert!(svg.contains("Other — 50.0%"), "{}", svg);
+        assert_eq!(legend(&svg).len(), 2);
+    }
+
+    #[test]
+    fn no_messages_draw_an_empty_ring() {
+        let svg = svg(&slices(&[0, 0]), 0);
+        assert!(svg.contains("No messages"));
+        assert!(legend(&svg).is_empty());
+    }
+}
@@ -0,0 +1,148 @@
+//! A Graphviz digraph of the results for `dot -Tsvg`: guilds and DMs hang
+//! off a root node and channels off their guild. Node IDs are derived from
+//! Discord IDs, so graphs of successive exports diff cleanly.
+
+use crate::buckets::CountStyle;
+use crate::errors::MyError;
+use crate::render::{display_name, ResultsView};
+use crate::{Channel, Conversation};
+use std::io::Write;
+
+/// Font sizes of the smallest and the largest conversation nodes.
+const FONT_SIZES: (f64, f64) = (10.0, 32.0);
+
+pub fn render_dot(view: &ResultsView, out: &mut dyn Write) -> Result<(), MyError> {
+    let largest = view
+        .conversations
+        .iter()
+        .map(|conversation| conversation.message_count())
+        .max()
+        .unwrap_or(0);
+    let total: usize = view
+        .conversations
+        .iter()
+        .map(|conversation| conversation.message_count())
+        .sum();
+
+    writeln!(out, "digraph discord {{")?;
+    writeln!(out, "    graph [rankdir=LR, overlap=false];")?;
+    writeln!(out, "    node [shape=box, style=rounded];")?;
+    writeln!(
+        out,
+        "    root [label=\"{}\", shape=ellipse];",
+        label("Discord", total, view)
+    )?;
+
+    for &conversation in &view.conversations {
+        let count = conversation.message_count();
+        let node = conversation_node(conversation);
+        writeln!(
+            out,
+            "    {} [label=\"{}\"{}];",
+            node,
+            label(&display_name(conversation), count, view),
+            font_size(count, largest, view)
+        )?;
+        writeln!(out, "    root -> {};", node)?;
+
+        let Conversation::Guild {
+            id: Some(_),
+            channels,
+            ..
+        } = conversation
+        else {
+            continue;
+        };
+        write_channels(&node, channels, view, out)?;
+    }
+
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+/// Channels of a guild, most messages first. Channels below
+/// `--min-messages` are folded into one node so small ones don't swamp
+/// the graph.
+fn write_channels(
+    guild_node: &str,
+    channels: &[Channel],
+    view: &ResultsView,
+    out: &mut dyn Write,
+) -> Result<(), MyError> {
+    let (shown, folded): (Vec<&Channel>, Vec<&Channel>) = channels
+        .iter()
+        .partition(|channel| channel.message_count >= view.min_messages);
+
+    for channel in shown {
+        let node = format!("channel_{}", channel.id);
+        writeln!(
+            out,
+            "    {} [label=\"{}\"];",
+            node,
+            label(&format!("#{}", channel.name), channel.message_count, view)
+        )?;
+        writeln!(out, "    {} -> {};", guild_node, node)?;
+    }
+    if !folded.is_empty() {
+        let node = format!("{}_smaller", guild_node);
+        let count = folded.iter().map(|channel| channel.message_count).sum();
+        let name = match folded.len() {
+            1 => "1 smaller channel".to_string(),
+            n => format!("{} smaller channels", n),
+        };
+        writeln!(
+            out,
+            "    {} [label=\"{}\", style=\"rounded,dashed\"];",
+            node,
+            label(&name, count, view)
+        )?;
+        writeln!(out, "    {} -> {};", guild_node, node)?;
+    }
+    Ok(())
+}
+
+fn conversation_node(conversation: &Conversation) -> String {
+    match conversation {
+        Conversation::DmOrGc { id, .. } => format!("dm_{}", id),
+        Conversation::Guild { id: Some(id), .. } => format!("guild_{}", id),
+        // Unknown guilds hold exactly one channel, which stands in for them
+        Conversation::Guild { id: None, .. } => format!("channel_{}", conversation.id()),
+    }
+}
+
+fn label(name: &str, count: usize, view: &ResultsView) -> String {
+    format!(
+        "{}\\n{} messages",
+        escape(name),
+        escape(&view.counts.grouped(count as u64))
+    )
+}
+
+/// Larger conversations get larger labels, by the square root of their
+/// share so areas grow with the count. Left out once counts are bucketed,
+/// as sizes would give the exact ratios away.
+fn font_size(count: usize, largest: usize, view: &ResultsView) -> String {
+    if view.counts != CountStyle::Exact || largest == 0 {
+        return String::new();
+    }
+    let (smallest, biggest) = FONT_SIZES;
+    let share = (count as f64 / largest as f64).sqrt();
+    format!(", fontsize={:.1}", smallest + (biggest - smallest) * share)
+}
+
+/// Escape text for a quoted DOT string. Non-ASCII characters become HTML
+/// entities, which Graphviz resolves in labels whatever the input encoding.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '&' => escaped.push_str("&amp;"),
+            c if c.is_ascii_control() => escaped.push(' '),
+            c if c.is_ascii() => escaped.push(c),
+            c => escaped.push_str(&format!("&#{};", c as u32)),
+        }
+    }
+    escaped
+}
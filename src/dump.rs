@@ -0,0 +1,174 @@
+//! `--dump`: everything known about a single conversation as one JSON
+//! document, for pages built around one friend or server. Figures that need
+//! a flag, such as sessions or the busiest day, are present only when it was
+//! given.
+
+use crate::analytics::Discrepancy;
+use crate::errors::MyError;
+use crate::peaks::PeakDay;
+use crate::report::Report;
+use crate::sessions::SessionStats;
+use crate::site::monthly_activity;
+use crate::zone::Zone;
+use crate::{Channel, Conversation, GroupDmPolicy};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+pub const DUMP_FORMAT: &str = "discord-gdpr-counter/dump";
+
+/// Bumped whenever the document changes incompatibly.
+pub const DUMP_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct Dump<'a> {
+    format: &'static str,
+    schema_version: u32,
+    id: &'a str,
+    kind: &'static str,
+    name: &'a str,
+    message_count: usize,
+    /// System messages left out by --exclude-system
+    excluded_system: usize,
+    /// Messages without contents or attachments, counted unless
+    /// --exclude-empty-messages left them out
+    empty_messages: usize,
+    first_message: Option<DateTime<Utc>>,
+    last_message: Option<DateTime<Utc>>,
+    /// Messages per month, keyed "YYYY-MM", before deduplication and system
+    /// message exclusion; empty for piped reports
+    monthly: BTreeMap<String, usize>,
+    /// Guild channels, most messages first; empty for DMs
+    channels: &'a [Channel],
+    /// A server missing from servers/index.json
+    departed: bool,
+    /// A DM with a bot, as far as the package tells
+    bot: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    note: Option<&'a str>,
+    /// Messages per calendar year, with --threshold-timeline
+    #[serde(skip_serializing_if = "Option::is_none")]
+    messages_by_year: Option<&'a BTreeMap<i32, usize>>,
+    /// With --session-stats
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sessions: Option<&'a SessionStats>,
+    /// With --activity-stats
+    #[serde(skip_serializing_if = "Option::is_none")]
+    peak: Option<PeakDay>,
+    /// Voice time in a guild, with --voice-stats
+    #[serde(skip_serializing_if = "Option::is_none")]
+    voice_seconds: Option<u64>,
+    /// Messages the analytics say were sent but the package lacks
+    #[serde(skip_serializing_if = "Option::is_none")]
+    discrepancy: Option<&'a Discrepancy>,
+}
+
+/// Write the dump of `conversation`, one of `report`'s conversations.
+pub fn write_dump(
+    conversation: &Conversation,
+    report: &Report,
+    group_dms_as: GroupDmPolicy,
+    timezone: Zone,
+    out: &mut dyn Write,
+) -> Result<(), MyError> {
+    let id = conversation.id();
+    let activity = monthly_activity(conversation, timezone)?;
+    let dump = Dump {
+        format: DUMP_FORMAT,
+        schema_version: DUMP_SCHEMA_VERSION,
+        id,
+        kind: conversation.effective_kind(group_dms_as).label(),
+        name: conversation.name(),
+        message_count: conversation.message_count(),
+        excluded_system: conversation.excluded_system(),
+        empty_messages: conversation.empty_messages(),
+        first_message: activity.first_message,
+        last_message: activity.last_message,
+        monthly: activity.monthly,
+        channels: match conversation {
+            Conversation::Guild { channels, .. } => channels,
+            Conversation::DmOrGc { .. } => &[],
+        },
+        departed: conversation.is_departed(),
+        bot: conversation.is_bot(),
+        note: report.note(id),
+        messages_by_year: report.messages_by_year.get(id),
+        sessions: report
+            .sessions
+            .as_ref()
+            .and_then(|sessions| sessions.by_conversation.get(id)),
+        peak: report.peak_day(id),
+        voice_seconds: match conversation {
+            Conversation::Guild { .. } => report
+                .voice
+                .as_ref()
+                .and_then(|voice| voice.seconds_by_guild.get(id).copied()),
+            Conversation::DmOrGc { .. } => None,
+        },
+        discrepancy: report.discrepancy(id),
+    };
+    serde_json::to_writer_pretty(&mut *out, &dump)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::selector::Selector;
+    use crate::testing::Package;
+    use serde_json::json;
+
+    fn dump(package: &Package, query: &str) -> String {
+        let report = package.analyze();
+        let conversation = Selector::new(query).select(&report.conversations).unwrap();
+        let mut out = Vec::new();
+        write_dump(
+            conversation,
+            &report,
+            GroupDmPolicy::default(),
+            Zone::default(),
+            &mut out,
+        )
+        .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn a_guild_dump_matches_the_golden_file() {
+        let package = Package::new();
+        package
+            .file("servers/index.json", r#"{"9": "Server"}"#)
+            .channel(
+                "1",
+                json!({"id": "1", "name": "general", "guild": {"id": "9"}}),
+                3,
+            )
+            .channel(
+                "2",
+                json!({"id": "2", "name": "memes", "guild": {"id": "9"}}),
+                1,
+            )
+            .file(
+                "messages/c2/messages.json",
+                r#"[{"ID": "5", "Timestamp": "2022-03-04 05:06:07"}]"#,
+            );
+
+        assert_eq!(dump(&package, "9"), include_str!("../testdata/dump.json"));
+    }
+
+    #[test]
+    fn dumps_carry_their_format_and_version() {
+        let package = Package::new();
+        package.channel("1", json!({"id": "1", "type": 1, "recipients": ["2"]}), 2);
+
+        let dump: serde_json::Value = serde_json::from_str(&dump(&package, "1")).unwrap();
+        assert_eq!(dump["format"], DUMP_FORMAT);
+        assert_eq!(dump["schema_version"], DUMP_SCHEMA_VERSION);
+        assert_eq!(dump["kind"], "dm");
+        assert_eq!(dump["channels"], json!([]));
+        assert_eq!(dump["monthly"], json!({"2021-01": 2}));
+        assert!(dump.get("sessions").is_none());
+    }
+}
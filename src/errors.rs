@@ -1,3 +1,5 @@
+use std::io;
+use std::path::PathBuf;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -8,6 +10,15 @@ pub enum MyError {
     #[error("JSON parsing error: {0}")]
     Json(#[from] serde_json::Error),
 
+    #[error("CSV parsing error: {0}")]
+    Csv(#[from] csv::Error),
+
+    #[error("Failed to write YAML: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+
+    #[error("Failed to write TOML: {0}")]
+    Toml(#[from] toml::ser::Error),
+
     #[cfg(feature = "zip")]
     #[error("Failed to process ZIP archive: {0}")]
     Zip(#[from] zip::result::ZipError),
@@ -19,6 +30,326 @@ pub enum MyError {
     #[error("Failed to create temporary directory: {0}")]
     TempDir(#[from] tempfile::PersistError),
 
+    #[cfg(feature = "zip")]
+    #[error("Cannot extract the archive: {0}")]
+    TempLocation(String),
+
+    #[cfg(feature = "progress")]
     #[error("Error in progress bar: {0}")]
     ProgressBar(String),
+
+    #[error("No conversation matches '{0}'")]
+    NoMatchingConversation(String),
+
+    #[error("'{0}' matches {1} conversations; use the conversation ID instead")]
+    AmbiguousSelection(String, usize),
+
+    #[error("Invalid report input: {0}")]
+    InvalidWireInput(String),
+
+    #[error("Cannot write {}: {1}", .0.display())]
+    OpenOutput(PathBuf, #[source] io::Error),
+
+    #[error("{} already exists; pass --force to overwrite it", .0.display())]
+    OutputExists(PathBuf),
+
+    #[error("Failed to write {0} output destination(s)")]
+    OutputFailed(usize),
+
+    #[error("Invalid template: {0}")]
+    Template(String),
+
+    #[error("Invalid notes file {0}")]
+    InvalidNotes(String),
+
+    #[error("{} is held by another running instance and --no-wait was given", .0.display())]
+    Locked(PathBuf),
+
+    #[error("Processing was cancelled")]
+    Cancelled,
+
+    #[error("Cannot split output: {0}")]
+    Split(String),
+
+    #[error("The package has no messages folder: {0}")]
+    NoMessagesFolder(String),
+
+    #[error("{0}")]
+    Channel(#[from] ChannelError),
+
+    #[cfg(feature = "sqlite")]
+    #[error("SQLite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+
+    #[cfg(feature = "charts")]
+    #[error("Cannot draw the chart: {0}")]
+    Chart(String),
+
+    #[cfg(feature = "parquet")]
+    #[error("Parquet error: {0}")]
+    Parquet(#[from] parquet::errors::ParquetError),
+
+    #[cfg(feature = "xlsx")]
+    #[error("Cannot write the workbook: {0}")]
+    Xlsx(#[from] rust_xlsxwriter::XlsxError),
+
+    #[cfg(feature = "xlsx")]
+    #[error("--format xlsx writes a binary workbook; name its file with --output")]
+    XlsxToTerminal,
+}
+
+/// Broad kinds of failure, each with its own process exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCategory {
+    /// The input path, the selection or an auxiliary file is unusable
+    Input,
+    /// The package as a whole cannot be read
+    Package,
+    /// Results could not be written
+    Output,
+    Cancelled,
+    /// Anything else, such as I/O failures outside the package
+    Other,
+}
+
+impl ErrorCategory {
+    /// Exit codes per category; 3 is taken by an unknown `--participant`,
+    /// 6 by a `--verify-against` mismatch.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Self::Other => 1,
+            Self::Input => 2,
+            Self::Package => 4,
+            Self::Output => 5,
+            Self::Cancelled => 130,
+        }
+    }
+}
+
+impl MyError {
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Self::InvalidInputPath(_)
+            | Self::NoMatchingConversation(_)
+            | Self::AmbiguousSelection(..)
+            | Self::InvalidWireInput(_)
+            | Self::InvalidNotes(_)
+            | Self::Template(_)
+            | Self::Split(_)
+            | Self::OutputExists(_) => ErrorCategory::Input,
+            #[cfg(feature = "zip")]
+            Self::TempLocation(_) => ErrorCategory::Input,
+            Self::Json(_) | Self::Csv(_) | Self::NoMessagesFolder(_) | Self::Channel(_) => {
+                ErrorCategory::Package
+            }
+            #[cfg(feature = "zip")]
+            Self::Zip(_) => ErrorCategory::Package,
+            Self::OutputFailed(_) | Self::OpenOutput(..) | Self::Yaml(_) | Self::Toml(_) => {
+                ErrorCategory::Output
+            }
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(_) => ErrorCategory::Output,
+            #[cfg(feature = "charts")]
+            Self::Chart(_) => ErrorCategory::Output,
+            #[cfg(feature = "parquet")]
+            Self::Parquet(_) => ErrorCategory::Output,
+            #[cfg(feature = "xlsx")]
+            Self::Xlsx(_) => ErrorCategory::Output,
+            #[cfg(feature = "xlsx")]
+            Self::XlsxToTerminal => ErrorCategory::Input,
+            Self::Cancelled => ErrorCategory::Cancelled,
+            #[cfg(feature = "zip")]
+            Self::TempDir(_) => ErrorCategory::Other,
+            #[cfg(feature = "progress")]
+            Self::ProgressBar(_) => ErrorCategory::Other,
+            Self::Io(_) | Self::Locked(_) => ErrorCategory::Other,
+        }
+    }
+}
+
+/// A failure confined to one channel. Processing skips the channel and
+/// reports it as a warning instead of giving up on the whole package.
+#[derive(Error, Debug)]
+#[error("channel {channel_id}: {kind}")]
+pub struct ChannelError {
+    pub channel_id: String,
+    /// The file that could not be read
+    pub path: PathBuf,
+    pub kind: ChannelErrorKind,
+}
+
+#[derive(Error, Debug)]
+pub enum ChannelErrorKind {
+    #[error("cannot read {0}")]
+    Io(#[from] io::Error),
+
+    #[error("malformed JSON ({0})")]
+    Json(#[from] serde_json::Error),
+
+    #[error("malformed CSV ({0})")]
+    Csv(#[from] csv::Error),
+
+    #[error("its messages file is empty while a .part file beside it is still being extracted")]
+    Unfinished,
+}
+
+impl ChannelErrorKind {
+    /// The channel-level kind of an error, or the error itself if it
+    /// concerns more than the channel.
+    pub fn try_from_error(error: MyError) -> Result<Self, MyError> {
+        match error {
+            MyError::Io(error) => Ok(Self::Io(error)),
+            MyError::Json(error) => Ok(Self::Json(error)),
+            MyError::Csv(error) => Ok(Self::Csv(error)),
+            error => Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn io_error() -> io::Error {
+        io::Error::new(io::ErrorKind::PermissionDenied, "denied")
+    }
+
+    fn json_error() -> serde_json::Error {
+        serde_json::from_str::<u8>("x").unwrap_err()
+    }
+
+    fn channel_error() -> ChannelError {
+        ChannelError {
+            channel_id: "1".to_string(),
+            path: PathBuf::from("messages/c1/messages.json"),
+            kind: ChannelErrorKind::Unfinished,
+        }
+    }
+
+    #[test]
+    fn each_variant_has_its_category_and_exit_code() {
+        let path = || PathBuf::from("out.json");
+        // Extended below by the variants of enabled features
+        #[allow(unused_mut)]
+        let mut cases: Vec<(MyError, ErrorCategory, u8)> = vec![
+            (MyError::Io(io_error()), ErrorCategory::Other, 1),
+            (MyError::Json(json_error()), ErrorCategory::Package, 4),
+            (MyError::Csv(io_error().into()), ErrorCategory::Package, 4),
+            (
+                MyError::Yaml(serde_yaml::from_str::<u8>("[").unwrap_err()),
+                ErrorCategory::Output,
+                5,
+            ),
+            (
+                MyError::Toml(toml::to_string(&1u8).unwrap_err()),
+                ErrorCategory::Output,
+                5,
+            ),
+            (
+                MyError::InvalidInputPath("x".into()),
+                ErrorCategory::Input,
+                2,
+            ),
+            (
+                MyError::NoMatchingConversation("x".into()),
+                ErrorCategory::Input,
+                2,
+            ),
+            (
+                MyError::AmbiguousSelection("x".into(), 2),
+                ErrorCategory::Input,
+                2,
+            ),
+            (
+                MyError::InvalidWireInput("x".into()),
+                ErrorCategory::Input,
+                2,
+            ),
+            (
+                MyError::OpenOutput(path(), io_error()),
+                ErrorCategory::Output,
+                5,
+            ),
+            (MyError::OutputExists(path()), ErrorCategory::Input, 2),
+            (MyError::OutputFailed(1), ErrorCategory::Output, 5),
+            (MyError::Template("x".into()), ErrorCategory::Input, 2),
+            (MyError::InvalidNotes("x".into()), ErrorCategory::Input, 2),
+            (MyError::Locked(path()), ErrorCategory::Other, 1),
+            (MyError::Cancelled, ErrorCategory::Cancelled, 130),
+            (MyError::Split("x".into()), ErrorCategory::Input, 2),
+            (
+                MyError::NoMessagesFolder("x".into()),
+                ErrorCategory::Package,
+                4,
+            ),
+            (MyError::Channel(channel_error()), ErrorCategory::Package, 4),
+        ];
+        #[cfg(feature = "zip")]
+        cases.extend([
+            (
+                MyError::Zip(zip::result::ZipError::FileNotFound),
+                ErrorCategory::Package,
+                4,
+            ),
+            (
+                MyError::TempDir(tempfile::PersistError {
+                    error: io_error(),
+                    file: tempfile::NamedTempFile::new().unwrap(),
+                }),
+                ErrorCategory::Other,
+                1,
+            ),
+            (MyError::TempLocation("x".into()), ErrorCategory::Input, 2),
+        ]);
+        #[cfg(feature = "progress")]
+        cases.push((MyError::ProgressBar("x".into()), ErrorCategory::Other, 1));
+        #[cfg(feature = "sqlite")]
+        cases.push((
+            MyError::Sqlite(rusqlite::Error::QueryReturnedNoRows),
+            ErrorCategory::Output,
+            5,
+        ));
+        #[cfg(feature = "charts")]
+        cases.push((MyError::Chart("x".into()), ErrorCategory::Output, 5));
+        #[cfg(feature = "parquet")]
+        cases.push((
+            MyError::Parquet(parquet::errors::ParquetError::General("x".into())),
+            ErrorCategory::Output,
+            5,
+        ));
+        #[cfg(feature = "xlsx")]
+        cases.extend([
+            (
+                MyError::Xlsx(rust_xlsxwriter::XlsxError::ParameterError("x".into())),
+                ErrorCategory::Output,
+                5,
+            ),
+            (MyError::XlsxToTerminal, ErrorCategory::Input, 2),
+        ]);
+
+        for (error, category, exit_code) in cases {
+            assert_eq!(error.category(), category, "{:?}", error);
+            assert_eq!(error.category().exit_code(), exit_code, "{:?}", error);
+        }
+    }
+
+    #[test]
+    fn only_reading_errors_are_confined_to_a_channel() {
+        assert!(matches!(
+            ChannelErrorKind::try_from_error(MyError::Io(io_error())),
+            Ok(ChannelErrorKind::Io(_))
+        ));
+        assert!(matches!(
+            ChannelErrorKind::try_from_error(MyError::Json(json_error())),
+            Ok(ChannelErrorKind::Json(_))
+        ));
+        assert!(matches!(
+            ChannelErrorKind::try_from_error(MyError::Csv(io_error().into())),
+            Ok(ChannelErrorKind::Csv(_))
+        ));
+        assert!(matches!(
+            ChannelErrorKind::try_from_error(MyError::Cancelled),
+            Err(MyError::Cancelled)
+        ));
+    }
 }
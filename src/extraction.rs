@@ -0,0 +1,244 @@
+//! Notices a package folder that an archive manager is still extracting
+//! into. Counting such a folder gives plausible-looking but incomplete
+//! results, so the signs are looked for around the two passes over the
+//! package: channel folders that appear after the index was built, messages
+//! files written after the run started, and empty messages files next to the
+//! `.part` file an extractor is still filling. A folder caught filling
+//! while indexing is given a moment to settle and then indexed again.
+
+use crate::errors::{ChannelError, ChannelErrorKind};
+use crate::file_operations::DataRoot;
+use crate::warnings::Warning;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How long the folder count has to hold still for extraction to be over.
+pub const SETTLE_INTERVAL: Duration = Duration::from_millis(500);
+/// How long to wait for a filling folder before counting it as it is.
+pub const SETTLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What the package folder looked like before the index was built.
+pub struct ExtractionWatch {
+    messages_folder: PathBuf,
+    started: SystemTime,
+    folders: usize,
+}
+
+impl ExtractionWatch {
+    /// Take note of the package before indexing it. `None` for packages
+    /// without a readable `messages/` folder, which have nothing to watch.
+    pub fn start(data_root: &DataRoot) -> Option<Self> {
+        let messages_folder = data_root.path.join("messages");
+        let started = SystemTime::now();
+        let folders = count_folders(&messages_folder)?;
+        Some(Self {
+            messages_folder,
+            started,
+            folders,
+        })
+    }
+
+    /// Whether `messages/` gained or lost folders since the watch started.
+    pub fn is_filling(&self) -> bool {
+        count_folders(&self.messages_folder).is_some_and(|folders| folders != self.folders)
+    }
+
+    /// Wait until `messages/` holds the same folders for `interval`, for at
+    /// most `timeout`. Once settled the watch starts over from the settled
+    /// folder, and `true` tells the caller to index it again.
+    pub fn settle(&mut self, interval: Duration, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut folders = count_folders(&self.messages_folder);
+        while Instant::now() + interval <= deadline {
+            thread::sleep(interval);
+            let now = count_folders(&self.messages_folder);
+            if now == folders {
+                self.started = SystemTime::now();
+                self.folders = now.unwrap_or(0);
+                return true;
+            }
+            folders = now;
+        }
+        false
+    }
+
+    /// Look for signs of extraction once every channel was counted, given
+    /// the messages files that were indexed and the channels that failed.
+    pub fn finish(self, files: &[PathBuf], channel_errors: &[ChannelError]) -> Option<Warning> {
+        let mut signs = Vec::new();
+
+        if let Some(folders) = count_folders(&self.messages_folder) {
+            if folders != self.folders {
+                signs.push(format!(
+                    "messages/ held {} folders when indexed and {} after counting",
+                    self.folders, folders
+                ));
+            }
+        }
+
+        let written = files
+            .iter()
+            .filter(|file| {
+                fs::metadata(file)
+                    .and_then(|metadata| metadata.modified())
+                    .is_ok_and(|modified| modified > self.started)
+            })
+            .count();
+        if written > 0 {
+            signs.push(format!(
+                "{} messages files were written while counting",
+                written
+            ));
+        }
+
+        let unfinished = channel_errors
+            .iter()
+            .filter(|error| matches!(error.kind, ChannelErrorKind::Unfinished))
+            .count();
+        if unfinished > 0 {
+            signs.push(format!(
+                "{} messages files are empty beside a .part file",
+                unfinished
+            ));
+        }
+
+        (!signs.is_empty()).then_some(Warning::StillExtracting { signs })
+    }
+}
+
+/// Whether `messages_file` is empty because an extractor is still writing
+/// it under a `.part` name next to it.
+pub fn is_unfinished(messages_file: &Path) -> bool {
+    let Ok(metadata) = fs::metadata(messages_file) else {
+        return false;
+    };
+    let mut part = messages_file.as_os_str().to_owned();
+    part.push(".part");
+    metadata.len() == 0 && Path::new(&part).exists()
+}
+
+fn count_folders(messages_folder: &Path) -> Option<usize> {
+    Some(
+        fs::read_dir(messages_folder)
+            .ok()?
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+            .count(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_operations::prepare_data_root;
+    use crate::testing::Package;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    fn watch(package: &Package) -> ExtractionWatch {
+        ExtractionWatch::start(&prepare_data_root(package.path(), None).unwrap()).unwrap()
+    }
+
+    fn add_channel(package: &Path, id: usize) {
+        let folder = package.join("messages").join(format!("c{}", id));
+        fs::create_dir_all(&folder).unwrap();
+        fs::write(folder.join("messages.json"), "[]").unwrap();
+    }
+
+    /// Adds a channel folder every few milliseconds, `count` of them or
+    /// until stopped.
+    fn extract_in_background(
+        package: &Path,
+        count: Option<usize>,
+    ) -> (thread::JoinHandle<()>, Arc<AtomicBool>) {
+        let stop = Arc::new(AtomicBool::new(false));
+        let package = package.to_path_buf();
+        let stopped = Arc::clone(&stop);
+        let handle = thread::spawn(move || {
+            for id in 0..count.unwrap_or(usize::MAX) {
+                if stopped.load(Ordering::Relaxed) {
+                    break;
+                }
+                add_channel(&package, id);
+                thread::sleep(Duration::from_millis(10));
+            }
+        });
+        (handle, stop)
+    }
+
+    #[test]
+    fn an_untouched_package_shows_no_signs() {
+        let package = Package::new();
+        package.channel("1", json!({"id": "1"}), 3);
+        let watch = watch(&package);
+        assert!(!watch.is_filling());
+        let files = [package.path().join("messages/c1/messages.json")];
+        assert!(watch.finish(&files, &[]).is_none());
+    }
+
+    #[test]
+    fn folders_added_in_the_background_are_noticed() {
+        let package = Package::new();
+        let watch = watch(&package);
+        let (extractor, _) = extract_in_background(package.path(), Some(5));
+        extractor.join().unwrap();
+
+        assert!(watch.is_filling());
+        let files = [package.path().join("messages/c0/messages.json")];
+        let Some(Warning::StillExtracting { signs }) = watch.finish(&files, &[]) else {
+            panic!("no extraction warning");
+        };
+        assert_eq!(
+            signs,
+            [
+                "messages/ held 0 folders when indexed and 5 after counting",
+                "1 messages files were written while counting",
+            ]
+        );
+    }
+
+    #[test]
+    fn waiting_settles_once_extraction_stops() {
+        let package = Package::new();
+        let mut watch = watch(&package);
+        let (extractor, _) = extract_in_background(package.path(), Some(20));
+        while !package.path().join("messages/c0").exists() {
+            thread::yield_now();
+        }
+
+        assert!(watch.settle(Duration::from_millis(300), Duration::from_secs(10)));
+        extractor.join().unwrap();
+        // Counting starts over from the settled folder
+        assert!(!watch.is_filling());
+        let files = [package.path().join("messages/c0/messages.json")];
+        assert!(watch.finish(&files, &[]).is_none());
+    }
+
+    #[test]
+    fn waiting_gives_up_on_a_folder_that_keeps_filling() {
+        let package = Package::new();
+        let mut watch = watch(&package);
+        let (extractor, stop) = extract_in_background(package.path(), None);
+
+        let settled = watch.settle(Duration::from_millis(100), Duration::from_millis(500));
+        stop.store(true, Ordering::Relaxed);
+        extractor.join().unwrap();
+        assert!(!settled);
+        assert!(watch.is_filling());
+    }
+
+    #[test]
+    fn an_empty_file_is_unfinished_only_beside_a_part_file() {
+        let package = Package::new();
+        let file = package.path().join("messages/c1/messages.json");
+        package.file("messages/c1/messages.json", "");
+        assert!(!is_unfinished(&file));
+        package.file("messages/c1/messages.json.part", "[{\"ID\": \"1\"");
+        assert!(is_unfinished(&file));
+        package.file("messages/c1/messages.json", "[]");
+        assert!(!is_unfinished(&file));
+    }
+}
@@ -0,0 +1,87 @@
+//! Flags that only exist with an optional cargo feature. A build without the
+//! feature has no such flags, so they are recognised here, before parsing,
+//! to name the missing feature instead of clap's "unexpected argument".
+
+use std::ffi::OsString;
+
+/// Each feature-gated flag, the feature it needs and whether this build has it.
+const FEATURE_FLAGS: &[(&str, &str, bool)] = &[
+    ("--force-extract", "zip", cfg!(feature = "zip")),
+    ("--temp-dir", "zip", cfg!(feature = "zip")),
+    ("--verify-against", "zip", cfg!(feature = "zip")),
+    ("--resolve-online", "net", cfg!(feature = "net")),
+    ("--resolve-limit", "net", cfg!(feature = "net")),
+    ("--copy", "clipboard", cfg!(feature = "clipboard")),
+    ("--color", "color", cfg!(feature = "color")),
+    ("--export-sqlite", "sqlite", cfg!(feature = "sqlite")),
+    ("--export-date", "sqlite", cfg!(feature = "sqlite")),
+    ("--chart", "charts", cfg!(feature = "charts")),
+    ("--chart-top", "charts", cfg!(feature = "charts")),
+    ("--chart-font", "charts", cfg!(feature = "charts")),
+    ("--export-parquet", "parquet", cfg!(feature = "parquet")),
+    ("--template-file", "templates", cfg!(feature = "templates")),
+];
+
+/// The first argument, before any `--`, that is a flag of a feature this
+/// build lacks, with that feature.
+pub fn compiled_out_flag(
+    args: impl IntoIterator<Item = OsString>,
+) -> Option<(&'static str, &'static str)> {
+    args.into_iter()
+        .take_while(|arg| arg != "--")
+        .find_map(|arg| {
+            // Paths need not be Unicode, but flags always are
+            let arg = arg.to_str()?;
+            let name = arg.split_once('=').map_or(arg, |(name, _)| name);
+            FEATURE_FLAGS
+                .iter()
+                .find(|&&(flag, _, enabled)| flag == name && !enabled)
+                .map(|&(flag, feature, _)| (flag, feature))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compiled_out(args: &[&str]) -> Option<(&'static str, &'static str)> {
+        compiled_out_flag(args.iter().map(OsString::from))
+    }
+
+    #[test]
+    fn only_flags_of_missing_features_are_named() {
+        for &(flag, feature, enabled) in FEATURE_FLAGS {
+            let expected = (!enabled).then_some((flag, feature));
+            assert_eq!(compiled_out(&["package", flag]), expected, "{}", flag);
+            let with_value = format!("{}=x", flag);
+            assert_eq!(compiled_out(&[&with_value]), expected, "{}", with_value);
+        }
+    }
+
+    #[test]
+    fn values_and_arguments_after_a_double_dash_are_no_flags() {
+        assert_eq!(compiled_out(&["package", "--limit", "5"]), None);
+        assert_eq!(compiled_out(&["package", "--", "--copy"]), None);
+        assert_eq!(compiled_out(&["--copyright"]), None);
+    }
+
+    /// Counting must not depend on which features are compiled in.
+    #[test]
+    fn every_configuration_counts_a_fixture_alike() {
+        use crate::testing::Package;
+        use serde_json::json;
+
+        let package = Package::new();
+        package
+            .channel("1", json!({"id": "1", "type": 1, "recipients": ["2"]}), 4)
+            .channel("3", json!({"id": "3", "guild": {"id": "9"}}), 2);
+        let mut counts: Vec<(String, usize)> = package
+            .analyze()
+            .conversations
+            .iter()
+            .map(|conversation| (conversation.id().to_string(), conversation.message_count()))
+            .collect();
+        counts.sort();
+        assert_eq!(counts, [("1".to_string(), 4), ("9".to_string(), 2)]);
+    }
+}
@@ -1,12 +1,31 @@
-use crate::errors::MyError;
-use crate::{Channel, Conversation};
-use indicatif::{ProgressBar, ProgressStyle};
-use serde_json::Value;
-use std::collections::HashMap;
+use crate::analysis::{Progress, ProgressSender};
+use crate::breadth::{BreadthTally, YearBreadth};
+use crate::budget::{Budget, CancelFlag, PartialRun, StopReason};
+use crate::errors::{ChannelError, ChannelErrorKind, MyError};
+use crate::extraction::is_unfinished;
+use crate::index::ChannelProbe;
+use crate::messages::{count_messages, MessageCounts, SystemExclusion};
+use crate::peaks::{peak_day, PeakDay};
+use crate::resolve::{strip_discriminator, NameResolver};
+use crate::sessions::{self, SessionSummary};
+use crate::volume::ProcessingStats;
+use crate::warnings::Warning;
+use crate::zone::Zone;
+use crate::{Channel, Conversation, ConversationType, GuildId, UNKNOWN_GUILD_NAME};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use clap::ValueEnum;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use serde::Deserialize;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::{self, File};
 use std::io::BufReader;
 use std::path::{Path, PathBuf};
+use std::slice;
+use std::time::Instant;
 
+#[cfg(feature = "zip")]
+use crate::humanize::fmt_bytes;
 #[cfg(feature = "zip")]
 use tempfile::TempDir;
 
@@ -25,19 +44,141 @@ pub struct DataRoot {
     pub path: PathBuf,
 }
 
+/// Switches that change how messages are counted.
+#[derive(Debug, Default, Clone)]
+pub struct ProcessOptions {
+    /// Count messages with the same ID within a channel only once
+    pub dedupe_messages: bool,
+    /// Skip system messages instead of counting them
+    pub exclude_system: Option<SystemExclusion>,
+    /// Skip messages without contents or attachments instead of counting them
+    pub exclude_empty_messages: bool,
+    /// Merge channels with identical normalized names within each guild
+    pub merge_same_name_channels: bool,
+    /// Start of the analytics coverage, for comparing counts against them
+    pub analytics_since: Option<DateTime<Utc>>,
+    /// Order in which channels are processed, relevant when stopping early
+    pub order: ProcessingOrder,
+    /// Seed for `ProcessingOrder::Random`
+    pub seed: Option<u64>,
+    pub budget: Budget,
+    pub cancel: CancelFlag,
+    /// Tally distinct servers, channels and DMs per year
+    pub breadth: bool,
+    /// Tally counted messages per conversation and year
+    pub messages_by_year: bool,
+    /// Find each conversation's day with the most counted messages
+    pub peak_days: bool,
+    /// Zone whose calendar decides day, month and year boundaries
+    pub timezone: Zone,
+    /// The package owner's user ID, left out when naming DMs by recipients
+    pub account_id: Option<String>,
+    /// Receives an event before every channel folder and once all are done
+    pub progress: Option<ProgressSender>,
+    /// Also send every conversation over `progress` once it is complete
+    pub stream_conversations: bool,
+    /// How many channels each guild keeps once its totals are known
+    pub channel_detail: ChannelDetail,
+    /// Group messages into sessions ending at gaps of this length
+    pub session_gap: Option<Duration>,
+    /// Keep the `#1234` discriminators of pre-migration usernames in DM names
+    pub keep_discriminators: bool,
+    /// Guilds listed in `servers/index.json`; the others are ones the owner
+    /// left. `None` when the package has no such index to tell by.
+    pub indexed_guilds: Option<HashSet<GuildId>>,
+}
+
+/// How much per-channel detail guilds retain. Guild totals are the same
+/// either way; dropping channels only saves memory on large packages.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelDetail {
+    #[default]
+    All,
+    /// Only the N channels with the most messages
+    Top(usize),
+    /// Guild totals only
+    None,
+}
+
+/// Order in which channel folders are processed. Only matters for partial
+/// runs, where it decides which channels make it into the result.
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessingOrder {
+    /// Largest message files first
+    #[default]
+    Size,
+    /// Shuffled, for a representative sample (honours --seed)
+    Random,
+    /// Directory listing order
+    Filesystem,
+}
+
+/// Everything counted for one channel, before any aggregation.
+struct ChannelRecord {
+    channel: ChannelProbe,
+    counts: MessageCounts,
+}
+
+/// What processing the package produced.
+pub struct ProcessOutcome {
+    pub conversations: Vec<Conversation>,
+    pub warnings: Vec<Warning>,
+    /// Set when processing stopped before every channel was counted
+    pub partial: Option<PartialRun>,
+    /// Messages per channel ID within the analytics coverage window
+    pub counted_since_analytics: HashMap<String, usize>,
+    /// Conversation ID each counted channel ended up in, which stays known
+    /// when guilds drop their channel detail
+    pub conversation_of_channel: HashMap<String, String>,
+    /// Set when `ProcessOptions::breadth` was requested
+    pub breadth: Option<Vec<YearBreadth>>,
+    /// Counted messages per conversation ID and year, set when
+    /// `ProcessOptions::messages_by_year` was requested
+    pub messages_by_year: Option<HashMap<String, BTreeMap<i32, usize>>>,
+    /// Busiest day per conversation ID, when `ProcessOptions::peak_days`
+    /// was requested
+    pub peak_days: Option<HashMap<String, PeakDay>>,
+    /// Set when `ProcessOptions::session_gap` was given
+    pub sessions: Option<SessionSummary>,
+    /// Channels skipped because their messages could not be read
+    pub channel_errors: Vec<ChannelError>,
+    pub first_message: Option<DateTime<Utc>>,
+    pub last_message: Option<DateTime<Utc>>,
+    /// Newest message per counted channel ID
+    pub last_messages: HashMap<String, DateTime<Utc>>,
+    /// Counted messages per channel ID
+    pub messages_by_channel: HashMap<String, usize>,
+    pub stats: ProcessingStats,
+}
+
+/// Channel names, guild names and what was noticed loading them.
 type Mappings = (
     Option<HashMap<String, String>>,
     Option<HashMap<String, String>>,
+    Vec<Warning>,
 );
 
-pub fn prepare_data_root(input_path: &Path) -> Result<DataRoot, MyError> {
+/// Make the package available as a folder. ZIP archives are extracted below
+/// `temp_location`, or the system's temp directory (honouring `TMPDIR`).
+pub fn prepare_data_root(
+    input_path: &Path,
+    temp_location: Option<&Path>,
+) -> Result<DataRoot, MyError> {
     #[cfg(feature = "zip")]
     {
         if input_path.is_file() {
             // Handle ZIP file
             let file = File::open(input_path)?;
             let mut archive = ZipArchive::new(file)?;
-            let temp_dir = TempDir::new()?;
+            let temp_location = temp_location.map_or_else(std::env::temp_dir, Path::to_path_buf);
+            check_temp_location(&temp_location, extracted_size(&archive))?;
+            let temp_dir = TempDir::new_in(&temp_location).map_err(|e| {
+                MyError::TempLocation(format!(
+                    "cannot create a directory in temp location {} ({}); use --temp-dir",
+                    temp_location.display(),
+                    e
+                ))
+            })?;
             archive.extract(temp_dir.path())?;
             Ok(DataRoot {
                 path: temp_dir.path().to_path_buf(),
@@ -55,6 +196,7 @@ pub fn prepare_data_root(input_path: &Path) -> Result<DataRoot, MyError> {
 
     #[cfg(not(feature = "zip"))]
     {
+        let _ = temp_location;
         if input_path.is_dir() {
             Ok(DataRoot {
                 path: input_path.to_path_buf(),
@@ -65,137 +207,1282 @@ pub fn prepare_data_root(input_path: &Path) -> Result<DataRoot, MyError> {
     }
 }
 
+/// Bytes the archive takes up once extracted, from its entries' headers.
+#[cfg(feature = "zip")]
+fn extracted_size(archive: &ZipArchive<File>) -> u64 {
+    archive
+        .decompressed_size()
+        .map_or(u64::MAX, |size| size.try_into().unwrap_or(u64::MAX))
+}
+
+#[cfg(feature = "zip")]
+fn check_temp_location(temp_location: &Path, needed: u64) -> Result<(), MyError> {
+    // Platforms that cannot report free space get the benefit of the doubt
+    let Ok(available) = fs4::available_space(temp_location) else {
+        return Ok(());
+    };
+    if available < needed {
+        return Err(MyError::TempLocation(format!(
+            "temp location {} has {} free but the archive needs ~{}; use --temp-dir",
+            temp_location.display(),
+            fmt_bytes(available),
+            fmt_bytes(needed)
+        )));
+    }
+    Ok(())
+}
+
+/// The package owner's user ID from `account/user.json`, if readable.
+pub fn load_account_id(data_root: &DataRoot) -> Result<Option<String>, MyError> {
+    #[derive(Deserialize)]
+    struct Account {
+        id: Option<String>,
+    }
+
+    let path = data_root.path.join("account").join("user.json");
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents = fs::read_to_string(path)?;
+    Ok(serde_json::from_str::<Account>(&contents)
+        .ok()
+        .and_then(|account| account.id))
+}
+
 pub fn load_mappings(data_root: &DataRoot) -> Result<Mappings, MyError> {
     let messages_folder = data_root.path.join("messages");
     let servers_folder = data_root.path.join("servers");
 
-    let channel_mapping = load_mapping(&messages_folder.join("index.json"))?;
+    let mut warnings = Vec::new();
+    let channel_mapping = load_mapping(&messages_folder.join("index.json"))?
+        .map(|mapping| normalize_channel_keys(mapping, &messages_folder, &mut warnings));
     let guild_mapping = load_mapping(&servers_folder.join("index.json"))?;
 
-    Ok((channel_mapping, guild_mapping))
+    Ok((channel_mapping, guild_mapping, warnings))
+}
+
+/// Exports disagree on whether channel keys carry the `c` of the folder
+/// names, so keys are stored without it. Should an index hold a channel in
+/// both forms, the key spelled like the channel's folder in `messages_folder`
+/// wins, and the bare one when there is no such folder; differing names are
+/// reported once per channel.
+fn normalize_channel_keys(
+    mapping: HashMap<String, String>,
+    messages_folder: &Path,
+    warnings: &mut Vec<Warning>,
+) -> HashMap<String, String> {
+    let (bare, prefixed): (Vec<_>, Vec<_>) = mapping
+        .into_iter()
+        .partition(|(id, _)| normalize_channel_id(id) == id);
+    let mut normalized: HashMap<String, String> = bare.into_iter().collect();
+    for (prefixed_id, name) in prefixed {
+        let id = normalize_channel_id(&prefixed_id).to_string();
+        let prefixed_used = messages_folder.join(&prefixed_id).is_dir();
+        match normalized.get(&id) {
+            Some(bare_name) if *bare_name != name => {
+                warnings.push(Warning::ChannelKeyCollision {
+                    channel_id: id.clone(),
+                    prefixed_id,
+                    prefixed_used,
+                });
+                if !prefixed_used {
+                    continue;
+                }
+            }
+            Some(_) => continue,
+            None => {}
+        }
+        normalized.insert(id, name);
+    }
+    normalized
 }
 
 fn load_mapping(path: &Path) -> Result<Option<HashMap<String, String>>, MyError> {
     if path.exists() {
         let file = File::open(path)?;
         let reader = BufReader::new(file);
-        let mapping = serde_json::from_reader(reader)?;
-        Ok(Some(mapping))
+        // Channels without a name are null in the 2021 vintage's index
+        let mapping: HashMap<String, Option<String>> = serde_json::from_reader(reader)?;
+        Ok(Some(
+            mapping
+                .into_iter()
+                .filter_map(|(id, name)| Some((id, name?)))
+                .collect(),
+        ))
     } else {
         Ok(None)
     }
 }
 
-pub fn process_conversations(
-    data_root: &DataRoot,
-    channel_mapping: &Option<HashMap<String, String>>,
-    guild_mapping: &Option<HashMap<String, String>>,
-) -> Result<Vec<Conversation>, MyError> {
+/// List the channel folders below `messages/`.
+pub fn channel_folders(data_root: &DataRoot) -> Result<Vec<PathBuf>, MyError> {
     let messages_folder = data_root.path.join("messages");
-    let entries = fs::read_dir(messages_folder)?;
+    if !messages_folder.is_dir() {
+        return Err(MyError::NoMessagesFolder(
+            messages_folder.display().to_string(),
+        ));
+    }
+    let mut folders = Vec::new();
+    for entry in fs::read_dir(messages_folder)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            folders.push(path);
+        }
+    }
+    Ok(folders)
+}
+
+/// Strip the single `c` that prefixes channel IDs in folder names and, in some
+/// exports, in `messages/index.json` keys.
+pub fn normalize_channel_id(id: &str) -> &str {
+    id.strip_prefix('c').unwrap_or(id)
+}
+
+fn order_channels(channels: &mut [ChannelProbe], options: &ProcessOptions) {
+    match options.order {
+        ProcessingOrder::Size => channels.sort_by_key(|channel| Reverse(channel.messages_size)),
+        ProcessingOrder::Random => {
+            let mut rng = match options.seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy(),
+            };
+            channels.shuffle(&mut rng);
+        }
+        ProcessingOrder::Filesystem => {}
+    }
+}
 
-    let progress = ProgressBar::new_spinner();
-    progress.set_style(
-        ProgressStyle::default_spinner()
-            .template("{spinner} {msg}")
-            .map_err(|e| MyError::ProgressBar(e.to_string()))?,
-    );
-    progress.enable_steady_tick(std::time::Duration::from_millis(100));
-    progress.set_message("Processing conversations...");
+/// Count the messages of the indexed channels and aggregate them into
+/// conversations.
+pub fn process_conversations(
+    mut channels: Vec<ChannelProbe>,
+    resolver: &mut dyn NameResolver,
+    options: &ProcessOptions,
+) -> Result<ProcessOutcome, MyError> {
+    order_channels(&mut channels, options);
+    let total = channels.len();
 
-    let mut conversations = Vec::new();
-    let mut guilds = HashMap::new();
+    let report_progress = |processed: usize, messages: usize| {
+        if let Some(ref progress) = options.progress {
+            // Nobody listening any more is no reason to stop counting
+            let _ = progress.send(Progress::Channels {
+                processed,
+                total,
+                messages,
+            });
+        }
+    };
+    let stream = |conversation: &Conversation| {
+        if let (true, Some(progress)) = (options.stream_conversations, &options.progress) {
+            let _ = progress.send(Progress::Conversation(Box::new(conversation.clone())));
+        }
+    };
 
-    for entry in entries {
-        let entry = entry?;
-        let path = entry.path();
+    let mut records = Vec::new();
+    let mut warnings = Vec::new();
+    let mut channel_errors = Vec::new();
+    let mut partial = None;
+    let started = Instant::now();
+    let mut counted = 0;
+    let mut bytes_read = 0;
+    let mut channels_processed = 0;
 
-        if path.is_dir() {
-            let channel_id = path
-                .file_name()
-                .and_then(|name| name.to_str())
-                .ok_or_else(|| {
-                    MyError::InvalidInputPath(format!(
-                        "Invalid channel ID in path: {}",
-                        path.display()
-                    ))
-                })?
-                .to_string();
-
-            let messages_file = path.join("messages.json");
-            let channel_info_file = path.join("channel.json");
-
-            if messages_file.exists() && channel_info_file.exists() {
-                let channel_info: Value = read_json(&channel_info_file)?;
-                let messages: Vec<Value> = read_json(&messages_file)?;
-                let channel_message_count = messages.len();
-
-                if let Some(guild_info) = channel_info.get("guild") {
-                    let guild_id = guild_info
-                        .get("id")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("Unknown");
-                    let guild_name = guild_mapping
-                        .as_ref()
-                        .and_then(|gm| gm.get(guild_id))
-                        .cloned()
-                        .unwrap_or_else(|| format!("Guild {}", guild_id));
-                    let channel_name = channel_info
-                        .get("name")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or(&channel_id)
-                        .to_string();
-
-                    let guild =
-                        guilds
-                            .entry(guild_id.to_string())
-                            .or_insert_with(|| Conversation::Guild {
-                                name: guild_name.clone(),
-                                message_count: 0,
-                                channels: Vec::new(),
-                            });
-
-                    if let Conversation::Guild {
-                        message_count,
-                        channels,
-                        ..
-                    } = guild
-                    {
-                        *message_count += channel_message_count;
-                        channels.push(Channel {
-                            name: channel_name,
-                            message_count: channel_message_count,
-                        });
+    for (processed, channel) in channels.into_iter().enumerate() {
+        report_progress(processed, counted);
+        let exhausted = options.budget.exceeded(started, counted);
+        if exhausted.is_some() {
+            options.cancel.cancel();
+        }
+        if options.cancel.is_cancelled() {
+            partial = Some(PartialRun {
+                stopped_by: exhausted.map_or(StopReason::Cancelled, StopReason::Budget),
+                channels_processed: processed,
+                channels_total: total,
+            });
+            break;
+        }
+
+        channels_processed += 1;
+        if let Some(record) = parse_channel(channel, options, &mut warnings, &mut channel_errors)? {
+            counted += record.counts.counted;
+            bytes_read += record.counts.bytes_read;
+            // A channel without a guild is a conversation of its own, so it
+            // can be streamed before the rest are counted; its warnings are
+            // raised by the reduce over every record below
+            if options.stream_conversations && record.channel.guild_id.is_none() {
+                reduce(slice::from_ref(&record), resolver, options, &mut Vec::new())
+                    .iter()
+                    .for_each(stream);
+            }
+            records.push(record);
+        }
+    }
+
+    if partial.is_none() {
+        report_progress(total, counted);
+    }
+    let conversations = reduce(&records, resolver, options, &mut warnings);
+    conversations
+        .iter()
+        .filter(|conversation| matches!(conversation, Conversation::Guild { id: Some(_), .. }))
+        .for_each(stream);
+    let stats = ProcessingStats::new(channels_processed, bytes_read, counted, started.elapsed());
+
+    let counted_since_analytics = records
+        .iter()
+        .map(|record| (record.channel.id.clone(), record.counts.counted_since))
+        .collect();
+    let conversation_of_channel: HashMap<String, String> = records
+        .iter()
+        .map(|record| {
+            let conversation_id = match record.channel.guild_id {
+                Some(ref guild_id) => guild_id.clone(),
+                None => record.channel.id.clone(),
+            };
+            (record.channel.id.clone(), conversation_id)
+        })
+        .collect();
+    let breadth = options.breadth.then(|| breadth_by_year(&records));
+    let messages_by_year = options.messages_by_year.then(|| {
+        let mut by_conversation: HashMap<String, BTreeMap<i32, usize>> = HashMap::new();
+        for record in &records {
+            let years = by_conversation
+                .entry(conversation_of_channel[&record.channel.id].clone())
+                .or_default();
+            for (&year, &count) in &record.counts.years {
+                *years.entry(year).or_default() += count;
+            }
+        }
+        by_conversation
+    });
+    let peak_days = options.peak_days.then(|| {
+        let mut by_conversation: HashMap<String, BTreeMap<NaiveDate, usize>> = HashMap::new();
+        for record in &mut records {
+            let days = by_conversation
+                .entry(conversation_of_channel[&record.channel.id].clone())
+                .or_default();
+            for (day, count) in std::mem::take(&mut record.counts.days) {
+                *days.entry(day).or_default() += count;
+            }
+        }
+        by_conversation
+            .into_iter()
+            .filter_map(|(id, days)| Some((id, peak_day(&days)?)))
+            .collect()
+    });
+    let messages_by_channel = records
+        .iter()
+        .map(|record| (record.channel.id.clone(), record.counts.counted))
+        .collect();
+    let last_messages = records
+        .iter()
+        .filter_map(|record| Some((record.channel.id.clone(), record.counts.last_message?)))
+        .collect();
+    let first_message = records.iter().filter_map(|r| r.counts.first_message).min();
+    let last_message = records.iter().filter_map(|r| r.counts.last_message).max();
+    let sessions = options.session_gap.map(|gap| {
+        let mut timestamps: HashMap<String, Vec<DateTime<Utc>>> = HashMap::new();
+        for record in &mut records {
+            timestamps
+                .entry(conversation_of_channel[&record.channel.id].clone())
+                .or_default()
+                .append(&mut record.counts.timestamps);
+        }
+        sessions::summarize(timestamps, gap)
+    });
+
+    Ok(ProcessOutcome {
+        conversations,
+        warnings,
+        partial,
+        counted_since_analytics,
+        conversation_of_channel,
+        breadth,
+        messages_by_year,
+        peak_days,
+        sessions,
+        channel_errors,
+        first_message,
+        last_message,
+        last_messages,
+        messages_by_channel,
+        stats,
+    })
+}
+
+/// Count a single channel. Returns `None` when its messages cannot be read,
+/// recording why in `channel_errors`.
+fn parse_channel(
+    channel: ChannelProbe,
+    options: &ProcessOptions,
+    warnings: &mut Vec<Warning>,
+    channel_errors: &mut Vec<ChannelError>,
+) -> Result<Option<ChannelRecord>, MyError> {
+    let counts = match count_messages(&channel.messages_file, options) {
+        Ok(counts) => counts,
+        Err(error) => {
+            let kind = match is_unfinished(&channel.messages_file) {
+                true => ChannelErrorKind::Unfinished,
+                false => ChannelErrorKind::try_from_error(error)?,
+            };
+            channel_errors.push(ChannelError {
+                channel_id: format!("c{}", channel.id),
+                path: channel.messages_file,
+                kind,
+            });
+            return Ok(None);
+        }
+    };
+    if counts.duplicates > 0 {
+        warnings.push(Warning::DuplicateMessages {
+            channel_id: format!("c{}", channel.id),
+            duplicates: counts.duplicates,
+        });
+    }
+
+    Ok(Some(ChannelRecord { channel, counts }))
+}
+
+/// The reduce step over channels counted independently of each other:
+/// aggregates their records into conversations on one thread, merging the
+/// channels of each guild. Reads nothing but the records and the names
+/// `resolver` gives.
+fn reduce(
+    records: &[ChannelRecord],
+    resolver: &mut dyn NameResolver,
+    options: &ProcessOptions,
+    warnings: &mut Vec<Warning>,
+) -> Vec<Conversation> {
+    let mut aggregator = Aggregator::default();
+    for record in records {
+        aggregator.add(record, resolver, options, warnings);
+    }
+    aggregator.finish(options, warnings)
+}
+
+/// State of `reduce`. DMs and channels of unidentifiable servers are
+/// complete on their own; guilds only once every channel was added.
+#[derive(Default)]
+struct Aggregator {
+    conversations: Vec<Conversation>,
+    guilds: HashMap<GuildId, Conversation>,
+    guild_memo: GuildMemo,
+    legacy_names: usize,
+}
+
+/// What is known about each guild of the run, resolved on its first channel
+/// and reused by every later one, so the resolver is asked once per guild.
+#[derive(Default)]
+struct GuildMemo {
+    guilds: HashMap<GuildId, ResolvedGuild>,
+}
+
+struct ResolvedGuild {
+    name: String,
+    /// Name the mapping gave, which `channel.json` names are checked against
+    mapped_name: Option<String>,
+    conflict_reported: bool,
+}
+
+impl GuildMemo {
+    /// The guild of `channel`: named by the mapping, else by its
+    /// `channel.json`. A `channel.json` naming it differently from the mapping
+    /// is reported once per guild.
+    fn resolve(
+        &mut self,
+        guild_id: &GuildId,
+        channel: &ChannelProbe,
+        resolver: &mut dyn NameResolver,
+        warnings: &mut Vec<Warning>,
+    ) -> &ResolvedGuild {
+        let guild = self.guilds.entry(guild_id.clone()).or_insert_with(|| {
+            let mapped_name = present(resolver.guild_name(guild_id));
+            let name = mapped_name
+                .clone()
+                .or_else(|| present(channel.guild_name.clone()))
+                .unwrap_or_else(|| format!("Guild {}", guild_id));
+            ResolvedGuild {
+                name,
+                mapped_name,
+                conflict_reported: false,
+            }
+        });
+
+        let conflicts = match (&guild.mapped_name, present(channel.guild_name.clone())) {
+            (Some(mapped), Some(named)) => mapped.trim() != named.trim(),
+            _ => false,
+        };
+        if conflicts && !guild.conflict_reported {
+            guild.conflict_reported = true;
+            warnings.push(Warning::GuildNameConflict {
+                guild_id: guild_id.clone(),
+                channel_id: format!("c{}", channel.id),
+            });
+        }
+        guild
+    }
+}
+
+impl Aggregator {
+    /// Add a counted channel.
+    fn add(
+        &mut self,
+        record: &ChannelRecord,
+        resolver: &mut dyn NameResolver,
+        options: &ProcessOptions,
+        warnings: &mut Vec<Warning>,
+    ) {
+        let ChannelRecord { channel, counts } = record;
+        let channel_message_count = counts.counted;
+        let excluded_system = counts.excluded_system;
+        let empty_messages = counts.empty_messages;
+
+        if channel.kind != ConversationType::Guild {
+            let others: Vec<&str> = channel
+                .recipients
+                .iter()
+                .map(String::as_str)
+                .filter(|id| Some(*id) != options.account_id.as_deref())
+                .collect();
+            let conversation_name = present(resolver.channel_name(&channel.id))
+                .or(present(channel.folder_name.clone()))
+                .or_else(|| recipients_name(channel.kind, &others))
+                .unwrap_or_else(|| format!("Conversation c{}", channel.id));
+            let conversation_name = match strip_discriminator(&conversation_name) {
+                Some(stripped) => {
+                    self.legacy_names += 1;
+                    match options.keep_discriminators {
+                        true => conversation_name,
+                        false => stripped.to_string(),
                     }
-                } else {
-                    // DM or GC
-                    let stripped_channel_id = channel_id.trim_start_matches('c');
-                    let conversation_name = channel_mapping
-                        .as_ref()
-                        .and_then(|cm| cm.get(stripped_channel_id))
-                        .cloned()
-                        .unwrap_or_else(|| format!("Conversation {}", channel_id));
-
-                    conversations.push(Conversation::DmOrGc {
-                        name: conversation_name,
-                        message_count: channel_message_count,
-                    });
+                }
+                None => conversation_name,
+            };
+
+            self.conversations.push(Conversation::DmOrGc {
+                id: channel.id.clone(),
+                name: conversation_name,
+                message_count: channel_message_count,
+                excluded_system,
+                empty_messages,
+                group: channel.kind == ConversationType::GroupDm,
+                recipients: channel.recipients.clone(),
+                is_bot: channel.is_bot,
+                messages_file: channel.messages_file.clone(),
+            });
+            return;
+        }
+
+        let probe = channel;
+        let guild_id = channel.guild_id.clone();
+        let channel = Channel {
+            name: present(channel.name.clone())
+                .or(present(channel.folder_name.clone()))
+                .unwrap_or_else(|| format!("c{}", channel.id)),
+            id: channel.id.clone(),
+            message_count: channel_message_count,
+            first_message: counts.first_message,
+            last_message: counts.last_message,
+            merged_ids: Vec::new(),
+            message_files: vec![channel.messages_file.clone()],
+        };
+
+        let Some(guild_id) = guild_id else {
+            // Never merge channels of unidentifiable servers into one guild
+            warnings.push(Warning::UnknownGuild {
+                channel_id: format!("c{}", channel.id),
+            });
+            self.conversations.push(Conversation::Guild {
+                id: None,
+                name: UNKNOWN_GUILD_NAME.to_string(),
+                message_count: channel_message_count,
+                excluded_system,
+                empty_messages,
+                channels: vec![channel],
+                departed: false,
+            });
+            return;
+        };
+
+        let guild_name = &self
+            .guild_memo
+            .resolve(&guild_id, probe, resolver, warnings)
+            .name;
+        let guild = self
+            .guilds
+            .entry(guild_id.clone())
+            .or_insert_with(|| Conversation::Guild {
+                departed: options
+                    .indexed_guilds
+                    .as_ref()
+                    .is_some_and(|indexed| !indexed.contains(&guild_id)),
+                id: Some(guild_id),
+                name: guild_name.clone(),
+                message_count: 0,
+                excluded_system: 0,
+                empty_messages: 0,
+                channels: Vec::new(),
+            });
+
+        if let Conversation::Guild {
+            message_count,
+            excluded_system: guild_excluded_system,
+            empty_messages: guild_empty_messages,
+            channels,
+            ..
+        } = guild
+        {
+            *message_count += channel_message_count;
+            *guild_excluded_system += excluded_system;
+            *guild_empty_messages += empty_messages;
+            retain_channel(channels, channel, options.channel_detail);
+        }
+    }
+
+    /// Every conversation: those complete on their own, then the guilds.
+    fn finish(self, options: &ProcessOptions, warnings: &mut Vec<Warning>) -> Vec<Conversation> {
+        let mut conversations = self.conversations;
+        if self.legacy_names > 0 {
+            warnings.push(Warning::LegacyUsernames {
+                count: self.legacy_names,
+            });
+        }
+
+        // Combine guilds into conversations
+        let mut guilds: Vec<_> = self.guilds.into_values().collect();
+        if options.merge_same_name_channels {
+            for guild in &mut guilds {
+                if let Conversation::Guild { channels, .. } = guild {
+                    merge_same_name_channels(channels);
                 }
             }
         }
+        guilds.iter_mut().for_each(Conversation::sort_channels);
+        conversations.extend(guilds);
+
+        conversations
+    }
+}
+
+/// Add a channel to its guild's list, keeping no more than `detail` allows.
+fn retain_channel(channels: &mut Vec<Channel>, channel: Channel, detail: ChannelDetail) {
+    match detail {
+        ChannelDetail::All => channels.push(channel),
+        ChannelDetail::None => {}
+        ChannelDetail::Top(limit) => {
+            channels.push(channel);
+            if channels.len() > limit {
+                let smallest = channels
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, channel)| channel.message_count)
+                    .map(|(index, _)| index);
+                if let Some(index) = smallest {
+                    channels.swap_remove(index);
+                }
+            }
+        }
+    }
+}
+
+/// A name for an unnamed DM from the IDs of the other participants: a 1:1
+/// DM is named after its counterpart, shortened as in "DM with user
+/// 2104…8812". `None` when nobody else is listed, as in DMs with a deleted
+/// account, which leaves the channel ID.
+fn recipients_name(kind: ConversationType, others: &[&str]) -> Option<String> {
+    match (kind, others) {
+        (_, []) => None,
+        (ConversationType::Dm, [other]) => Some(format!("DM with user {}", short_id(other))),
+        _ => Some(format!("Conversation with {}", others.join(", "))),
+    }
+}
+
+/// Snowflakes run to 19 digits; the first and last four tell them apart
+/// well enough to look one up.
+fn short_id(id: &str) -> String {
+    match id.chars().count() > 9 {
+        true => {
+            let chars: Vec<char> = id.chars().collect();
+            let (head, tail) = (&chars[..4], &chars[chars.len() - 4..]);
+            format!(
+                "{}…{}",
+                head.iter().collect::<String>(),
+                tail.iter().collect::<String>()
+            )
+        }
+        false => id.to_string(),
+    }
+}
+
+/// Treat empty and whitespace-only names as missing.
+fn present(name: Option<String>) -> Option<String> {
+    name.filter(|name| !name.trim().is_empty())
+}
+
+fn breadth_by_year(records: &[ChannelRecord]) -> Vec<YearBreadth> {
+    let mut breadth = BreadthTally::default();
+    for record in records {
+        let channel = &record.channel;
+        match channel.kind {
+            ConversationType::Guild => breadth.add_guild_channel(
+                channel.guild_id.as_deref(),
+                &channel.id,
+                &record.counts.years,
+            ),
+            _ => breadth.add_dm(&channel.id, &record.counts.years),
+        }
+    }
+    breadth.finish()
+}
+
+/// Merge channels of one guild whose names only differ in case, whitespace or
+/// a leading `#`, keeping the ID of the channel with the most messages.
+fn merge_same_name_channels(channels: &mut Vec<Channel>) {
+    fn normalize(name: &str) -> String {
+        name.trim().trim_start_matches('#').to_lowercase()
     }
 
-    progress.finish_and_clear();
+    channels.sort_by_key(|channel| Reverse(channel.message_count));
+    let mut merged: Vec<Channel> = Vec::with_capacity(channels.len());
+    let mut index_by_name: HashMap<String, usize> = HashMap::new();
+
+    for channel in channels.drain(..) {
+        let Some(&index) = index_by_name.get(&normalize(&channel.name)) else {
+            index_by_name.insert(normalize(&channel.name), merged.len());
+            merged.push(channel);
+            continue;
+        };
 
-    // Combine guilds into conversations
-    conversations.extend(guilds.into_values());
+        let target = &mut merged[index];
+        if target.merged_ids.is_empty() {
+            target.merged_ids.push(target.id.clone());
+        }
+        target.merged_ids.push(channel.id);
+        target.message_count += channel.message_count;
+        target.first_message = match (target.first_message, channel.first_message) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        target.last_message = target.last_message.max(channel.last_message);
+        target.message_files.extend(channel.message_files);
+    }
 
-    Ok(conversations)
+    *channels = merged;
 }
 
-fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T, MyError> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
-    let data = serde_json::from_reader(reader)?;
-    Ok(data)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::analyze;
+    use crate::render::OutputFormat;
+    use crate::resolve::MappingResolver;
+    use crate::testing::{channel, rendered, view, Package};
+    use crate::UNKNOWN_GUILD_NAME;
+    use serde_json::json;
+
+    #[test]
+    fn channels_of_unidentifiable_servers_stay_apart() {
+        let package = Package::new();
+        package
+            .channel(
+                "1",
+                json!({"id": "1", "type": 0, "name": "general", "guild": {"name": "Lost"}}),
+                3,
+            )
+            .channel(
+                "2",
+                json!({"id": "2", "type": 0, "name": "random", "guild": {"name": "Lost"}}),
+                2,
+            );
+        let report = package.analyze();
+
+        let mut unknown: Vec<(&str, usize)> = report
+            .conversations
+            .iter()
+            .filter(|conversation| conversation.is_unknown_guild())
+            .map(|conversation| {
+                assert_eq!(conversation.name(), UNKNOWN_GUILD_NAME);
+                (conversation.id(), conversation.message_count())
+            })
+            .collect();
+        unknown.sort_unstable();
+        assert_eq!(unknown, [("1", 3), ("2", 2)]);
+        let warned = report
+            .warnings
+            .iter()
+            .filter(|warning| matches!(warning, Warning::UnknownGuild { .. }))
+            .count();
+        assert_eq!(warned, 2);
+    }
+
+    #[test]
+    fn same_name_channels_merge_into_the_largest() {
+        let mut channels = vec![
+            channel("1", "general", 2),
+            channel("2", "#General ", 5),
+            channel("3", "random", 1),
+        ];
+        merge_same_name_channels(&mut channels);
+
+        assert_eq!(channels.len(), 2);
+        assert_eq!(channels[0].id, "2");
+        assert_eq!(channels[0].message_count, 7);
+        assert_eq!(channels[0].merged_ids, ["2", "1"]);
+        assert!(channels[1].merged_ids.is_empty());
+    }
+
+    #[test]
+    fn same_name_channels_only_merge_within_their_guild() {
+        let package = Package::new();
+        for (id, guild) in [("1", "10"), ("2", "10"), ("3", "20")] {
+            package.channel(
+                id,
+                json!({"id": id, "type": 0, "name": "general", "guild": {"id": guild, "name": "S"}}),
+                1,
+            );
+        }
+        let mut options = package.options();
+        options.process.merge_same_name_channels = true;
+        let report = analyze(&options).unwrap();
+
+        let mut channels: Vec<(&str, usize)> = report
+            .conversations
+            .iter()
+            .map(|guild| match guild {
+                Conversation::Guild { channels, .. } => {
+                    assert_eq!(channels.len(), 1);
+                    (guild.id(), channels[0].message_count)
+                }
+                Conversation::DmOrGc { .. } => unreachable!("only guild channels"),
+            })
+            .collect();
+        channels.sort_unstable();
+        assert_eq!(channels, [("10", 2), ("20", 1)]);
+    }
+
+    fn record(
+        id: &str,
+        kind: ConversationType,
+        guild_id: Option<&str>,
+        counted: usize,
+    ) -> ChannelRecord {
+        ChannelRecord {
+            channel: ChannelProbe {
+                id: id.to_string(),
+                kind,
+                guild_id: guild_id.map(str::to_string),
+                guild_name: None,
+                name: Some(format!("channel-{}", id)),
+                folder_name: None,
+                recipients: Vec::new(),
+                is_bot: None,
+                messages_file: PathBuf::new(),
+                messages_size: 0,
+            },
+            counts: MessageCounts {
+                counted,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn reduced(
+        records: &[ChannelRecord],
+        options: &ProcessOptions,
+    ) -> (Vec<Conversation>, Vec<Warning>) {
+        let mut resolver = MappingResolver::new(
+            Some(HashMap::from([("4".to_string(), "Alice".to_string())])),
+            Some(HashMap::from([("10".to_string(), "Server".to_string())])),
+        );
+        let mut warnings = Vec::new();
+        let mut conversations = reduce(records, &mut resolver, options, &mut warnings);
+        conversations.sort_by(|a, b| a.id().cmp(b.id()));
+        (conversations, warnings)
+    }
+
+    #[test]
+    fn reduce_merges_the_channels_of_each_guild() {
+        let records = [
+            record("1", ConversationType::Guild, Some("10"), 5),
+            record("2", ConversationType::Guild, Some("20"), 1),
+            record("3", ConversationType::Guild, Some("10"), 7),
+        ];
+        let (conversations, warnings) = reduced(&records, &ProcessOptions::default());
+        assert!(warnings.is_empty());
+
+        let guilds: Vec<(&str, &str, usize, Vec<&str>)> = conversations
+            .iter()
+            .map(|guild| match guild {
+                Conversation::Guild { channels, .. } => (
+                    guild.id(),
+                    guild.name(),
+                    guild.message_count(),
+                    channels.iter().map(|channel| channel.id.as_str()).collect(),
+                ),
+                Conversation::DmOrGc { .. } => unreachable!("only guild channels"),
+            })
+            .collect();
+        assert_eq!(
+            guilds,
+            [
+                ("10", "Server", 12, vec!["3", "1"]),
+                ("20", "Guild 20", 1, vec!["2"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn guild_totals_are_the_same_at_any_channel_detail() {
+        let package = Package::new();
+        for (id, guild, messages) in [
+            ("1", "10", 5),
+            ("2", "10", 3),
+            ("3", "10", 8),
+            ("4", "20", 2),
+        ] {
+            package.channel(
+                id,
+                json!({"id": id, "type": 0, "name": id, "guild": {"id": guild, "name": "S"}}),
+                messages,
+            );
+        }
+        let analyzed = |detail: ChannelDetail| {
+            let mut options = package.options();
+            options.process.channel_detail = detail;
+            let mut guilds: Vec<(String, usize, Vec<String>)> = analyze(&options)
+                .unwrap()
+                .conversations
+                .iter()
+                .map(|guild| match guild {
+                    Conversation::Guild { channels, .. } => (
+                        guild.id().to_string(),
+                        guild.message_count(),
+                        channels.iter().map(|channel| channel.id.clone()).collect(),
+                    ),
+                    Conversation::DmOrGc { .. } => unreachable!("only guild channels"),
+                })
+                .collect();
+            guilds.sort_unstable();
+            guilds
+        };
+
+        let all = analyzed(ChannelDetail::All);
+        assert_eq!(
+            all.iter()
+                .map(|(id, total, channels)| (id.as_str(), *total, channels.len()))
+                .collect::<Vec<_>>(),
+            [("10", 16, 3), ("20", 2, 1)]
+        );
+        for (detail, kept) in [
+            (ChannelDetail::None, vec![vec![], vec![]]),
+            (ChannelDetail::Top(1), vec![vec!["3"], vec!["4"]]),
+        ] {
+            let guilds = analyzed(detail);
+            let totals = |guilds: &[(String, usize, Vec<String>)]| {
+                guilds
+                    .iter()
+                    .map(|(id, total, _)| (id.clone(), *total))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(totals(&guilds), totals(&all), "{:?}", detail);
+            let channels: Vec<&Vec<String>> =
+                guilds.iter().map(|(_, _, channels)| channels).collect();
+            assert_eq!(channels, kept.iter().collect::<Vec<_>>(), "{:?}", detail);
+        }
+    }
+
+    #[test]
+    fn reduce_keeps_channels_of_unknown_guilds_apart() {
+        let records = [
+            record("1", ConversationType::Guild, None, 2),
+            record("2", ConversationType::Guild, None, 3),
+        ];
+        let (conversations, warnings) = reduced(&records, &ProcessOptions::default());
+
+        assert_eq!(conversations.len(), 2);
+        for (conversation, (id, count)) in conversations.iter().zip([("1", 2), ("2", 3)]) {
+            assert!(conversation.is_unknown_guild());
+            assert_eq!(conversation.name(), UNKNOWN_GUILD_NAME);
+            assert_eq!(
+                (conversation.id(), conversation.message_count()),
+                (id, count)
+            );
+        }
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings
+            .iter()
+            .all(|warning| matches!(warning, Warning::UnknownGuild { .. })));
+    }
+
+    #[test]
+    fn reduce_passes_dms_through() {
+        let mut dm = record("4", ConversationType::Dm, None, 9);
+        dm.channel.recipients = vec!["100".to_string(), "200".to_string()];
+        dm.channel.is_bot = Some(false);
+        let records = [dm, record("5", ConversationType::GroupDm, None, 1)];
+        let (conversations, warnings) = reduced(&records, &ProcessOptions::default());
+        assert!(warnings.is_empty());
+
+        match &conversations[..] {
+            [Conversation::DmOrGc {
+                id,
+                name,
+                message_count: 9,
+                group: false,
+                recipients,
+                is_bot: Some(false),
+                ..
+            }, Conversation::DmOrGc {
+                message_count: 1,
+                group: true,
+                ..
+            }] => {
+                assert_eq!((id.as_str(), name.as_str()), ("4", "Alice"));
+                assert_eq!(recipients, &["100", "200"]);
+            }
+            other => panic!("unexpected conversations: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unnamed_dms_are_named_after_the_other_participants() {
+        let dm = |id: &str, kind, recipients: &[&str]| {
+            let mut record = record(id, kind, None, 1);
+            record.channel.name = None;
+            record.channel.recipients = recipients.iter().map(|id| id.to_string()).collect();
+            record
+        };
+        let records = [
+            dm("11", ConversationType::Dm, &["100", "210412345678908812"]),
+            dm("12", ConversationType::Dm, &["100", "123456789"]),
+            // A DM with a deleted account lists only the owner
+            dm("13", ConversationType::Dm, &["100"]),
+            dm("14", ConversationType::GroupDm, &["200", "100", "300"]),
+            dm("15", ConversationType::Dm, &[]),
+        ];
+        let options = ProcessOptions {
+            account_id: Some("100".to_string()),
+            ..Default::default()
+        };
+        let (conversations, _) = reduced(&records, &options);
+        let names: Vec<&str> = conversations.iter().map(Conversation::name).collect();
+        assert_eq!(
+            names,
+            [
+                "DM with user 2104…8812",
+                "DM with user 123456789",
+                "Conversation c13",
+                "Conversation with 200, 300",
+                "Conversation c15",
+            ]
+        );
+    }
+
+    #[test]
+    fn the_owner_is_known_from_the_account_data() {
+        let package = Package::new();
+        package
+            .file("account/user.json", r#"{"id": "100", "username": "me"}"#)
+            .channel(
+                "1",
+                json!({"id": "1", "type": 1, "recipients": ["100", "2104000000008812"]}),
+                1,
+            );
+
+        let report = package.analyze();
+        assert_eq!(report.conversations[0].name(), "DM with user 2104…8812");
+    }
+
+    #[test]
+    fn recipients_are_read_for_matching_participants() {
+        let package = Package::new();
+        package
+            .channel(
+                "1",
+                json!({"id": "1", "type": 1, "recipients": ["100", "200"]}),
+                1,
+            )
+            .channel(
+                "2",
+                json!({"id": "2", "type": 3, "recipients": ["100", "200", "300"]}),
+                1,
+            )
+            .channel(
+                "3",
+                json!({"id": "3", "type": 0, "name": "general", "guild": {"id": "10", "name": "S"}}),
+                1,
+            );
+        let report = package.analyze();
+
+        let matching = |user_id: &str| -> Vec<&str> {
+            let mut ids: Vec<&str> = report
+                .conversations
+                .iter()
+                .filter(|conversation| conversation.has_participant(user_id))
+                .map(Conversation::id)
+                .collect();
+            ids.sort_unstable();
+            ids
+        };
+        assert_eq!(matching("200"), ["1", "2"]);
+        assert_eq!(matching("300"), ["2"]);
+        // Guilds have no member lists, whoever wrote in them
+        assert!(matching("10").is_empty());
+        assert!(matching("999").is_empty());
+    }
+
+    #[test]
+    fn reduce_marks_guilds_missing_from_the_index_departed() {
+        let records = [
+            record("1", ConversationType::Guild, Some("10"), 1),
+            record("2", ConversationType::Guild, Some("20"), 1),
+        ];
+        let options = ProcessOptions {
+            indexed_guilds: Some(HashSet::from(["10".to_string()])),
+            ..Default::default()
+        };
+        let (conversations, _) = reduced(&records, &options);
+        let departed: Vec<bool> = conversations
+            .iter()
+            .map(Conversation::is_departed)
+            .collect();
+        assert_eq!(departed, [false, true]);
+    }
+
+    /// Resolves every guild as "Server", counting the lookups.
+    #[derive(Default)]
+    struct CountingResolver {
+        guild_lookups: HashMap<String, usize>,
+    }
+
+    impl NameResolver for CountingResolver {
+        fn guild_name(&mut self, guild_id: &str) -> Option<String> {
+            *self.guild_lookups.entry(guild_id.to_string()).or_default() += 1;
+            Some("Server".to_string())
+        }
+
+        fn channel_name(&mut self, _channel_id: &str) -> Option<String> {
+            None
+        }
+    }
+
+    #[test]
+    fn each_guild_is_resolved_and_warned_about_once() {
+        let records: Vec<ChannelRecord> = (1..=50)
+            .map(|id| {
+                let mut record = record(&id.to_string(), ConversationType::Guild, Some("10"), 1);
+                record.channel.guild_name = Some("Old name".to_string());
+                record
+            })
+            .chain([record("51", ConversationType::Guild, Some("20"), 1)])
+            .collect();
+        let mut resolver = CountingResolver::default();
+        let mut warnings = Vec::new();
+        let conversations = reduce(
+            &records,
+            &mut resolver,
+            &ProcessOptions::default(),
+            &mut warnings,
+        );
+
+        assert_eq!(conversations.len(), 2);
+        assert_eq!(
+            resolver.guild_lookups,
+            HashMap::from([("10".to_string(), 1), ("20".to_string(), 1)])
+        );
+        assert!(matches!(
+            warnings.as_slice(),
+            [Warning::GuildNameConflict { guild_id, channel_id }]
+                if guild_id == "10" && channel_id == "c1"
+        ));
+    }
+
+    #[test]
+    fn index_keys_match_folders_with_or_without_the_prefix() {
+        for (key, folder) in [("c1", "c1"), ("c1", "1"), ("1", "c1"), ("1", "1")] {
+            let package = Package::new();
+            package
+                .file(
+                    "messages/index.json",
+                    &json!({ key: "Alice", "c2": "Bob" }).to_string(),
+                )
+                .file(
+                    &format!("messages/{}/channel.json", folder),
+                    r#"{"id": "1"}"#,
+                )
+                .file(&format!("messages/{}/messages.json", folder), "[]");
+
+            let report = package.analyze();
+            let names: Vec<(&str, &str)> = report
+                .conversations
+                .iter()
+                .map(|conversation| (conversation.id(), conversation.name()))
+                .collect();
+            assert_eq!(names, [("1", "Alice")], "key {}, folder {}", key, folder);
+        }
+    }
+
+    #[test]
+    fn colliding_index_keys_follow_the_folder_and_warn_once() {
+        for (folder, name) in [("c1", "Prefixed"), ("1", "Bare")] {
+            let package = Package::new();
+            package
+                .file(
+                    "messages/index.json",
+                    &json!({"1": "Bare", "c1": "Prefixed", "2": "Same", "c2": "Same"}).to_string(),
+                )
+                .file(&format!("messages/{}/channel.json", folder), "{}")
+                .file(&format!("messages/{}/messages.json", folder), "[]");
+
+            let report = package.analyze();
+            assert_eq!(report.conversations[0].name(), name, "folder {}", folder);
+            let collisions: Vec<&Warning> = report
+                .warnings
+                .iter()
+                .filter(|warning| matches!(warning, Warning::ChannelKeyCollision { .. }))
+                .collect();
+            assert_eq!(
+                collisions,
+                [&Warning::ChannelKeyCollision {
+                    channel_id: "1".to_string(),
+                    prefixed_id: "c1".to_string(),
+                    prefixed_used: folder == "c1",
+                }],
+                "folder {}",
+                folder
+            );
+        }
+    }
+
+    #[test]
+    fn colliding_index_keys_without_a_folder_keep_the_bare_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let mapping = HashMap::from([
+            ("1".to_string(), "Bare".to_string()),
+            ("c1".to_string(), "Prefixed".to_string()),
+            ("c3".to_string(), "Only prefixed".to_string()),
+        ]);
+        let mut warnings = Vec::new();
+        let normalized = normalize_channel_keys(mapping, dir.path(), &mut warnings);
+
+        assert_eq!(
+            normalized,
+            HashMap::from([
+                ("1".to_string(), "Bare".to_string()),
+                ("3".to_string(), "Only prefixed".to_string()),
+            ])
+        );
+        assert_eq!(warnings.len(), 1);
+    }
+
+    /// An archive holding `entries`, stored uncompressed.
+    #[cfg(feature = "zip")]
+    fn archive(dir: &Path, entries: &[(&str, usize)]) -> PathBuf {
+        use std::io::Write;
+        use zip::write::SimpleFileOptions;
+
+        let path = dir.join("package.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&path).unwrap());
+        for &(name, size) in entries {
+            writer
+                .start_file(name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(&vec![b'x'; size]).unwrap();
+        }
+        writer.finish().unwrap();
+        path
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn extracted_size_sums_the_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = archive(
+            dir.path(),
+            &[
+                ("messages/index.json", 2),
+                ("messages/c1/messages.json", 1000),
+                ("README.txt", 0),
+            ],
+        );
+        let archive = ZipArchive::new(File::open(path).unwrap()).unwrap();
+        assert_eq!(extracted_size(&archive), 1002);
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn a_full_temp_location_is_reported_with_the_needed_space() {
+        let dir = tempfile::tempdir().unwrap();
+        let error = check_temp_location(dir.path(), u64::MAX).unwrap_err();
+        assert!(matches!(error, MyError::TempLocation(ref message)
+            if message.contains("free but the archive needs") && message.ends_with("use --temp-dir")));
+        assert!(check_temp_location(dir.path(), 0).is_ok());
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn a_missing_temp_location_is_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = archive(dir.path(), &[("messages/index.json", 2)]);
+        let missing = dir.path().join("missing");
+        let error = prepare_data_root(&path, Some(&missing)).err().unwrap();
+        assert!(matches!(error, MyError::TempLocation(ref message)
+            if message.starts_with("cannot create a directory in temp location")));
+
+        let extracted = prepare_data_root(&path, Some(dir.path())).unwrap();
+        assert!(extracted.path.starts_with(dir.path()));
+        assert!(extracted.path.join("messages/index.json").is_file());
+    }
+
+    #[test]
+    fn blank_mapped_names_fall_through_to_the_fallbacks() {
+        let package = Package::new();
+        package
+            .file(
+                "messages/index.json",
+                &json!({"1": "", "2": "   ", "3": "\t"}).to_string(),
+            )
+            .file("servers/index.json", &json!({"10": " "}).to_string())
+            .channel("1", json!({"id": "1", "type": 1, "recipients": ["100"]}), 1)
+            .channel("2", json!({"id": "2", "type": 1}), 1)
+            .channel(
+                "3",
+                json!({"id": "3", "name": "", "guild": {"id": "10", "name": "Server"}}),
+                1,
+            );
+
+        let report = package.analyze();
+        let mut names: Vec<(&str, &str)> = report
+            .conversations
+            .iter()
+            .map(|conversation| (conversation.id(), conversation.name()))
+            .collect();
+        names.sort_unstable();
+        assert_eq!(
+            names,
+            [
+                ("1", "DM with user 100"),
+                ("10", "Server"),
+                ("2", "Conversation c2")
+            ]
+        );
+        let Conversation::Guild { channels, .. } = &report.conversations[2] else {
+            panic!("the guild comes last");
+        };
+        assert_eq!(channels[0].name, "c3");
+
+        let json: serde_json::Value =
+            serde_json::from_str(&rendered(OutputFormat::Json, &view(&report))).unwrap();
+        let mut json_names: Vec<&str> = json["conversations"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|conversation| conversation["name"].as_str().unwrap())
+            .collect();
+        json_names.sort_unstable();
+        assert_eq!(
+            json_names,
+            ["Conversation c2", "DM with user 100", "Server"]
+        );
+    }
 }
@@ -0,0 +1,132 @@
+use crate::errors::MyError;
+use crate::humanize::fmt_bytes;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+#[cfg(feature = "zip")]
+use std::fs::File;
+
+#[cfg(feature = "zip")]
+use zip::read::ZipArchive;
+
+/// Top-level folders listed on their own; everything else is summed as "other".
+const TOP_LEVEL_FOLDERS: &[&str] = &["messages", "servers", "account", "activity"];
+
+/// Number of individual files listed.
+const LARGEST_FILES: usize = 10;
+
+/// Where the bytes of a package live.
+#[derive(Debug, Default)]
+pub struct Footprint {
+    pub total: u64,
+    /// Bytes per top-level folder, with "other" for everything else
+    pub folders: BTreeMap<String, u64>,
+    /// The largest files by path within the package
+    pub largest: Vec<(String, u64)>,
+    /// Entries whose size could not be read, e.g. for lack of permission
+    pub skipped: usize,
+}
+
+impl Footprint {
+    fn add(&mut self, path: &str, size: u64) {
+        let top = path.split('/').next().unwrap_or_default();
+        let folder = match TOP_LEVEL_FOLDERS
+            .iter()
+            .find(|folder| top.eq_ignore_ascii_case(folder))
+        {
+            Some(folder) => folder,
+            None => "other",
+        };
+        *self.folders.entry(folder.to_string()).or_default() += size;
+        self.total += size;
+        self.largest.push((path.to_string(), size));
+    }
+
+    fn finish(mut self) -> Self {
+        self.largest
+            .sort_by_key(|(path, size)| (Reverse(*size), path.clone()));
+        self.largest.truncate(LARGEST_FILES);
+        self
+    }
+}
+
+/// Measure a package without extracting it: ZIP archives from their central
+/// directory, folders from a metadata walk that skips unreadable entries.
+pub fn measure(input_path: &Path) -> Result<Footprint, MyError> {
+    #[cfg(feature = "zip")]
+    if input_path.is_file() {
+        return measure_archive(input_path);
+    }
+    if !input_path.is_dir() {
+        return Err(MyError::InvalidInputPath(input_path.display().to_string()));
+    }
+
+    let mut footprint = Footprint::default();
+    walk(input_path, input_path, &mut footprint);
+    Ok(footprint.finish())
+}
+
+#[cfg(feature = "zip")]
+fn measure_archive(path: &Path) -> Result<Footprint, MyError> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let mut footprint = Footprint::default();
+    for index in 0..archive.len() {
+        let entry = archive.by_index_raw(index)?;
+        if entry.is_file() {
+            footprint.add(entry.name().trim_start_matches('/'), entry.size());
+        }
+    }
+    Ok(footprint.finish())
+}
+
+fn walk(root: &Path, folder: &Path, footprint: &mut Footprint) {
+    let Ok(entries) = fs::read_dir(folder) else {
+        footprint.skipped += 1;
+        return;
+    };
+    for entry in entries {
+        let Ok(entry) = entry else {
+            footprint.skipped += 1;
+            continue;
+        };
+        let path = entry.path();
+        match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => walk(root, &path, footprint),
+            Ok(metadata) => {
+                let relative = path.strip_prefix(root).unwrap_or(&path);
+                let relative = relative.to_string_lossy().replace('\\', "/");
+                footprint.add(&relative, metadata.len());
+            }
+            Err(_) => footprint.skipped += 1,
+        }
+    }
+}
+
+pub fn write_footprint(footprint: &Footprint, out: &mut dyn Write) -> Result<(), MyError> {
+    writeln!(out, "Package size: {}", fmt_bytes(footprint.total))?;
+    let mut folders: Vec<(&String, &u64)> = footprint.folders.iter().collect();
+    folders.sort_by_key(|(_, size)| Reverse(**size));
+    for (folder, size) in folders {
+        let label = match folder.as_str() {
+            "other" => folder.clone(),
+            folder => format!("{}/", folder),
+        };
+        writeln!(out, "    {:<10} {:>10}", label, fmt_bytes(*size))?;
+    }
+
+    writeln!(out, "Largest files:")?;
+    for (path, size) in &footprint.largest {
+        writeln!(out, "    {:>10}  {}", fmt_bytes(*size), path)?;
+    }
+    if footprint.skipped > 0 {
+        writeln!(
+            out,
+            "    ({} entries could not be read and were skipped)",
+            footprint.skipped
+        )?;
+    }
+    Ok(())
+}
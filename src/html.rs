@@ -0,0 +1,203 @@
+//! A single self-contained HTML page: styles and the table sorting script
+//! are inlined, and nothing is fetched, so the report works offline and the
+//! data never leaves the machine it is opened on.
+
+use crate::buckets::CountStyle;
+use crate::errors::MyError;
+use crate::numbers::percent_value;
+use crate::render::ResultsView;
+use crate::{Channel, Conversation};
+use std::io::Write;
+
+/// Conversations drawn in the bar chart.
+const CHART_CONVERSATIONS: usize = 10;
+
+const STYLE: &str = "
+body { font-family: system-ui, sans-serif; margin: 2rem auto; max-width: 60rem; color: #222; }
+h1 { font-size: 1.5rem; }
+.totals { display: flex; gap: 2rem; margin-bottom: 2rem; }
+.totals div { font-size: 0.9rem; color: #555; }
+.totals strong { display: block; font-size: 1.6rem; color: #222; }
+.chart div { display: flex; align-items: center; margin: 0.2rem 0; font-size: 0.9rem; }
+.chart span { width: 14rem; overflow: hidden; text-overflow: ellipsis; white-space: nowrap; }
+.chart .bar { height: 1rem; background: #5865f2; margin-right: 0.5rem; }
+table { border-collapse: collapse; width: 100%; }
+th, td { text-align: left; padding: 0.3rem 0.6rem; border-bottom: 1px solid #ddd; vertical-align: top; }
+th { cursor: pointer; user-select: none; }
+td.count { text-align: right; white-space: nowrap; }
+details ul { margin: 0.3rem 0; padding-left: 1.2rem; font-size: 0.9rem; }
+";
+
+/// Sorts the table by the clicked column; a second click reverses it.
+const SCRIPT: &str = "
+document.querySelectorAll('th').forEach((th, column) => {
+  th.addEventListener('click', () => {
+    const body = th.closest('table').tBodies[0];
+    const descending = th.dataset.order !== 'desc';
+    th.dataset.order = descending ? 'desc' : 'asc';
+    const key = row => row.cells[column].dataset.sort ?? row.cells[column].textContent.toLowerCase();
+    const rows = Array.from(body.rows).sort((a, b) => {
+      const [x, y] = [key(a), key(b)];
+      const order = isNaN(x) || isNaN(y) ? x.localeCompare(y) : x - y;
+      return descending ? -order : order;
+    });
+    rows.forEach(row => body.appendChild(row));
+  });
+});
+";
+
+pub fn render_html(view: &ResultsView, out: &mut dyn Write) -> Result<(), MyError> {
+    let report = view.report;
+    let total: usize = report
+        .conversations
+        .iter()
+        .map(Conversation::message_count)
+        .sum();
+    let dms = report
+        .conversations
+        .iter()
+        .filter(|conversation| matches!(conversation, Conversation::DmOrGc { .. }))
+        .count();
+    let guilds = report
+        .conversations
+        .iter()
+        .filter(|conversation| {
+            matches!(conversation, Conversation::Guild { .. }) && !conversation.is_unknown_guild()
+        })
+        .count();
+
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html lang=\"en\">")?;
+    writeln!(out, "<head>")?;
+    writeln!(out, "<meta charset=\"utf-8\">")?;
+    writeln!(out, "<title>Discord message counts</title>")?;
+    writeln!(out, "<style>{}</style>", STYLE)?;
+    writeln!(out, "</head>")?;
+    writeln!(out, "<body>")?;
+    writeln!(out, "<h1>Discord message counts</h1>")?;
+    if let Some(ref partial) = report.partial {
+        writeln!(out, "<p>{}</p>", escape(&partial.to_string()))?;
+    }
+
+    writeln!(out, "<section class=\"totals\">")?;
+    writeln!(
+        out,
+        "<div><strong>{}</strong>messages</div>",
+        escape(&view.counts.grouped(total as u64))
+    )?;
+    writeln!(out, "<div><strong>{}</strong>DMs and group DMs</div>", dms)?;
+    writeln!(out, "<div><strong>{}</strong>servers</div>", guilds)?;
+    writeln!(out, "</section>")?;
+
+    // Bar lengths would reveal the exact ratios that bucketing hides
+    if view.counts == CountStyle::Exact {
+        write_chart(view, out)?;
+    }
+    write_table(view, out)?;
+
+    writeln!(out, "<script>{}</script>", SCRIPT)?;
+    writeln!(out, "</body>")?;
+    writeln!(out, "</html>")?;
+    Ok(())
+}
+
+fn write_chart(view: &ResultsView, out: &mut dyn Write) -> Result<(), MyError> {
+    let top = &view.conversations[..view.conversations.len().min(CHART_CONVERSATIONS)];
+    let Some(largest) = top.iter().map(|c| c.message_count()).max() else {
+        return Ok(());
+    };
+
+    writeln!(out, "<section class=\"chart\">")?;
+    for conversation in top {
+        writeln!(
+            out,
+            "<div><span title=\"{name}\">{name}</span><div class=\"bar\" style=\"width: {width}%\"></div>{count}</div>",
+            name = escape(&display_name(conversation)),
+            width = percent_value(conversation.message_count() as u64, largest as u64, 1),
+            count = view.counts.grouped(conversation.message_count() as u64)
+        )?;
+    }
+    writeln!(out, "</section>")?;
+    Ok(())
+}
+
+fn write_table(view: &ResultsView, out: &mut dyn Write) -> Result<(), MyError> {
+    writeln!(out, "<table>")?;
+    writeln!(
+        out,
+        "<thead><tr><th>Conversation</th><th>Type</th><th>Messages</th></tr></thead>"
+    )?;
+    writeln!(out, "<tbody>")?;
+    let ranks = view.conversations.len();
+    for (rank, &conversation) in view.conversations.iter().enumerate() {
+        let name = display_name(conversation);
+        let cell = match conversation {
+            Conversation::Guild { channels, .. } if !conversation.is_unknown_guild() => {
+                channel_list(&name, channels, view)
+            }
+            _ => escape(&name),
+        };
+        // Sorting by rank keeps the exact counts out of the page when bucketed
+        writeln!(
+            out,
+            "<tr><td data-sort=\"{}\">{}</td><td>{}</td><td class=\"count\" data-sort=\"{}\">{}</td></tr>",
+            escape(&name.to_lowercase()),
+            cell,
+            conversation.effective_kind(view.group_dms_as).label(),
+            ranks - rank,
+            escape(&view.counts.grouped(conversation.message_count() as u64))
+        )?;
+    }
+    writeln!(out, "</tbody>")?;
+    writeln!(out, "</table>")?;
+    Ok(())
+}
+
+/// Channels of unknown servers are named after themselves, as in the text tree.
+fn display_name(conversation: &Conversation) -> String {
+    match conversation {
+        Conversation::Guild { channels, .. } if conversation.is_unknown_guild() => format!(
+            "{} in {}",
+            channels.first().map_or("", |channel| channel.name.as_str()),
+            conversation.name()
+        ),
+        _ => conversation.name().to_string(),
+    }
+}
+
+/// A guild's name that expands to its channels, most messages first.
+fn channel_list(name: &str, channels: &[Channel], view: &ResultsView) -> String {
+    if channels.is_empty() {
+        return escape(name);
+    }
+    let items: String = channels
+        .iter()
+        .map(|channel| {
+            format!(
+                "<li>{} ({})</li>",
+                escape(&channel.name),
+                escape(&view.counts.grouped(channel.message_count as u64))
+            )
+        })
+        .collect();
+    format!(
+        "<details><summary>{}</summary><ul>{}</ul></details>",
+        escape(name),
+        items
+    )
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,240 @@
+//! Durations, sizes, rates and counts as people read them, shared by every
+//! feature that prints one. Each value is rounded half-up in its last shown
+//! unit, so the same input always prints the same text.
+//!
+//! Sizes use decimal units (1 kB = 1000 B, 1 MB = 1000 kB), as file managers
+//! on most platforms and Discord's own download page do. Durations and rates
+//! use abbreviated units ("3h 12m", "412/mo"), which need no plural forms;
+//! counts with a noun go through [`fmt_count`].
+
+use crate::numbers::fmt_ratio;
+use crate::render::format_thousands;
+use std::time::Duration;
+
+const MILLISECOND: u128 = 1;
+const SECOND: u128 = 1000 * MILLISECOND;
+const MINUTE: u128 = 60 * SECOND;
+const HOUR: u128 = 60 * MINUTE;
+const DAY: u128 = 24 * HOUR;
+/// Calendar years vary; 365 days keeps every year the same length.
+const YEAR: u128 = 365 * DAY;
+
+/// Largest unit first.
+const DURATION_UNITS: [(u128, &str); 6] = [
+    (YEAR, "y"),
+    (DAY, "d"),
+    (HOUR, "h"),
+    (MINUTE, "m"),
+    (SECOND, "s"),
+    (MILLISECOND, "ms"),
+];
+
+const SIZE_UNITS: [&str; 7] = ["B", "kB", "MB", "GB", "TB", "PB", "EB"];
+
+/// A duration in at most `precision` units counted from its largest one,
+/// e.g. "3h 12m" for two, the last rounded half-up: "2y" rather than
+/// "1y 364d" one hour short of two years. Units that come out zero are left
+/// out. Zero is "0s" and anything shorter than a millisecond "<1ms".
+pub fn fmt_duration(duration: Duration, precision: usize) -> String {
+    let nanos = duration.as_nanos();
+    if nanos == 0 {
+        return "0s".to_string();
+    }
+    let millis = (nanos + 500_000) / 1_000_000;
+    if millis == 0 {
+        return "<1ms".to_string();
+    }
+
+    let leading = leading_unit(millis);
+    let smallest = (leading + precision.max(1) - 1).min(DURATION_UNITS.len() - 1);
+    let (step, _) = DURATION_UNITS[smallest];
+    let mut rest = (millis + step / 2) / step * step;
+    // Rounding up may carry into a larger unit, e.g. 59m 40s to 1h
+    let leading = leading_unit(rest);
+
+    let mut parts = Vec::new();
+    for &(unit, label) in &DURATION_UNITS[leading..=smallest] {
+        let amount = rest / unit;
+        rest %= unit;
+        if amount > 0 {
+            parts.push(format!("{}{}", amount, label));
+        }
+    }
+    parts.join(" ")
+}
+
+fn leading_unit(millis: u128) -> usize {
+    DURATION_UNITS
+        .iter()
+        .position(|&(unit, _)| millis >= unit)
+        .unwrap_or(DURATION_UNITS.len() - 1)
+}
+
+/// A size in the largest decimal unit that keeps it at or above one, with
+/// one decimal place, e.g. "8.4 GB"; plain bytes below 1 kB, e.g. "512 B".
+/// A value that would round to 1000 moves up a unit: 999,960 B is "1.0 MB".
+pub fn fmt_bytes(bytes: u64) -> String {
+    if bytes < 1000 {
+        return format!("{} B", bytes);
+    }
+    let bytes = u128::from(bytes);
+    let mut unit = 1u128;
+    for label in &SIZE_UNITS[1..] {
+        unit *= 1000;
+        let tenths = (bytes * 20 + unit) / (unit * 2);
+        if tenths < 10_000 || *label == SIZE_UNITS[SIZE_UNITS.len() - 1] {
+            return format!("{}.{} {}", tenths / 10, tenths % 10, label);
+        }
+    }
+    unreachable!("the last unit always returns")
+}
+
+/// An amount per `unit`, e.g. "412/mo" or "2.5/d": one decimal place below
+/// ten, whole numbers with thousands separators above. Negative and
+/// non-finite amounts print as zero.
+pub fn fmt_rate(per_unit: f64, unit: &str) -> String {
+    // Also keeps -0.0 from printing its sign
+    let per_unit = match per_unit.is_finite() && per_unit > 0.0 {
+        true => per_unit,
+        false => 0.0,
+    };
+    let tenths = (per_unit * 10.0).round();
+    let amount = match tenths < 100.0 {
+        true => format!("{:.1}", tenths / 10.0),
+        // Saturates at u64::MAX, far beyond any real rate
+        false => format_thousands(per_unit.round() as u64),
+    };
+    format!("{}/{}", amount, unit)
+}
+
+/// A count rounded to at most three significant digits: 950, 1.2k, 35k,
+/// 1.4M.
+pub fn fmt_compact(n: u64) -> String {
+    match n {
+        0..1_000 => n.to_string(),
+        1_000..9_950 => format!("{}k", fmt_ratio(n, 1_000, 1)),
+        9_950..999_500 => format!("{}k", fmt_ratio(n, 1_000, 0)),
+        999_500..9_950_000 => format!("{}M", fmt_ratio(n, 1_000_000, 1)),
+        _ => format!("{}M", fmt_ratio(n, 1_000_000, 0)),
+    }
+}
+
+/// A count with its noun, singular only for exactly one: "1 session",
+/// "0 sessions", "12,480 sessions". `noun` takes an `s` for the plural.
+pub fn fmt_count(count: u64, noun: &str) -> String {
+    match count {
+        1 => format!("1 {}", noun),
+        _ => format!("{} {}s", format_thousands(count), noun),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn minutes(n: u64) -> Duration {
+        Duration::from_secs(n * 60)
+    }
+
+    #[test]
+    fn durations_from_zero_to_the_largest() {
+        assert_eq!(fmt_duration(Duration::ZERO, 2), "0s");
+        assert_eq!(fmt_duration(Duration::from_micros(499), 2), "<1ms");
+        assert_eq!(fmt_duration(Duration::from_micros(500), 2), "1ms");
+        assert_eq!(fmt_duration(Duration::from_millis(1500), 1), "2s");
+        assert_eq!(fmt_duration(Duration::MAX, 1), "584942417355y");
+        assert_eq!(fmt_duration(Duration::MAX, 2), "584942417355y 26d");
+    }
+
+    #[test]
+    fn durations_round_in_their_last_unit() {
+        assert_eq!(fmt_duration(minutes(192), 2), "3h 12m");
+        assert_eq!(
+            fmt_duration(minutes(192) + Duration::from_secs(30), 2),
+            "3h 13m"
+        );
+        assert_eq!(
+            fmt_duration(minutes(192) + Duration::from_secs(29), 2),
+            "3h 12m"
+        );
+        // Precision counts units from the largest, zero ones included
+        assert_eq!(fmt_duration(minutes(60) + Duration::from_secs(5), 2), "1h");
+        assert_eq!(fmt_duration(minutes(90), 0), "2h");
+        assert_eq!(
+            fmt_duration(minutes(60) + Duration::from_millis(1), 10),
+            "1h 1ms"
+        );
+    }
+
+    #[test]
+    fn rounding_carries_into_the_larger_unit() {
+        assert_eq!(fmt_duration(minutes(59) + Duration::from_secs(40), 1), "1h");
+        let two_years = Duration::from_secs(2 * 365 * 24 * 3600);
+        assert_eq!(fmt_duration(two_years - minutes(60), 2), "2y");
+        assert_eq!(fmt_duration(two_years - minutes(60), 3), "1y 364d 23h");
+    }
+
+    #[test]
+    fn sizes_use_decimal_units() {
+        assert_eq!(fmt_bytes(0), "0 B");
+        assert_eq!(fmt_bytes(999), "999 B");
+        assert_eq!(fmt_bytes(1000), "1.0 kB");
+        assert_eq!(fmt_bytes(1049), "1.0 kB");
+        assert_eq!(fmt_bytes(1050), "1.1 kB");
+        assert_eq!(fmt_bytes(8_400_000_000), "8.4 GB");
+        assert_eq!(fmt_bytes(u64::MAX), "18.4 EB");
+    }
+
+    #[test]
+    fn sizes_that_round_to_a_thousand_move_up_a_unit() {
+        assert_eq!(fmt_bytes(999_949), "999.9 kB");
+        assert_eq!(fmt_bytes(999_950), "1.0 MB");
+        assert_eq!(fmt_bytes(999_950_000_000), "1.0 TB");
+    }
+
+    #[test]
+    fn rates_switch_to_whole_numbers_at_ten() {
+        assert_eq!(fmt_rate(0.0, "mo"), "0.0/mo");
+        assert_eq!(fmt_rate(0.04, "d"), "0.0/d");
+        assert_eq!(fmt_rate(2.5, "d"), "2.5/d");
+        assert_eq!(fmt_rate(9.94, "mo"), "9.9/mo");
+        assert_eq!(fmt_rate(9.96, "mo"), "10/mo");
+        assert_eq!(fmt_rate(412.4, "mo"), "412/mo");
+        assert_eq!(fmt_rate(12_480.0, "y"), "12,480/y");
+        assert_eq!(fmt_rate(1e30, "y"), "18,446,744,073,709,551,615/y");
+    }
+
+    #[test]
+    fn unusable_rates_are_zero() {
+        for rate in [-3.0, -0.0, f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert_eq!(fmt_rate(rate, "mo"), "0.0/mo", "{}", rate);
+        }
+    }
+
+    #[test]
+    fn compact_counts_keep_three_significant_digits() {
+        let cases = [
+            (0, "0"),
+            (999, "999"),
+            (1_000, "1.0k"),
+            (9_949, "9.9k"),
+            (9_950, "10k"),
+            (35_000, "35k"),
+            (999_499, "999k"),
+            (999_500, "1.0M"),
+            (9_949_999, "9.9M"),
+            (9_950_000, "10M"),
+            (u64::MAX, "18446744073710M"),
+        ];
+        for (n, compact) in cases {
+            assert_eq!(fmt_compact(n), compact, "{}", n);
+        }
+    }
+
+    #[test]
+    fn counts_are_singular_only_for_one() {
+        assert_eq!(fmt_count(0, "session"), "0 sessions");
+        assert_eq!(fmt_count(1, "session"), "1 session");
+        assert_eq!(fmt_count(12_480, "session"), "12,480 sessions");
+    }
+}
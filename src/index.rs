@@ -0,0 +1,465 @@
+//! A cheap first pass over the package that reads only each channel's
+//! `channel.json` and the size of its messages file. Folder names are parsed,
+//! the vintage detected and channels classified here and nowhere else, so the
+//! counting pass only has to read the messages of the channels it is given.
+
+use crate::errors::MyError;
+use crate::file_operations::{channel_folders, normalize_channel_id, DataRoot};
+use crate::vintage::{detect, PackageProbe, PackageVintage};
+use crate::warnings::Warning;
+use crate::{ConversationType, GuildId};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Everything known about a channel before its messages are read.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelProbe {
+    /// Channel ID without the folder's `c` prefix
+    pub id: String,
+    pub kind: ConversationType,
+    /// Guild of a guild channel; `None` when the export leaves its ID out
+    pub guild_id: Option<GuildId>,
+    /// Guild name from `channel.json`, which newer exports include
+    pub guild_name: Option<String>,
+    /// Name from `channel.json`
+    pub name: Option<String>,
+    /// Name embedded in the folder name of very old exports
+    pub folder_name: Option<String>,
+    /// Other participants of DMs and group DMs
+    pub recipients: Vec<String>,
+    /// Whether a DM is with a bot: `None` unless the recipient objects carry
+    /// Discord's `bot` flag, which most exports leave out
+    #[serde(default)]
+    pub is_bot: Option<bool>,
+    /// `messages.json` or `messages.csv`, whose extension decides how it is read
+    pub messages_file: PathBuf,
+    /// Size of the messages file in bytes
+    pub messages_size: u64,
+}
+
+/// The channels of a package, in directory listing order.
+#[derive(Debug, Default)]
+pub struct ChannelIndex {
+    pub vintage: PackageVintage,
+    pub channels: Vec<ChannelProbe>,
+    /// Unrecognized folders and unreadable channel info
+    pub warnings: Vec<Warning>,
+}
+
+/// Channel ID, and in very old exports the name, taken from a folder name.
+#[derive(Debug, Clone)]
+pub struct ChannelFolder {
+    pub path: PathBuf,
+    pub id: String,
+    pub name: Option<String>,
+    /// Whether the folder is named `c<ID>` rather than a bare `<ID>`
+    pub prefixed: bool,
+}
+
+/// The parts of a channel's `channel.json` that are used. Every field is
+/// optional since some exports leave them out.
+#[derive(Debug, Default, Deserialize)]
+struct ChannelInfo {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(rename = "type", default)]
+    channel_type: Option<ChannelType>,
+    /// Other participants of DMs and group DMs
+    #[serde(default)]
+    recipients: Vec<Recipient>,
+    /// Present (and not null) only for guild channels
+    #[serde(default)]
+    guild: Option<GuildInfo>,
+}
+
+/// Discord's channel type, numeric in API-shaped exports and named in others.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ChannelType {
+    Code(u64),
+    Name(String),
+}
+
+impl ChannelType {
+    fn is_group_dm(&self) -> bool {
+        match self {
+            Self::Code(code) => *code == 3,
+            Self::Name(name) => name.eq_ignore_ascii_case("GROUP_DM"),
+        }
+    }
+}
+
+/// A recipient is a bare user ID in most exports and a user object in some.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Recipient {
+    Id(String),
+    Number(u64),
+    User {
+        id: String,
+        #[serde(default)]
+        bot: Option<bool>,
+    },
+}
+
+impl Recipient {
+    fn id(&self) -> String {
+        match self {
+            Self::Id(id) | Self::User { id, .. } => id.clone(),
+            Self::Number(id) => id.to_string(),
+        }
+    }
+
+    fn bot(&self) -> Option<bool> {
+        match self {
+            Self::User { bot, .. } => *bot,
+            Self::Id(_) | Self::Number(_) => None,
+        }
+    }
+}
+
+/// A DM is with a bot when any recipient is flagged as one, and with a person
+/// only when every recipient says it isn't; the owner, listed among them in
+/// some exports, is never a bot. Group DMs are never classified.
+fn is_bot_dm(kind: ConversationType, recipients: &[Recipient]) -> Option<bool> {
+    if kind != ConversationType::Dm || recipients.is_empty() {
+        return None;
+    }
+    let flags: Vec<Option<bool>> = recipients.iter().map(Recipient::bot).collect();
+    match flags.contains(&Some(true)) {
+        true => Some(true),
+        false if flags.iter().all(Option::is_some) => Some(false),
+        false => None,
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct GuildInfo {
+    #[serde(default)]
+    id: Option<GuildId>,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+/// Index every channel folder below `messages/`. Folders without a
+/// `channel.json` or messages file are not channels and are left out, the
+/// latter with a warning.
+pub fn build_index(data_root: &DataRoot) -> Result<ChannelIndex, MyError> {
+    let mut index = ChannelIndex::default();
+    let mut folders = Vec::new();
+    for path in channel_folders(data_root)? {
+        match parse_channel_folder(&path) {
+            Some(folder) => folders.push(folder),
+            None => index.warnings.push(Warning::UnrecognizedFolder {
+                folder: path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned(),
+            }),
+        }
+    }
+
+    index.vintage = detect(&PackageProbe::collect(data_root, &folders)?);
+    for folder in folders {
+        if let Some(channel) = probe_channel(folder, index.vintage, &mut index.warnings)? {
+            index.channels.push(channel);
+        }
+    }
+    Ok(index)
+}
+
+/// Accepts the `c<ID>` folders of current exports as well as the bare `<ID>`
+/// and `<ID> - <name>` folders of very old ones.
+pub fn parse_channel_folder(path: &Path) -> Option<ChannelFolder> {
+    let folder_name = path.file_name()?.to_str()?;
+    let (id, name) = match folder_name.split_once(" - ") {
+        Some((id, name)) => (id, Some(name.trim()).filter(|name| !name.is_empty())),
+        None => (folder_name, None),
+    };
+    let prefixed = id.starts_with('c');
+    let id = normalize_channel_id(id);
+    if id.is_empty() || !id.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    Some(ChannelFolder {
+        path: path.to_path_buf(),
+        id: id.to_string(),
+        name: name.map(str::to_string),
+        prefixed,
+    })
+}
+
+fn probe_channel(
+    folder: ChannelFolder,
+    vintage: PackageVintage,
+    warnings: &mut Vec<Warning>,
+) -> Result<Option<ChannelProbe>, MyError> {
+    let channel_info_file = folder.path.join("channel.json");
+    let Some(messages_file) = vintage.messages_file(&folder.path) else {
+        warnings.push(Warning::MissingMessagesFile {
+            channel_id: format!("c{}", folder.id),
+        });
+        return Ok(None);
+    };
+    if !channel_info_file.exists() {
+        return Ok(None);
+    }
+
+    let channel_info = match read_channel_info(&channel_info_file)? {
+        Ok(channel_info) => channel_info,
+        Err(error) => {
+            warnings.push(Warning::UnreadableChannelInfo {
+                channel_id: format!("c{}", folder.id),
+                reason: error.to_string(),
+            });
+            ChannelInfo::default()
+        }
+    };
+    let (kind, guild_id) = classify(&channel_info);
+    let guild_name = channel_info
+        .guild
+        .as_ref()
+        .and_then(|guild| guild.name.clone());
+
+    Ok(Some(ChannelProbe {
+        id: folder.id,
+        kind,
+        guild_id,
+        guild_name,
+        name: channel_info.name,
+        folder_name: folder.name,
+        is_bot: is_bot_dm(kind, &channel_info.recipients),
+        recipients: channel_info.recipients.iter().map(Recipient::id).collect(),
+        messages_size: fs::metadata(&messages_file).map_or(0, |m| m.len()),
+        messages_file,
+    }))
+}
+
+/// Guild channels are recognized by their guild, group DMs by their type;
+/// everything else is a DM.
+fn classify(channel_info: &ChannelInfo) -> (ConversationType, Option<GuildId>) {
+    match channel_info.guild {
+        Some(ref guild) => (ConversationType::Guild, guild.id.clone()),
+        None if channel_info
+            .channel_type
+            .as_ref()
+            .is_some_and(ChannelType::is_group_dm) =>
+        {
+            (ConversationType::GroupDm, None)
+        }
+        None => (ConversationType::Dm, None),
+    }
+}
+
+/// Read a `channel.json`. Malformed content is returned as the inner error so
+/// the channel can still be counted without its info; only I/O errors are fatal.
+fn read_channel_info(path: &Path) -> Result<Result<ChannelInfo, serde_json::Error>, MyError> {
+    let contents = fs::read_to_string(path)?;
+    // Serde would happily read a struct from an array, which is never valid here
+    Ok(
+        serde_json::from_str::<serde_json::Value>(&contents).and_then(|value| match value {
+            serde_json::Value::Object(_) => serde_json::from_value(value),
+            _ => Err(serde::de::Error::custom("expected an object")),
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_operations::prepare_data_root;
+    use crate::testing::Package;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    /// Probe a channel folder `c1` whose `channel.json` holds `info`.
+    fn probe(info: &str) -> (ChannelProbe, Vec<Warning>) {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("c1");
+        fs::create_dir(&path).unwrap();
+        fs::write(path.join("channel.json"), info).unwrap();
+        fs::write(path.join("messages.json"), "[]").unwrap();
+
+        let folder = parse_channel_folder(&path).unwrap();
+        let mut warnings = Vec::new();
+        let channel = probe_channel(folder, PackageVintage::V2023, &mut warnings)
+            .unwrap()
+            .expect("a channel folder");
+        (channel, warnings)
+    }
+
+    fn is_unreadable(warnings: &[Warning]) -> bool {
+        matches!(
+            warnings,
+            [Warning::UnreadableChannelInfo { channel_id, .. }] if channel_id == "c1"
+        )
+    }
+
+    #[test]
+    fn an_empty_object_is_a_dm_without_info() {
+        let (channel, warnings) = probe("{}");
+        assert_eq!(channel.kind, ConversationType::Dm);
+        assert_eq!(channel.guild_id, None);
+        assert_eq!(channel.name, None);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn an_array_is_unreadable_info() {
+        let (channel, warnings) = probe(r#"[{"guild": {"id": "9"}}]"#);
+        assert_eq!(channel.kind, ConversationType::Dm);
+        assert_eq!(channel.guild_id, None);
+        assert!(is_unreadable(&warnings));
+    }
+
+    #[test]
+    fn truncated_json_is_unreadable_info() {
+        let (channel, warnings) = probe(r#"{"id": "1", "guild": {"id": "#);
+        assert_eq!(channel.kind, ConversationType::Dm);
+        assert!(is_unreadable(&warnings));
+    }
+
+    #[test]
+    fn a_guild_channel_keeps_its_guild() {
+        let (channel, warnings) =
+            probe(r#"{"id": "1", "name": "general", "guild": {"id": "9", "name": "Server"}}"#);
+        assert_eq!(channel.kind, ConversationType::Guild);
+        assert_eq!(channel.guild_id.as_deref(), Some("9"));
+        assert_eq!(channel.guild_name.as_deref(), Some("Server"));
+        assert_eq!(channel.name.as_deref(), Some("general"));
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn dms_and_group_dms_are_told_apart_by_type() {
+        let (dm, _) = probe(r#"{"id": "1", "type": 1, "recipients": ["2", 3]}"#);
+        assert_eq!(dm.kind, ConversationType::Dm);
+        assert_eq!(dm.recipients, ["2", "3"]);
+
+        let (group, _) = probe(r#"{"id": "1", "type": "GROUP_DM", "recipients": ["2", "3"]}"#);
+        assert_eq!(group.kind, ConversationType::GroupDm);
+    }
+
+    fn folder(name: &str) -> Option<(String, Option<String>, bool)> {
+        parse_channel_folder(Path::new("messages").join(name).as_path())
+            .map(|folder| (folder.id, folder.name, folder.prefixed))
+    }
+
+    #[test]
+    fn folder_names_of_every_vintage() {
+        assert_eq!(folder("c123"), Some(("123".into(), None, true)));
+        assert_eq!(folder("123"), Some(("123".into(), None, false)));
+        assert_eq!(
+            folder("123 - general chat"),
+            Some(("123".into(), Some("general chat".into()), false))
+        );
+        assert_eq!(folder("123 - "), Some(("123".into(), None, false)));
+    }
+
+    #[test]
+    fn garbage_folder_names_are_rejected() {
+        for name in [
+            "",
+            "c",
+            "notes",
+            "c12a",
+            "123-general",
+            "general - 123",
+            "cc123",
+        ] {
+            assert_eq!(folder(name), None, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn unrecognized_folders_are_skipped_with_a_warning() {
+        let package = Package::new();
+        package
+            .channel("1", json!({"id": "1"}), 1)
+            .file("messages/backup/channel.json", "{}")
+            .file("messages/backup/messages.json", "[]");
+
+        let index = build_index(&prepare_data_root(package.path(), None).unwrap()).unwrap();
+        assert_eq!(index.channels.len(), 1);
+        assert!(matches!(
+            index.warnings.as_slice(),
+            [Warning::UnrecognizedFolder { folder }] if folder == "backup"
+        ));
+    }
+
+    #[test]
+    fn the_folder_name_names_a_channel_nothing_else_names() {
+        let package = Package::new();
+        package
+            .file("messages/123 - general/channel.json", "{}")
+            .file("messages/123 - general/messages.json", "[]");
+
+        let report = package.analyze();
+        assert_eq!(report.conversations.len(), 1);
+        assert_eq!(report.conversations[0].id(), "123");
+        assert_eq!(report.conversations[0].name(), "general");
+    }
+
+    #[test]
+    fn probes_record_the_messages_file_and_its_size() {
+        let package = Package::new();
+        package
+            .channel("1", json!({"id": "1"}), 2)
+            .file("messages/c2/channel.json", "{}")
+            .file(
+                "messages/c2/messages.csv",
+                "ID,Timestamp,Contents,Attachments\n",
+            )
+            // Without a channel.json a folder is no channel
+            .file("messages/c3/messages.json", "[]");
+
+        let index = build_index(&prepare_data_root(package.path(), None).unwrap()).unwrap();
+        let mut files: Vec<(&str, &str, u64)> = index
+            .channels
+            .iter()
+            .map(|channel| {
+                let file = channel.messages_file.file_name().unwrap();
+                (
+                    channel.id.as_str(),
+                    file.to_str().unwrap(),
+                    channel.messages_size,
+                )
+            })
+            .collect();
+        files.sort();
+        let json_size = fs::metadata(package.path().join("messages/c1/messages.json"))
+            .unwrap()
+            .len();
+        assert_eq!(
+            files,
+            [("1", "messages.json", json_size), ("2", "messages.csv", 34)]
+        );
+    }
+
+    #[test]
+    fn bot_dms_need_every_recipient_flagged() {
+        let bot = |info: &str| probe(info).0.is_bot;
+        assert_eq!(
+            bot(r#"{"type": 1, "recipients": [{"id": "2", "bot": true}, "3"]}"#),
+            Some(true)
+        );
+        assert_eq!(
+            bot(
+                r#"{"type": 1, "recipients": [{"id": "2", "bot": false}, {"id": "3", "bot": false}]}"#
+            ),
+            Some(false)
+        );
+        assert_eq!(
+            bot(r#"{"type": 1, "recipients": [{"id": "2", "bot": false}, "3"]}"#),
+            None
+        );
+        assert_eq!(
+            bot(r#"{"type": 3, "recipients": [{"id": "2", "bot": true}]}"#),
+            None
+        );
+        assert_eq!(bot(r#"{"type": 1}"#), None);
+    }
+}
@@ -0,0 +1,278 @@
+//! Keeps the channel index of a package between runs, so repeated runs on a
+//! slow medium skip reading every `channel.json` again. Entries are keyed by
+//! a fingerprint of the package that is cheap to take: the archive's size
+//! and modification time, or for folders the modification times of
+//! `messages/` and its index together with the names of the channel folders
+//! and the size and modification time of each `channel.json`. That takes no
+//! more than a look at the metadata of the files the index is built from,
+//! and notices a channel edited inside its folder as well as folders added
+//! or removed. An index built while the folder is still being extracted into
+//! is not stored.
+
+use crate::errors::MyError;
+use crate::extraction::ExtractionWatch;
+use crate::file_operations::DataRoot;
+use crate::index::{build_index, ChannelIndex, ChannelProbe};
+use crate::lockfile::FileLock;
+use crate::paths::cache_file;
+use crate::vintage::PackageVintage;
+use crate::warnings::Warning;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Folder below the cache directory holding one file per fingerprint.
+const CACHE_FOLDER: &str = "index";
+
+/// Bumped whenever the cached layout changes, which invalidates every entry.
+const INDEX_CACHE_VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize)]
+struct CachedIndex {
+    version: u32,
+    vintage: PackageVintage,
+    /// Probes with their messages files relative to the data root, since
+    /// archives are extracted somewhere else on every run
+    channels: Vec<ChannelProbe>,
+    warnings: Vec<Warning>,
+}
+
+/// The channel index of the package, from the cache when the package has
+/// not changed since it was stored. A cache that cannot be read or written
+/// only costs the time of building the index.
+///
+/// The entry is locked while it is read and built, so an instance started
+/// on the same package meanwhile waits for it, calling `waiting`, and then
+/// reads what it stored. Without `wait` it fails with `MyError::Locked`.
+pub fn cached_index(
+    input_path: &Path,
+    data_root: &DataRoot,
+    watch: Option<&ExtractionWatch>,
+    wait: bool,
+    waiting: impl FnOnce(),
+) -> Result<ChannelIndex, MyError> {
+    let Ok(folder) = cache_file(CACHE_FOLDER) else {
+        return build_index(data_root);
+    };
+    index_cached_in(&folder, input_path, data_root, watch, wait, waiting)
+}
+
+fn index_cached_in(
+    folder: &Path,
+    input_path: &Path,
+    data_root: &DataRoot,
+    watch: Option<&ExtractionWatch>,
+    wait: bool,
+    waiting: impl FnOnce(),
+) -> Result<ChannelIndex, MyError> {
+    let Some(fingerprint) = fingerprint(input_path, data_root) else {
+        return build_index(data_root);
+    };
+    let path = folder.join(format!("{}.json", fingerprint));
+    let _lock = match FileLock::acquire(&path, wait, waiting) {
+        Ok(lock) => Some(lock),
+        Err(error @ MyError::Locked(_)) => return Err(error),
+        // A cache folder without locks is used like one without a cache
+        Err(_) => return build_index(data_root),
+    };
+
+    if let Some(index) = load(&path, data_root) {
+        return Ok(index);
+    }
+    let index = build_index(data_root)?;
+    // A folder still being extracted into may have changed while indexing
+    let filling = watch.is_some_and(ExtractionWatch::is_filling)
+        || self::fingerprint(input_path, data_root).as_ref() != Some(&fingerprint);
+    if !filling {
+        let _ = store(&path, &index, data_root);
+    }
+    Ok(index)
+}
+
+fn load(path: &Path, data_root: &DataRoot) -> Option<ChannelIndex> {
+    let file = File::open(path).ok()?;
+    let cached: CachedIndex = serde_json::from_reader(BufReader::new(file)).ok()?;
+    if cached.version != INDEX_CACHE_VERSION {
+        return None;
+    }
+    Some(ChannelIndex {
+        vintage: cached.vintage,
+        channels: cached
+            .channels
+            .into_iter()
+            .map(|mut channel| {
+                channel.messages_file = data_root.path.join(&channel.messages_file);
+                channel
+            })
+            .collect(),
+        warnings: cached.warnings,
+    })
+}
+
+fn store(path: &Path, index: &ChannelIndex, data_root: &DataRoot) -> Result<(), MyError> {
+    let relative = |file: &Path| -> PathBuf {
+        file.strip_prefix(&data_root.path)
+            .unwrap_or(file)
+            .to_path_buf()
+    };
+    let cached = CachedIndex {
+        version: INDEX_CACHE_VERSION,
+        vintage: index.vintage,
+        channels: index
+            .channels
+            .iter()
+            .map(|channel| ChannelProbe {
+                messages_file: relative(&channel.messages_file),
+                ..channel.clone()
+            })
+            .collect(),
+        warnings: index.warnings.clone(),
+    };
+    if let Some(folder) = path.parent() {
+        fs::create_dir_all(folder)?;
+    }
+    serde_json::to_writer(BufWriter::new(File::create(path)?), &cached)?;
+    Ok(())
+}
+
+/// `None` when the package cannot be fingerprinted, e.g. on file systems
+/// without modification times; such packages are never cached.
+fn fingerprint(input_path: &Path, data_root: &DataRoot) -> Option<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(INDEX_CACHE_VERSION.to_le_bytes());
+    hasher.update(
+        fs::canonicalize(input_path)
+            .ok()?
+            .to_string_lossy()
+            .as_bytes(),
+    );
+
+    if input_path.is_file() {
+        let metadata = fs::metadata(input_path).ok()?;
+        hasher.update(metadata.len().to_le_bytes());
+        hasher.update(modified(&metadata)?.to_le_bytes());
+    } else {
+        let messages_folder = data_root.path.join("messages");
+        hasher.update(modified(&fs::metadata(&messages_folder).ok()?)?.to_le_bytes());
+        // The index decides the vintage of some packages, so it counts too
+        if let Ok(metadata) = fs::metadata(messages_folder.join("index.json")) {
+            hasher.update(metadata.len().to_le_bytes());
+            hasher.update(modified(&metadata)?.to_le_bytes());
+        }
+        let mut names: Vec<String> = fs::read_dir(&messages_folder)
+            .ok()?
+            .filter_map(|entry| Some(entry.ok()?.file_name().to_string_lossy().into_owned()))
+            .collect();
+        names.sort_unstable();
+        for name in names {
+            hasher.update(name.as_bytes());
+            hasher.update([0]);
+            // A channel edited in place changes neither folder names nor
+            // the mtime of messages/
+            if let Ok(metadata) = fs::metadata(messages_folder.join(&name).join("channel.json")) {
+                hasher.update(metadata.len().to_le_bytes());
+                hasher.update(modified(&metadata)?.to_le_bytes());
+            }
+        }
+    }
+
+    let digest = hasher.finalize();
+    Some(digest.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+fn modified(metadata: &fs::Metadata) -> Option<u128> {
+    Some(
+        metadata
+            .modified()
+            .ok()?
+            .duration_since(UNIX_EPOCH)
+            .ok()?
+            .as_nanos(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_operations::prepare_data_root;
+    use crate::testing::Package;
+    use serde_json::json;
+
+    fn indexed(cache: &Path, package: &Package, watch: Option<&ExtractionWatch>) -> ChannelIndex {
+        let data_root = prepare_data_root(package.path(), None).unwrap();
+        index_cached_in(cache, package.path(), &data_root, watch, false, || {}).unwrap()
+    }
+
+    fn channel(id: &str, name: &str) -> serde_json::Value {
+        json!({"id": id, "type": 0, "name": name, "guild": {"id": "9", "name": "Guild"}})
+    }
+
+    fn names(index: &ChannelIndex) -> Vec<&str> {
+        let mut names: Vec<&str> = index
+            .channels
+            .iter()
+            .filter_map(|channel| channel.name.as_deref())
+            .collect();
+        names.sort_unstable();
+        names
+    }
+
+    #[test]
+    fn an_unchanged_package_is_read_from_the_cache() {
+        let cache = tempfile::tempdir().unwrap();
+        let package = Package::new();
+        package.channel("1", channel("1", "general"), 1);
+        let stored = indexed(cache.path(), &package, None);
+
+        // Messages files are not part of the fingerprint, so the cached size
+        // shows the index was not built again
+        package.file("messages/c1/messages.json", "[]");
+        let cached = indexed(cache.path(), &package, None);
+        assert_eq!(
+            cached.channels[0].messages_size,
+            stored.channels[0].messages_size
+        );
+    }
+
+    #[test]
+    fn changing_a_folder_rebuilds_the_index() {
+        let cache = tempfile::tempdir().unwrap();
+        let package = Package::new();
+        package.channel("1", channel("1", "general"), 1);
+        assert_eq!(names(&indexed(cache.path(), &package, None)), ["general"]);
+
+        package.file(
+            "messages/c1/channel.json",
+            &channel("1", "off-topic").to_string(),
+        );
+        assert_eq!(names(&indexed(cache.path(), &package, None)), ["off-topic"]);
+
+        package.channel("2", channel("2", "memes"), 1);
+        assert_eq!(
+            names(&indexed(cache.path(), &package, None)),
+            ["memes", "off-topic"]
+        );
+    }
+
+    #[test]
+    fn an_index_of_a_folder_being_filled_is_not_stored() {
+        let cache = tempfile::tempdir().unwrap();
+        let package = Package::new();
+        package.channel("1", channel("1", "general"), 1);
+        let data_root = prepare_data_root(package.path(), None).unwrap();
+        let watch = ExtractionWatch::start(&data_root).unwrap();
+        package.channel("2", channel("2", "memes"), 1);
+
+        indexed(cache.path(), &package, Some(&watch));
+        let stored = fs::read_dir(cache.path()).unwrap().filter_map(Result::ok);
+        assert_eq!(
+            stored
+                .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+                .count(),
+            0
+        );
+    }
+}
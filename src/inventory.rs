@@ -0,0 +1,179 @@
+//! `--coverage`: the channels `messages/index.json` lists, the channel
+//! folders the package holds and the channels with messages, compared. Each
+//! difference is a concrete sign of an incomplete export: channels listed
+//! without a folder were left out, folders not listed were added or come
+//! from another export, and empty channels lost their messages.
+
+use crate::errors::MyError;
+use crate::file_operations::{channel_folders, normalize_channel_id, DataRoot};
+use crate::index::parse_channel_folder;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::path::Path;
+
+/// Channel IDs shown per difference; `--coverage-export` lists them all.
+const EXAMPLE_LIMIT: usize = 5;
+
+/// Channel IDs without their `c` prefix, sorted.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Coverage {
+    /// Channels `messages/index.json` lists; `None` without one
+    pub indexed: Option<usize>,
+    /// Channel folders below `messages/`
+    pub folders: usize,
+    /// Folders with at least one counted message; `None` when not counted
+    pub with_messages: Option<usize>,
+    pub indexed_without_folder: Vec<String>,
+    pub folder_not_indexed: Vec<String>,
+    /// Folders without a single counted message
+    pub empty: Vec<String>,
+}
+
+/// Channel IDs from the keys of `messages/index.json`, including channels
+/// the index has no name for, or `None` when the package has no index.
+pub fn indexed_channels(data_root: &DataRoot) -> Result<Option<HashSet<String>>, MyError> {
+    let path = data_root.path.join("messages").join("index.json");
+    if !path.exists() {
+        return Ok(None);
+    }
+    let index: HashMap<String, Option<String>> =
+        serde_json::from_reader(BufReader::new(File::open(path)?))?;
+    Ok(Some(
+        index
+            .into_keys()
+            .map(|id| normalize_channel_id(&id).to_string())
+            .collect(),
+    ))
+}
+
+/// Channel IDs of every folder named after a channel, whatever it holds.
+pub fn folder_channels(data_root: &DataRoot) -> Result<HashSet<String>, MyError> {
+    Ok(channel_folders(data_root)?
+        .iter()
+        .filter_map(|path| parse_channel_folder(path))
+        .map(|folder| folder.id)
+        .collect())
+}
+
+/// Compare the three sets. Without `with_messages`, as in `doctor`, which
+/// counts nothing, empty channels are not looked for.
+pub fn coverage(
+    indexed: Option<&HashSet<String>>,
+    folders: &HashSet<String>,
+    with_messages: Option<&HashSet<String>>,
+) -> Coverage {
+    let sorted = |ids: Vec<&String>| -> Vec<String> {
+        ids.into_iter()
+            .cloned()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    };
+    Coverage {
+        indexed: indexed.map(HashSet::len),
+        folders: folders.len(),
+        with_messages: with_messages.map(|with| folders.intersection(with).count()),
+        indexed_without_folder: indexed
+            .map(|indexed| sorted(indexed.difference(folders).collect()))
+            .unwrap_or_default(),
+        folder_not_indexed: indexed
+            .map(|indexed| sorted(folders.difference(indexed).collect()))
+            .unwrap_or_default(),
+        empty: with_messages
+            .map(|with| sorted(folders.difference(with).collect()))
+            .unwrap_or_default(),
+    }
+}
+
+impl Coverage {
+    /// The differences with a description each, in the order they are listed.
+    fn differences(&self) -> Vec<(&'static str, &'static str, &[String])> {
+        let mut differences = Vec::new();
+        if self.indexed.is_some() {
+            differences.push((
+                "indexed_without_folder",
+                "listed in messages/index.json without a folder",
+                &self.indexed_without_folder[..],
+            ));
+            differences.push((
+                "folder_not_indexed",
+                "with a folder but not listed in messages/index.json",
+                &self.folder_not_indexed[..],
+            ));
+        }
+        if self.with_messages.is_some() {
+            differences.push(("empty", "with a folder but no messages", &self.empty[..]));
+        }
+        differences
+    }
+}
+
+pub fn write_coverage(coverage: &Coverage, out: &mut dyn Write) -> Result<(), MyError> {
+    writeln!(out, "Coverage:")?;
+    match coverage.indexed {
+        Some(indexed) => writeln!(out, "    {} channels in messages/index.json", indexed)?,
+        None => writeln!(out, "    no messages/index.json to compare with")?,
+    }
+    writeln!(out, "    {} channel folders", coverage.folders)?;
+    if let Some(with_messages) = coverage.with_messages {
+        writeln!(out, "    {} channels with messages", with_messages)?;
+    }
+    for (_, description, ids) in coverage.differences() {
+        writeln!(
+            out,
+            "    {} channels {}{}",
+            ids.len(),
+            description,
+            examples(ids)
+        )?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+/// The doctor's summary: counts only, on a single line.
+pub fn write_condensed(coverage: &Coverage, out: &mut dyn Write) -> Result<(), MyError> {
+    let differences: Vec<String> = coverage
+        .differences()
+        .iter()
+        .map(|(_, description, ids)| format!("{} {}", ids.len(), description))
+        .collect();
+    match differences.is_empty() {
+        true => writeln!(
+            out,
+            "Coverage: no messages/index.json to compare the folders with"
+        )?,
+        false => writeln!(out, "Coverage: {}", differences.join(", "))?,
+    }
+    Ok(())
+}
+
+/// Every channel of every difference as `difference,channel_id` rows.
+pub fn export_coverage(coverage: &Coverage, path: &Path) -> Result<(), MyError> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(["difference", "channel_id"])?;
+    for (difference, _, ids) in coverage.differences() {
+        for id in ids {
+            writer.write_record([difference, &format!("c{}", id)])?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn examples(ids: &[String]) -> String {
+    if ids.is_empty() {
+        return String::new();
+    }
+    let shown: Vec<String> = ids
+        .iter()
+        .take(EXAMPLE_LIMIT)
+        .map(|id| format!("c{}", id))
+        .collect();
+    match ids.len() > EXAMPLE_LIMIT {
+        true => format!(": {}, …", shown.join(", ")),
+        false => format!(": {}", shown.join(", ")),
+    }
+}
@@ -0,0 +1,328 @@
+//! Stable JSON for scripts and jq: the filtered conversations in display
+//! order, with the run's metadata and optional sections alongside. NDJSON
+//! carries the same conversation objects, one per line.
+
+use crate::analytics::{Discrepancy, ReferenceCounts, VoiceStats};
+use crate::breadth::YearBreadth;
+use crate::buckets::{count_bucket, CountStyle};
+use crate::budget::PartialRun;
+use crate::concentration::Concentration;
+use crate::errors::MyError;
+use crate::inventory::Coverage;
+use crate::provenance::EffectiveOption;
+use crate::render::ResultsView;
+use crate::report::Report;
+use crate::sessions::SessionSummary;
+use crate::timeline::ThresholdTimeline;
+use crate::totals::{totals, Totals};
+use crate::vintage::PackageVintage;
+use crate::volume::ProcessingStats;
+use crate::{Channel, Conversation, FilterStage, GroupDmPolicy};
+use chrono::NaiveDate;
+use serde::Serialize;
+use std::io::Write;
+
+pub const JSON_FORMAT: &str = "discord-gdpr-counter/json";
+
+/// Bumped whenever the shape of the document changes incompatibly.
+pub const JSON_SCHEMA_VERSION: u32 = 2;
+
+#[derive(Serialize)]
+struct Document<'a> {
+    format: &'a str,
+    schema_version: u32,
+    meta: Meta<'a>,
+    conversations: Vec<JsonConversation<'a>>,
+    /// What is shown of everything counted; left out with --no-summary and
+    /// once counts are bucketed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<Totals>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    concentration: Option<&'a Concentration>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    discrepancies: &'a [Discrepancy],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    breadth_by_year: Option<&'a [YearBreadth]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    threshold_timeline: Option<&'a ThresholdTimeline>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    voice: Option<&'a VoiceStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sessions: Option<&'a SessionSummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    coverage: Option<&'a Coverage>,
+}
+
+#[derive(Serialize)]
+struct Meta<'a> {
+    /// How many conversations were left after each filtering step
+    filter_stages: &'a [FilterStage],
+    /// Messages across every counted conversation, before filtering; left
+    /// out once counts are bucketed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total_messages: Option<usize>,
+    /// The counted total next to Discord's own figure, when the package has
+    /// one; left out once counts are bucketed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reference_counts: Option<&'a ReferenceCounts>,
+    vintage: Option<PackageVintage>,
+    /// Set when processing stopped before every channel was counted
+    partial: Option<&'a PartialRun>,
+    excluded_system: bool,
+    /// Empty stubs left out by --exclude-empty-messages
+    #[serde(skip_serializing_if = "Option::is_none")]
+    excluded_empty_messages: Option<usize>,
+    /// Empty stubs across every counted conversation, with
+    /// --empty-message-stats; left out once counts are bucketed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    empty_messages: Option<usize>,
+    /// DMs with bots left out by --exclude-bots
+    #[serde(skip_serializing_if = "Option::is_none")]
+    excluded_bot_dms: Option<usize>,
+    /// Servers missing from servers/index.json that were left out, unless
+    /// --include-departed or --only-departed was given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    excluded_departed_guilds: Option<usize>,
+    /// Data read while counting; left out once counts are bucketed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    processing_stats: Option<&'a ProcessingStats>,
+    /// Every option the run used, with where its value came from; left out
+    /// of redacted reports, as paths and filters can name people
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    options: &'a [EffectiveOption],
+}
+
+/// An exact count as `message_count`, or its range as `count_bucket` once
+/// counts are bucketed.
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Count {
+    MessageCount(usize),
+    CountBucket(String),
+}
+
+impl Count {
+    fn new(n: usize, counts: CountStyle) -> Self {
+        match counts {
+            CountStyle::Exact => Self::MessageCount(n),
+            CountStyle::Bucketed => Self::CountBucket(count_bucket(n as u64)),
+        }
+    }
+}
+
+/// The busiest day's count as `peak_count`, or `peak_count_bucket` once
+/// counts are bucketed.
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum PeakCount {
+    PeakCount(usize),
+    PeakCountBucket(String),
+}
+
+impl PeakCount {
+    fn new(n: usize, counts: CountStyle) -> Self {
+        match counts {
+            CountStyle::Exact => Self::PeakCount(n),
+            CountStyle::Bucketed => Self::PeakCountBucket(count_bucket(n as u64)),
+        }
+    }
+}
+
+/// Empty stubs as `empty_messages`, or `empty_messages_bucket` once counts
+/// are bucketed.
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum EmptyCount {
+    EmptyMessages(usize),
+    EmptyMessagesBucket(String),
+}
+
+impl EmptyCount {
+    fn new(n: usize, counts: CountStyle) -> Self {
+        match counts {
+            CountStyle::Exact => Self::EmptyMessages(n),
+            CountStyle::Bucketed => Self::EmptyMessagesBucket(count_bucket(n as u64)),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct JsonConversation<'a> {
+    kind: &'static str,
+    id: &'a str,
+    name: &'a str,
+    #[serde(flatten)]
+    count: Count,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    note: Option<&'a str>,
+    /// Busiest day in --timezone's calendar, with --activity-stats
+    #[serde(skip_serializing_if = "Option::is_none")]
+    peak_day: Option<NaiveDate>,
+    #[serde(flatten)]
+    peak_count: Option<PeakCount>,
+    /// Messages without contents or attachments, with --empty-message-stats
+    #[serde(flatten)]
+    empty_messages: Option<EmptyCount>,
+    /// Guild channels, most messages first; absent for DMs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    channels: Option<Vec<JsonChannel<'a>>>,
+    /// A server missing from servers/index.json; absent for all others
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    departed: bool,
+}
+
+#[derive(Serialize)]
+struct JsonChannel<'a> {
+    id: &'a str,
+    name: &'a str,
+    #[serde(flatten)]
+    count: Count,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    previous_names: Vec<&'a str>,
+    /// IDs of all channels combined into this one by --merge-same-name-channels
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    merged_ids: &'a [String],
+}
+
+/// What a conversation's object depends on besides the conversation itself.
+/// Without a report, as while streaming, notes and previous names are left out.
+#[derive(Clone, Copy)]
+pub struct JsonContext<'a> {
+    pub counts: CountStyle,
+    pub group_dms_as: GroupDmPolicy,
+    pub report: Option<&'a Report>,
+    /// Include each conversation's empty stubs
+    pub empty_messages: bool,
+}
+
+impl<'a> JsonContext<'a> {
+    pub fn of(view: &ResultsView<'a>) -> Self {
+        Self {
+            counts: view.counts,
+            group_dms_as: view.group_dms_as,
+            report: Some(view.report),
+            empty_messages: view.empty_message_stats,
+        }
+    }
+}
+
+pub fn render_json(view: &ResultsView, out: &mut dyn Write) -> Result<(), MyError> {
+    let report = view.report;
+    let context = JsonContext::of(view);
+    let conversations = view
+        .conversations
+        .iter()
+        .map(|&conversation| json_conversation(conversation, context))
+        .collect();
+
+    // Sections built on exact totals would give them away once counts are bucketed
+    let exact = view.counts == CountStyle::Exact;
+    let total: usize = report
+        .conversations
+        .iter()
+        .map(Conversation::message_count)
+        .sum();
+    let empty: usize = report
+        .conversations
+        .iter()
+        .map(Conversation::empty_messages)
+        .sum();
+    let document = Document {
+        format: JSON_FORMAT,
+        schema_version: JSON_SCHEMA_VERSION,
+        meta: Meta {
+            filter_stages: &view.filter_stages,
+            total_messages: exact.then_some(total),
+            reference_counts: report.reference_counts.as_ref().filter(|_| exact),
+            vintage: report.vintage,
+            partial: report.partial.as_ref(),
+            excluded_system: view.excluded_system,
+            excluded_empty_messages: view.excluded_empty.then_some(empty),
+            empty_messages: view.empty_message_stats.then_some(empty).filter(|_| exact),
+            excluded_bot_dms: view.excluded_bots.then(|| report.bot_dms()),
+            excluded_departed_guilds: view.excluded_departed.then(|| report.departed_guilds()),
+            processing_stats: report.processing_stats.as_ref().filter(|_| exact),
+            options: view.options,
+        },
+        conversations,
+        summary: (view.summary && exact).then(|| totals(view)),
+        concentration: view.concentration.as_ref().filter(|_| exact),
+        discrepancies: match exact {
+            true => &report.discrepancies,
+            false => &[],
+        },
+        breadth_by_year: report.breadth_by_year.as_deref(),
+        threshold_timeline: view.threshold_timeline.as_ref(),
+        voice: report.voice.as_ref(),
+        sessions: report.sessions.as_ref(),
+        coverage: report.coverage.as_ref(),
+    };
+    serde_json::to_writer_pretty(&mut *out, &document)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// The conversations of `view` in order, one object of the `--format json`
+/// shape per line.
+pub fn render_ndjson(view: &ResultsView, out: &mut dyn Write) -> Result<(), MyError> {
+    let context = JsonContext::of(view);
+    for &conversation in &view.conversations {
+        write_ndjson_line(conversation, context, out)?;
+    }
+    Ok(())
+}
+
+/// Write a single conversation as a line of NDJSON.
+pub fn write_ndjson_line(
+    conversation: &Conversation,
+    context: JsonContext,
+    out: &mut dyn Write,
+) -> Result<(), MyError> {
+    serde_json::to_writer(&mut *out, &json_conversation(conversation, context))?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// A conversation as the JSON formats, and YAML, write it.
+pub fn json_conversation<'a>(
+    conversation: &'a Conversation,
+    context: JsonContext<'a>,
+) -> JsonConversation<'a> {
+    let peak = context
+        .report
+        .and_then(|report| report.peak_day(conversation.id()));
+    JsonConversation {
+        kind: conversation.effective_kind(context.group_dms_as).label(),
+        id: conversation.id(),
+        name: conversation.name(),
+        count: Count::new(conversation.message_count(), context.counts),
+        note: context
+            .report
+            .and_then(|report| report.note(conversation.id())),
+        peak_day: peak.map(|peak| peak.day),
+        peak_count: peak.map(|peak| PeakCount::new(peak.count, context.counts)),
+        empty_messages: context
+            .empty_messages
+            .then(|| EmptyCount::new(conversation.empty_messages(), context.counts)),
+        channels: match conversation {
+            Conversation::Guild { channels, .. } => Some(json_channels(channels, context)),
+            Conversation::DmOrGc { .. } => None,
+        },
+        departed: conversation.is_departed(),
+    }
+}
+
+fn json_channels<'a>(channels: &'a [Channel], context: JsonContext<'a>) -> Vec<JsonChannel<'a>> {
+    channels
+        .iter()
+        .map(|channel| JsonChannel {
+            id: &channel.id,
+            name: &channel.name,
+            count: Count::new(channel.message_count, context.counts),
+            previous_names: context.report.map_or_else(Vec::new, |report| {
+                report.previous_names(&channel.id, &channel.name)
+            }),
+            merged_ids: &channel.merged_ids,
+        })
+        .collect()
+}
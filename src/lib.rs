@@ -0,0 +1,355 @@
+//! Counting of Discord data packages behind the command line tool, for
+//! other frontends: [`analysis::analyze`] turns a package into the same
+//! [`report::Report`] the CLI renders, and [`analysis::spawn_analysis`] does
+//! so on a worker thread that streams progress and can be cancelled.
+
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+pub mod accessible;
+pub mod analysis;
+pub mod analytics;
+pub mod bars;
+pub mod breadth;
+pub mod breakdown;
+pub mod buckets;
+pub mod budget;
+#[cfg(feature = "charts")]
+pub mod chart;
+#[cfg(feature = "clipboard")]
+pub mod clipboard;
+pub mod color;
+pub mod concentration;
+pub mod coverage;
+pub mod diff;
+pub mod discord;
+pub mod doctor;
+pub mod donut;
+pub mod dot;
+pub mod dump;
+pub mod errors;
+pub mod extraction;
+pub mod features;
+pub mod file_operations;
+pub mod footprint;
+pub mod html;
+pub mod humanize;
+pub mod index;
+pub mod index_cache;
+pub mod inventory;
+pub mod json;
+pub mod lockfile;
+pub mod mermaid;
+pub mod messages;
+pub mod metrics;
+#[cfg(feature = "zip")]
+pub mod mirror;
+pub mod notes;
+pub mod numbers;
+pub mod output;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+pub mod paths;
+pub mod peaks;
+pub mod progress;
+pub mod provenance;
+pub mod redact;
+pub mod render;
+pub mod report;
+pub mod resolve;
+pub mod schema;
+pub mod sections;
+pub mod selector;
+pub mod sessions;
+pub mod site;
+pub mod spinner;
+pub mod split;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod suggestions;
+pub mod table;
+pub mod template;
+#[cfg(test)]
+mod testing;
+pub mod timeline;
+pub mod toml_format;
+pub mod totals;
+pub mod vintage;
+pub mod volume;
+pub mod warnings;
+pub mod width;
+pub mod window;
+pub mod wire;
+#[cfg(feature = "xlsx")]
+pub mod xlsx;
+pub mod yaml;
+pub mod zone;
+
+/// Label for channels whose server cannot be identified
+pub const UNKNOWN_GUILD_NAME: &str = "(unknown server)";
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConversationType {
+    Dm,
+    GroupDm,
+    Guild,
+}
+
+impl ConversationType {
+    /// Short kind label used by the machine-readable formats.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Dm => "dm",
+            Self::GroupDm => "group_dm",
+            Self::Guild => "guild",
+        }
+    }
+}
+
+/// Where group DMs are counted wherever conversations are split by type.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GroupDmPolicy {
+    /// Together with one-on-one DMs
+    Dms,
+    /// As a type of their own
+    #[default]
+    Separate,
+    /// Together with guilds
+    Guilds,
+}
+
+pub type GuildId = String;
+
+/// A filtering step and how many conversations were left after it.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct FilterStage {
+    pub stage: FilterStageKind,
+    pub remaining: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterStageKind {
+    Found,
+    Participant,
+    ConversationType,
+    Departed,
+    Bots,
+    MinMessages,
+    Limit,
+}
+
+impl FilterStageKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Found => "found",
+            Self::Participant => "with the participant",
+            Self::ConversationType => "after type filter",
+            Self::Departed => "after departed-server filter",
+            Self::Bots => "after bot filter",
+            Self::MinMessages => "after min-messages",
+            Self::Limit => "shown (limit)",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Conversation {
+    #[serde(rename = "dm")]
+    DmOrGc {
+        id: String,
+        name: String,
+        message_count: usize,
+        #[serde(default)]
+        excluded_system: usize,
+        /// Messages without contents or attachments, counted or not
+        #[serde(default)]
+        empty_messages: usize,
+        /// A group DM rather than a one-on-one conversation
+        #[serde(default)]
+        group: bool,
+        /// User IDs of the other participants, as listed in channel.json
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        recipients: Vec<String>,
+        /// Whether the other side is a bot; `None` when the package doesn't say
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        is_bot: Option<bool>,
+        #[serde(skip)]
+        messages_file: PathBuf,
+    },
+    /// A guild, or with `id: None` a single channel of an unidentifiable guild
+    Guild {
+        id: Option<GuildId>,
+        name: String,
+        message_count: usize,
+        #[serde(default)]
+        excluded_system: usize,
+        #[serde(default)]
+        empty_messages: usize,
+        channels: Vec<Channel>,
+        /// Missing from `servers/index.json`: a server the owner left or
+        /// that was deleted
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        departed: bool,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Channel {
+    pub id: String,
+    pub name: String,
+    pub message_count: usize,
+    #[serde(default)]
+    pub first_message: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub last_message: Option<DateTime<Utc>>,
+    /// IDs of all channels combined into this one by --merge-same-name-channels
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub merged_ids: Vec<String>,
+    #[serde(skip)]
+    pub message_files: Vec<PathBuf>,
+}
+
+impl Conversation {
+    /// Order a guild's channels as every output lists them: most messages
+    /// first, ties by ID. Done once, when the conversation is complete.
+    pub fn sort_channels(&mut self) {
+        if let Self::Guild { channels, .. } = self {
+            channels.sort_by(|a, b| {
+                b.message_count
+                    .cmp(&a.message_count)
+                    .then_with(|| a.id.cmp(&b.id))
+            });
+        }
+    }
+
+    /// The conversation as `--flat` lists it: a guild becomes one guild per
+    /// channel, holding only that channel's messages, so that no guild total
+    /// is listed next to its channels.
+    pub fn explode(&self) -> Vec<Conversation> {
+        match self {
+            Self::Guild {
+                id: Some(id),
+                name,
+                channels,
+                departed,
+                ..
+            } => channels
+                .iter()
+                .map(|channel| Self::Guild {
+                    id: Some(id.clone()),
+                    name: name.clone(),
+                    message_count: channel.message_count,
+                    excluded_system: 0,
+                    empty_messages: 0,
+                    channels: vec![channel.clone()],
+                    departed: *departed,
+                })
+                .collect(),
+            // DMs and channels of unknown servers already are single records
+            _ => vec![self.clone()],
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        match self {
+            Self::DmOrGc { id, .. } => id,
+            Self::Guild { id: Some(id), .. } => id,
+            // Unknown guilds always hold exactly one channel
+            Self::Guild {
+                id: None, channels, ..
+            } => channels.first().map_or("", |channel| &channel.id),
+        }
+    }
+
+    pub fn excluded_system(&self) -> usize {
+        match self {
+            Self::DmOrGc {
+                excluded_system, ..
+            } => *excluded_system,
+            Self::Guild {
+                excluded_system, ..
+            } => *excluded_system,
+        }
+    }
+
+    pub fn empty_messages(&self) -> usize {
+        match self {
+            Self::DmOrGc { empty_messages, .. } => *empty_messages,
+            Self::Guild { empty_messages, .. } => *empty_messages,
+        }
+    }
+
+    /// Type of the conversation once `policy` has placed group DMs. Every
+    /// split by type goes through here so the buckets always add up.
+    pub fn effective_kind(&self, policy: GroupDmPolicy) -> ConversationType {
+        match (self, policy) {
+            (Self::Guild { .. }, _) => ConversationType::Guild,
+            (Self::DmOrGc { group: false, .. }, _) | (_, GroupDmPolicy::Dms) => {
+                ConversationType::Dm
+            }
+            (_, GroupDmPolicy::Separate) => ConversationType::GroupDm,
+            (_, GroupDmPolicy::Guilds) => ConversationType::Guild,
+        }
+    }
+
+    pub fn has_participant(&self, user_id: &str) -> bool {
+        match self {
+            Self::DmOrGc { recipients, .. } => recipients.iter().any(|id| id == user_id),
+            // The package has no member lists for guilds
+            Self::Guild { .. } => false,
+        }
+    }
+
+    pub fn is_unknown_guild(&self) -> bool {
+        matches!(self, Self::Guild { id: None, .. })
+    }
+
+    /// A DM with a bot. DMs the package has no bot flag for count as people.
+    pub fn is_bot(&self) -> bool {
+        matches!(
+            self,
+            Self::DmOrGc {
+                is_bot: Some(true),
+                ..
+            }
+        )
+    }
+
+    pub fn is_departed(&self) -> bool {
+        matches!(self, Self::Guild { departed: true, .. })
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Self::DmOrGc { name, .. } => name,
+            Self::Guild { name, .. } => name,
+        }
+    }
+
+    pub fn message_count(&self) -> usize {
+        match self {
+            Self::DmOrGc { message_count, .. } => *message_count,
+            Self::Guild { message_count, .. } => *message_count,
+        }
+    }
+
+    /// Messages files backing this conversation; empty for piped reports.
+    pub fn message_files(&self) -> Vec<PathBuf> {
+        let files = match self {
+            Self::DmOrGc { messages_file, .. } => vec![messages_file.clone()],
+            Self::Guild { channels, .. } => channels
+                .iter()
+                .flat_map(|c| c.message_files.iter().cloned())
+                .collect(),
+        };
+        files
+            .into_iter()
+            .filter(|file| !file.as_os_str().is_empty())
+            .collect()
+    }
+}
@@ -0,0 +1,202 @@
+//! Advisory locks between instances sharing files that persist between runs,
+//! such as two runs over one package filling the same index cache entry. A
+//! lock is a file beside the locked one, created exclusively, that names the
+//! owner's process and when it was taken; dropping the lock removes it.
+//!
+//! A lock whose owner is no longer running, or that is older than any run
+//! should take, was left behind by a crashed instance and is taken over.
+//! Whether a process runs can only be told on Linux; elsewhere the age
+//! decides alone.
+
+use crate::errors::MyError;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Age after which a lock counts as abandoned even if its owner seems to run,
+/// since process IDs are reused.
+const STALE_AFTER: Duration = Duration::from_secs(30 * 60);
+/// How often a waiting instance checks the lock again.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+pub struct FileLock {
+    path: PathBuf,
+}
+
+impl FileLock {
+    /// Lock `path` for this process. While another instance holds the lock,
+    /// wait for it, calling `waiting` once, or fail right away without `wait`.
+    pub fn acquire(path: &Path, wait: bool, waiting: impl FnOnce()) -> Result<Self, MyError> {
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(".lock");
+        let path = PathBuf::from(lock_path);
+        let mut waiting = Some(waiting);
+        loop {
+            if try_create(&path)? {
+                return Ok(Self { path });
+            }
+            if is_stale(&path) {
+                // Whoever removes it first takes over; a lock that is already
+                // gone is no failure
+                match fs::remove_file(&path) {
+                    Err(error) if error.kind() != ErrorKind::NotFound => return Err(error.into()),
+                    _ => continue,
+                }
+            }
+            if !wait {
+                return Err(MyError::Locked(path));
+            }
+            if let Some(waiting) = waiting.take() {
+                waiting();
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Create the lock file with this process as its owner, or `false` if it
+/// exists already.
+fn try_create(path: &Path) -> Result<bool, MyError> {
+    if let Some(folder) = path.parent() {
+        fs::create_dir_all(folder)?;
+    }
+    let mut file = match OpenOptions::new().write(true).create_new(true).open(path) {
+        Ok(file) => file,
+        Err(error) if error.kind() == ErrorKind::AlreadyExists => return Ok(false),
+        Err(error) => return Err(error.into()),
+    };
+    writeln!(
+        file,
+        "{} {}",
+        std::process::id(),
+        unix_seconds(SystemTime::now())
+    )?;
+    Ok(true)
+}
+
+/// Whether the lock at `path` was abandoned. A lock that cannot be read may
+/// be one its owner is still writing, so only its age counts then.
+fn is_stale(path: &Path) -> bool {
+    let owner = fs::read_to_string(path).ok().and_then(|content| {
+        let (pid, taken) = content.trim().split_once(' ')?;
+        Some((pid.parse::<u32>().ok()?, taken.parse::<u64>().ok()?))
+    });
+    let taken = match owner {
+        Some((pid, _)) if process_ended(pid) == Some(true) => return true,
+        Some((_, taken)) => taken,
+        None => match fs::metadata(path).and_then(|metadata| metadata.modified()) {
+            Ok(modified) => unix_seconds(modified),
+            // Removed in the meantime, which the next attempt will notice
+            Err(_) => return false,
+        },
+    };
+    unix_seconds(SystemTime::now()).saturating_sub(taken) > STALE_AFTER.as_secs()
+}
+
+/// Whether process `pid` has ended, where that can be told.
+fn process_ended(pid: u32) -> Option<bool> {
+    if cfg!(target_os = "linux") {
+        return match fs::metadata(format!("/proc/{}", pid)) {
+            Ok(_) => Some(false),
+            Err(error) if error.kind() == ErrorKind::NotFound => Some(true),
+            Err(_) => None,
+        };
+    }
+    None
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    /// A lock file for `locked` as another instance would have left it.
+    fn leave_lock(locked: &Path, pid: u32, taken: u64) -> PathBuf {
+        let mut path = locked.as_os_str().to_owned();
+        path.push(".lock");
+        fs::write(&path, format!("{} {}\n", pid, taken)).unwrap();
+        path.into()
+    }
+
+    #[test]
+    fn a_held_lock_is_refused_without_waiting() {
+        let dir = tempfile::tempdir().unwrap();
+        let locked = dir.path().join("entry.json");
+        let lock = FileLock::acquire(&locked, false, || {}).unwrap();
+        assert!(dir.path().join("entry.json.lock").is_file());
+
+        assert!(matches!(
+            FileLock::acquire(&locked, false, || {}),
+            Err(MyError::Locked(_))
+        ));
+        drop(lock);
+        assert!(!dir.path().join("entry.json.lock").exists());
+        assert!(FileLock::acquire(&locked, false, || {}).is_ok());
+    }
+
+    #[test]
+    fn a_waiting_instance_gets_the_lock_once_released() {
+        let dir = tempfile::tempdir().unwrap();
+        let locked = dir.path().join("entry.json");
+        let lock = FileLock::acquire(&locked, true, || {}).unwrap();
+
+        let (waiting_sender, waiting) = mpsc::channel();
+        let waiter = {
+            let locked = locked.clone();
+            thread::spawn(move || {
+                let _lock = FileLock::acquire(&locked, true, move || {
+                    waiting_sender.send(()).unwrap();
+                })
+                .unwrap();
+            })
+        };
+        // The waiter reports that it waits, once, and only gets the lock later
+        waiting.recv_timeout(Duration::from_secs(5)).unwrap();
+        thread::sleep(POLL_INTERVAL * 3);
+        assert!(!waiter.is_finished());
+        drop(lock);
+        waiter.join().unwrap();
+        assert!(waiting.try_recv().is_err());
+    }
+
+    #[test]
+    fn old_locks_are_taken_over_even_if_their_owner_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let locked = dir.path().join("entry.json");
+        let stale = SystemTime::now() - STALE_AFTER - Duration::from_secs(60);
+        leave_lock(&locked, std::process::id(), unix_seconds(stale));
+        assert!(FileLock::acquire(&locked, false, || {}).is_ok());
+    }
+
+    #[test]
+    fn recent_locks_of_running_owners_are_respected() {
+        let dir = tempfile::tempdir().unwrap();
+        let locked = dir.path().join("entry.json");
+        let path = leave_lock(&locked, std::process::id(), unix_seconds(SystemTime::now()));
+        assert!(!is_stale(&path));
+        assert!(FileLock::acquire(&locked, false, || {}).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn locks_of_ended_processes_are_taken_over() {
+        let dir = tempfile::tempdir().unwrap();
+        let locked = dir.path().join("entry.json");
+        // Above any process ID Linux hands out
+        leave_lock(&locked, u32::MAX, unix_seconds(SystemTime::now()));
+        assert!(FileLock::acquire(&locked, false, || {}).is_ok());
+    }
+}
@@ -1,161 +1,1918 @@
-use clap::{Parser, ValueEnum};
-use std::{cmp::Reverse, path::PathBuf};
+use chrono::Datelike;
+#[cfg(feature = "sqlite")]
+use chrono::Utc;
+use clap::{
+    builder::PossibleValuesParser, error::ErrorKind, parser::ValueSource, CommandFactory,
+    FromArgMatches, Parser, Subcommand, ValueEnum,
+};
+use rand::{rngs::StdRng, SeedableRng};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, HashMap},
+    ffi::OsString,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Duration,
+};
 
-mod errors;
-mod file_operations;
+#[cfg(feature = "charts")]
+use discord_gdpr_counter::chart;
+#[cfg(feature = "clipboard")]
+use discord_gdpr_counter::clipboard;
+#[cfg(feature = "zip")]
+use discord_gdpr_counter::mirror;
+#[cfg(feature = "parquet")]
+use discord_gdpr_counter::parquet;
+#[cfg(feature = "sqlite")]
+use discord_gdpr_counter::sqlite;
+use discord_gdpr_counter::{
+    analysis, breakdown, buckets, budget, color, concentration, diff, doctor, donut, dump, errors,
+    features, file_operations, footprint, inventory, json, messages, metrics, notes, output,
+    progress, provenance, redact, render, report, schema, sections, selector, site, split,
+    suggestions, template, timeline, volume, warnings, width, window, wire, zone, Conversation,
+    ConversationType, FilterStage, FilterStageKind, GroupDmPolicy,
+};
 
-use errors::MyError;
-use file_operations::{load_mappings, prepare_data_root, process_conversations};
+use analysis::{spawn_analysis, AnalysisOptions, Progress, Stage, Timings};
+use buckets::CountStyle;
+use budget::Budget;
+#[cfg(feature = "color")]
+use color::ColorMode;
+use color::Palette;
+use concentration::{concentration, ConcentrationLevels};
+use errors::{ErrorCategory, MyError};
+use file_operations::{ChannelDetail, ProcessOptions, ProcessingOrder};
+use json::JsonContext;
+use messages::{message_datetimes, sample_messages, Message, SystemExclusion};
+use output::Output;
+use progress::{progress_sink, ProgressMode};
+use provenance::{
+    effective_options, override_option, write_effective_options, EffectiveOption, Source,
+};
+use redact::Redaction;
+use render::{
+    render, render_to_targets, write_hidden_hint, write_tree, OutputFormat, OutputTarget,
+    ResultsView, TreeFormat, TreeGlyphs,
+};
+use report::Report;
+use sections::GroupBy;
+use selector::Selector;
+use suggestions::{suggest, RunSummary};
+use template::Template;
+use timeline::threshold_timeline;
+use volume::ProcessingStats;
+use warnings::{print_warnings, Warning};
+use width::NameWidth;
+use window::{window_note, DisplayWindow};
+use zone::Zone;
+
+/// Exit code when `--participant` matches no conversation at all.
+const NO_PARTICIPANT_EXIT_CODE: u8 = 3;
+
+/// Exit code when `--verify-against` found the input to differ from the archive.
+#[cfg(feature = "zip")]
+const MIRROR_MISMATCH_EXIT_CODE: u8 = 6;
+
+/// Conversations shown on a terminal when no limit is given
+const DEFAULT_TERMINAL_LIMIT: usize = 25;
+
+/// Maximum number of characters shown per sampled message
+const SAMPLE_CONTENT_LIMIT: usize = 200;
 
 /// Discord Message Counter
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Cli {
-    /// Path to the Discord data package (ZIP file or extracted folder)
-    input_path: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Path to the Discord data package (ZIP file or extracted folder), or '-'
+    /// to read a report emitted with '--emit internal' from stdin
+    #[arg(required_unless_present = "explain")]
+    input_path: Option<PathBuf>,
 
-    /// Limit the number of conversations displayed
+    /// Explain how a metric is computed and what affects it, or every metric with 'all'
+    #[arg(long, value_name = "METRIC", value_parser = PossibleValuesParser::new(metrics::explain_choices()))]
+    explain: Option<String>,
+
+    /// Limit the number of conversations displayed; 0 shows all of them
+    /// [default: 25 on a terminal, unlimited otherwise]
     #[arg(short, long)]
     limit: Option<usize>,
 
-    /// Filter by conversation type (dm, guild)
+    /// Show every conversation, even on a terminal
+    #[arg(long, conflicts_with = "limit")]
+    all: bool,
+
+    /// Show only DMs and group DMs with this user ID among their recipients
+    #[arg(long, value_name = "USER_ID")]
+    participant: Option<String>,
+
+    /// Filter by conversation type (dm, group-dm, guild)
     #[arg(short, long, value_enum, value_name = "TYPE")]
     conversation_type: Option<ConversationType>,
 
+    /// Also list servers you're no longer in, those missing from
+    /// servers/index.json, in a section of their own
+    #[arg(long)]
+    include_departed: bool,
+
+    /// List only servers you're no longer in
+    #[arg(long, conflicts_with = "include_departed")]
+    only_departed: bool,
+
+    /// Leave out DMs with bots; DMs the package has no bot flag for are kept
+    #[arg(long)]
+    exclude_bots: bool,
+
+    /// List only DMs with bots
+    #[arg(long, conflicts_with = "exclude_bots")]
+    only_bots: bool,
+
+    /// Where group DMs are counted when conversations are split by type
+    #[arg(long, value_enum, value_name = "TYPE", default_value_t = GroupDmPolicy::Separate)]
+    group_dms_as: GroupDmPolicy,
+
+    /// Messages each conversation's share is taken of; channels always show
+    /// their share of the guild
+    #[arg(long, value_enum, value_name = "TOTAL", default_value_t = PercentOf::All)]
+    percent_of: PercentOf,
+
+    /// Keep the #1234 discriminators of pre-migration usernames in DM names
+    #[arg(long)]
+    keep_discriminators: bool,
+
     /// Minimum message count to display
     #[arg(short, long, default_value_t = 1)]
     min_messages: usize,
+
+    /// Show a single conversation, selected by name or ID
+    #[arg(long, value_name = "CONVERSATION")]
+    detail: Option<String>,
+
+    /// Write everything known about a single conversation, selected by name
+    /// or ID, as one JSON document to --output or stdout
+    #[arg(long, value_name = "CONVERSATION", conflicts_with = "detail")]
+    dump: Option<String>,
+
+    /// Allow reading message contents (required by content-based features)
+    #[arg(long)]
+    scan_content: bool,
+
+    /// Print N randomly selected messages of the --detail conversation, at
+    /// most 10000
+    #[arg(long, value_name = "N", value_parser = messages::parse_sample_size, requires_all = ["detail", "scan_content"])]
+    sample_messages: Option<usize>,
+
+    /// Break the --detail conversation down into rows per time unit
+    #[arg(long, value_enum, value_name = "UNIT", requires = "detail")]
+    breakdown: Option<Breakdown>,
+
+    /// Render the --breakdown rows as a single-row heatmap
+    #[arg(long, requires = "breakdown")]
+    heatmap: bool,
+
+    /// Write the results to FILE instead of stdout, in the format its
+    /// extension names unless --format is given; with --breakdown, write its
+    /// rows as CSV to FILE
+    #[arg(long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// Replace files that --output, --also-write, --export-site,
+    /// --split-output or --export-parquet would write if they already exist
+    #[arg(long)]
+    force: bool,
+
+    /// Seed for random sampling and ordering, for reproducible output
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Count messages with the same ID within a channel only once
+    #[arg(long)]
+    dedupe_messages: bool,
+
+    /// Skip Discord system messages (pins, boosts, calls); 'aggressive' also
+    /// matches known system contents in packages without a message type
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "typed"
+    )]
+    exclude_system: Option<SystemExclusion>,
+
+    /// Skip messages with neither contents nor attachments, stubs Discord
+    /// keeps of deleted content
+    #[arg(long)]
+    exclude_empty_messages: bool,
+
+    /// Show how many messages per conversation and overall are empty stubs
+    #[arg(long)]
+    empty_message_stats: bool,
+
+    /// Stop processing after this many seconds and report partial results
+    #[arg(long, value_name = "SECONDS")]
+    max_seconds: Option<u64>,
+
+    /// Stop processing once this many messages were counted and report partial results
+    #[arg(long, value_name = "N")]
+    max_messages: Option<usize>,
+
+    /// Order in which channels are processed, deciding what a partial result covers
+    #[arg(long, value_enum, value_name = "ORDER", default_value_t = ProcessingOrder::Size)]
+    processing_order: ProcessingOrder,
+
+    /// Merge channels with the same name within a guild, e.g. a recreated #general
+    #[arg(long)]
+    merge_same_name_channels: bool,
+
+    /// Show guild totals without their channels, which saves memory on large
+    /// packages; ignored where channel detail is needed
+    #[arg(long)]
+    compact: bool,
+
+    /// Keep only the N channels with the most messages per guild
+    #[arg(long, value_name = "N")]
+    channels_limit: Option<usize>,
+
+    /// Annotate conversations whose analytics show more than N messages
+    /// beyond what the package contains
+    #[arg(long, value_name = "N", default_value_t = 50)]
+    discrepancy_threshold: u64,
+
+    /// Time zone for day, week, month and year boundaries: UTC, an offset
+    /// such as +05:30, or a name such as Europe/Berlin
+    #[arg(long, value_name = "ZONE", default_value = "UTC")]
+    timezone: Zone,
+
+    /// Restrict charts and breakdowns to START..END (END exclusive, either
+    /// side optional) without changing any counts
+    #[arg(long, value_name = "START..END")]
+    display_window: Option<DisplayWindow>,
+
+    /// Summarize how many distinct servers, channels and DMs were active per year
+    #[arg(long)]
+    breadth: bool,
+
+    /// Count, per year, the conversations with at least N messages in that
+    /// year; --conversation-type dm tracks friendships, guild servers
+    #[arg(long, value_name = "N", value_parser = timeline::parse_threshold)]
+    threshold_timeline: Option<usize>,
+
+    /// Report how many sessions of messages less than --session-gap apart
+    /// each conversation had, and how long they were
+    #[arg(long)]
+    session_stats: bool,
+
+    /// Also show each conversation's own busiest day and how many messages
+    /// it had, in --timezone's calendar
+    #[arg(long)]
+    activity_stats: bool,
+
+    /// Minutes without a message that end a session
+    #[arg(long, value_name = "MINUTES", default_value_t = 30)]
+    session_gap: u32,
+
+    /// Shares of all messages, in percent, to count the fewest conversations
+    /// holding them for
+    #[arg(long, value_name = "PERCENTS", default_value = "50,80,95")]
+    concentration_levels: ConcentrationLevels,
+
+    /// Order of the listed conversations [default: messages]; makes
+    /// '--format ndjson' wait for every result instead of streaming
+    #[arg(long, value_enum, value_name = "KEY")]
+    sort: Option<SortKey>,
+
+    /// Show earlier names of guild channels as recorded in the analytics
+    #[arg(long)]
+    historical_names: bool,
+
+    /// Report time spent in voice channels per guild from the analytics
+    #[arg(long)]
+    voice_stats: bool,
+
+    /// Compare the channels messages/index.json lists with the channel
+    /// folders and the channels with messages, with examples of each difference
+    #[arg(long)]
+    coverage: bool,
+
+    /// Write every channel of every --coverage difference to a CSV file;
+    /// implies --coverage
+    #[arg(long, value_name = "FILE.csv")]
+    coverage_export: Option<PathBuf>,
+
+    /// Render linear sentences without tree glyphs for screen readers; implies
+    /// --progress none and plain digits unless overridden
+    #[arg(long)]
+    accessible: bool,
+
+    /// Same as --progress none
+    #[arg(long, overrides_with = "progress")]
+    no_progress: bool,
+
+    /// How to show progress on stderr while counting
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        default_value_t,
+        overrides_with = "no_progress"
+    )]
+    progress: ProgressMode,
+
+    /// Group digits with thousands separators even with --accessible
+    #[arg(long)]
+    thousands_separators: bool,
+
+    /// Neither read nor store the cached channel index of the package
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Fail instead of waiting while another instance indexes the same
+    /// package for the cache
+    #[arg(long, conflicts_with = "no_cache")]
+    no_wait: bool,
+
+    /// Print how long each stage of the run took to stderr
+    #[arg(long)]
+    timing: bool,
+
+    /// Maximum number of distinct warnings listed; 0 lists all of them
+    #[arg(long, value_name = "N", default_value_t = 20)]
+    warnings_limit: usize,
+
+    /// Show where the package's bytes live instead of counting messages
+    #[arg(long)]
+    package_breakdown: bool,
+
+    /// For contributors: instead of counting, read every channel.json and a
+    /// sample of messages strictly and list the fields the tool doesn't know
+    #[arg(long)]
+    validate_strict: bool,
+
+    /// Don't suggest flags to try after the results
+    #[arg(long)]
+    no_suggestions: bool,
+
+    /// Hide informational lines such as the filter summary
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// Leave out the summary of totals after the conversations, in text and
+    /// JSON
+    #[arg(long)]
+    no_summary: bool,
+
+    /// Print additional per-conversation details
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// Extract a ZIP archive even when a folder extracted from it lies next to it
+    #[cfg(feature = "zip")]
+    #[arg(long)]
+    force_extract: bool,
+
+    /// Extract ZIP archives below PATH instead of the system's temp directory
+    #[cfg(feature = "zip")]
+    #[arg(long, value_name = "PATH")]
+    temp_dir: Option<PathBuf>,
+
+    /// Check that the messages files of the input folder match those of the
+    /// original ZIP archive it was copied from, e.g. with attachments removed
+    #[cfg(feature = "zip")]
+    #[arg(long, value_name = "ORIGINAL")]
+    verify_against: Option<PathBuf>,
+
+    /// Look up names of guilds missing from the package via Discord's API
+    /// (uses the DISCORD_BOT_TOKEN environment variable when set)
+    #[cfg(feature = "net")]
+    #[arg(long)]
+    resolve_online: bool,
+
+    /// Maximum number of API requests made by --resolve-online
+    #[cfg(feature = "net")]
+    #[arg(long, value_name = "N", default_value_t = 50)]
+    resolve_limit: usize,
+
+    /// Output format written to stdout
+    #[arg(short, long, value_enum, value_name = "FORMAT", default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// Write one line per conversation from PATTERN instead of a format, with
+    /// {name}, {id}, {kind}, {count}, {rank}, {percent} and {channels}
+    #[arg(long, value_name = "PATTERN", conflicts_with = "format")]
+    template: Option<String>,
+
+    /// Render the results with a Jinja2-style template file instead of a
+    /// format, given `conversations` and `total`
+    #[cfg(feature = "templates")]
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["format", "template"])]
+    template_file: Option<PathBuf>,
+
+    /// Also put what is written to stdout on the system clipboard
+    #[cfg(feature = "clipboard")]
+    #[arg(long)]
+    copy: bool,
+
+    /// Color text and table output: only on a terminal without NO_COLOR
+    /// set (auto), always or never
+    #[cfg(feature = "color")]
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t = ColorMode::Auto)]
+    color: ColorMode,
+
+    /// Characters the text tree's branches are drawn with; ascii uses |--
+    /// and `-- for terminals that garble box-drawing characters
+    #[arg(long, value_enum, value_name = "STYLE", default_value_t = TreeGlyphs::Unicode)]
+    tree_style: TreeGlyphs,
+
+    /// Columns guild channels are indented by in the text tree
+    #[arg(long, value_name = "N", default_value_t = 4)]
+    tree_indent: usize,
+
+    /// Never shorten names in the text listing; by default they are cut
+    /// short with … so each line fits the terminal
+    #[arg(long, conflicts_with = "max_name_len")]
+    full_names: bool,
+
+    /// Shorten names in the text listing to at most N columns, also when
+    /// not writing to a terminal
+    #[arg(long, value_name = "N")]
+    max_name_len: Option<usize>,
+
+    /// Wrap '--format mermaid' in a ```mermaid code block for Markdown
+    #[arg(long)]
+    fenced: bool,
+
+    /// Split the text listing into sections in a fixed order; channels of
+    /// unknown servers always come last in theirs
+    #[arg(long, value_enum, value_name = "GROUPING")]
+    group_by: Option<GroupBy>,
+
+    /// List sections without conversations as "none" instead of leaving
+    /// them out
+    #[arg(long, requires = "group_by")]
+    show_empty_sections: bool,
+
+    /// List guild channels as rows of their own in tabular formats
+    #[arg(long)]
+    channels: bool,
+
+    /// Add a total row per guild to CSV output, before its channel rows
+    #[arg(long)]
+    guild_totals: bool,
+
+    /// List every channel and DM on its own line as 'Guild / channel',
+    /// sorted by count across all guilds, with --limit counting channels;
+    /// other formats list each channel as a guild of one channel
+    #[arg(long, conflicts_with = "guild_totals")]
+    flat: bool,
+
+    /// Replace names and IDs before sharing: numbered placeholders (full) or
+    /// short hashes of the participants' user IDs (hashed), which match
+    /// across reports made with the same --redact-salt
+    #[arg(long, value_enum, value_name = "MODE", num_args = 0..=1, require_equals = true, default_missing_value = "full")]
+    redact: Option<Redaction>,
+
+    /// Salt for --redact=hashed; only reports sharing it can be correlated
+    #[arg(long, value_name = "SALT", default_value = "", requires = "redact")]
+    redact_salt: String,
+
+    /// Show message counts as ranges such as 10k–25k instead of exact
+    /// numbers; the order still follows the exact counts
+    #[arg(long, conflicts_with_all = ["emit", "export_site"])]
+    bucket_counts: bool,
+
+    /// Keep --notes annotations in redacted output
+    #[arg(long, requires = "redact")]
+    redact_keep_notes: bool,
+
+    /// Show annotations from a TOML or JSON file mapping conversation IDs to notes
+    #[arg(long, value_name = "FILE")]
+    notes: Option<PathBuf>,
+
+    /// Write index.json and per-conversation detail files for a static web viewer to DIR
+    #[arg(long, value_name = "DIR")]
+    export_site: Option<PathBuf>,
+
+    /// Also write the results in --format to DIR as one file per guild and
+    /// one for all DMs, with manifest.json listing each conversation's file
+    #[arg(long, value_name = "DIR")]
+    split_output: Option<PathBuf>,
+
+    /// Upsert the results into the SQLite database FILE, keyed by ID and --export-date
+    #[cfg(feature = "sqlite")]
+    #[arg(long, value_name = "FILE", conflicts_with = "bucket_counts")]
+    export_sqlite: Option<PathBuf>,
+
+    /// Draw how all messages split between the largest conversations as a
+    /// donut chart to the SVG file FILE
+    #[arg(long, value_name = "FILE")]
+    chart_svg: Option<PathBuf>,
+
+    /// Conversations given a slice of their own by --chart-svg; the rest
+    /// share one "Other" slice
+    #[arg(long, value_name = "N", default_value_t = 8, requires = "chart_svg")]
+    chart_svg_top: usize,
+
+    /// Draw the conversations with the most messages as a bar chart to the PNG file FILE
+    #[cfg(feature = "charts")]
+    #[arg(long, value_name = "FILE", conflicts_with = "bucket_counts")]
+    chart: Option<PathBuf>,
+
+    /// Number of conversations drawn by --chart
+    #[cfg(feature = "charts")]
+    #[arg(long, value_name = "N", default_value_t = 10, requires = "chart")]
+    chart_top: usize,
+
+    /// TrueType font for --chart [default: a system font such as DejaVu Sans]
+    #[cfg(feature = "charts")]
+    #[arg(long, value_name = "FILE", requires = "chart")]
+    chart_font: Option<PathBuf>,
+
+    /// Write one row per channel and DM to the Parquet file FILE
+    #[cfg(feature = "parquet")]
+    #[arg(long, value_name = "FILE", conflicts_with = "bucket_counts")]
+    export_parquet: Option<PathBuf>,
+
+    /// Date the package was exported, recorded with --export-sqlite [default: today]
+    #[cfg(feature = "sqlite")]
+    #[arg(long, value_name = "YYYY-MM-DD", requires = "export_sqlite")]
+    export_date: Option<chrono::NaiveDate>,
+
+    /// Additionally write the results in FORMAT to PATH; repeatable
+    #[arg(long, value_name = "FORMAT:PATH")]
+    also_write: Vec<OutputTarget>,
+
+    /// Write the unfiltered report in a machine format instead of rendering it
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    emit: Option<Emit>,
+
+    /// Every option with where its value came from
+    #[arg(skip)]
+    effective_options: Vec<EffectiveOption>,
+
+    /// What the input path holds, as the subcommand run says
+    #[arg(skip)]
+    input_kind: InputKind,
 }
 
-#[derive(ValueEnum, Clone, Debug)]
-enum ConversationType {
-    Dm,
-    Guild,
+impl Cli {
+    /// Parse the arguments, taking the format from the --output file's
+    /// extension unless --format was given.
+    fn parse_inferring_format() -> Self {
+        Self::parse_from_inferring_format(std::env::args_os())
+    }
+
+    fn parse_from_inferring_format(args: impl IntoIterator<Item = OsString>) -> Self {
+        let command = Cli::command();
+        let matches = command.clone().get_matches_from(args);
+        let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
+        match cli.command.take() {
+            Some(Command::Count { args }) => return Self::parse_subcommand("count", args),
+            Some(Command::Render { args }) => return Self::parse_subcommand("render", args),
+            command => cli.command = command,
+        }
+        cli.effective_options = effective_options(&command, &matches);
+        #[cfg(feature = "templates")]
+        let templated = cli.template.is_some() || cli.template_file.is_some();
+        #[cfg(not(feature = "templates"))]
+        let templated = cli.template.is_some();
+
+        if !templated && matches.value_source("format") != Some(ValueSource::CommandLine) {
+            if let Some(format) = cli.output.as_deref().and_then(OutputFormat::of_path) {
+                cli.format = format;
+                if let Some(name) = format.to_possible_value() {
+                    override_option(
+                        &mut cli.effective_options,
+                        "format",
+                        name.get_name(),
+                        Source::InferredFromOutput,
+                    );
+                }
+            }
+        }
+        cli
+    }
+
+    /// Parse the arguments of `count` or `render`, which are those of a run
+    /// without a subcommand.
+    fn parse_subcommand(name: &str, args: Vec<OsString>) -> Self {
+        let bin = format!("{} {}", Cli::command().get_name(), name);
+        let mut cli = Self::parse_from_inferring_format(std::iter::once(bin.into()).chain(args));
+        let stdin = cli.input_path.as_deref() == Some(Path::new("-"));
+        let problem = match (cli.command.is_some(), name) {
+            (true, _) => Some(format!("'{}' takes no further subcommand", name)),
+            (false, "count") if stdin => Some(
+                "'count' reads a data package; pass a report on stdin to 'render -'".to_string(),
+            ),
+            _ => None,
+        };
+        if let Some(problem) = problem {
+            Cli::command()
+                .error(ErrorKind::ArgumentConflict, problem)
+                .exit();
+        }
+        cli.input_kind = match name {
+            "count" => InputKind::Package,
+            _ => InputKind::Report,
+        };
+        cli
+    }
+
+    fn count_style(&self) -> CountStyle {
+        match self.bucket_counts {
+            true => CountStyle::Bucketed,
+            false => CountStyle::Exact,
+        }
+    }
+
+    fn departed(&self) -> DepartedFilter {
+        match (self.include_departed, self.only_departed) {
+            (_, true) => DepartedFilter::Only,
+            (true, false) => DepartedFilter::Include,
+            (false, false) => DepartedFilter::Exclude,
+        }
+    }
+
+    fn bots(&self) -> BotFilter {
+        match (self.exclude_bots, self.only_bots) {
+            (_, true) => BotFilter::Only,
+            (true, false) => BotFilter::Exclude,
+            (false, false) => BotFilter::Include,
+        }
+    }
+
+    fn sort(&self) -> SortKey {
+        self.sort.unwrap_or(SortKey::Messages)
+    }
+
+    /// Whether `--format ndjson` is written while counting, in the order
+    /// conversations complete. Options that need every conversation, or
+    /// change them once counted, make it wait for the full results.
+    fn streams_ndjson(&self) -> bool {
+        #[cfg(feature = "clipboard")]
+        let copies = self.copy;
+        #[cfg(not(feature = "clipboard"))]
+        let copies = false;
+
+        self.format == OutputFormat::Ndjson
+            && !copies
+            && self.sort.is_none()
+            && self.limit.is_none()
+            && self.detail.is_none()
+            && self.emit.is_none()
+            && self.redact.is_none()
+            && self.notes.is_none()
+            && !self.historical_names
+            && !self.flat
+    }
 }
 
-#[derive(Debug)]
-enum Conversation {
-    DmOrGc {
-        name: String,
-        message_count: usize,
+#[derive(Subcommand)]
+enum Command {
+    /// Check a data package for common problems
+    Doctor {
+        /// Path to the Discord data package (ZIP file or extracted folder)
+        input_path: PathBuf,
+    },
+    /// Count a data package; takes every argument a run without a
+    /// subcommand does, e.g. '--emit internal'
+    #[command(disable_help_flag = true)]
+    Count {
+        #[arg(
+            trailing_var_arg = true,
+            allow_hyphen_values = true,
+            value_name = "ARGS"
+        )]
+        args: Vec<OsString>,
+    },
+    /// Render a report emitted with '--emit internal', from a file or from
+    /// stdin with '-'; takes every argument a run without a subcommand does
+    #[command(disable_help_flag = true)]
+    Render {
+        #[arg(
+            trailing_var_arg = true,
+            allow_hyphen_values = true,
+            value_name = "ARGS"
+        )]
+        args: Vec<OsString>,
     },
-    Guild {
-        name: String,
-        message_count: usize,
-        channels: Vec<Channel>,
+    /// Compare the counts of two reports emitted with '--emit internal'
+    Diff {
+        /// The earlier report, or '-' for stdin
+        old: PathBuf,
+        /// The later report, or '-' for stdin
+        new: PathBuf,
     },
 }
 
-#[derive(Debug, Clone)]
-struct Channel {
-    name: String,
-    message_count: usize,
+/// What the input path holds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum InputKind {
+    /// A data package, or a report on stdin when the path is '-'
+    #[default]
+    Any,
+    /// A data package, for `count`
+    Package,
+    /// A report emitted with '--emit internal', for `render`
+    Report,
 }
 
-impl Conversation {
-    fn message_count(&self) -> usize {
+#[derive(ValueEnum, Clone, Debug)]
+enum Breakdown {
+    /// ISO 8601 weeks
+    Week,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum Emit {
+    /// Versioned JSON read by 'render' and 'diff', and on stdin when the
+    /// input path is '-'
+    Internal,
+}
+
+/// What the listed conversations are ordered by, largest first.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum SortKey {
+    Messages,
+    /// Number of sessions; implies --session-stats
+    Sessions,
+}
+
+/// What the share shown beside each conversation is a share of.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum PercentOf {
+    /// The conversations left after filtering
+    Filtered,
+    /// Every counted conversation
+    #[default]
+    All,
+}
+
+/// Which guilds are listed by whether the owner is still in them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DepartedFilter {
+    /// Only guilds still in `servers/index.json`
+    Exclude,
+    Include,
+    /// Only guilds missing from it
+    Only,
+}
+
+impl DepartedFilter {
+    fn keeps(self, conversation: &Conversation) -> bool {
         match self {
-            Self::DmOrGc { message_count, .. } => *message_count,
-            Self::Guild { message_count, .. } => *message_count,
+            Self::Exclude => !conversation.is_departed(),
+            Self::Include => true,
+            Self::Only => conversation.is_departed(),
         }
     }
+}
+
+/// Which DMs are listed by whether the other side is a bot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BotFilter {
+    Include,
+    Exclude,
+    Only,
+}
 
-    fn print_tree(&self) {
+impl BotFilter {
+    fn keeps(self, conversation: &Conversation) -> bool {
         match self {
-            Self::DmOrGc {
-                name,
-                message_count,
-            } => {
-                println!("{} [{} messages]", name, message_count);
-            }
-            Self::Guild {
-                name,
-                message_count,
-                channels,
-            } => {
-                println!("{} [{} messages]", name, message_count);
-                let mut sorted_channels = channels.clone();
-                sorted_channels.sort_unstable_by(|a, b| b.message_count.cmp(&a.message_count));
-                for (i, channel) in sorted_channels.iter().enumerate() {
-                    let connector = if i == sorted_channels.len() - 1 {
-                        "└──"
-                    } else {
-                        "├──"
-                    };
-                    println!(
-                        "    {} {} [{} messages]",
-                        connector, channel.name, channel.message_count
-                    );
-                }
-                println!();
-            }
+            Self::Include => true,
+            Self::Exclude => !conversation.is_bot(),
+            Self::Only => conversation.is_bot(),
         }
     }
 }
 
-fn main() -> Result<(), MyError> {
-    let cli = Cli::parse();
+fn main() -> ExitCode {
+    if let Some((flag, feature)) = features::compiled_out_flag(std::env::args_os().skip(1)) {
+        eprintln!(
+            "Error: {} needs the '{}' feature, which this build was compiled without",
+            flag, feature
+        );
+        return ExitCode::from(ErrorCategory::Input.exit_code());
+    }
+    match run(Cli::parse_inferring_format()) {
+        Ok(code) => code,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            ExitCode::from(error.category().exit_code())
+        }
+    }
+}
 
-    // Prepare data root
-    let data_root = prepare_data_root(&cli.input_path)?;
+fn run(cli: Cli) -> Result<ExitCode, MyError> {
+    if let Some(Command::Doctor { input_path }) = &cli.command {
+        return doctor::run(input_path).map(|()| ExitCode::SUCCESS);
+    }
+    if let Some(Command::Diff { old, new }) = &cli.command {
+        return diff::run(old, new).map(|()| ExitCode::SUCCESS);
+    }
+    if let Some(ref metric) = cli.explain {
+        metrics::explain(metric, &mut io::stdout().lock())?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    let input_path = cli.input_path.clone().expect("clap requires an input path");
+    #[cfg(feature = "templates")]
+    let template_file = cli.template_file.as_deref();
+    #[cfg(not(feature = "templates"))]
+    let template_file: Option<&Path> = None;
+    let template = template::load(cli.template.as_deref(), template_file)?;
+    if cli.verbose {
+        write_effective_options(&cli.effective_options, &mut io::stderr().lock())?;
+    }
+    #[cfg(feature = "xlsx")]
+    if cli.format == OutputFormat::Xlsx && cli.output.is_none() && io::stdout().is_terminal() {
+        return Err(MyError::XlsxToTerminal);
+    }
+    if cli.validate_strict {
+        schema::run(&input_path, &mut io::stdout().lock())?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    if cli.package_breakdown {
+        let footprint = footprint::measure(&input_path)?;
+        footprint::write_footprint(&footprint, &mut io::stdout().lock())?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    // Count the package, or take an already counted report
+    let reads_report = match cli.input_kind {
+        InputKind::Any => input_path == Path::new("-"),
+        InputKind::Package => false,
+        InputKind::Report => true,
+    };
+    let mut timings = Timings::default();
+    let mut stream = match !reads_report && cli.streams_ndjson() {
+        true => Some(open_output(&cli)?),
+        false => None,
+    };
+    let mut report = if reads_report {
+        wire::read_report_at(&input_path)?
+    } else {
+        count_package(
+            &input_path,
+            &cli,
+            &mut timings,
+            stream.as_mut().map(|out| out as _),
+        )?
+    };
+
+    // Incomplete results should not be read before this is, so it comes first
+    for warning in &report.warnings {
+        if matches!(warning, Warning::StillExtracting { .. }) {
+            eprintln!("Warning: {}", warning);
+            eprintln!();
+        }
+    }
 
-    // Load mappings
-    let (channel_mapping, guild_mapping) = load_mappings(&data_root)?;
+    // Notes are the user's own words about people, so redaction drops them
+    // too; kept ones are attached first to be keyed by the redacted IDs
+    if let (Some(ref path), true) = (&cli.notes, keeps_notes(&cli)) {
+        attach_notes(&mut report, notes::load_notes(path)?);
+    }
+
+    if let Some(mode) = cli.redact {
+        redact::redact(&mut report, mode, &cli.redact_salt);
+    }
+
+    if let Some(Emit::Internal) = cli.emit {
+        wire::write_report(&report, io::stdout().lock())?;
+        return Ok(ExitCode::SUCCESS);
+    }
 
-    // Process conversations
-    let conversations = process_conversations(&data_root, &channel_mapping, &guild_mapping)?;
+    timings.enter(Stage::Rendering);
+
+    if let Some(ref query) = cli.dump {
+        let conversation = Selector::new(query).select(&report.conversations)?;
+        let mut out = open_output(&cli)?;
+        dump::write_dump(
+            conversation,
+            &report,
+            cli.group_dms_as,
+            cli.timezone,
+            &mut out,
+        )?;
+        out.finish()?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    // Show a single conversation in detail
+    if let Some(ref query) = cli.detail {
+        let conversation = Selector::new(query).select(&report.conversations)?;
+        let mut stdout = io::stdout().lock();
+        if let Some(ref partial) = report.partial {
+            println!("{}", partial);
+            println!();
+        }
+        if let Some(ref window) = cli.display_window {
+            println!("{}", window_note(window));
+            println!();
+        }
+        let format = TreeFormat {
+            glyphs: cli.tree_style,
+            indent: cli.tree_indent,
+            counts: cli.count_style(),
+            palette: stdout_palette(&cli, io::stdout().is_terminal()),
+            names: name_width(&cli, io::stdout().is_terminal()),
+            verbose: cli.verbose,
+            empty_messages: cli.empty_message_stats,
+            excluded_empty: cli.exclude_empty_messages,
+            share_total: report
+                .conversations
+                .iter()
+                .map(|c| c.message_count() as u64)
+                .sum(),
+        };
+        write_tree(conversation, &report, format, false, &mut stdout)?;
 
-    // Filter and sort conversations
-    let filtered_conversations = filter_and_sort_conversations(
-        conversations,
-        &cli.conversation_type,
+        if let Some(n) = cli.sample_messages {
+            let mut rng = match cli.seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy(),
+            };
+            let sample = sample_messages(&conversation.message_files(), n, &mut rng)?;
+            print_message_sample(&sample);
+        }
+
+        if let Some(Breakdown::Week) = cli.breakdown {
+            let datetimes = message_datetimes(&conversation.message_files())?;
+            let weeks = breakdown::weekly_counts(
+                datetimes
+                    .iter()
+                    .map(|dt| cli.timezone.local_date(dt))
+                    .filter(|&date| cli.display_window.is_none_or(|w| w.contains(date))),
+            );
+            if cli.heatmap {
+                breakdown::print_weekly_heatmap(&weeks);
+            } else {
+                breakdown::print_weekly_rows(&weeks);
+            }
+            if let Some(ref output) = cli.output {
+                breakdown::write_weekly_csv(&weeks, output, cli.force)?;
+            }
+        }
+
+        timings.finish();
+        if cli.timing {
+            timings.print();
+            report
+                .processing_stats
+                .iter()
+                .for_each(ProcessingStats::print);
+        }
+        print_warnings(&report.warnings, cli.warnings_limit);
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(ref user_id) = cli.participant {
+        if !report
+            .conversations
+            .iter()
+            .any(|c| c.has_participant(user_id))
+        {
+            eprintln!(
+                "No DM or group DM has user {} among its recipients. Guild channels cannot \
+                 be matched by participant since the package has no member lists.",
+                user_id
+            );
+            return Ok(ExitCode::from(NO_PARTICIPANT_EXIT_CODE));
+        }
+    }
+
+    // Filter and sort conversations, or with --flat their channels
+    let flattened: Vec<Conversation> = match cli.flat {
+        true => report
+            .conversations
+            .iter()
+            .flat_map(Conversation::explode)
+            .collect(),
+        false => Vec::new(),
+    };
+    let (mut filtered_conversations, filter_stages) = filter_and_sort_conversations(
+        match cli.flat {
+            true => &flattened,
+            false => &report.conversations,
+        },
+        cli.participant.as_deref(),
+        cli.conversation_type,
+        cli.group_dms_as,
+        cli.departed(),
+        cli.bots(),
         cli.min_messages,
-        cli.limit,
     );
+    if let (SortKey::Sessions, Some(sessions)) = (cli.sort(), &report.sessions) {
+        // Stable, so conversations with as many sessions stay ordered by count
+        filtered_conversations.sort_by_key(|conv| {
+            Reverse(
+                sessions
+                    .by_conversation
+                    .get(conv.id())
+                    .map_or(0, |s| s.sessions),
+            )
+        });
+    }
+    if filtered_conversations.is_empty() {
+        // Structured outputs still get their (empty) documents below
+        eprintln!("{}", empty_result_diagnostic(&report, &filter_stages, &cli));
+    }
+
+    // Only text on a terminal is cut short by default; anything piped or
+    // written to a file stays complete unless a limit was asked for
+    let explicit_limit = cli.limit.filter(|&limit| limit > 0);
+    let interactive =
+        cli.format == OutputFormat::Text && cli.output.is_none() && io::stdout().is_terminal();
+    let stdout_limit = match cli.limit.is_none() && !cli.all && interactive {
+        true => Some(DEFAULT_TERMINAL_LIMIT),
+        false => explicit_limit,
+    };
+
+    // Over every conversation left after filtering, not just the displayed ones
+    let concentration = concentration(
+        filtered_conversations.iter().map(|c| c.message_count()),
+        &cli.concentration_levels,
+    );
+    let threshold_timeline = cli.threshold_timeline.map(|threshold| {
+        threshold_timeline(
+            // Flattened channels share their guild's ID, and count once
+            filtered_conversations
+                .iter()
+                .map(|c| c.id())
+                .collect::<BTreeSet<_>>(),
+            &report.messages_by_year,
+            threshold,
+        )
+    });
+
+    let share_total: usize = match cli.percent_of {
+        PercentOf::Filtered => filtered_conversations
+            .iter()
+            .map(|c| c.message_count())
+            .sum(),
+        PercentOf::All => report.conversations.iter().map(|c| c.message_count()).sum(),
+    };
 
-    // Print conversations
-    print_conversations(filtered_conversations);
+    let view = |limit: Option<usize>| {
+        let conversations = limited(&filtered_conversations, limit).to_vec();
+        let mut filter_stages = filter_stages.clone();
+        if conversations.len() < filtered_conversations.len() {
+            filter_stages.push(FilterStage {
+                stage: FilterStageKind::Limit,
+                remaining: conversations.len(),
+            });
+        }
+        ResultsView {
+            report: &report,
+            conversations,
+            filter_stages,
+            quiet: cli.quiet,
+            verbose: cli.verbose,
+            excluded_system: cli.exclude_system.is_some(),
+            excluded_empty: cli.exclude_empty_messages,
+            empty_message_stats: cli.empty_message_stats,
+            summary: !cli.no_summary,
+            share_total: share_total as u64,
+            excluded_bots: cli.exclude_bots,
+            excluded_departed: cli.departed() == DepartedFilter::Exclude,
+            channels: cli.channels,
+            guild_totals: cli.guild_totals,
+            group_dms_as: cli.group_dms_as,
+            display_window: cli.display_window,
+            accessible: cli.accessible,
+            timezone: cli.timezone,
+            thousands_separators: cli.thousands_separators,
+            counts: cli.count_style(),
+            concentration: concentration.clone(),
+            threshold_timeline: threshold_timeline.clone(),
+            min_messages: cli.min_messages,
+            fenced: cli.fenced,
+            group_by: cli.group_by,
+            show_empty_sections: cli.show_empty_sections,
+            palette: Palette::default(),
+            tree_glyphs: cli.tree_style,
+            tree_indent: cli.tree_indent,
+            flat: cli.flat,
+            names: NameWidth::Full,
+            options: match cli.redact {
+                Some(_) => &[],
+                None => &cli.effective_options,
+            },
+        }
+    };
+
+    // Print conversations, unless they were streamed while counting, then
+    // write every additional output from the same results
+    let streamed = stream.is_some();
+    let mut out = match stream {
+        Some(out) => out,
+        None => open_output(&cli)?,
+    };
+    let terminal = cli.output.is_none() && io::stdout().is_terminal();
+    let stdout_view = ResultsView {
+        palette: stdout_palette(&cli, terminal),
+        names: name_width(&cli, terminal),
+        ..view(stdout_limit)
+    };
+    if !streamed {
+        render_results(template.as_ref(), cli.format, &stdout_view, &mut out)?;
+    }
+    // Without a clipboard the results are still on stdout, so that's no failure
+    #[cfg(feature = "clipboard")]
+    if cli.copy {
+        let mut rendered = Vec::new();
+        let plain = ResultsView {
+            palette: Palette::default(),
+            names: name_width(&cli, false),
+            ..stdout_view.clone()
+        };
+        render_results(template.as_ref(), cli.format, &plain, &mut rendered)?;
+        if let Err(error) = clipboard::copy(&String::from_utf8_lossy(&rendered)) {
+            eprintln!(
+                "Warning: could not copy the results to the clipboard ({})",
+                error
+            );
+        }
+    }
+    let hidden = &filtered_conversations[stdout_view.conversations.len()..];
+    if interactive && !hidden.is_empty() {
+        write_hidden_hint(hidden, &stdout_view, &mut out)?;
+    }
+    if interactive && !cli.quiet && !cli.no_suggestions && !cli.accessible {
+        let summary = RunSummary {
+            guild_messages: report
+                .conversations
+                .iter()
+                .filter(|c| matches!(c, Conversation::Guild { .. }))
+                .map(|c| c.message_count() as u64)
+                .sum(),
+            total_messages: report
+                .conversations
+                .iter()
+                .map(|c| c.message_count() as u64)
+                .sum(),
+            hidden: hidden.len(),
+            years: report
+                .first_message
+                .zip(report.last_message)
+                .map(|(first, last)| {
+                    (
+                        cli.timezone.local_date(&first).year(),
+                        cli.timezone.local_date(&last).year(),
+                    )
+                }),
+            type_filtered: cli.conversation_type.is_some(),
+            breadth_shown: cli.breadth,
+        };
+        for suggestion in suggest(&summary) {
+            let tip = format!("Tip: {}", suggestion);
+            writeln!(out, "{}", stdout_view.palette.dim(&tip))?;
+        }
+    }
+    out.finish()?;
+    let failures = render_to_targets(&cli.also_write, &view(explicit_limit), cli.force);
+    if let Some(ref dir) = cli.export_site {
+        site::export_site(&view(explicit_limit), dir, cli.force)?;
+    }
+    if let (Some(path), Some(coverage)) = (&cli.coverage_export, &report.coverage) {
+        inventory::export_coverage(coverage, path)?;
+    }
+    if let Some(ref dir) = cli.split_output {
+        split::split_output(&view(explicit_limit), cli.format, dir, cli.force)?;
+    }
+    #[cfg(feature = "sqlite")]
+    if let Some(ref path) = cli.export_sqlite {
+        let export_date = cli
+            .export_date
+            .unwrap_or_else(|| cli.timezone.local_date(&Utc::now()));
+        sqlite::export_sqlite(&view(explicit_limit), path, export_date)?;
+    }
+    if let Some(ref path) = cli.chart_svg {
+        donut::write_donut(&view(explicit_limit), path, cli.chart_svg_top)?;
+    }
+    #[cfg(feature = "charts")]
+    if let Some(ref path) = cli.chart {
+        chart::write_chart(
+            &view(explicit_limit),
+            path,
+            cli.chart_font.as_deref(),
+            cli.chart_top,
+        )?;
+    }
+    #[cfg(feature = "parquet")]
+    if let Some(ref path) = cli.export_parquet {
+        parquet::export_parquet(&view(explicit_limit), path, cli.force)?;
+    }
+    timings.finish();
+
+    if cli.timing {
+        timings.print();
+        report
+            .processing_stats
+            .iter()
+            .for_each(ProcessingStats::print);
+    }
+    print_warnings(&report.warnings, cli.warnings_limit);
 
-    Ok(())
+    if !failures.is_empty() {
+        for (target, error) in &failures {
+            eprintln!("Failed to write {}: {}", target.path.display(), error);
+        }
+        return Err(MyError::OutputFailed(failures.len()));
+    }
+
+    // The results are shown all the same, but cannot be trusted as the original's
+    #[cfg(feature = "zip")]
+    if report
+        .warnings
+        .iter()
+        .any(|warning| matches!(warning, Warning::MirrorMismatch { .. }))
+    {
+        return Ok(ExitCode::from(MIRROR_MISMATCH_EXIT_CODE));
+    }
+
+    Ok(ExitCode::SUCCESS)
 }
 
-fn filter_and_sort_conversations(
-    conversations: Vec<Conversation>,
-    conversation_type: &Option<ConversationType>,
-    min_messages: usize,
-    limit: Option<usize>,
-) -> Vec<Conversation> {
-    let mut filtered: Vec<_> = conversations
-        .into_iter()
-        .filter(|conv| {
-            if conv.message_count() < min_messages {
-                return false;
+/// How many channels guilds need to keep for everything the options ask for.
+fn channel_detail(cli: &Cli) -> ChannelDetail {
+    #[cfg(feature = "sqlite")]
+    let exports_sqlite = cli.export_sqlite.is_some();
+    #[cfg(not(feature = "sqlite"))]
+    let exports_sqlite = false;
+    #[cfg(feature = "parquet")]
+    let exports_parquet = cli.export_parquet.is_some();
+    #[cfg(not(feature = "parquet"))]
+    let exports_parquet = false;
+    let writes = |format: OutputFormat| {
+        cli.format == format || cli.also_write.iter().any(|target| target.format == format)
+    };
+    #[cfg(feature = "xlsx")]
+    let writes_xlsx = writes(OutputFormat::Xlsx);
+    #[cfg(not(feature = "xlsx"))]
+    let writes_xlsx = false;
+    // Merging compares all channels; the others read or write every channel
+    let needs_all = cli.channels
+        || cli.flat
+        || cli.merge_same_name_channels
+        || cli.detail.is_some()
+        || cli.dump.is_some()
+        || cli.export_site.is_some()
+        || cli.split_output.is_some()
+        || cli.emit.is_some()
+        || writes(OutputFormat::Csv)
+        || writes(OutputFormat::Html)
+        || writes(OutputFormat::Dot)
+        || writes_xlsx
+        || exports_sqlite
+        || exports_parquet;
+    match (needs_all, cli.compact, cli.channels_limit) {
+        (true, _, _) => ChannelDetail::All,
+        (false, _, Some(limit)) => ChannelDetail::Top(limit),
+        (false, true, None) => ChannelDetail::None,
+        (false, false, None) => ChannelDetail::All,
+    }
+}
+
+/// Colors for stdout, as far as `--color` allows them there.
+#[cfg(feature = "color")]
+fn stdout_palette(cli: &Cli, terminal: bool) -> Palette {
+    Palette {
+        enabled: cli.color.enabled(terminal),
+    }
+}
+
+#[cfg(not(feature = "color"))]
+fn stdout_palette(_cli: &Cli, _terminal: bool) -> Palette {
+    Palette::default()
+}
+
+/// How far names in the text listing are shortened: to the terminal's width
+/// on a terminal, and only with --max-name-len elsewhere.
+fn name_width(cli: &Cli, terminal: bool) -> NameWidth {
+    match cli.max_name_len {
+        _ if cli.full_names => NameWidth::Full,
+        Some(width) => NameWidth::Fixed(width),
+        None if terminal => crossterm::terminal::size().map_or(NameWidth::Full, |(columns, _)| {
+            NameWidth::Line(columns.into())
+        }),
+        None => NameWidth::Full,
+    }
+}
+
+/// The results through the user's template, or else in `format`.
+fn render_results(
+    template: Option<&Template>,
+    format: OutputFormat,
+    view: &ResultsView,
+    out: &mut dyn Write,
+) -> Result<(), MyError> {
+    match template {
+        Some(template) => template.render(view, out),
+        None => render(format, view, out),
+    }
+}
+
+/// Where the results go: the `--output` file, or stdout.
+fn open_output(cli: &Cli) -> Result<Output, MyError> {
+    Output::open(cli.output.as_deref(), cli.force)
+}
+
+/// Count the package, writing every conversation that passes the filters to
+/// `stream` as NDJSON as soon as it is complete.
+fn count_package(
+    input_path: &Path,
+    cli: &Cli,
+    timings: &mut Timings,
+    mut stream: Option<&mut dyn Write>,
+) -> Result<Report, MyError> {
+    #[cfg(feature = "zip")]
+    let (input_path, sibling_warning) = extracted_sibling(input_path, cli)?;
+    #[cfg(feature = "zip")]
+    let input_path = input_path.as_path();
+
+    let options = AnalysisOptions {
+        input_path: input_path.to_path_buf(),
+        process: ProcessOptions {
+            dedupe_messages: cli.dedupe_messages,
+            exclude_system: cli.exclude_system,
+            exclude_empty_messages: cli.exclude_empty_messages,
+            merge_same_name_channels: cli.merge_same_name_channels,
+            breadth: cli.breadth,
+            messages_by_year: cli.threshold_timeline.is_some(),
+            peak_days: cli.activity_stats,
+            timezone: cli.timezone,
+            channel_detail: channel_detail(cli),
+            keep_discriminators: cli.keep_discriminators,
+            indexed_guilds: None,
+            session_gap: (cli.session_stats || cli.sort() == SortKey::Sessions)
+                .then(|| chrono::Duration::minutes(cli.session_gap.into())),
+            order: cli.processing_order,
+            seed: cli.seed,
+            stream_conversations: stream.is_some(),
+            budget: Budget {
+                max_duration: cli.max_seconds.map(Duration::from_secs),
+                max_messages: cli.max_messages,
+            },
+            ..Default::default()
+        },
+        discrepancy_threshold: cli.discrepancy_threshold,
+        voice_stats: cli.voice_stats,
+        coverage: cli.coverage || cli.coverage_export.is_some(),
+        historical_names: cli.historical_names,
+        cache_index: !cli.no_cache,
+        wait_for_locks: !cli.no_wait,
+        #[cfg(feature = "zip")]
+        temp_dir: cli.temp_dir.clone(),
+        #[cfg(feature = "zip")]
+        verify_against: cli.verify_against.clone(),
+        #[cfg(feature = "net")]
+        resolve_online: cli.resolve_online,
+        #[cfg(feature = "net")]
+        resolve_limit: cli.resolve_limit,
+    };
+
+    let progress_mode = match cli.no_progress {
+        true => ProgressMode::None,
+        false => cli.progress,
+    };
+    let mut progress_sink = progress_sink(progress_mode, cli.accessible)?;
+
+    let (handle, progress, cancel) = spawn_analysis(options);
+    // Ctrl-C stops the count between channels; a second one while it winds
+    // down exits at once. Without a handler, Ctrl-C simply ends the process.
+    let _ = ctrlc::set_handler(move || match cancel.is_cancelled() {
+        true => std::process::exit(ErrorCategory::Cancelled.exit_code().into()),
+        false => cancel.cancel(),
+    });
+    // The channel closes once the worker is done
+    for event in progress {
+        match event {
+            Progress::Stage(stage) => {
+                timings.enter(stage);
+                progress_sink.stage(stage);
             }
-            if let Some(ref ctype) = conversation_type {
-                matches!(
-                    (ctype, conv),
-                    (ConversationType::Dm, Conversation::DmOrGc { .. })
-                        | (ConversationType::Guild, Conversation::Guild { .. })
-                )
-            } else {
-                true
+            Progress::Channels {
+                processed,
+                total,
+                messages,
+            } => progress_sink.channels(processed, total, messages),
+            Progress::Conversation(conversation) => {
+                if let Some(ref mut out) = stream {
+                    if passes_filters(&conversation, cli) {
+                        let context = JsonContext {
+                            counts: cli.count_style(),
+                            group_dms_as: cli.group_dms_as,
+                            report: None,
+                            empty_messages: cli.empty_message_stats,
+                        };
+                        json::write_ndjson_line(&conversation, context, &mut **out)?;
+                    }
+                }
             }
-        })
-        .collect();
+        }
+    }
+    timings.finish();
+    progress_sink.finish();
+
+    let report = handle
+        .join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+    #[cfg(feature = "zip")]
+    let report = report.map(|mut report| {
+        report.warnings.extend(sibling_warning);
+        report
+    });
+    report
+}
+
+/// The folder extracted next to an archive input, when it holds the same
+/// messages files, so the archive need not be extracted again. A folder that
+/// differs is left alone with a warning.
+#[cfg(feature = "zip")]
+fn extracted_sibling(input_path: &Path, cli: &Cli) -> Result<(PathBuf, Option<Warning>), MyError> {
+    let sibling = match input_path.is_file() && !cli.force_extract {
+        true => mirror::sibling_folder(input_path),
+        false => None,
+    };
+    let Some(folder) = sibling else {
+        return Ok((input_path.to_path_buf(), None));
+    };
+    if !mirror::matches_by_size(&folder, input_path)? {
+        let warning = Warning::StaleSiblingFolder {
+            folder: folder.display().to_string(),
+        };
+        return Ok((input_path.to_path_buf(), Some(warning)));
+    }
+    if !cli.quiet {
+        eprintln!(
+            "Reading {}, extracted from the archive; use --force-extract to extract it anyway.",
+            folder.display()
+        );
+    }
+    Ok((folder, None))
+}
+
+/// The filters of `filter_and_sort_conversations`, for a single conversation.
+fn passes_filters(conversation: &Conversation, cli: &Cli) -> bool {
+    cli.participant
+        .as_deref()
+        .is_none_or(|user_id| conversation.has_participant(user_id))
+        && cli
+            .conversation_type
+            .is_none_or(|ctype| conversation.effective_kind(cli.group_dms_as) == ctype)
+        && cli.departed().keeps(conversation)
+        && cli.bots().keeps(conversation)
+        && (cli.min_messages <= 1 || conversation.message_count() >= cli.min_messages)
+}
+
+fn filter_and_sort_conversations<'a>(
+    conversations: &'a [Conversation],
+    participant: Option<&str>,
+    conversation_type: Option<ConversationType>,
+    group_dms_as: GroupDmPolicy,
+    departed: DepartedFilter,
+    bots: BotFilter,
+    min_messages: usize,
+) -> (Vec<&'a Conversation>, Vec<FilterStage>) {
+    let mut filtered: Vec<_> = conversations.iter().collect();
+    let mut stages = vec![FilterStage {
+        stage: FilterStageKind::Found,
+        remaining: filtered.len(),
+    }];
+
+    if let Some(user_id) = participant {
+        filtered.retain(|conv| conv.has_participant(user_id));
+        stages.push(FilterStage {
+            stage: FilterStageKind::Participant,
+            remaining: filtered.len(),
+        });
+    }
+
+    if let Some(ctype) = conversation_type {
+        filtered.retain(|conv| conv.effective_kind(group_dms_as) == ctype);
+        stages.push(FilterStage {
+            stage: FilterStageKind::ConversationType,
+            remaining: filtered.len(),
+        });
+    }
+
+    // Departed servers are only worth a stage when there are any to leave out
+    let before = filtered.len();
+    filtered.retain(|conv| departed.keeps(conv));
+    if departed == DepartedFilter::Only || filtered.len() < before {
+        stages.push(FilterStage {
+            stage: FilterStageKind::Departed,
+            remaining: filtered.len(),
+        });
+    }
+
+    if bots != BotFilter::Include {
+        filtered.retain(|conv| bots.keeps(conv));
+        stages.push(FilterStage {
+            stage: FilterStageKind::Bots,
+            remaining: filtered.len(),
+        });
+    }
+
+    if min_messages > 1 {
+        filtered.retain(|conv| conv.message_count() >= min_messages);
+        stages.push(FilterStage {
+            stage: FilterStageKind::MinMessages,
+            remaining: filtered.len(),
+        });
+    }
 
     // Sort conversations by message count in descending order
     filtered.sort_unstable_by_key(|conv| Reverse(conv.message_count()));
 
-    // Apply limit if specified
-    if let Some(limit) = limit {
-        filtered.truncate(limit);
+    (filtered, stages)
+}
+
+/// Whether --notes annotations make it into the output.
+fn keeps_notes(cli: &Cli) -> bool {
+    cli.redact.is_none() || cli.redact_keep_notes
+}
+
+/// Attach notes to the report, warning about IDs that match no conversation.
+fn attach_notes(report: &mut Report, notes: HashMap<String, String>) {
+    let mut unknown: Vec<String> = notes
+        .keys()
+        .filter(|id| !report.conversations.iter().any(|c| c.id() == id.as_str()))
+        .cloned()
+        .collect();
+    if !unknown.is_empty() {
+        unknown.sort();
+        report
+            .warnings
+            .push(Warning::UnknownNoteIds { ids: unknown });
     }
+    report.notes = notes;
+}
 
-    filtered
+/// Explain which filter left nothing to show, and what would have matched.
+fn empty_result_diagnostic(report: &Report, stages: &[FilterStage], cli: &Cli) -> String {
+    let Some(emptied) = stages.iter().find(|stage| stage.remaining == 0) else {
+        return "No conversations to show.".to_string();
+    };
+
+    match emptied.stage {
+        FilterStageKind::Found => "The package contains no countable conversations.".to_string(),
+        FilterStageKind::Participant => format!(
+            "No conversation has user {} among its recipients.",
+            cli.participant.as_deref().unwrap_or_default()
+        ),
+        FilterStageKind::ConversationType => format!(
+            "No conversation is of type {}; see --group-dms-as for how group DMs are typed.",
+            cli.conversation_type.map_or("", |ctype| ctype.label())
+        ),
+        FilterStageKind::Departed => match cli.departed() {
+            DepartedFilter::Only => "No server is missing from servers/index.json, so there \
+                are no servers you're no longer in."
+                .to_string(),
+            _ => "Every conversation is in a server you're no longer in; see \
+                --include-departed."
+                .to_string(),
+        },
+        FilterStageKind::Bots => match cli.bots() {
+            BotFilter::Only => "No DM is flagged as being with a bot; most packages leave \
+                the flag out."
+                .to_string(),
+            _ => "Every conversation is a DM with a bot.".to_string(),
+        },
+        FilterStageKind::MinMessages => {
+            let largest = report
+                .conversations
+                .iter()
+                .filter(|conv| {
+                    cli.participant
+                        .as_deref()
+                        .is_none_or(|user_id| conv.has_participant(user_id))
+                })
+                .filter(|conv| {
+                    cli.conversation_type
+                        .is_none_or(|ctype| conv.effective_kind(cli.group_dms_as) == ctype)
+                })
+                .filter(|conv| cli.departed().keeps(conv))
+                .filter(|conv| cli.bots().keeps(conv))
+                .map(Conversation::message_count)
+                .max()
+                .unwrap_or_default();
+            format!(
+                "No conversation has at least {} messages; the largest has {}, \
+                 so --min-messages {} would show it.",
+                cli.min_messages, largest, largest
+            )
+        }
+        FilterStageKind::Limit => "No conversations to show.".to_string(),
+    }
+}
+
+fn limited<'a, 'b>(
+    conversations: &'b [&'a Conversation],
+    limit: Option<usize>,
+) -> &'b [&'a Conversation] {
+    match limit {
+        Some(limit) => &conversations[..limit.min(conversations.len())],
+        None => conversations,
+    }
 }
 
-fn print_conversations(conversations: Vec<Conversation>) {
-    for conversation in conversations {
-        conversation.print_tree();
+fn print_message_sample(sample: &[Message]) {
+    println!("Random sample of {} messages:", sample.len());
+    for message in sample {
+        let contents = message.contents.as_deref().unwrap_or("").trim();
+        let body = if contents.is_empty() && message.has_attachments() {
+            "[attachment]".to_string()
+        } else if contents.chars().count() > SAMPLE_CONTENT_LIMIT {
+            let truncated: String = contents.chars().take(SAMPLE_CONTENT_LIMIT).collect();
+            format!("{}…", truncated)
+        } else {
+            contents.to_string()
+        };
+        println!("    {}  {}", message.date(), body.replace('\n', " "));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn cli(args: &[&str]) -> Cli {
+        Cli::try_parse_from(["discord-gdpr-counter", "package"].iter().chain(args)).unwrap()
+    }
+
+    fn report(dms: &[(&str, usize)]) -> Report {
+        let conversations: Vec<_> = dms
+            .iter()
+            .map(|&(id, count)| json!({"kind": "dm", "id": id, "name": id, "message_count": count}))
+            .collect();
+        serde_json::from_value(json!({
+            "conversations": conversations,
+            "reference_counts": null,
+        }))
+        .unwrap()
+    }
+
+    fn stages(kinds: &[(FilterStageKind, usize)]) -> Vec<FilterStage> {
+        kinds
+            .iter()
+            .map(|&(stage, remaining)| FilterStage { stage, remaining })
+            .collect()
+    }
+
+    #[test]
+    fn min_messages_diagnostic_names_the_largest_count() {
+        let report = report(&[("1", 5), ("2", 40)]);
+        let stages = stages(&[
+            (FilterStageKind::Found, 2),
+            (FilterStageKind::MinMessages, 0),
+        ]);
+        assert_eq!(
+            empty_result_diagnostic(&report, &stages, &cli(&["--min-messages", "100"])),
+            "No conversation has at least 100 messages; the largest has 40, \
+             so --min-messages 40 would show it."
+        );
+    }
+
+    #[test]
+    fn diagnostic_names_the_first_filter_that_left_nothing() {
+        let report = report(&[("1", 5)]);
+        let by_type = stages(&[
+            (FilterStageKind::Found, 1),
+            (FilterStageKind::ConversationType, 0),
+            (FilterStageKind::MinMessages, 0),
+        ]);
+        assert!(empty_result_diagnostic(
+            &report,
+            &by_type,
+            &cli(&["--conversation-type", "guild"])
+        )
+        .starts_with("No conversation is of type guild;"));
+        assert_eq!(
+            empty_result_diagnostic(&report, &stages(&[(FilterStageKind::Found, 0)]), &cli(&[])),
+            "The package contains no countable conversations."
+        );
+    }
+
+    #[test]
+    fn bot_filters_treat_unknown_dms_as_people() {
+        let mut report = report(&[("bot", 1), ("person", 2), ("unknown", 3)]);
+        for (conversation, flag) in
+            report
+                .conversations
+                .iter_mut()
+                .zip([Some(true), Some(false), None])
+        {
+            if let Conversation::DmOrGc { is_bot, .. } = conversation {
+                *is_bot = flag;
+            }
+        }
+        let ids = |bots: BotFilter| -> Vec<&str> {
+            let (kept, stages) = filter_and_sort_conversations(
+                &report.conversations,
+                None,
+                None,
+                GroupDmPolicy::default(),
+                DepartedFilter::Include,
+                bots,
+                0,
+            );
+            assert_eq!(
+                stages
+                    .iter()
+                    .any(|stage| stage.stage == FilterStageKind::Bots),
+                bots != BotFilter::Include
+            );
+            let mut ids: Vec<&str> = kept.iter().map(|conversation| conversation.id()).collect();
+            ids.sort_unstable();
+            ids
+        };
+        assert_eq!(ids(cli(&["--exclude-bots"]).bots()), ["person", "unknown"]);
+        assert_eq!(ids(cli(&["--only-bots"]).bots()), ["bot"]);
+        assert_eq!(ids(cli(&[]).bots()), ["bot", "person", "unknown"]);
+        assert_eq!(report.bot_dms(), 1);
+    }
+
+    #[test]
+    fn participant_filter_matches_dm_recipients_by_id() {
+        let report: Report = serde_json::from_value(json!({
+            "conversations": [
+                {"kind": "dm", "id": "1", "name": "Alice", "message_count": 3,
+                 "recipients": ["100", "200"]},
+                {"kind": "dm", "id": "2", "name": "Friends", "message_count": 2, "group": true,
+                 "recipients": ["100", "200", "300"]},
+                {"kind": "guild", "id": "3", "name": "Server", "message_count": 9, "channels": []},
+            ],
+            "reference_counts": null,
+        }))
+        .unwrap();
+        let filtered = |user_id: &str| {
+            filter_and_sort_conversations(
+                &report.conversations,
+                Some(user_id),
+                None,
+                GroupDmPolicy::default(),
+                DepartedFilter::Include,
+                BotFilter::Include,
+                0,
+            )
+        };
+
+        let (kept, stages) = filtered("200");
+        let ids: Vec<&str> = kept.iter().map(|conversation| conversation.id()).collect();
+        assert_eq!(ids, ["1", "2"]);
+        assert_eq!(stages[1].stage, FilterStageKind::Participant);
+
+        let (kept, stages) = filtered("999");
+        assert!(kept.is_empty());
+        assert_eq!(
+            empty_result_diagnostic(&report, &stages, &cli(&["--participant", "999"])),
+            "No conversation has user 999 among its recipients."
+        );
+        // Distinct from the exit codes of every error category
+        assert!([
+            ErrorCategory::Input,
+            ErrorCategory::Package,
+            ErrorCategory::Output,
+            ErrorCategory::Cancelled,
+            ErrorCategory::Other,
+        ]
+        .iter()
+        .all(|category| category.exit_code() != NO_PARTICIPANT_EXIT_CODE));
+    }
+
+    #[test]
+    fn departed_filters_split_guilds_by_the_index() {
+        let report: Report = serde_json::from_value(json!({
+            "conversations": [
+                {"kind": "dm", "id": "1", "name": "Alice", "message_count": 3},
+                {"kind": "guild", "id": "2", "name": "Joined", "message_count": 2, "channels": []},
+                {"kind": "guild", "id": "3", "name": "Left", "message_count": 1, "channels": [],
+                 "departed": true},
+            ],
+            "reference_counts": null,
+        }))
+        .unwrap();
+        let ids = |departed: DepartedFilter| -> (Vec<&str>, bool) {
+            let (kept, stages) = filter_and_sort_conversations(
+                &report.conversations,
+                None,
+                None,
+                GroupDmPolicy::default(),
+                departed,
+                BotFilter::Include,
+                0,
+            );
+            let staged = stages
+                .iter()
+                .any(|stage| stage.stage == FilterStageKind::Departed);
+            (
+                kept.iter().map(|conversation| conversation.id()).collect(),
+                staged,
+            )
+        };
+        assert_eq!(ids(cli(&[]).departed()), (vec!["1", "2"], true));
+        assert_eq!(
+            ids(cli(&["--include-departed"]).departed()),
+            (vec!["1", "2", "3"], false)
+        );
+        assert_eq!(ids(cli(&["--only-departed"]).departed()), (vec!["3"], true));
+        assert_eq!(report.departed_guilds(), 1);
+    }
+
+    #[test]
+    fn notes_are_kept_unless_redacting() {
+        assert!(keeps_notes(&cli(&["--notes", "notes.toml"])));
+        assert!(!keeps_notes(&cli(&[
+            "--notes",
+            "notes.toml",
+            "--redact=full"
+        ])));
+        assert!(keeps_notes(&cli(&[
+            "--notes",
+            "notes.toml",
+            "--redact=hashed",
+            "--redact-keep-notes"
+        ])));
+    }
+
+    #[test]
+    fn notes_for_missing_conversations_are_warned_about() {
+        let mut report = report(&[("1", 5)]);
+        let notes = [("1", "friend"), ("9", "gone"), ("3", "also gone")]
+            .map(|(id, note)| (id.to_string(), note.to_string()));
+        attach_notes(&mut report, HashMap::from(notes));
+        assert_eq!(report.note("1"), Some("friend"));
+        assert!(matches!(
+            &report.warnings[..],
+            [Warning::UnknownNoteIds { ids }] if ids == &["3", "9"]
+        ));
+    }
+
+    #[test]
+    fn compact_drops_channels_nothing_needs() {
+        assert_eq!(channel_detail(&cli(&[])), ChannelDetail::All);
+        assert_eq!(channel_detail(&cli(&["--compact"])), ChannelDetail::None);
+        assert_eq!(
+            channel_detail(&cli(&["--channels-limit", "3"])),
+            ChannelDetail::Top(3)
+        );
+        assert_eq!(
+            channel_detail(&cli(&["--compact", "--channels-limit", "3"])),
+            ChannelDetail::Top(3)
+        );
+    }
+
+    #[test]
+    fn options_reading_every_channel_keep_them_all() {
+        for args in [
+            &["--channels"][..],
+            &["--flat"],
+            &["--merge-same-name-channels"],
+            &["--format", "csv"],
+            &["--format", "html"],
+            &["--also-write", "dot:graph.dot"],
+            &["--export-site", "site"],
+            &["--split-output", "split"],
+        ] {
+            let compact = [&["--compact", "--channels-limit", "1"][..], args].concat();
+            assert_eq!(
+                channel_detail(&cli(&compact)),
+                ChannelDetail::All,
+                "{:?}",
+                args
+            );
+        }
+        // Other formats only show guild totals
+        assert_eq!(
+            channel_detail(&cli(&["--compact", "--format", "json"])),
+            ChannelDetail::None
+        );
+    }
+
+    #[test]
+    fn limit_zero_shows_everything() {
+        let report = report(&[("1", 5), ("2", 40), ("3", 1)]);
+        let conversations: Vec<&Conversation> = report.conversations.iter().collect();
+        let limit = cli(&["--limit", "0"]).limit.filter(|&limit| limit > 0);
+        assert_eq!(limited(&conversations, limit).len(), 3);
+        assert_eq!(limited(&conversations, Some(2)).len(), 2);
+        assert!(
+            Cli::try_parse_from(["discord-gdpr-counter", "package", "--all", "--limit", "2"])
+                .is_err()
+        );
     }
 }
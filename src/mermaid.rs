@@ -0,0 +1,94 @@
+//! A Mermaid flowchart of the results for Markdown documents: guilds branch
+//! into their channels, DMs are leaves of the root. GitHub renders it when
+//! it is wrapped in a fenced code block, which `--fenced` adds.
+
+use crate::errors::MyError;
+use crate::render::{display_name, ResultsView};
+use crate::Conversation;
+use std::io::Write;
+
+pub fn render_mermaid(view: &ResultsView, out: &mut dyn Write) -> Result<(), MyError> {
+    let total: usize = view
+        .conversations
+        .iter()
+        .map(|conversation| conversation.message_count())
+        .sum();
+
+    if view.fenced {
+        writeln!(out, "```mermaid")?;
+    }
+    writeln!(out, "graph TD")?;
+    writeln!(out, "    root([\"{}\"])", label("Discord", total, view))?;
+
+    for &conversation in &view.conversations {
+        let count = conversation.message_count();
+        match conversation {
+            Conversation::Guild {
+                id: Some(id),
+                name,
+                channels,
+                ..
+            } => {
+                let node = format!("guild_{}", id);
+                writeln!(
+                    out,
+                    "    root --> {}[\"{}\"]",
+                    node,
+                    label(name, count, view)
+                )?;
+                for channel in channels {
+                    writeln!(
+                        out,
+                        "    {} --> channel_{}[\"{}\"]",
+                        node,
+                        channel.id,
+                        label(&format!("#{}", channel.name), channel.message_count, view)
+                    )?;
+                }
+            }
+            // Unknown guilds hold exactly one channel, which stands in for them
+            Conversation::Guild { .. } => writeln!(
+                out,
+                "    root --> channel_{}[\"{}\"]",
+                conversation.id(),
+                label(&display_name(conversation), count, view)
+            )?,
+            Conversation::DmOrGc { id, name, .. } => writeln!(
+                out,
+                "    root --> dm_{}([\"{}\"])",
+                id,
+                label(name, count, view)
+            )?,
+        }
+    }
+
+    if view.fenced {
+        writeln!(out, "```")?;
+    }
+    Ok(())
+}
+
+fn label(name: &str, count: usize, view: &ResultsView) -> String {
+    format!(
+        "{}<br/>{} messages",
+        escape(name),
+        escape(&view.counts.grouped(count as u64))
+    )
+}
+
+/// Mermaid ends node text at quotes and reads `#` as the start of an entity,
+/// so those and anything else with a meaning in its syntax become entities.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("#quot;"),
+            '#' | '[' | ']' | '(' | ')' | '{' | '}' | '|' | '<' | '>' | '`' | ';' => {
+                escaped.push_str(&format!("#{};", c as u32))
+            }
+            c if c.is_control() => escaped.push(' '),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
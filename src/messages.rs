@@ -0,0 +1,736 @@
+use crate::errors::MyError;
+use crate::file_operations::ProcessOptions;
+use crate::metrics::Metric;
+use crate::vintage::MessageFormat;
+use crate::volume::CountingReader;
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, Utc};
+use clap::ValueEnum;
+use rand::Rng;
+use serde::de::{Deserializer, SeqAccess, Visitor};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+/// A single entry of a channel's `messages.json`, or a row of its `messages.csv`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Message {
+    #[serde(rename = "ID", default, deserialize_with = "deserialize_snowflake")]
+    pub id: Option<u64>,
+    #[serde(rename = "Timestamp", default)]
+    pub timestamp: Option<String>,
+    #[serde(rename = "Contents", default)]
+    pub contents: Option<String>,
+    #[serde(rename = "Attachments", default)]
+    pub attachments: Option<String>,
+    /// Only present in some vintages
+    #[serde(rename = "Type", alias = "type", default)]
+    pub message_type: Option<MessageType>,
+}
+
+/// Discord's message type, numeric in API-shaped exports and named in others.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum MessageType {
+    Code(u64),
+    Name(String),
+}
+
+/// Message types a user actually writes: default, reply, slash command and
+/// context menu command. Everything else is a system artifact.
+const USER_MESSAGE_TYPES: &[u64] = &[0, 19, 20, 23];
+const USER_MESSAGE_TYPE_NAMES: &[&str] = &[
+    "DEFAULT",
+    "REPLY",
+    "CHAT_INPUT_COMMAND",
+    "CONTEXT_MENU_COMMAND",
+];
+
+/// Years a message timestamp can plausibly fall into.
+const PLAUSIBLE_YEARS: std::ops::RangeInclusive<i32> = 1970..=9999;
+
+/// Contents that Discord writes on the user's behalf in vintages without a
+/// type field. Deliberately short, matched only as a full-message prefix.
+const SYSTEM_CONTENT_PREFIXES: &[&str] = &[
+    "pinned a message to this channel",
+    "just boosted the server",
+    "started a call",
+    "added a recipient",
+    "removed a recipient",
+    "changed the channel name",
+    "changed the channel icon",
+];
+
+impl MessageType {
+    fn is_user_message(&self) -> bool {
+        match self {
+            Self::Code(code) => USER_MESSAGE_TYPES.contains(code),
+            Self::Name(name) => {
+                let name = name.to_ascii_uppercase();
+                USER_MESSAGE_TYPE_NAMES.contains(&name.as_str())
+            }
+        }
+    }
+}
+
+/// How aggressively system messages are excluded from counts.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SystemExclusion {
+    /// Skip messages whose type field marks them as non-default
+    Typed,
+    /// Additionally match known system contents when there is no type field
+    Aggressive,
+}
+
+impl Message {
+    /// The calendar date part of the timestamp, regardless of vintage.
+    pub fn date(&self) -> &str {
+        self.timestamp
+            .as_deref()
+            .and_then(|ts| ts.get(..10))
+            .unwrap_or("????-??-??")
+    }
+
+    /// Parse the timestamp, accepting both the plain `2023-01-01 12:00:00`
+    /// form and the offset-carrying RFC 3339 form of older vintages. Years
+    /// no real message can have are treated as unparseable, since calendar
+    /// arithmetic near chrono's limits would overflow.
+    pub fn datetime(&self) -> Option<DateTime<Utc>> {
+        self.parse_timestamp()
+            .filter(|datetime| PLAUSIBLE_YEARS.contains(&datetime.year()))
+    }
+
+    fn parse_timestamp(&self) -> Option<DateTime<Utc>> {
+        let timestamp = self.timestamp.as_deref()?.trim();
+        if let Ok(datetime) = DateTime::parse_from_rfc3339(timestamp) {
+            return Some(datetime.with_timezone(&Utc));
+        }
+        if let Ok(datetime) = DateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S%.f%:z") {
+            return Some(datetime.with_timezone(&Utc));
+        }
+        NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S%.f")
+            .ok()
+            .map(|naive| naive.and_utc())
+    }
+
+    /// Whether this is a Discord system artifact rather than a written message.
+    pub fn is_system(&self, mode: SystemExclusion) -> bool {
+        match (&self.message_type, mode) {
+            (Some(message_type), _) => !message_type.is_user_message(),
+            (None, SystemExclusion::Typed) => false,
+            (None, SystemExclusion::Aggressive) => {
+                let contents = self.contents.as_deref().unwrap_or("").trim();
+                SYSTEM_CONTENT_PREFIXES
+                    .iter()
+                    .any(|prefix| contents.starts_with(prefix))
+            }
+        }
+    }
+
+    pub fn has_attachments(&self) -> bool {
+        self.attachments
+            .as_deref()
+            .is_some_and(|a| !a.trim().is_empty())
+    }
+
+    /// Whether this is a stub Discord kept of a message whose content is
+    /// gone: no contents at all and no attachments. Whitespace is something
+    /// written, so whitespace-only contents are not a stub.
+    pub fn is_empty_stub(&self) -> bool {
+        self.contents.as_deref().is_none_or(str::is_empty) && !self.has_attachments()
+    }
+}
+
+/// Snowflakes are numbers in newer packages and strings in older ones.
+fn deserialize_snowflake<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Snowflake {
+        Number(u64),
+        String(String),
+        Other(serde::de::IgnoredAny),
+    }
+
+    Ok(match Snowflake::deserialize(deserializer)? {
+        Snowflake::Number(id) => Some(id),
+        Snowflake::String(id) => id.parse().ok(),
+        Snowflake::Other(_) => None,
+    })
+}
+
+/// Result of counting a single messages file.
+#[derive(Debug, Default, Clone)]
+pub struct MessageCounts {
+    pub counted: usize,
+    pub duplicates: usize,
+    pub excluded_system: usize,
+    /// Empty stubs among the messages left after system and duplicate
+    /// exclusion, whether or not they were counted
+    pub empty_messages: usize,
+    pub first_message: Option<DateTime<Utc>>,
+    pub last_message: Option<DateTime<Utc>>,
+    /// Counted messages at or after `ProcessOptions::analytics_since`
+    pub counted_since: usize,
+    /// Counted messages per calendar year, for years with at least one
+    pub years: BTreeMap<i32, usize>,
+    /// Every counted message's timestamp, only collected for session stats
+    pub timestamps: Vec<DateTime<Utc>>,
+    /// Counted messages per local calendar day, only collected for
+    /// `--activity-stats`
+    pub days: BTreeMap<NaiveDate, usize>,
+    /// Size of the messages file as read
+    pub bytes_read: u64,
+}
+
+struct MessageVisitor<F>(F);
+
+impl<'de, F: FnMut(Message)> Visitor<'de> for MessageVisitor<F> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an array of messages")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(mut self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(message) = seq.next_element::<Message>()? {
+            (self.0)(message);
+        }
+        Ok(())
+    }
+}
+
+/// Stream the messages of a `messages.json` or `messages.csv` file without
+/// retaining them. Returns the number of bytes read.
+pub fn for_each_message<F: FnMut(Message)>(path: &Path, f: F) -> Result<u64, MyError> {
+    let mut file = CountingReader::new(File::open(path)?);
+    read_messages(&mut file, MessageFormat::of(path), f)?;
+    Ok(file.bytes_read())
+}
+
+/// Stream the messages `reader` holds in `format`, wherever they come from.
+pub fn read_messages<R: Read, F: FnMut(Message)>(
+    reader: R,
+    format: MessageFormat,
+    mut f: F,
+) -> Result<(), MyError> {
+    match format {
+        MessageFormat::Csv => {
+            let mut reader = csv::Reader::from_reader(BufReader::new(reader));
+            for message in reader.deserialize() {
+                f(message?);
+            }
+        }
+        MessageFormat::Json => {
+            let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(reader));
+            deserializer.deserialize_seq(MessageVisitor(f))?;
+            deserializer.end()?;
+        }
+    }
+    Ok(())
+}
+
+pub const METRIC: Metric = Metric {
+    name: "message_count",
+    definition: "Messages in a channel's messages file, one per entry. A guild's count is \
+        the sum of its channels; a DM's is that of its single channel.",
+    affected_by: "--dedupe-messages counts entries with an already seen ID once per channel; \
+        --exclude-system leaves out pins, boosts, calls and other system messages; \
+        --exclude-empty-messages leaves out messages with neither contents nor attachments, \
+        which --empty-message-stats counts as empty_messages; \
+        --max-seconds and --max-messages stop early and leave the remaining channels out. \
+        --min-messages, --conversation-type and --participant hide conversations without \
+        changing any count.",
+    edge_cases: "Channels whose messages cannot be read are skipped with a warning and count \
+        nothing. Messages without a usable timestamp are still counted.",
+};
+
+/// Count the messages in a messages file. With deduplication, messages
+/// whose ID was already seen in this file are counted as duplicates instead.
+pub fn count_messages(path: &Path, options: &ProcessOptions) -> Result<MessageCounts, MyError> {
+    let mut file = CountingReader::new(File::open(path)?);
+    let mut counts = tally_messages(&mut file, MessageFormat::of(path), options)?;
+    counts.bytes_read = file.bytes_read();
+    Ok(counts)
+}
+
+/// `count_messages` over the messages `reader` holds in `format`; leaves
+/// `bytes_read` at 0.
+pub fn tally_messages<R: Read>(
+    reader: R,
+    format: MessageFormat,
+    options: &ProcessOptions,
+) -> Result<MessageCounts, MyError> {
+    let mut counts = MessageCounts::default();
+    let mut seen = HashSet::new();
+
+    read_messages(reader, format, |message| {
+        if let Some(mode) = options.exclude_system {
+            if message.is_system(mode) {
+                counts.excluded_system += 1;
+                return;
+            }
+        }
+        if options.dedupe_messages {
+            if let Some(id) = message.id {
+                if !seen.insert(id) {
+                    counts.duplicates += 1;
+                    return;
+                }
+            }
+        }
+        if message.is_empty_stub() {
+            counts.empty_messages += 1;
+            if options.exclude_empty_messages {
+                return;
+            }
+        }
+        counts.counted += 1;
+
+        let datetime = message.datetime();
+        let since = options.analytics_since;
+        if since.is_none() || datetime.is_none() || datetime >= since {
+            counts.counted_since += 1;
+        }
+
+        if let Some(datetime) = datetime {
+            if options.session_gap.is_some() {
+                counts.timestamps.push(datetime);
+            }
+            if options.peak_days {
+                *counts
+                    .days
+                    .entry(options.timezone.local_date(&datetime))
+                    .or_default() += 1;
+            }
+            *counts
+                .years
+                .entry(options.timezone.local_date(&datetime).year())
+                .or_default() += 1;
+            counts.first_message = Some(counts.first_message.map_or(datetime, |d| d.min(datetime)));
+            counts.last_message = Some(counts.last_message.map_or(datetime, |d| d.max(datetime)));
+        }
+    })?;
+
+    Ok(counts)
+}
+
+/// Collect the timestamps of all messages in the given messages files.
+pub fn message_datetimes(files: &[PathBuf]) -> Result<Vec<DateTime<Utc>>, MyError> {
+    let mut datetimes = Vec::new();
+    for messages_file in files {
+        if !messages_file.exists() {
+            continue;
+        }
+        for_each_message(messages_file, |message| {
+            if let Some(datetime) = message.datetime() {
+                datetimes.push(datetime);
+            }
+        })?;
+    }
+    Ok(datetimes)
+}
+
+/// Most messages `--sample-messages` prints; more is no sample anymore.
+pub const MAX_SAMPLE: usize = 10_000;
+
+/// Parse the size of `--sample-messages`, between 1 and `MAX_SAMPLE`.
+pub fn parse_sample_size(s: &str) -> Result<usize, String> {
+    match s.parse() {
+        Ok(n @ 1..=MAX_SAMPLE) => Ok(n),
+        _ => Err(format!(
+            "'{}' is not a number of messages from 1 to {}",
+            s, MAX_SAMPLE
+        )),
+    }
+}
+
+/// Pick `n` messages uniformly at random across the given messages files
+/// using reservoir sampling, so only the sample is ever held in memory.
+pub fn sample_messages<R: Rng>(
+    files: &[PathBuf],
+    n: usize,
+    rng: &mut R,
+) -> Result<Vec<Message>, MyError> {
+    // Grown as messages come in: a conversation may hold fewer than `n`
+    let mut reservoir = Vec::new();
+    let mut seen = 0usize;
+
+    for messages_file in files {
+        if !messages_file.exists() {
+            continue;
+        }
+        for_each_message(messages_file, |message| {
+            seen += 1;
+            if reservoir.len() < n {
+                reservoir.push(message);
+            } else {
+                let slot = rng.gen_range(0..seen);
+                if slot < n {
+                    reservoir[slot] = message;
+                }
+            }
+        })?;
+    }
+
+    reservoir.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+    Ok(reservoir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::Zone;
+    use proptest::prelude::*;
+    use rand::{rngs::StdRng, SeedableRng};
+    use serde_json::Value;
+    use std::fs;
+
+    #[test]
+    fn sample_size_is_bounded() {
+        assert_eq!(parse_sample_size("1"), Ok(1));
+        assert_eq!(parse_sample_size("10000"), Ok(MAX_SAMPLE));
+        assert!(parse_sample_size("0").is_err());
+        assert!(parse_sample_size("10001").is_err());
+        assert!(parse_sample_size("18446744073709551615").is_err());
+        assert!(parse_sample_size("-1").is_err());
+    }
+
+    #[test]
+    fn oversized_sample_holds_every_message() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("messages.json");
+        fs::write(
+            &path,
+            r#"[{"ID": "2", "Timestamp": "2021-01-02 00:00:00"},
+                {"ID": "1", "Timestamp": "2021-01-01 00:00:00"}]"#,
+        )
+        .unwrap();
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let sample = sample_messages(&[path], usize::MAX, &mut rng).unwrap();
+        let ids: Vec<_> = sample.iter().map(|message| message.id).collect();
+        assert_eq!(ids, [Some(1), Some(2)]);
+    }
+
+    /// Found by the properties below: a timestamp at chrono's year limits
+    /// overflowed once shifted into a time zone.
+    #[test]
+    fn far_off_years_are_unparseable() {
+        for timestamp in [
+            "262142-12-31 23:59:59",
+            "+262142-12-31T23:59:59-14:00",
+            "-262143-01-01T00:00:00Z",
+            "0000-01-01 00:00:00",
+        ] {
+            let message = Message {
+                timestamp: Some(timestamp.to_string()),
+                ..Default::default()
+            };
+            assert_eq!(message.datetime(), None, "{}", timestamp);
+        }
+
+        let options = ProcessOptions {
+            peak_days: true,
+            timezone: "+14:00".parse().unwrap(),
+            ..Default::default()
+        };
+        let messages = r#"[{"ID": "1", "Timestamp": "262142-12-31 23:59:59"}]"#;
+        let counts = tally_messages(messages.as_bytes(), MessageFormat::Json, &options).unwrap();
+        assert_eq!(counts.counted, 1);
+        assert!(counts.days.is_empty());
+    }
+
+    /// Counts of `messages` in JSON with system messages excluded by `mode`.
+    fn without_system(messages: Value, mode: Option<SystemExclusion>) -> MessageCounts {
+        let options = ProcessOptions {
+            exclude_system: mode,
+            ..Default::default()
+        };
+        let json = messages.to_string();
+        tally_messages(json.as_bytes(), MessageFormat::Json, &options).unwrap()
+    }
+
+    #[test]
+    fn numeric_types_mark_system_messages() {
+        let messages = serde_json::json!([
+            {"ID": "1", "Type": 0, "Contents": "hello"},
+            {"ID": "2", "Type": 19, "Contents": "a reply"},
+            {"ID": "3", "Type": 6, "Contents": ""},
+            {"ID": "4", "type": 8, "Contents": ""},
+        ]);
+        let counts = without_system(messages.clone(), Some(SystemExclusion::Typed));
+        assert_eq!((counts.counted, counts.excluded_system), (2, 2));
+        let counts = without_system(messages, None);
+        assert_eq!((counts.counted, counts.excluded_system), (4, 0));
+    }
+
+    #[test]
+    fn named_types_mark_system_messages() {
+        let messages = serde_json::json!([
+            {"ID": "1", "Type": "DEFAULT", "Contents": "hello"},
+            {"ID": "2", "Type": "reply", "Contents": "a reply"},
+            {"ID": "3", "Type": "CHANNEL_PINNED_MESSAGE", "Contents": ""},
+            {"ID": "4", "Type": "GUILD_BOOST", "Contents": ""},
+        ]);
+        let counts = without_system(messages, Some(SystemExclusion::Typed));
+        assert_eq!((counts.counted, counts.excluded_system), (2, 2));
+    }
+
+    #[test]
+    fn untyped_vintages_only_match_contents_aggressively() {
+        let messages = serde_json::json!([
+            {"ID": "1", "Contents": "pinned a message to this channel."},
+            {"ID": "2", "Contents": "  just boosted the server!"},
+            {"ID": "3", "Contents": "I just boosted the server"},
+            {"ID": "4", "Contents": "hello"},
+        ]);
+        let counts = without_system(messages.clone(), Some(SystemExclusion::Typed));
+        assert_eq!((counts.counted, counts.excluded_system), (4, 0));
+        let counts = without_system(messages, Some(SystemExclusion::Aggressive));
+        assert_eq!((counts.counted, counts.excluded_system), (2, 2));
+    }
+
+    #[test]
+    fn a_type_field_overrides_the_contents() {
+        let messages = serde_json::json!([
+            {"ID": "1", "Type": 0, "Contents": "pinned a message to this channel"},
+        ]);
+        let counts = without_system(messages, Some(SystemExclusion::Aggressive));
+        assert_eq!((counts.counted, counts.excluded_system), (1, 0));
+    }
+
+    #[test]
+    fn csv_exports_have_no_type_column() {
+        let csv = "ID,Timestamp,Contents,Attachments\n\
+                   1,2021-01-01 00:00:00,started a call,\n\
+                   2,2021-01-01 00:00:01,hello,\n";
+        let options = ProcessOptions {
+            exclude_system: Some(SystemExclusion::Aggressive),
+            ..Default::default()
+        };
+        let counts = tally_messages(csv.as_bytes(), MessageFormat::Csv, &options).unwrap();
+        assert_eq!((counts.counted, counts.excluded_system), (1, 1));
+    }
+
+    #[test]
+    fn only_messages_without_contents_or_attachments_are_stubs() {
+        let message = |contents: Option<&str>, attachments: Option<&str>| Message {
+            contents: contents.map(str::to_string),
+            attachments: attachments.map(str::to_string),
+            ..Default::default()
+        };
+        assert!(message(None, None).is_empty_stub());
+        assert!(message(Some(""), Some("")).is_empty_stub());
+        // Blank attachment fields list nothing
+        assert!(message(Some(""), Some("  ")).is_empty_stub());
+        assert!(!message(Some("   "), None).is_empty_stub());
+        assert!(!message(Some("\n"), None).is_empty_stub());
+        assert!(!message(Some(""), Some("https://cdn.discordapp.com/a.png")).is_empty_stub());
+        assert!(!message(Some("hi"), None).is_empty_stub());
+    }
+
+    /// Counted messages and empty stubs of a file of every kind of message.
+    fn stubs(format: MessageFormat, options: &ProcessOptions) -> (usize, usize) {
+        let bytes = match format {
+            MessageFormat::Json => serde_json::json!([
+                {"ID": "1", "Contents": "hello"},
+                {"ID": "2", "Contents": ""},
+                {"ID": "3"},
+                {"ID": "4", "Contents": "   "},
+                {"ID": "5", "Contents": "", "Attachments": "https://cdn.discordapp.com/a.png"},
+                {"ID": "2", "Contents": ""},
+                {"ID": "6", "Contents": "", "Type": 6},
+            ])
+            .to_string()
+            .into_bytes(),
+            // Old exports have no type, so the system message is not one
+            MessageFormat::Csv => "ID,Timestamp,Contents,Attachments\n\
+                                   1,,hello,\n\
+                                   2,,,\n\
+                                   3,,,\n\
+                                   4,,   ,\n\
+                                   5,,,https://cdn.discordapp.com/a.png\n\
+                                   2,,,\n"
+                .as_bytes()
+                .to_vec(),
+        };
+        let counts = tally_messages(bytes.as_slice(), format, options).unwrap();
+        (counts.counted, counts.empty_messages)
+    }
+
+    #[test]
+    fn empty_stubs_are_counted_or_excluded() {
+        let mut options = ProcessOptions::default();
+        assert_eq!(stubs(MessageFormat::Json, &options), (7, 4));
+        assert_eq!(stubs(MessageFormat::Csv, &options), (6, 3));
+
+        options.exclude_empty_messages = true;
+        assert_eq!(stubs(MessageFormat::Json, &options), (3, 4));
+        assert_eq!(stubs(MessageFormat::Csv, &options), (3, 3));
+    }
+
+    #[test]
+    fn stubs_are_what_other_exclusions_leave() {
+        let options = ProcessOptions {
+            dedupe_messages: true,
+            exclude_system: Some(SystemExclusion::Typed),
+            exclude_empty_messages: true,
+            ..Default::default()
+        };
+        // The duplicate and the pin are gone before stubs are looked for
+        assert_eq!(stubs(MessageFormat::Json, &options), (3, 2));
+    }
+
+    const ZONES: &[&str] = &["UTC", "+14:00", "-12:00", "Pacific/Apia", "America/Adak"];
+
+    /// Years at chrono's limits, where converting between zones overflows.
+    const EDGE_YEARS: &[i32] = &[-262_144, -262_143, 262_142, 262_143];
+
+    /// Timestamps in every form exports use, with any year, and noise.
+    fn timestamp() -> impl Strategy<Value = String> {
+        let year = || prop_oneof![0..=10_000i32, proptest::sample::select(EDGE_YEARS)];
+        let date = "-(0[1-9]|1[0-2])-(0[1-9]|[12][0-9]|3[01])";
+        let time = "[ T]([01][0-9]|2[0-3]):[0-5][0-9]:[0-5][0-9](\\.[0-9]{1,9})?";
+        let offset = "(Z|[+-](0[0-9]|1[0-4]):[0-5][0-9])?";
+        prop_oneof![
+            (year(), date, time, offset).prop_map(|(year, date, time, offset)| {
+                format!("{:04}{}{}{}", year, date, time, offset)
+            }),
+            ".{0,24}",
+        ]
+    }
+
+    fn message() -> impl Strategy<Value = Value> {
+        (
+            proptest::option::of(prop_oneof![
+                "[0-9]{1,20}".prop_map(Value::from),
+                any::<u64>().prop_map(Value::from)
+            ]),
+            proptest::option::of(timestamp()),
+            proptest::option::of(".{0,40}"),
+            proptest::option::of(".{0,10}"),
+            proptest::option::of(prop_oneof![
+                (0u64..30).prop_map(Value::from),
+                "[A-Z_]{0,20}".prop_map(Value::from)
+            ]),
+        )
+            .prop_map(|(id, timestamp, contents, attachments, message_type)| {
+                let mut message = serde_json::Map::new();
+                let fields = [
+                    ("ID", id),
+                    ("Timestamp", timestamp.map(Value::from)),
+                    ("Contents", contents.map(Value::from)),
+                    ("Attachments", attachments.map(Value::from)),
+                    ("Type", message_type),
+                ];
+                for (key, value) in fields {
+                    if let Some(value) = value {
+                        message.insert(key.to_string(), value);
+                    }
+                }
+                Value::Object(message)
+            })
+    }
+
+    fn options() -> impl Strategy<Value = ProcessOptions> {
+        (
+            any::<bool>(),
+            proptest::option::of(prop_oneof![
+                Just(SystemExclusion::Typed),
+                Just(SystemExclusion::Aggressive)
+            ]),
+            any::<bool>(),
+            any::<bool>(),
+            proptest::option::of(1i64..10_000),
+            proptest::sample::select(ZONES),
+        )
+            .prop_map(
+                |(dedupe, exclude_system, exclude_empty, peak_days, gap, zone)| ProcessOptions {
+                    dedupe_messages: dedupe,
+                    exclude_system,
+                    exclude_empty_messages: exclude_empty,
+                    peak_days,
+                    session_gap: gap.map(chrono::Duration::minutes),
+                    timezone: zone.parse().unwrap(),
+                    ..Default::default()
+                },
+            )
+    }
+
+    /// The messages as a `messages.csv`, each field a string as in real exports.
+    fn to_csv(messages: &[Value]) -> Vec<u8> {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer
+            .write_record(["ID", "Timestamp", "Contents", "Attachments"])
+            .unwrap();
+        for message in messages {
+            let field = |key: &str| match &message[key] {
+                Value::Null => String::new(),
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            writer
+                .write_record([
+                    field("ID"),
+                    field("Timestamp"),
+                    field("Contents"),
+                    field("Attachments"),
+                ])
+                .unwrap();
+        }
+        writer.into_inner().unwrap()
+    }
+
+    proptest! {
+        #[test]
+        fn arbitrary_bytes_end_in_counts_or_an_error(bytes in proptest::collection::vec(any::<u8>(), 0..512)) {
+            for format in [MessageFormat::Json, MessageFormat::Csv] {
+                let _ = tally_messages(bytes.as_slice(), format, &ProcessOptions::default());
+            }
+        }
+
+        #[test]
+        fn every_message_is_accounted_for(
+            messages in proptest::collection::vec(message(), 0..40),
+            options in options(),
+        ) {
+            let json = Value::from(messages.clone()).to_string();
+            let csv = to_csv(&messages);
+            for (bytes, format) in [(json.as_bytes(), MessageFormat::Json), (csv.as_slice(), MessageFormat::Csv)] {
+                let Ok(counts) = tally_messages(bytes, format, &options) else {
+                    continue;
+                };
+                let excluded_empty = match options.exclude_empty_messages {
+                    true => counts.empty_messages,
+                    false => 0,
+                };
+                prop_assert_eq!(
+                    counts.counted + counts.duplicates + counts.excluded_system + excluded_empty,
+                    messages.len()
+                );
+                prop_assert!(counts.counted_since <= counts.counted);
+                prop_assert!(counts.years.values().sum::<usize>() <= counts.counted);
+                prop_assert!(counts.first_message <= counts.last_message);
+            }
+        }
+
+        #[test]
+        fn timestamps_convert_in_every_zone(timestamp in timestamp()) {
+            let message = Message {
+                timestamp: Some(timestamp),
+                ..Default::default()
+            };
+            let _ = message.date();
+            if let Some(datetime) = message.datetime() {
+                for zone in ZONES {
+                    let date = zone.parse::<Zone>().unwrap().local_date(&datetime);
+                    prop_assert!(date.year() >= 1969 && date.year() <= 10_000);
+                }
+            }
+        }
+    }
+}
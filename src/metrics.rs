@@ -0,0 +1,207 @@
+//! What `--explain` prints. Every metric declares its explanation as a
+//! `METRIC` constant in the module that computes it, so the two are read and
+//! changed together; this module only lists and prints them.
+
+use crate::{analytics, breadth, buckets, concentration, messages, peaks, sessions, timeline};
+use std::io::Write;
+
+/// A computed figure and what it means.
+pub struct Metric {
+    /// Name accepted by `--explain`, matching its key in `--format json`
+    pub name: &'static str,
+    /// What the number is and how it is computed
+    pub definition: &'static str,
+    /// Options that change the number, and how
+    pub affected_by: &'static str,
+    /// What happens with missing, empty or unusual input
+    pub edge_cases: &'static str,
+}
+
+/// Every metric, in the order `--explain all` lists them.
+pub const METRICS: &[&Metric] = &[
+    &messages::METRIC,
+    &buckets::METRIC,
+    &concentration::METRIC,
+    &sessions::METRIC,
+    &peaks::METRIC,
+    &breadth::METRIC,
+    &timeline::METRIC,
+    &analytics::REFERENCE_METRIC,
+    &analytics::DISCREPANCY_METRIC,
+    &analytics::VOICE_METRIC,
+];
+
+/// Values accepted by `--explain`: every metric's name, then `all`.
+pub fn explain_choices() -> Vec<&'static str> {
+    METRICS
+        .iter()
+        .map(|metric| metric.name)
+        .chain(["all"])
+        .collect()
+}
+
+/// Print the named metric, or every one of them for `all`.
+pub fn explain(name: &str, out: &mut dyn Write) -> std::io::Result<()> {
+    let metrics = METRICS
+        .iter()
+        .filter(|metric| name == "all" || metric.name == name);
+    for (index, metric) in metrics.enumerate() {
+        if index > 0 {
+            writeln!(out)?;
+        }
+        writeln!(out, "{}", metric.name)?;
+        writeln!(out, "    {}", metric.definition)?;
+        writeln!(out, "    Affected by: {}", metric.affected_by)?;
+        writeln!(out, "    Edge cases: {}", metric.edge_cases)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analytics::{Discrepancy, ReferenceCounts, ReportedCount, VoiceStats};
+    use crate::breadth::YearBreadth;
+    use crate::buckets::CountStyle;
+    use crate::concentration::concentration;
+    use crate::peaks::PeakDay;
+    use crate::render::OutputFormat;
+    use crate::sessions::SessionSummary;
+    use crate::testing::{channel, dm, guild, rendered, report, view};
+    use crate::timeline::ThresholdTimeline;
+    use chrono::NaiveDate;
+    use serde_json::Value;
+    use std::collections::HashSet;
+
+    /// Keys holding part of a metric without being named after it.
+    const PART_OF: &[(&str, &str)] = &[
+        ("total_messages", "message_count"),
+        ("summary", "message_count"),
+        ("empty_messages", "message_count"),
+        ("peak_count", "peak_day"),
+        ("peak_count_bucket", "count_bucket"),
+        ("empty_messages_bucket", "count_bucket"),
+    ];
+
+    /// Keys that name, label or describe the run rather than measure.
+    const NOT_METRICS: &[&str] = &[
+        "format",
+        "schema_version",
+        "meta",
+        "conversations",
+        "kind",
+        "id",
+        "name",
+        "note",
+        "channels",
+        "previous_names",
+        "merged_ids",
+        "departed",
+        "filter_stages",
+        "vintage",
+        "partial",
+        "excluded_system",
+        "excluded_empty_messages",
+        "excluded_bot_dms",
+        "excluded_departed_guilds",
+        "processing_stats",
+        "options",
+        "coverage",
+    ];
+
+    /// Keys holding more keys to check rather than a value.
+    const NESTED: &[&str] = &["meta", "conversations", "channels"];
+
+    /// Every metric `value` shows, failing on keys that are neither a
+    /// metric nor known not to be one.
+    fn metrics_in(value: &Value, seen: &mut HashSet<&'static str>) {
+        match value {
+            Value::Array(items) => items.iter().for_each(|item| metrics_in(item, seen)),
+            Value::Object(object) => {
+                for (key, value) in object {
+                    let metric = METRICS
+                        .iter()
+                        .map(|metric| metric.name)
+                        .find(|name| name == key)
+                        .or_else(|| {
+                            PART_OF
+                                .iter()
+                                .find(|(part, _)| part == key)
+                                .map(|&(_, name)| name)
+                        });
+                    match metric {
+                        Some(name) => {
+                            seen.insert(name);
+                        }
+                        None => assert!(
+                            NOT_METRICS.contains(&key.as_str()),
+                            "{} has no explanation",
+                            key
+                        ),
+                    }
+                    if NESTED.contains(&key.as_str()) {
+                        metrics_in(value, seen);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn every_metric_in_json_is_explained() {
+        let mut report = report(vec![
+            dm("1", "Alice", 30),
+            guild(Some("10"), "Server", vec![channel("11", "general", 20)]),
+        ]);
+        report.reference_counts = Some(ReferenceCounts {
+            counted: 50,
+            reported: ReportedCount {
+                count: 60,
+                source: "the account data".to_string(),
+            },
+        });
+        report.discrepancies = vec![Discrepancy {
+            conversation_id: "1".to_string(),
+            analytics_sent: 40,
+            counted: 30,
+        }];
+        report.voice = Some(VoiceStats {
+            total_seconds: 60,
+            sessions: 1,
+            ..Default::default()
+        });
+        report.breadth_by_year = Some(vec![YearBreadth {
+            year: 2021,
+            servers: 1,
+            channels: 1,
+            dms: 1,
+        }]);
+        report.sessions = Some(SessionSummary::default());
+        report.peak_days.insert(
+            "1".to_string(),
+            PeakDay {
+                day: NaiveDate::from_ymd_opt(2021, 1, 1).unwrap(),
+                count: 5,
+            },
+        );
+        let mut view = view(&report);
+        view.summary = true;
+        view.empty_message_stats = true;
+        view.concentration = concentration([30, 20], &"50".parse().unwrap());
+        view.threshold_timeline = Some(ThresholdTimeline {
+            threshold: 10,
+            years: Vec::new(),
+        });
+
+        let mut seen = HashSet::new();
+        for counts in [CountStyle::Exact, CountStyle::Bucketed] {
+            view.counts = counts;
+            let json: Value = serde_json::from_str(&rendered(OutputFormat::Json, &view)).unwrap();
+            metrics_in(&json, &mut seen);
+        }
+        for metric in METRICS {
+            assert!(seen.contains(metric.name), "{} is not in JSON", metric.name);
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! Checks a copy of a package, e.g. one with its attachments deleted to
+//! save space, against the original archive. Only the messages files decide
+//! the counts, so only they are compared: by size and CRC-32 as recorded in
+//! the archive's central directory, without extracting anything. The same
+//! comparison, by size only, decides whether a folder extracted next to an
+//! archive can be read instead of extracting the archive again.
+
+use crate::errors::MyError;
+use crate::vintage::MessageFormat;
+use crate::warnings::Warning;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use zip::read::ZipArchive;
+
+/// Size and CRC-32 of a messages file in the original archive.
+struct Entry {
+    size: u64,
+    crc32: u32,
+}
+
+/// Compare the messages files below `data_root` with those in `original`,
+/// returning a warning for every file that is missing, extra or different.
+pub fn verify_mirror(data_root: &Path, original: &Path) -> Result<Vec<Warning>, MyError> {
+    let mut entries = archive_entries(original)?;
+    let mut warnings = Vec::new();
+    let mismatch = |file: &str, problem: String| Warning::MirrorMismatch {
+        file: file.to_string(),
+        problem,
+    };
+    for (name, path) in mirror_files(data_root)? {
+        let Some(entry) = entries.remove(&name) else {
+            warnings.push(mismatch(
+                &name,
+                "is not in the original archive".to_string(),
+            ));
+            continue;
+        };
+        let size = fs::metadata(&path)?.len();
+        if size != entry.size {
+            warnings.push(mismatch(
+                &name,
+                format!("has {} bytes instead of {}", size, entry.size),
+            ));
+        } else if crc32(&path)? != entry.crc32 {
+            warnings.push(mismatch(
+                &name,
+                "differs from the original with the same size".to_string(),
+            ));
+        }
+    }
+    for name in entries.into_keys() {
+        warnings.push(mismatch(&name, "is missing from the copy".to_string()));
+    }
+    Ok(warnings)
+}
+
+/// The folder next to `archive` named after it, such as `package/` beside
+/// `package.zip`, if there is one.
+pub fn sibling_folder(archive: &Path) -> Option<PathBuf> {
+    let folder = archive.with_extension("");
+    (folder != archive && folder.join("messages").is_dir()).then_some(folder)
+}
+
+/// Whether `data_root` holds exactly the messages files of `archive`, with
+/// the same sizes. Cheap enough to decide whether a folder can stand in for
+/// extracting the archive, unlike comparing checksums.
+pub fn matches_by_size(data_root: &Path, archive: &Path) -> Result<bool, MyError> {
+    let mut entries = archive_entries(archive)?;
+    for (name, path) in mirror_files(data_root)? {
+        match entries.remove(&name) {
+            Some(entry) if fs::metadata(&path)?.len() == entry.size => {}
+            _ => return Ok(false),
+        }
+    }
+    Ok(entries.is_empty())
+}
+
+/// Messages files of an archive, by name, read from its central directory.
+fn archive_entries(archive: &Path) -> Result<BTreeMap<String, Entry>, MyError> {
+    let mut archive = ZipArchive::new(File::open(archive)?)?;
+    let mut entries = BTreeMap::new();
+    for index in 0..archive.len() {
+        let file = archive.by_index_raw(index)?;
+        if is_messages_file(Path::new(file.name())) {
+            entries.insert(
+                file.name().to_string(),
+                Entry {
+                    size: file.size(),
+                    crc32: file.crc32(),
+                },
+            );
+        }
+    }
+    Ok(entries)
+}
+
+/// Messages files of the copy, by the name their entry has in an archive.
+fn mirror_files(data_root: &Path) -> Result<Vec<(String, PathBuf)>, MyError> {
+    let messages_folder = data_root.join("messages");
+    let mut files = Vec::new();
+    for folder in fs::read_dir(&messages_folder)? {
+        let folder = folder?;
+        if !folder.file_type()?.is_dir() {
+            continue;
+        }
+        for format in [MessageFormat::Json, MessageFormat::Csv] {
+            let path = folder.path().join(format.file_name());
+            if path.is_file() {
+                let name = format!(
+                    "messages/{}/{}",
+                    folder.file_name().to_string_lossy(),
+                    format.file_name()
+                );
+                files.push((name, path));
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Whether an archive entry is the messages file of a channel folder.
+fn is_messages_file(name: &Path) -> bool {
+    let mut components = name.iter();
+    components.next().is_some_and(|first| first == "messages")
+        && components.next().is_some()
+        && components.next().is_some_and(|file| {
+            [MessageFormat::Json, MessageFormat::Csv]
+                .iter()
+                .any(|format| file == format.file_name())
+        })
+        && components.next().is_none()
+}
+
+fn crc32(path: &Path) -> Result<u32, MyError> {
+    let mut file = File::open(path)?;
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(hasher.finalize());
+        }
+        hasher.update(&buffer[..read]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Package;
+    use serde_json::json;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    /// A package of two channels and an attachment, and the original
+    /// archive of it.
+    fn mirrored() -> (Package, tempfile::TempDir, PathBuf) {
+        let package = Package::new();
+        package
+            .channel("1", json!({"id": "1"}), 3)
+            .channel("2", json!({"id": "2"}), 5)
+            .file("messages/c1/attachment.png", "not really a picture");
+
+        let dir = tempfile::tempdir().unwrap();
+        let original = dir.path().join("package.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&original).unwrap());
+        for name in [
+            "messages/c1/channel.json",
+            "messages/c1/messages.json",
+            "messages/c1/attachment.png",
+            "messages/c2/channel.json",
+            "messages/c2/messages.json",
+        ] {
+            writer
+                .start_file(name, SimpleFileOptions::default())
+                .unwrap();
+            writer
+                .write_all(&fs::read(package.path().join(name)).unwrap())
+                .unwrap();
+        }
+        writer.finish().unwrap();
+        (package, dir, original)
+    }
+
+    fn problems(package: &Package, original: &Path) -> Vec<String> {
+        verify_mirror(package.path(), original)
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn a_stripped_copy_matches_its_original() {
+        let (package, _dir, original) = mirrored();
+        fs::remove_file(package.path().join("messages/c1/attachment.png")).unwrap();
+        assert!(problems(&package, &original).is_empty());
+        assert!(matches_by_size(package.path(), &original).unwrap());
+    }
+
+    #[test]
+    fn tampering_is_detected() {
+        let (package, _dir, original) = mirrored();
+        // The same size, so only the checksum tells
+        let file = package.path().join("messages/c1/messages.json");
+        let tampered = fs::read_to_string(&file).unwrap().replace("\"3\"", "\"4\"");
+        fs::write(&file, tampered).unwrap();
+        package.file("messages/c2/messages.json", "[]");
+        package.file("messages/c3/messages.json", "[]");
+
+        let expected = [
+            "messages/c1/messages.json differs from the original with the same size",
+            "messages/c2/messages.json has 2 bytes instead of",
+            "messages/c3/messages.json is not in the original archive",
+        ];
+        let problems = problems(&package, &original);
+        assert_eq!(problems.len(), expected.len(), "{:?}", problems);
+        for problem in expected {
+            assert!(
+                problems.iter().any(|found| found.starts_with(problem)),
+                "{} in {:?}",
+                problem,
+                problems
+            );
+        }
+        // A checksum is not compared when deciding on a sibling folder
+        assert!(!matches_by_size(package.path(), &original).unwrap());
+    }
+
+    #[test]
+    fn missing_messages_files_are_reported() {
+        let (package, _dir, original) = mirrored();
+        fs::remove_file(package.path().join("messages/c2/messages.json")).unwrap();
+        assert_eq!(
+            problems(&package, &original),
+            [
+                "messages/c2/messages.json is missing from the copy, so the counts of this copy \
+              may differ from the original's"
+            ]
+        );
+        assert!(!matches_by_size(package.path(), &original).unwrap());
+    }
+
+    #[test]
+    fn analysing_a_tampered_copy_warns() {
+        let (package, _dir, original) = mirrored();
+        package.file("messages/c2/messages.json", "[]");
+        let mut options = package.options();
+        options.verify_against = Some(original);
+        let report = crate::analysis::analyze(&options).unwrap();
+        assert!(report.warnings.iter().any(
+            |warning| matches!(warning, Warning::MirrorMismatch { file, .. } if file == "messages/c2/messages.json")
+        ));
+    }
+}
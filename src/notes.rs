@@ -0,0 +1,157 @@
+use crate::errors::MyError;
+use crate::file_operations::normalize_channel_id;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Read a map of conversation ID to note, as TOML for `.toml` files and
+/// JSON otherwise. IDs may carry the `c` prefix of channel folders.
+pub fn load_notes(path: &Path) -> Result<HashMap<String, String>, MyError> {
+    let contents = fs::read_to_string(path)?;
+    let notes: HashMap<String, String> = if path.extension().is_some_and(|ext| ext == "toml") {
+        toml::from_str(&contents)
+            .map_err(|e| MyError::InvalidNotes(format!("{}: {}", path.display(), e)))?
+    } else {
+        serde_json::from_str(&contents)
+            .map_err(|e| MyError::InvalidNotes(format!("{}: {}", path.display(), e)))?
+    };
+
+    Ok(notes
+        .into_iter()
+        .map(|(id, note)| (normalize_channel_id(id.trim()).to_string(), note))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redact::{redact, Redaction};
+    use crate::render::OutputFormat;
+    use crate::report::Report;
+    use crate::testing::{dm, rendered, report, view};
+    use crate::width::NameWidth;
+    use std::io::Write;
+
+    const NOTE: &str = "college friend";
+
+    fn notes_file(extension: &str, contents: &str) -> HashMap<String, String> {
+        let mut file = tempfile::Builder::new()
+            .suffix(extension)
+            .tempfile()
+            .unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        load_notes(file.path()).unwrap()
+    }
+
+    #[test]
+    fn toml_and_json_files_read_alike() {
+        let expected = HashMap::from([
+            ("123".to_string(), NOTE.to_string()),
+            ("456".to_string(), "left 2022".to_string()),
+        ]);
+        assert_eq!(
+            notes_file(
+                ".toml",
+                "123 = \"college friend\"\n\" c456 \" = \"left 2022\"\n"
+            ),
+            expected
+        );
+        assert_eq!(
+            notes_file(".json", r#"{"c123": "college friend", "456": "left 2022"}"#),
+            expected
+        );
+    }
+
+    #[test]
+    fn malformed_files_are_invalid_notes() {
+        let mut file = tempfile::Builder::new().suffix(".json").tempfile().unwrap();
+        file.write_all(b"[\"not a map\"]").unwrap();
+        assert!(matches!(
+            load_notes(file.path()),
+            Err(MyError::InvalidNotes(_))
+        ));
+    }
+
+    /// Prepares the report for a case, returning whether Alice passes the filters.
+    type Setup = fn(&mut Report) -> bool;
+
+    /// Whether the note of Alice shows in `format`, given how the run is
+    /// set up.
+    fn shows_note(format: OutputFormat, setup: Setup, names: NameWidth) -> bool {
+        let mut report = report(vec![dm("1", "Alice Wonderland", 30), dm("2", "Bob", 10)]);
+        report.notes.insert("1".to_string(), NOTE.to_string());
+        let keep_alice = setup(&mut report);
+        let mut view = view(&report);
+        view.names = names;
+        if !keep_alice {
+            view.conversations
+                .retain(|conversation| conversation.name() != "Alice Wonderland");
+        }
+        rendered(format, &view).contains(NOTE)
+    }
+
+    #[test]
+    fn notes_across_filters_and_formats() {
+        let formats = [
+            OutputFormat::Text,
+            OutputFormat::Table,
+            OutputFormat::Json,
+            OutputFormat::Tsv,
+            OutputFormat::Nuon,
+        ];
+        let cases: [(&str, Setup, NameWidth, bool); 5] = [
+            ("shown", |_| true, NameWidth::Full, true),
+            ("names truncated", |_| true, NameWidth::Fixed(4), true),
+            ("filtered out", |_| false, NameWidth::Full, false),
+            (
+                "redacted, notes kept",
+                |report| {
+                    redact(report, Redaction::Hashed, "salt");
+                    true
+                },
+                NameWidth::Full,
+                true,
+            ),
+            (
+                "redacted, notes dropped",
+                |report| {
+                    report.notes.clear();
+                    redact(report, Redaction::Full, "");
+                    true
+                },
+                NameWidth::Full,
+                false,
+            ),
+        ];
+        for format in formats {
+            for (case, setup, names, expected) in cases {
+                assert_eq!(
+                    shows_note(format, setup, names),
+                    expected,
+                    "{:?}, {}",
+                    format,
+                    case
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn notes_are_dimmed_and_kept_whole_when_names_are_cut() {
+        let mut report = report(vec![dm("1", "Alice Wonderland", 30)]);
+        report.notes.insert("1".to_string(), NOTE.to_string());
+        let mut view = view(&report);
+        view.names = NameWidth::Fixed(4);
+        let text = rendered(OutputFormat::Text, &view);
+        assert!(!text.contains("Wonderland"), "{}", text);
+        assert!(text.contains(&format!(" — {} [", NOTE)), "{}", text);
+
+        view.palette.enabled = true;
+        let text = rendered(OutputFormat::Text, &view);
+        assert!(
+            text.contains(&format!("\x1b[2m — {}\x1b[0m", NOTE)),
+            "{}",
+            text
+        );
+    }
+}
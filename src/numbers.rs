@@ -0,0 +1,139 @@
+//! Number formatting shared by every renderer. Ratios are computed on
+//! integers and rounded half-up, so the same inputs always print the same
+//! digits and no value ever prints as `-0.0`.
+
+/// `numerator / denominator` with `decimals` decimal places, rounded half-up.
+/// A zero denominator gives zero.
+pub fn fmt_ratio(numerator: u64, denominator: u64, decimals: u32) -> String {
+    let scale = 10u128.pow(decimals);
+    fixed(divide(u128::from(numerator) * scale, denominator), decimals)
+}
+
+/// Percentage digits without the sign, e.g. "47.3". Shows 100 only when the
+/// ratio is exactly one: anything short of it stops at 99.9 and the like.
+pub fn percent_value(numerator: u64, denominator: u64, decimals: u32) -> String {
+    let whole = 100 * 10u128.pow(decimals);
+    let mut scaled = divide(u128::from(numerator) * whole, denominator);
+    if scaled == whole && numerator < denominator {
+        scaled -= 1;
+    }
+    fixed(scaled, decimals)
+}
+
+/// Percentage with its sign, e.g. "47.3%".
+pub fn fmt_percent(numerator: u64, denominator: u64, decimals: u32) -> String {
+    format!("{}%", percent_value(numerator, denominator, decimals))
+}
+
+fn divide(numerator: u128, denominator: u64) -> u128 {
+    let denominator = u128::from(denominator);
+    if denominator == 0 {
+        return 0;
+    }
+    let (quotient, remainder) = (numerator / denominator, numerator % denominator);
+    quotient + u128::from(remainder * 2 >= denominator)
+}
+
+/// Print a value scaled by `10^decimals` with that many decimal places.
+fn fixed(scaled: u128, decimals: u32) -> String {
+    let scale = 10u128.pow(decimals);
+    match decimals {
+        0 => scaled.to_string(),
+        _ => format!(
+            "{}.{:0width$}",
+            scaled / scale,
+            scaled % scale,
+            width = decimals as usize
+        ),
+    }
+}
+
+/// Split `units` between `weights` in proportion, by largest remainder, so
+/// the parts always add up to `units` exactly. Ties in the remainder go to
+/// the earlier weight. All zero weights give all zero parts.
+pub fn apportion(weights: &[u64], units: u64) -> Vec<u64> {
+    let total: u128 = weights.iter().map(|&weight| u128::from(weight)).sum();
+    if total == 0 {
+        return vec![0; weights.len()];
+    }
+    let exact: Vec<(u128, u128)> = weights
+        .iter()
+        .map(|&weight| {
+            let scaled = u128::from(weight) * u128::from(units);
+            (scaled / total, scaled % total)
+        })
+        .collect();
+    let mut parts: Vec<u64> = exact.iter().map(|&(part, _)| part as u64).collect();
+    let short = units - parts.iter().sum::<u64>();
+    let mut by_remainder: Vec<usize> = (0..exact.len()).collect();
+    by_remainder.sort_by(|&a, &b| exact[b].1.cmp(&exact[a].1).then(a.cmp(&b)));
+    for &index in by_remainder.iter().take(short as usize) {
+        parts[index] += 1;
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn rounds_half_up() {
+        assert_eq!(fmt_ratio(1, 2, 0), "1");
+        assert_eq!(fmt_ratio(1, 8, 2), "0.13");
+        assert_eq!(fmt_ratio(1, 3, 3), "0.333");
+        assert_eq!(fmt_ratio(5, 0, 1), "0.0");
+        assert_eq!(fmt_percent(1, 200, 0), "1%");
+    }
+
+    #[test]
+    fn a_hundred_percent_only_when_exact() {
+        assert_eq!(fmt_percent(9_999, 10_000, 1), "99.9%");
+        assert_eq!(fmt_percent(u64::MAX - 1, u64::MAX, 2), "99.99%");
+        assert_eq!(fmt_percent(7, 7, 1), "100.0%");
+        assert_eq!(fmt_percent(0, 0, 1), "0.0%");
+    }
+
+    #[test]
+    fn apportioning_breaks_ties_towards_earlier_weights() {
+        assert_eq!(apportion(&[1, 1, 1], 100), [34, 33, 33]);
+        assert_eq!(apportion(&[0, 0], 10), [0, 0]);
+        assert_eq!(apportion(&[], 10), Vec::<u64>::new());
+        assert_eq!(
+            apportion(&[u64::MAX, u64::MAX], u64::MAX),
+            [u64::MAX / 2 + 1, u64::MAX / 2]
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn percentages_read_a_hundred_only_when_exact(
+            (numerator, denominator) in any::<u64>().prop_flat_map(|d| (0..=d, Just(d))),
+            decimals in 0u32..6,
+        ) {
+            let percent = fmt_percent(numerator, denominator, decimals);
+            prop_assert!(!percent.starts_with('-'));
+            let value: f64 = percent.trim_end_matches('%').parse().unwrap();
+            prop_assert!((0.0..=100.0).contains(&value));
+            prop_assert_eq!(value == 100.0, numerator == denominator && denominator > 0);
+        }
+
+        #[test]
+        fn apportioned_parts_add_up_and_stay_near_their_share(
+            weights in proptest::collection::vec(0u64..1_000_000_000_000, 1..20),
+            units in 0u64..100_000,
+        ) {
+            let parts = apportion(&weights, units);
+            let total: u128 = weights.iter().map(|&weight| u128::from(weight)).sum();
+            prop_assume!(total > 0);
+            prop_assert_eq!(parts.iter().sum::<u64>(), units);
+            for (&weight, &part) in weights.iter().zip(&parts) {
+                let exact = u128::from(weight) * u128::from(units);
+                // Within one unit of the exact share either way
+                prop_assert!(u128::from(part) * total + total > exact);
+                prop_assert!(u128::from(part) * total < exact + total);
+            }
+        }
+    }
+}
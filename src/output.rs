@@ -0,0 +1,196 @@
+//! Where the main results go: stdout, or the `--output` file. The file is
+//! written under a temporary name beside it and renamed into place once
+//! complete, so a run that fails halfway never leaves half a report behind
+//! and never clobbers the previous one.
+
+use crate::errors::MyError;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, StdoutLock, Write};
+use std::path::{Path, PathBuf};
+
+pub enum Output {
+    Stdout(StdoutLock<'static>),
+    File(AtomicFile),
+}
+
+impl Output {
+    /// The file at `path`, or stdout without one. An existing file is only
+    /// replaced with `force`.
+    pub fn open(path: Option<&Path>, force: bool) -> Result<Self, MyError> {
+        Ok(match path {
+            Some(path) => Self::File(AtomicFile::create(path, force)?),
+            None => Self::Stdout(io::stdout().lock()),
+        })
+    }
+
+    /// Flush everything written and, for a file, move it into place.
+    pub fn finish(self) -> Result<(), MyError> {
+        match self {
+            Self::Stdout(mut stdout) => Ok(stdout.flush()?),
+            Self::File(file) => file.commit(),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Stdout(stdout) => stdout.write(buf),
+            Self::File(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Stdout(stdout) => stdout.flush(),
+            Self::File(file) => file.flush(),
+        }
+    }
+}
+
+/// A file written under a temporary name in the destination's folder, so
+/// that the final rename stays on one file system. Dropped without
+/// `commit`, the temporary file is removed again.
+pub struct AtomicFile {
+    path: PathBuf,
+    temp: PathBuf,
+    /// Taken by `commit`
+    out: Option<BufWriter<File>>,
+}
+
+impl AtomicFile {
+    pub fn create(path: &Path, force: bool) -> Result<Self, MyError> {
+        if !force && path.exists() {
+            return Err(MyError::OutputExists(path.to_path_buf()));
+        }
+        let temp = temp_path(path);
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)
+            .map_err(|error| MyError::OpenOutput(path.to_path_buf(), error))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            temp,
+            out: Some(BufWriter::new(file)),
+        })
+    }
+
+    pub fn commit(mut self) -> Result<(), MyError> {
+        let Some(out) = self.out.take() else {
+            return Ok(());
+        };
+        let file = out.into_inner().map_err(|error| error.into_error())?;
+        file.sync_all()?;
+        drop(file);
+        if let Err(error) = fs::rename(&self.temp, &self.path) {
+            let _ = fs::remove_file(&self.temp);
+            return Err(MyError::OpenOutput(self.path.clone(), error));
+        }
+        Ok(())
+    }
+
+    fn out(&mut self) -> &mut BufWriter<File> {
+        self.out.as_mut().expect("written after commit")
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.out().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out().flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if self.out.take().is_some() {
+            let _ = fs::remove_file(&self.temp);
+        }
+    }
+}
+
+/// `.NAME.PID.tmp` beside `path`; hidden, and unique per process.
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map_or_else(|| "output".into(), |name| name.to_string_lossy());
+    path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Names in `dir`, sorted.
+    fn listing(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn committed_files_replace_the_destination() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("counts.csv");
+        let mut file = AtomicFile::create(&path, false).unwrap();
+        file.write_all(b"new").unwrap();
+        // Nothing at the destination until the commit
+        assert_eq!(
+            listing(dir.path()),
+            [temp_path(&path).file_name().unwrap().to_string_lossy()]
+        );
+
+        file.commit().unwrap();
+        assert_eq!(listing(dir.path()), ["counts.csv"]);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+    }
+
+    #[test]
+    fn abandoned_files_leave_the_previous_one_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("counts.csv");
+        fs::write(&path, "old").unwrap();
+
+        let mut file = AtomicFile::create(&path, true).unwrap();
+        file.write_all(b"half a rep").unwrap();
+        drop(file);
+
+        assert_eq!(listing(dir.path()), ["counts.csv"]);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+    }
+
+    #[test]
+    fn existing_files_are_only_replaced_with_force() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("counts.csv");
+        fs::write(&path, "old").unwrap();
+
+        assert!(matches!(
+            AtomicFile::create(&path, false),
+            Err(MyError::OutputExists(ref existing)) if *existing == path
+        ));
+        let mut output = Output::open(Some(&path), true).unwrap();
+        output.write_all(b"new").unwrap();
+        output.finish().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(listing(dir.path()), ["counts.csv"]);
+    }
+
+    #[test]
+    fn unwritable_destinations_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing/counts.csv");
+        assert!(matches!(
+            AtomicFile::create(&path, false),
+            Err(MyError::OpenOutput(ref failed, _)) if *failed == path
+        ));
+        assert!(listing(dir.path()).is_empty());
+    }
+}
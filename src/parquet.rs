@@ -0,0 +1,125 @@
+//! Results as a Parquet file with one row per channel or DM, for reading
+//! straight into Polars or pandas. Guild columns of DMs and of channels in
+//! unidentified servers are nulls rather than empty strings.
+
+use crate::errors::MyError;
+use crate::output::AtomicFile;
+use crate::render::ResultsView;
+use crate::{Conversation, ConversationType};
+use ::parquet::arrow::ArrowWriter;
+use ::parquet::errors::ParquetError;
+use arrow_array::{ArrayRef, RecordBatch, StringArray, UInt64Array};
+use arrow_schema::{DataType, Field, Schema};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Column values of every row, in the order of the schema.
+#[derive(Default)]
+struct Columns<'a> {
+    conversation_type: Vec<&'static str>,
+    guild_id: Vec<Option<&'a str>>,
+    guild_name: Vec<Option<&'a str>>,
+    channel_id: Vec<&'a str>,
+    channel_name: Vec<&'a str>,
+    message_count: Vec<u64>,
+}
+
+/// Write the rows to `path`, which is only replaced with `force`.
+pub fn export_parquet(view: &ResultsView, path: &Path, force: bool) -> Result<(), MyError> {
+    let mut columns = Columns::default();
+    for &conversation in &view.conversations {
+        match conversation {
+            Conversation::DmOrGc {
+                id,
+                name,
+                message_count,
+                ..
+            } => {
+                columns
+                    .conversation_type
+                    .push(conversation.effective_kind(view.group_dms_as).label());
+                columns.guild_id.push(None);
+                columns.guild_name.push(None);
+                columns.channel_id.push(id);
+                columns.channel_name.push(name);
+                columns.message_count.push(*message_count as u64);
+            }
+            Conversation::Guild {
+                id, name, channels, ..
+            } => {
+                // The name of an unidentified server is only a placeholder
+                let guild_name = id.as_ref().map(|_| name.as_str());
+                for channel in channels {
+                    columns
+                        .conversation_type
+                        .push(ConversationType::Guild.label());
+                    columns.guild_id.push(id.as_deref());
+                    columns.guild_name.push(guild_name);
+                    columns.channel_id.push(&channel.id);
+                    columns.channel_name.push(&channel.name);
+                    columns.message_count.push(channel.message_count as u64);
+                }
+            }
+        }
+    }
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("conversation_type", DataType::Utf8, false),
+        Field::new("guild_id", DataType::Utf8, true),
+        Field::new("guild_name", DataType::Utf8, true),
+        Field::new("channel_id", DataType::Utf8, false),
+        Field::new("channel_name", DataType::Utf8, false),
+        Field::new("message_count", DataType::UInt64, false),
+    ]));
+    let arrays: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from(columns.conversation_type)),
+        Arc::new(StringArray::from(columns.guild_id)),
+        Arc::new(StringArray::from(columns.guild_name)),
+        Arc::new(StringArray::from(columns.channel_id)),
+        Arc::new(StringArray::from(columns.channel_name)),
+        Arc::new(UInt64Array::from(columns.message_count)),
+    ];
+    let batch = RecordBatch::try_new(schema.clone(), arrays).map_err(ParquetError::from)?;
+
+    let mut writer = ArrowWriter::try_new(AtomicFile::create(path, force)?, schema, None)?;
+    writer.write(&batch)?;
+    writer.into_inner()?.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{channel, dm, guild, report, view};
+    use ::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use std::fs::{self, File};
+
+    #[test]
+    fn rows_are_written_and_only_replaced_with_force() {
+        let report = report(vec![
+            guild(
+                Some("10"),
+                "Server",
+                vec![channel("11", "general", 3), channel("12", "memes", 1)],
+            ),
+            dm("2", "Alice", 2),
+        ]);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("counts.parquet");
+        fs::write(&path, "mine").unwrap();
+        assert!(matches!(
+            export_parquet(&view(&report), &path, false),
+            Err(MyError::OutputExists(_))
+        ));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "mine");
+
+        export_parquet(&view(&report), &path, true).unwrap();
+        let rows: usize = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap()
+            .map(|batch| batch.unwrap().num_rows())
+            .sum();
+        assert_eq!(rows, 3);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
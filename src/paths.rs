@@ -0,0 +1,91 @@
+//! Locations of files that persist between runs. Each directory follows the
+//! platform's conventions (XDG on Linux, Known Folders on Windows, Library on
+//! macOS) and can be overridden through an environment variable.
+
+use crate::errors::MyError;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+const APP_DIR: &str = "discord-gdpr-counter";
+
+/// Overrides the directory returned by `cache_dir`.
+pub const CACHE_DIR_VAR: &str = "DISCORD_GDPR_COUNTER_CACHE_DIR";
+
+/// Overrides the directory returned by `config_dir`.
+pub const CONFIG_DIR_VAR: &str = "DISCORD_GDPR_COUNTER_CONFIG_DIR";
+
+/// Directory for data that can be rebuilt at any time, created on demand.
+pub fn cache_dir() -> Result<PathBuf, MyError> {
+    app_dir(CACHE_DIR_VAR, dirs::cache_dir())
+}
+
+/// Directory for settings the user edits, created on demand.
+pub fn config_dir() -> Result<PathBuf, MyError> {
+    app_dir(CONFIG_DIR_VAR, dirs::config_dir())
+}
+
+/// The configuration file inside `config_dir`; the file itself need not exist.
+pub fn config_file() -> Result<PathBuf, MyError> {
+    Ok(config_dir()?.join("config.toml"))
+}
+
+/// `var` when it is set, else this tool's folder in `platform_dir`, falling
+/// back to the temp directory where the platform has none.
+fn app_dir(var: &str, platform_dir: Option<PathBuf>) -> Result<PathBuf, MyError> {
+    let dir = match env::var_os(var).filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => platform_dir.unwrap_or_else(env::temp_dir).join(APP_DIR),
+    };
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Path of a file inside `cache_dir`.
+pub fn cache_file(name: &str) -> Result<PathBuf, MyError> {
+    Ok(cache_dir()?.join(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Each test owns its variable, as the environment is shared by every
+    // test of the process.
+
+    #[test]
+    fn cache_dir_follows_its_variable_and_is_created() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("nested").join("cache");
+        env::set_var(CACHE_DIR_VAR, &dir);
+        assert_eq!(cache_dir().unwrap(), dir);
+        assert!(dir.is_dir());
+        assert_eq!(cache_file("index.json").unwrap(), dir.join("index.json"));
+        env::remove_var(CACHE_DIR_VAR);
+    }
+
+    #[test]
+    fn config_file_follows_its_variable_and_its_folder_is_created() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("nested").join("config");
+        env::set_var(CONFIG_DIR_VAR, &dir);
+        assert_eq!(config_file().unwrap(), dir.join("config.toml"));
+        assert!(dir.is_dir());
+        assert!(!dir.join("config.toml").exists());
+        env::remove_var(CONFIG_DIR_VAR);
+    }
+
+    #[test]
+    fn an_empty_variable_falls_back_to_the_platform_folder() {
+        let temp = tempfile::tempdir().unwrap();
+        let var = "DISCORD_GDPR_COUNTER_TEST_EMPTY_DIR";
+        env::set_var(var, "");
+        let platform = temp.path().join("platform");
+        assert_eq!(
+            app_dir(var, Some(platform.clone())).unwrap(),
+            platform.join(APP_DIR)
+        );
+        assert!(platform.join(APP_DIR).is_dir());
+        env::remove_var(var);
+    }
+}
@@ -0,0 +1,34 @@
+//! `--activity-stats`: each conversation's own busiest day. Days are
+//! calendar days of `--timezone`, tallied per channel while counting and
+//! summed per conversation, so a guild's peak spans all of its channels.
+
+use crate::metrics::Metric;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub const METRIC: Metric = Metric {
+    name: "peak_day",
+    definition: "The calendar day with the most counted messages in a conversation \
+        (peak_day), and how many there were (peak_count). A guild's days are the sums over \
+        its channels.",
+    affected_by: "Collected with --activity-stats. --timezone decides where a day begins. \
+        --exclude-system and --dedupe-messages remove messages before days are tallied.",
+    edge_cases: "Messages without a usable timestamp are left out, so a conversation \
+        without any has no peak. Of equally busy days the earliest is the peak.",
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeakDay {
+    pub day: NaiveDate,
+    pub count: usize,
+}
+
+/// The day with the most messages, the earliest of equally busy ones.
+pub fn peak_day(days: &BTreeMap<NaiveDate, usize>) -> Option<PeakDay> {
+    days.iter()
+        .fold(None, |peak: Option<PeakDay>, (&day, &count)| match peak {
+            Some(peak) if peak.count >= count => Some(peak),
+            _ => Some(PeakDay { day, count }),
+        })
+}
@@ -0,0 +1,235 @@
+//! Where progress goes while a package is counted, chosen with `--progress`:
+//! the redrawing spinner for terminals, one line now and then for CI logs,
+//! JSON lines for other programs, or nothing. Every sink sees the same
+//! events and writes to stderr.
+
+use crate::analysis::Stage;
+use crate::errors::MyError;
+use crate::humanize::{fmt_compact, fmt_duration};
+use crate::spinner::Spinner;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+/// Longest a plain line waits while channels are processed.
+const PLAIN_INTERVAL: Duration = Duration::from_secs(10);
+/// Channels after which a plain line is due even sooner.
+const PLAIN_CHANNELS: usize = 500;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ProgressMode {
+    /// The spinner on terminals, plain lines otherwise, nothing with --accessible
+    #[default]
+    Auto,
+    /// A spinner redrawn in place
+    Bar,
+    /// A log line every few seconds or channels, without carriage returns
+    Plain,
+    /// One JSON object per event and line
+    Json,
+    /// Nothing at all
+    None,
+}
+
+/// Receives the progress of a run, in order.
+pub trait ProgressSink {
+    fn stage(&mut self, stage: Stage);
+
+    /// `processed` of `total` channel folders counted so far, holding
+    /// `messages` messages.
+    fn channels(&mut self, processed: usize, total: usize, messages: usize);
+
+    fn finish(&mut self);
+}
+
+/// The sink for `mode`, with `auto` resolved for stderr.
+pub fn progress_sink(
+    mode: ProgressMode,
+    accessible: bool,
+) -> Result<Box<dyn ProgressSink>, MyError> {
+    let mode = match mode {
+        ProgressMode::Auto if accessible => ProgressMode::None,
+        ProgressMode::Auto if io::stderr().is_terminal() => ProgressMode::Bar,
+        ProgressMode::Auto => ProgressMode::Plain,
+        mode => mode,
+    };
+    Ok(match mode {
+        ProgressMode::Bar => Box::new(Spinner::start(true)?),
+        ProgressMode::Plain => Box::new(PlainProgress::new(io::stderr())),
+        ProgressMode::Json => Box::new(JsonProgress(io::stderr())),
+        _ => Box::new(Spinner::start(false)?),
+    })
+}
+
+impl ProgressSink for Spinner {
+    fn stage(&mut self, stage: Stage) {
+        self.set_message(format!("{}...", stage.label()));
+    }
+
+    fn channels(&mut self, processed: usize, total: usize, _messages: usize) {
+        self.set_message(format!(
+            "{}... ({}/{})",
+            Stage::ProcessingChannels.label(),
+            processed,
+            total
+        ));
+    }
+
+    fn finish(&mut self) {
+        Spinner::finish(self);
+    }
+}
+
+/// A line for every `PLAIN_CHANNELS` channels or `PLAIN_INTERVAL`, whichever
+/// comes first, and one once all channels are done. Of the stages, only
+/// waiting for another instance is logged, as it may take a while.
+pub struct PlainProgress<W> {
+    out: W,
+    started: Instant,
+    /// When the last line was written and how many channels it reported
+    last_line: (Instant, usize),
+}
+
+impl<W: Write> PlainProgress<W> {
+    pub fn new(out: W) -> Self {
+        let now = Instant::now();
+        Self {
+            out,
+            started: now,
+            last_line: (now, 0),
+        }
+    }
+}
+
+impl<W: Write> ProgressSink for PlainProgress<W> {
+    fn stage(&mut self, stage: Stage) {
+        if stage == Stage::WaitingForLock {
+            let _ = writeln!(self.out, "{}…", stage.label().to_lowercase());
+        }
+    }
+
+    fn channels(&mut self, processed: usize, total: usize, messages: usize) {
+        self.channels_at(Instant::now(), processed, total, messages);
+    }
+
+    fn finish(&mut self) {}
+}
+
+impl<W: Write> PlainProgress<W> {
+    /// Channel progress as of `now`, which tests set instead of waiting.
+    fn channels_at(&mut self, now: Instant, processed: usize, total: usize, messages: usize) {
+        let (written, reported) = self.last_line;
+        let due = processed == total
+            || processed >= reported + PLAIN_CHANNELS
+            || now.duration_since(written) >= PLAIN_INTERVAL;
+        // The last line may already have reported every channel
+        if !due || (processed == reported && processed > 0) {
+            return;
+        }
+        // A log that cannot be written is no reason to stop counting
+        let _ = writeln!(
+            self.out,
+            "processed {}/{} channels, {} messages, elapsed {}",
+            processed,
+            total,
+            fmt_compact(messages as u64),
+            fmt_duration(now.duration_since(self.started), 1)
+        );
+        self.last_line = (now, processed);
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum ProgressEvent {
+    Stage {
+        stage: &'static str,
+    },
+    Channels {
+        processed: usize,
+        total: usize,
+        messages: usize,
+    },
+}
+
+/// Every event as a JSON object on a line of its own.
+pub struct JsonProgress<W>(W);
+
+impl<W: Write> JsonProgress<W> {
+    fn write(&mut self, event: ProgressEvent) {
+        if serde_json::to_writer(&mut self.0, &event).is_ok() {
+            let _ = writeln!(self.0);
+        }
+    }
+}
+
+impl<W: Write> ProgressSink for JsonProgress<W> {
+    fn stage(&mut self, stage: Stage) {
+        self.write(ProgressEvent::Stage {
+            stage: stage.label(),
+        });
+    }
+
+    fn channels(&mut self, processed: usize, total: usize, messages: usize) {
+        self.write(ProgressEvent::Channels {
+            processed,
+            total,
+            messages,
+        });
+    }
+
+    fn finish(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(progress: &PlainProgress<Vec<u8>>) -> Vec<&str> {
+        std::str::from_utf8(&progress.out)
+            .unwrap()
+            .lines()
+            .collect()
+    }
+
+    #[test]
+    fn plain_lines_wait_for_enough_channels_or_time() {
+        let mut progress = PlainProgress::new(Vec::new());
+        let start = progress.started;
+        let at = |seconds| start + Duration::from_secs(seconds);
+
+        progress.channels_at(at(1), 100, 2000, 1_000);
+        progress.channels_at(at(2), 499, 2000, 4_990);
+        assert!(lines(&progress).is_empty());
+        progress.channels_at(at(3), 500, 2000, 5_000);
+        progress.channels_at(at(4), 600, 2000, 6_000);
+        // Ten seconds after the last line even a few channels are due
+        progress.channels_at(at(13), 610, 2000, 6_100);
+        progress.channels_at(at(14), 2000, 2000, 1_234_567);
+
+        assert_eq!(
+            lines(&progress),
+            [
+                "processed 500/2000 channels, 5.0k messages, elapsed 3s",
+                "processed 610/2000 channels, 6.1k messages, elapsed 13s",
+                "processed 2000/2000 channels, 1.2M messages, elapsed 14s",
+            ]
+        );
+    }
+
+    #[test]
+    fn plain_lines_never_repeat_or_redraw() {
+        let mut progress = PlainProgress::new(Vec::new());
+        let start = progress.started;
+        progress.channels_at(start, 0, 0, 0);
+        progress.channels_at(start + PLAIN_INTERVAL, 3, 3, 7);
+        progress.channels_at(start + PLAIN_INTERVAL * 2, 3, 3, 7);
+        progress.stage(Stage::WaitingForLock);
+
+        let out = String::from_utf8(progress.out).unwrap();
+        assert!(!out.contains('\r'));
+        assert_eq!(out.matches("processed 3/3").count(), 1);
+        assert_eq!(out.lines().count(), 3);
+    }
+}
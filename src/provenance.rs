@@ -0,0 +1,181 @@
+//! The options a run actually used, each with where its value came from, so
+//! a report can be traced back to the invocation behind it. `--verbose`
+//! prints them before counting starts and JSON records them under
+//! `meta.options`. A value comes from the command line, an option's default
+//! or, for `--format`, the `--output` file's extension; no option is read
+//! from a config file or the environment.
+
+use crate::errors::MyError;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Command};
+use serde::Serialize;
+use std::io::Write;
+use unicode_width::UnicodeWidthStr;
+
+/// Options whose values are never shown, since they would undo what they
+/// are for.
+const HIDDEN_VALUES: &[&str] = &["redact_salt"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    CommandLine,
+    Default,
+    /// Derived from the `--output` file's extension
+    InferredFromOutput,
+}
+
+impl Source {
+    fn label(self) -> &'static str {
+        match self {
+            Self::CommandLine => "command line",
+            Self::Default => "default",
+            Self::InferredFromOutput => "inferred from --output",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EffectiveOption {
+    /// The flag, such as `--format`, or the argument's name if positional
+    pub name: String,
+    /// `None` for options left unset without a default
+    pub value: Option<String>,
+    pub source: Source,
+    #[serde(skip)]
+    id: String,
+}
+
+/// Every option of `command` as parsed into `matches`, in the order of
+/// `--help`.
+pub fn effective_options(command: &Command, matches: &ArgMatches) -> Vec<EffectiveOption> {
+    command
+        .get_arguments()
+        .filter(|arg| !matches!(arg.get_id().as_str(), "help" | "version"))
+        .map(|arg| {
+            let id = arg.get_id().as_str();
+            let value = match HIDDEN_VALUES.contains(&id) {
+                true => Some("(hidden)".to_string()),
+                false => matches.get_raw(id).map(|values| {
+                    values
+                        .map(|value| value.to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                }),
+            };
+            let source = match matches.value_source(id) {
+                Some(ValueSource::CommandLine) => Source::CommandLine,
+                _ => Source::Default,
+            };
+            EffectiveOption {
+                name: arg
+                    .get_long()
+                    .map_or_else(|| id.to_string(), |long| format!("--{}", long)),
+                value,
+                source,
+                id: id.to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Record that the option with `id` was changed after parsing.
+pub fn override_option(options: &mut [EffectiveOption], id: &str, value: &str, source: Source) {
+    if let Some(option) = options.iter_mut().find(|option| option.id == id) {
+        option.value = Some(value.to_string());
+        option.source = source;
+    }
+}
+
+/// The options as aligned columns of name, value and source.
+pub fn write_effective_options(
+    options: &[EffectiveOption],
+    out: &mut dyn Write,
+) -> Result<(), MyError> {
+    let value = |option: &EffectiveOption| option.value.as_deref().unwrap_or("-").to_string();
+    let name_width = options.iter().map(|o| o.name.width()).max().unwrap_or(0);
+    let value_width = options.iter().map(|o| value(o).width()).max().unwrap_or(0);
+    writeln!(out, "Effective options:")?;
+    for option in options {
+        let value = value(option);
+        writeln!(
+            out,
+            "    {}{}  {}{}  {}",
+            option.name,
+            " ".repeat(name_width - option.name.width()),
+            value,
+            " ".repeat(value_width - value.width()),
+            option.source.label()
+        )?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Arg;
+
+    fn command() -> Command {
+        Command::new("test")
+            .arg(Arg::new("format").long("format").default_value("text"))
+            .arg(Arg::new("limit").long("limit"))
+            .arg(Arg::new("redact_salt").long("redact-salt"))
+    }
+
+    fn options(args: &[&str]) -> Vec<EffectiveOption> {
+        let command = command();
+        let matches = command
+            .clone()
+            .try_get_matches_from(std::iter::once("test").chain(args.iter().copied()))
+            .unwrap();
+        effective_options(&command, &matches)
+    }
+
+    fn labels(options: &[EffectiveOption]) -> Vec<(&str, Option<&str>, Source)> {
+        options
+            .iter()
+            .map(|option| (option.name.as_str(), option.value.as_deref(), option.source))
+            .collect()
+    }
+
+    #[test]
+    fn each_value_is_labeled_with_its_source() {
+        assert_eq!(
+            labels(&options(&["--limit", "5", "--redact-salt", "pepper"])),
+            [
+                ("--format", Some("text"), Source::Default),
+                ("--limit", Some("5"), Source::CommandLine),
+                ("--redact-salt", Some("(hidden)"), Source::CommandLine),
+            ]
+        );
+    }
+
+    #[test]
+    fn the_command_line_wins_over_a_default_and_inference_over_both() {
+        let mut options = options(&["--format", "csv"]);
+        assert_eq!(
+            labels(&options)[0],
+            ("--format", Some("csv"), Source::CommandLine)
+        );
+        override_option(&mut options, "format", "json", Source::InferredFromOutput);
+        assert_eq!(
+            labels(&options)[0],
+            ("--format", Some("json"), Source::InferredFromOutput)
+        );
+    }
+
+    #[test]
+    fn the_table_aligns_names_and_values() {
+        let mut out = Vec::new();
+        write_effective_options(&options(&["--limit", "12"]), &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Effective options:\n\
+             \x20   --format       text      default\n\
+             \x20   --limit        12        command line\n\
+             \x20   --redact-salt  (hidden)  default\n\n"
+        );
+    }
+}
@@ -0,0 +1,332 @@
+//! Replacing names and IDs before results are shared. Hashed redaction
+//! derives names from the other participants' user IDs rather than
+//! per-account channel IDs, so two people who agree on a salt can tell when
+//! they talk to the same person. The hashes cannot be reversed into names, but the same ID and
+//! salt always give the same hash: correlation is the point.
+
+use crate::report::Report;
+use crate::{Conversation, UNKNOWN_GUILD_NAME};
+use clap::ValueEnum;
+use sha2::{Digest, Sha256};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+/// Hex digits kept from each hash.
+const HASH_LENGTH: usize = 8;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Redaction {
+    /// Numbered placeholders such as "Person 1"
+    Full,
+    /// Short salted hashes of user, server and channel IDs
+    Hashed,
+}
+
+/// Replacement IDs, the same for every mention of a source ID: salted
+/// hashes, or under full redaction sequence numbers that mean nothing
+/// outside the report.
+struct Pseudonyms<'a> {
+    mode: Redaction,
+    salt: &'a str,
+    assigned: HashMap<String, String>,
+}
+
+impl Pseudonyms<'_> {
+    fn hash(&self, kind: &str, id: &str) -> String {
+        let digest = Sha256::digest(format!("{}:{}:{}", self.salt, kind, id));
+        digest
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>()[..HASH_LENGTH]
+            .to_string()
+    }
+
+    /// The replacement of `id`, derived from `kind` and `source` when it has
+    /// none yet; hashed names use the same derivation.
+    fn assign(&mut self, id: &str, kind: &str, source: &str) -> String {
+        if let Some(pseudonym) = self.assigned.get(id) {
+            return pseudonym.clone();
+        }
+        let pseudonym = match self.mode {
+            Redaction::Hashed => self.hash(kind, source),
+            Redaction::Full => (self.assigned.len() + 1).to_string(),
+        };
+        self.assigned.insert(id.to_string(), pseudonym.clone());
+        pseudonym
+    }
+
+    /// The replacement of an ID not tied to a conversation, such as one named
+    /// in a warning.
+    fn id(&mut self, id: &str) -> String {
+        self.assign(id, "channel", id)
+    }
+
+    /// `text` with every run of digits, such as the ID in a `c<id>` folder
+    /// name, replaced.
+    fn ids_within(&mut self, text: &str) -> String {
+        let mut redacted = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find(|c: char| c.is_ascii_digit()) {
+            redacted.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let end = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            redacted.push_str(&self.id(&rest[..end]));
+            rest = &rest[end..];
+        }
+        redacted.push_str(rest);
+        redacted
+    }
+
+    fn rekey<V>(&mut self, map: &mut HashMap<String, V>) {
+        *map = map
+            .drain()
+            .map(|(id, value)| (self.id(&id), value))
+            .collect();
+    }
+}
+
+/// Replace every conversation and channel name in the report, and every ID
+/// wherever the report mentions one. Recipient IDs are dropped afterwards so
+/// the redacted report can be shared as a whole; whether notes are kept is
+/// left to the caller.
+pub fn redact(report: &mut Report, mode: Redaction, salt: &str) {
+    let account_id = report.account_id.take();
+    // Earlier channel names are as revealing as the current ones
+    report.previous_names.clear();
+    let mut pseudonyms = Pseudonyms {
+        mode,
+        salt,
+        assigned: HashMap::new(),
+    };
+
+    // Numbering follows the ranking, so "Person 1" is the most active DM
+    let mut order: Vec<usize> = (0..report.conversations.len()).collect();
+    order.sort_by_key(|&i| Reverse(report.conversations[i].message_count()));
+
+    let (mut people, mut groups, mut servers, mut orphans) = (0, 0, 0, 0);
+    for i in order {
+        match &mut report.conversations[i] {
+            Conversation::DmOrGc {
+                id,
+                name,
+                group,
+                recipients,
+                ..
+            } => {
+                let mut others: Vec<&str> = recipients
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|id| Some(*id) != account_id.as_deref())
+                    .collect();
+                others.sort_unstable();
+                let label = if *group { "Group" } else { "Person" };
+                let counter = if *group { &mut groups } else { &mut people };
+                // Without a recipient there is nothing stable to hash
+                *id = match others.is_empty() {
+                    true => pseudonyms.id(id),
+                    false => pseudonyms.assign(id, "user", &others.join(",")),
+                };
+                *name = match (mode, others.is_empty()) {
+                    (Redaction::Hashed, false) => format!("{} {}", label, id),
+                    _ => {
+                        *counter += 1;
+                        format!("{} {}", label, counter)
+                    }
+                };
+                recipients.clear();
+            }
+            Conversation::Guild {
+                id, name, channels, ..
+            } => {
+                if let Some(id) = id {
+                    *id = pseudonyms.assign(id, "guild", id);
+                    *name = match mode {
+                        Redaction::Hashed => format!("Server {}", id),
+                        Redaction::Full => {
+                            servers += 1;
+                            format!("Server {}", servers)
+                        }
+                    };
+                } else {
+                    *name = UNKNOWN_GUILD_NAME.to_string();
+                }
+
+                for channel in channels.iter_mut() {
+                    channel.id = pseudonyms.id(&channel.id);
+                    for merged in &mut channel.merged_ids {
+                        *merged = pseudonyms.id(merged);
+                    }
+                }
+                // Ties are ordered by ID, which now is the replacement
+                channels.sort_by(|a, b| {
+                    b.message_count
+                        .cmp(&a.message_count)
+                        .then_with(|| a.id.cmp(&b.id))
+                });
+
+                let unknown_guild = id.is_none();
+                for (index, channel) in channels.iter_mut().enumerate() {
+                    channel.name = match (mode, unknown_guild) {
+                        (Redaction::Hashed, _) => format!("channel {}", channel.id),
+                        // Channels of unknown servers are listed side by side
+                        (Redaction::Full, true) => {
+                            orphans += 1;
+                            format!("channel {}", orphans)
+                        }
+                        (Redaction::Full, false) => format!("channel {}", index + 1),
+                    };
+                }
+            }
+        }
+    }
+
+    redact_mentions(report, &mut pseudonyms);
+}
+
+/// IDs outside the conversations: the maps keyed by conversation, and the
+/// findings that name channels or their folders.
+fn redact_mentions(report: &mut Report, pseudonyms: &mut Pseudonyms) {
+    pseudonyms.rekey(&mut report.notes);
+    pseudonyms.rekey(&mut report.messages_by_year);
+    pseudonyms.rekey(&mut report.peak_days);
+    for discrepancy in &mut report.discrepancies {
+        discrepancy.conversation_id = pseudonyms.id(&discrepancy.conversation_id);
+    }
+    if let Some(voice) = &mut report.voice {
+        pseudonyms.rekey(&mut voice.seconds_by_guild);
+    }
+    if let Some(sessions) = &mut report.sessions {
+        pseudonyms.rekey(&mut sessions.by_conversation);
+        if let Some(longest) = &mut sessions.longest {
+            longest.conversation_id = pseudonyms.id(&longest.conversation_id);
+        }
+    }
+    if let Some(coverage) = &mut report.coverage {
+        for id in coverage
+            .indexed_without_folder
+            .iter_mut()
+            .chain(&mut coverage.folder_not_indexed)
+            .chain(&mut coverage.empty)
+        {
+            *id = pseudonyms.id(id);
+        }
+    }
+    for warning in &mut report.warnings {
+        for id in warning.ids_mut() {
+            *id = pseudonyms.id(id);
+        }
+        for name in warning.paths_mut() {
+            *name = pseudonyms.ids_within(name);
+        }
+    }
+    report.warnings.sort();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::OutputFormat;
+    use crate::testing::{channel, dm, guild, rendered, report, view};
+    use crate::warnings::Warning;
+
+    const GUILD: &str = "900000000000000001";
+    const CHANNELS: [&str; 2] = ["900000000000000002", "900000000000000003"];
+    const DM: &str = "900000000000000004";
+    const FRIEND: &str = "900000000000000005";
+    const ORPHAN: &str = "900000000000000006";
+
+    fn fixture() -> Report {
+        let mut friend = dm(DM, "friend", 3);
+        if let Conversation::DmOrGc { recipients, .. } = &mut friend {
+            *recipients = vec![FRIEND.to_string()];
+        }
+        let mut report = report(vec![
+            guild(
+                Some(GUILD),
+                "Server",
+                vec![
+                    channel(CHANNELS[0], "general", 5),
+                    channel(CHANNELS[1], "memes", 2),
+                ],
+            ),
+            friend,
+            guild(None, UNKNOWN_GUILD_NAME, vec![channel(ORPHAN, "lost", 1)]),
+        ]);
+        report
+            .notes
+            .insert(DM.to_string(), "met at school".to_string());
+        report.warnings = vec![
+            Warning::UnknownGuild {
+                channel_id: ORPHAN.to_string(),
+            },
+            Warning::UnrecognizedFolder {
+                folder: format!("c{}", CHANNELS[1]),
+            },
+        ];
+        report
+    }
+
+    fn outputs(report: &Report) -> String {
+        let view = view(report);
+        [OutputFormat::Json, OutputFormat::Dot, OutputFormat::Mermaid]
+            .into_iter()
+            .map(|format| rendered(format, &view))
+            .collect()
+    }
+
+    #[test]
+    fn no_source_id_survives_redaction() {
+        let sources = [GUILD, CHANNELS[0], CHANNELS[1], DM, ORPHAN, FRIEND];
+        let unredacted = outputs(&fixture());
+        assert!(sources[..5].iter().all(|id| unredacted.contains(id)));
+        for mode in [Redaction::Full, Redaction::Hashed] {
+            let mut report = fixture();
+            redact(&mut report, mode, "salt");
+            let output = outputs(&report);
+            for id in sources {
+                assert!(!output.contains(id), "{:?} kept {}", mode, id);
+            }
+            let warnings = format!("{:?}", report.warnings);
+            assert!(
+                sources.iter().all(|id| !warnings.contains(id)),
+                "{:?}",
+                mode
+            );
+        }
+    }
+
+    #[test]
+    fn hashed_ids_match_the_names_and_key_the_notes() {
+        let mut report = fixture();
+        redact(&mut report, Redaction::Hashed, "salt");
+        for conversation in &report.conversations {
+            if let Conversation::Guild { id: Some(_), .. } | Conversation::DmOrGc { .. } =
+                conversation
+            {
+                assert!(conversation.name().ends_with(conversation.id()));
+            }
+        }
+        let person = &report.conversations[1];
+        assert_eq!(report.note(person.id()), Some("met at school"));
+
+        // The same person and salt give the same ID in anyone's report
+        let mut other = fixture();
+        redact(&mut other, Redaction::Hashed, "salt");
+        assert_eq!(other.conversations[1].id(), person.id());
+    }
+
+    #[test]
+    fn fully_redacted_ids_stay_distinct() {
+        let mut report = fixture();
+        redact(&mut report, Redaction::Full, "salt");
+        let mut ids: Vec<&str> = report.conversations.iter().map(Conversation::id).collect();
+        if let Conversation::Guild { channels, .. } = &report.conversations[0] {
+            ids.extend(channels.iter().map(|channel| channel.id.as_str()));
+        }
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids.len(), 5);
+    }
+}
@@ -0,0 +1,1384 @@
+use crate::accessible::render_accessible;
+use crate::analytics::{ReferenceCounts, VoiceStats};
+use crate::bars::render_bars;
+use crate::breadth::{write_breadth_table, YearBreadth};
+use crate::buckets::{count_bucket, CountStyle};
+use crate::color::{Palette, TOP_CONVERSATIONS};
+use crate::concentration::Concentration;
+use crate::discord::render_discord;
+use crate::dot::render_dot;
+use crate::errors::MyError;
+use crate::html::render_html;
+use crate::humanize::fmt_duration;
+use crate::inventory::write_coverage;
+use crate::json::{render_json, render_ndjson};
+use crate::mermaid::render_mermaid;
+use crate::output::Output;
+use crate::provenance::EffectiveOption;
+use crate::report::Report;
+use crate::sections::{sections, GroupBy, DEPARTED_SECTION_TITLE};
+use crate::sessions::write_session_stats;
+use crate::table::render_table;
+use crate::timeline::{write_threshold_timeline, ThresholdTimeline};
+use crate::toml_format::render_toml;
+use crate::totals::{totals, write_totals};
+use crate::width::NameWidth;
+use crate::window::{window_note, DisplayWindow};
+use crate::yaml::render_yaml;
+use crate::zone::Zone;
+use crate::{
+    wire, Channel, Conversation, FilterStage, FilterStageKind, GroupDmPolicy, UNKNOWN_GUILD_NAME,
+};
+use clap::ValueEnum;
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use unicode_width::UnicodeWidthStr;
+
+/// Formats the results can be rendered in.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable conversation tree
+    Text,
+    /// Versioned JSON accepted on stdin when the input path is '-'
+    Internal,
+    /// Tab-separated values with a header row
+    Tsv,
+    /// Nushell object notation
+    Nuon,
+    /// Conversations and their channels as a JSON document
+    Json,
+    /// One JSON object per conversation and line, written while counting
+    /// unless --sort, --limit or another option needs every result first
+    Ndjson,
+    /// Comma-separated values for spreadsheets, one row per DM and guild channel
+    Csv,
+    /// A self-contained HTML page with a sortable table and a chart
+    Html,
+    /// A Graphviz digraph of guilds, channels and DMs
+    Dot,
+    /// A Mermaid flowchart of guilds, channels and DMs for Markdown
+    Mermaid,
+    /// An aligned table with each conversation's share of all messages
+    Table,
+    /// Code blocks of at most 2000 characters each, to paste into Discord
+    Discord,
+    /// The conversations of the JSON format as a YAML list
+    Yaml,
+    /// An Excel workbook with a sheet of conversations and one of channels;
+    /// needs --output
+    #[cfg(feature = "xlsx")]
+    Xlsx,
+    /// A Markdown table for GitHub or Discord; guild channels become
+    /// indented rows with --channels
+    Markdown,
+    /// `[[conversation]]` tables with the fields of the JSON format
+    Toml,
+    /// A horizontal bar per conversation, scaled to the largest; guild
+    /// channels get bars of their own with --channels
+    Bars,
+}
+
+impl OutputFormat {
+    /// File name extension for files written in this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Text | Self::Table | Self::Bars => "txt",
+            Self::Internal | Self::Json => "json",
+            Self::Tsv => "tsv",
+            Self::Nuon => "nuon",
+            Self::Ndjson => "ndjson",
+            Self::Csv => "csv",
+            Self::Html => "html",
+            Self::Dot => "dot",
+            Self::Mermaid => "mmd",
+            Self::Discord | Self::Markdown => "md",
+            Self::Yaml => "yaml",
+            Self::Toml => "toml",
+            #[cfg(feature = "xlsx")]
+            Self::Xlsx => "xlsx",
+        }
+    }
+
+    /// The format a file's extension names, for `--output` without
+    /// `--format`. Extensions several formats share go to the most common.
+    pub fn of_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        Some(match extension.as_str() {
+            "txt" => Self::Text,
+            "json" => Self::Json,
+            "ndjson" | "jsonl" => Self::Ndjson,
+            "csv" => Self::Csv,
+            "tsv" => Self::Tsv,
+            "html" | "htm" => Self::Html,
+            "md" | "markdown" => Self::Markdown,
+            "yaml" | "yml" => Self::Yaml,
+            "toml" => Self::Toml,
+            "nuon" => Self::Nuon,
+            "dot" | "gv" => Self::Dot,
+            "mmd" => Self::Mermaid,
+            #[cfg(feature = "xlsx")]
+            "xlsx" => Self::Xlsx,
+            _ => return None,
+        })
+    }
+}
+
+/// An additional destination for the results, written as `FORMAT:PATH`.
+#[derive(Debug, Clone)]
+pub struct OutputTarget {
+    pub format: OutputFormat,
+    pub path: PathBuf,
+}
+
+impl FromStr for OutputTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (format, path) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected FORMAT:PATH, got '{}'", s))?;
+        if path.is_empty() {
+            return Err(format!("missing path after '{}:'", format));
+        }
+        Ok(Self {
+            format: OutputFormat::from_str(format, true)?,
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+/// Read-only view of a run's results, shared by every renderer so a single
+/// processing pass can feed several outputs.
+#[derive(Clone)]
+pub struct ResultsView<'a> {
+    pub report: &'a Report,
+    /// Conversations left after filtering, in display order
+    pub conversations: Vec<&'a Conversation>,
+    /// How many conversations were left after each filtering step
+    pub filter_stages: Vec<FilterStage>,
+    /// Suppress informational lines
+    pub quiet: bool,
+    pub verbose: bool,
+    /// Whether system messages were excluded from the counts
+    pub excluded_system: bool,
+    /// Whether empty stub messages were excluded from the counts
+    pub excluded_empty: bool,
+    /// Show how many messages are empty stubs
+    pub empty_message_stats: bool,
+    /// Show the summary after the conversation list
+    pub summary: bool,
+    /// Messages each conversation's share is taken of, per --percent-of
+    pub share_total: u64,
+    /// Whether DMs with bots were left out of the listing
+    pub excluded_bots: bool,
+    /// Whether servers you're no longer in were left out of the listing
+    pub excluded_departed: bool,
+    /// Whether tabular formats list guild channels as rows of their own
+    pub channels: bool,
+    /// Whether CSV adds a total row before each guild's channels
+    pub guild_totals: bool,
+    /// Where group DMs are counted in the kind column
+    pub group_dms_as: GroupDmPolicy,
+    /// Period charts and breakdowns are restricted to
+    pub display_window: Option<DisplayWindow>,
+    /// Render text as linear sentences for screen readers
+    pub accessible: bool,
+    /// Zone whose calendar decides day and month boundaries
+    pub timezone: Zone,
+    /// Group digits in accessible text, which is otherwise left plain
+    pub thousands_separators: bool,
+    /// Whether message counts are shown exactly or as coarse ranges
+    pub counts: CountStyle,
+    /// How few filtered conversations hold most of their messages
+    pub concentration: Option<Concentration>,
+    /// Conversations over a message threshold per year
+    pub threshold_timeline: Option<ThresholdTimeline>,
+    /// Channels with fewer messages are folded together in graphs
+    pub min_messages: usize,
+    /// Wrap Mermaid diagrams in a fenced code block
+    pub fenced: bool,
+    /// Split the text listing into sections
+    pub group_by: Option<GroupBy>,
+    /// List empty sections as "none" instead of leaving them out
+    pub show_empty_sections: bool,
+    /// Colors of text and tables; off for everything but stdout
+    pub palette: Palette,
+    /// Characters the text tree is drawn with
+    pub tree_glyphs: TreeGlyphs,
+    /// Columns channels are indented by in the text tree
+    pub tree_indent: usize,
+    /// Conversations are guild channels and DMs sorted together, one line each
+    pub flat: bool,
+    /// How far names in the text listing are shortened; only stdout is
+    /// shortened to the terminal's width
+    pub names: NameWidth,
+    /// Every option the run used, with where its value came from
+    pub options: &'a [EffectiveOption],
+}
+
+/// Characters the branches of the text tree are drawn with.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TreeGlyphs {
+    /// Box-drawing characters
+    #[default]
+    Unicode,
+    /// Plain ASCII, for terminals without box-drawing characters
+    Ascii,
+}
+
+impl TreeGlyphs {
+    fn branch(self, last: bool) -> &'static str {
+        match (self, last) {
+            (Self::Unicode, false) => "├──",
+            (Self::Unicode, true) => "└──",
+            (Self::Ascii, false) => "|--",
+            (Self::Ascii, true) => "`--",
+        }
+    }
+}
+
+/// How `write_tree` draws a conversation.
+#[derive(Debug, Clone, Copy)]
+pub struct TreeFormat {
+    pub glyphs: TreeGlyphs,
+    /// Columns channels are indented by
+    pub indent: usize,
+    pub counts: CountStyle,
+    pub palette: Palette,
+    /// Also show how many system messages were excluded
+    pub verbose: bool,
+    /// Also show how many messages are empty stubs
+    pub empty_messages: bool,
+    /// Whether empty stubs were left out of the counts
+    pub excluded_empty: bool,
+    /// Messages each conversation's share is taken of
+    pub share_total: u64,
+    /// How far names are shortened to fit the line
+    pub names: NameWidth,
+}
+
+impl TreeFormat {
+    fn indent(self) -> String {
+        " ".repeat(self.indent)
+    }
+}
+
+/// The share `empty` stubs have of a conversation's messages, `counted` of
+/// which were counted. Stubs left out of the counts are added back first, so
+/// the share reads the same with and without --exclude-empty-messages.
+pub fn empty_share(empty: usize, counted: usize, excluded: bool, counts: CountStyle) -> String {
+    let read = counted + if excluded { empty } else { 0 };
+    format!("{}%", counts.percent(empty as u64, read as u64))
+}
+
+/// A conversation's name in graphs and charts, where a channel of an unknown
+/// server stands in for the server.
+pub fn display_name(conversation: &Conversation) -> String {
+    match conversation {
+        Conversation::Guild { channels, .. } if conversation.is_unknown_guild() => format!(
+            "#{} in {}",
+            channels.first().map_or("", |channel| channel.name.as_str()),
+            conversation.name()
+        ),
+        _ => conversation.name().to_string(),
+    }
+}
+
+/// A note following a name, dimmed so it reads as an aside; without a note
+/// there is nothing to paint.
+fn dimmed(palette: Palette, note: &str) -> String {
+    match note.is_empty() {
+        true => String::new(),
+        false => palette.dim(note),
+    }
+}
+
+/// A flat record shared by the tabular and structured formats.
+pub struct Row<'a> {
+    pub kind: &'static str,
+    pub id: &'a str,
+    pub name: &'a str,
+    /// Name of the guild a channel row belongs to
+    pub parent: Option<&'a str>,
+    pub message_count: usize,
+    pub note: Option<&'a str>,
+}
+
+impl<'a> ResultsView<'a> {
+    pub fn tree_format(&self) -> TreeFormat {
+        TreeFormat {
+            glyphs: self.tree_glyphs,
+            indent: self.tree_indent,
+            counts: self.counts,
+            palette: self.palette,
+            verbose: self.verbose,
+            empty_messages: self.empty_message_stats,
+            excluded_empty: self.excluded_empty,
+            share_total: self.share_total,
+            names: self.names,
+        }
+    }
+
+    /// One row per conversation, each followed by its channel rows when
+    /// `channels` is set.
+    pub fn rows(&self) -> Vec<Row<'a>> {
+        let mut rows = Vec::new();
+        for &conversation in &self.conversations {
+            rows.push(Row {
+                kind: conversation.effective_kind(self.group_dms_as).label(),
+                id: conversation.id(),
+                name: conversation.name(),
+                parent: None,
+                message_count: conversation.message_count(),
+                note: self.report.note(conversation.id()),
+            });
+
+            if let (true, Conversation::Guild { name, channels, .. }) =
+                (self.channels, conversation)
+            {
+                rows.extend(channels.iter().map(|channel| Row {
+                    kind: "channel",
+                    id: &channel.id,
+                    name: &channel.name,
+                    parent: Some(name),
+                    message_count: channel.message_count,
+                    note: None,
+                }));
+            }
+        }
+        rows
+    }
+}
+
+pub fn render(
+    format: OutputFormat,
+    view: &ResultsView,
+    out: &mut dyn Write,
+) -> Result<(), MyError> {
+    match format {
+        OutputFormat::Text if view.accessible => render_accessible(view, out),
+        OutputFormat::Text => render_text(view, out),
+        OutputFormat::Internal => wire::write_report(view.report, out),
+        OutputFormat::Tsv => render_tsv(view, out),
+        OutputFormat::Nuon => render_nuon(view, out),
+        OutputFormat::Json => render_json(view, out),
+        OutputFormat::Ndjson => render_ndjson(view, out),
+        OutputFormat::Csv => render_csv(view, out),
+        OutputFormat::Html => render_html(view, out),
+        OutputFormat::Dot => render_dot(view, out),
+        OutputFormat::Mermaid => render_mermaid(view, out),
+        OutputFormat::Table => render_table(view, out),
+        OutputFormat::Discord => render_discord(view, out),
+        OutputFormat::Markdown => render_markdown(view, out),
+        OutputFormat::Yaml => render_yaml(view, out),
+        OutputFormat::Toml => render_toml(view, out),
+        OutputFormat::Bars => render_bars(view, out),
+        #[cfg(feature = "xlsx")]
+        OutputFormat::Xlsx => crate::xlsx::render_xlsx(view, out),
+    }
+}
+
+/// Render to every target, continuing past failures. Each file is written
+/// like `--output`: atomically, and replacing an existing one only with
+/// `force`. Returns the targets that could not be written together with
+/// their errors.
+pub fn render_to_targets(
+    targets: &[OutputTarget],
+    view: &ResultsView,
+    force: bool,
+) -> Vec<(OutputTarget, MyError)> {
+    let mut failures = Vec::new();
+    for target in targets {
+        let result = Output::open(Some(&target.path), force).and_then(|mut out| {
+            render(target.format, view, &mut out)?;
+            out.finish()
+        });
+        if let Err(error) = result {
+            failures.push((target.clone(), error));
+        }
+    }
+    failures
+}
+
+fn render_text(view: &ResultsView, out: &mut dyn Write) -> Result<(), MyError> {
+    if let Some(ref partial) = view.report.partial {
+        writeln!(out, "{}", partial)?;
+        writeln!(out)?;
+    }
+    if let Some(ref window) = view.display_window {
+        writeln!(out, "{}", window_note(window))?;
+        writeln!(out)?;
+    }
+    if view.verbose {
+        if let Some(vintage) = view.report.vintage {
+            writeln!(out, "Package vintage: {}", vintage)?;
+            writeln!(out)?;
+        }
+    }
+    if !view.quiet {
+        write_filter_stages(&view.filter_stages, out)?;
+    }
+
+    match view.group_by {
+        Some(group_by) => {
+            for section in sections(
+                &view.conversations,
+                group_by,
+                view.group_dms_as,
+                view.show_empty_sections,
+            ) {
+                writeln!(out, "{}:", section.title)?;
+                if section.conversations.is_empty() {
+                    writeln!(out, "none")?;
+                    writeln!(out)?;
+                }
+                write_conversations(&section.conversations, view, out)?;
+            }
+        }
+        None => write_conversations(&view.conversations, view, out)?,
+    }
+
+    if view.summary && !view.quiet {
+        write_totals(&totals(view), view.counts, out)?;
+    }
+
+    if let (false, Some(concentration)) = (view.quiet, &view.concentration) {
+        writeln!(
+            out,
+            "{}",
+            concentration.sentence(&view.counts.grouped(concentration.total_messages))
+        )?;
+        writeln!(out)?;
+    }
+
+    if let Some(ref breadth) = view.report.breadth_by_year {
+        let breadth: Vec<YearBreadth> = breadth
+            .iter()
+            .filter(|year| {
+                view.display_window
+                    .is_none_or(|w| w.overlaps_year(year.year))
+            })
+            .cloned()
+            .collect();
+        write_breadth_table(&breadth, out)?;
+    }
+
+    if let Some(ref timeline) = view.threshold_timeline {
+        write_threshold_timeline(timeline, view.display_window, out)?;
+    }
+
+    if let Some(ref voice) = view.report.voice {
+        write_voice_stats(voice, view.report, view.verbose, view.tree_glyphs, out)?;
+    }
+
+    if let Some(ref sessions) = view.report.sessions {
+        write_session_stats(sessions, view, out)?;
+    }
+
+    if let Some(ref coverage) = view.report.coverage {
+        write_coverage(coverage, out)?;
+    }
+
+    if let Some(ref reference_counts) = view.report.reference_counts {
+        write_reference_counts(reference_counts, view.counts, out)?;
+    }
+
+    if !view.report.discrepancies.is_empty() {
+        let missing: u64 = view.report.discrepancies.iter().map(|d| d.missing()).sum();
+        let since = view
+            .report
+            .analytics_since
+            .map(|since| format!(" since {}", view.timezone.local_date(&since)))
+            .unwrap_or_default();
+        writeln!(
+            out,
+            "Estimated undercount: ~{} messages across {} conversations sent{} but missing from the package",
+            view.counts.grouped(missing),
+            view.report.discrepancies.len(),
+            since
+        )?;
+    }
+
+    if view.excluded_system {
+        let excluded: usize = view
+            .report
+            .conversations
+            .iter()
+            .map(Conversation::excluded_system)
+            .sum();
+        writeln!(out, "Excluded {} system messages from the counts", excluded)?;
+    }
+
+    let empty: usize = view
+        .report
+        .conversations
+        .iter()
+        .map(Conversation::empty_messages)
+        .sum();
+    if view.excluded_empty {
+        writeln!(
+            out,
+            "Excluded {} empty messages from the counts",
+            view.counts.grouped(empty as u64)
+        )?;
+    }
+    if view.empty_message_stats {
+        let counted: usize = view
+            .report
+            .conversations
+            .iter()
+            .map(Conversation::message_count)
+            .sum();
+        writeln!(
+            out,
+            "Empty messages (no contents or attachments): {}, {} of all messages",
+            view.counts.grouped(empty as u64),
+            empty_share(empty, counted, view.excluded_empty, view.counts)
+        )?;
+    }
+
+    if view.excluded_bots {
+        writeln!(out, "Excluded {} DMs with bots", view.report.bot_dms())?;
+    }
+    let departed = view.report.departed_guilds();
+    if view.excluded_departed && departed > 0 {
+        writeln!(
+            out,
+            "Hid {} servers you're no longer in (--include-departed lists them)",
+            departed
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Conversation trees, followed by servers you're no longer in under a
+/// heading with their subtotal, and channels of unknown servers listed
+/// together at the end.
+fn write_conversations(
+    conversations: &[&Conversation],
+    view: &ResultsView,
+    out: &mut dyn Write,
+) -> Result<(), MyError> {
+    if view.flat {
+        return write_flat(conversations, view, out);
+    }
+    let (unknown, known): (Vec<&Conversation>, Vec<&Conversation>) = conversations
+        .iter()
+        .partition(|conversation| conversation.is_unknown_guild());
+
+    // The largest of all listed conversations, also when split into sections
+    let top: Vec<&str> = view
+        .conversations
+        .iter()
+        .filter(|conversation| !conversation.is_unknown_guild())
+        .take(TOP_CONVERSATIONS)
+        .map(|conversation| conversation.id())
+        .collect();
+    let (departed, current): (Vec<&Conversation>, Vec<&Conversation>) = known
+        .into_iter()
+        .partition(|conversation| conversation.is_departed());
+    for conversation in current {
+        write_tree(
+            conversation,
+            view.report,
+            view.tree_format(),
+            top.contains(&conversation.id()),
+            out,
+        )?;
+    }
+
+    // Servers missing from servers/index.json, with a subtotal of their own
+    if !departed.is_empty() {
+        let subtotal: usize = departed.iter().map(|c| c.message_count()).sum();
+        writeln!(
+            out,
+            "{} [{} messages]:",
+            DEPARTED_SECTION_TITLE,
+            view.palette.count(&view.counts.format(subtotal as u64))
+        )?;
+        writeln!(out)?;
+        for conversation in departed {
+            write_tree(
+                conversation,
+                view.report,
+                view.tree_format(),
+                top.contains(&conversation.id()),
+                out,
+            )?;
+        }
+    }
+
+    // Channels of unidentifiable servers are listed individually, never summed
+    if !unknown.is_empty() {
+        writeln!(out, "{}", view.palette.guild(UNKNOWN_GUILD_NAME))?;
+        let channels: Vec<&Channel> = unknown
+            .iter()
+            .filter_map(|conversation| match conversation {
+                Conversation::Guild { channels, .. } => channels.first(),
+                Conversation::DmOrGc { .. } => None,
+            })
+            .collect();
+        // Without a guild to be part of, they show their share of the total
+        let format = view.tree_format();
+        write_channels(&channels, view.report, format, format.share_total, out)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// One line per channel or DM in the order given, which for `--flat` is by
+/// count across all guilds. Guilds are named before each of their channels.
+fn write_flat(
+    conversations: &[&Conversation],
+    view: &ResultsView,
+    out: &mut dyn Write,
+) -> Result<(), MyError> {
+    let TreeFormat {
+        counts,
+        palette,
+        names,
+        ..
+    } = view.tree_format();
+    // Flattened channels share their guild's ID, so the top lines are told
+    // apart by identity
+    let top = &view.conversations[..TOP_CONVERSATIONS.min(view.conversations.len())];
+    for &conversation in conversations {
+        let is_top = top.iter().any(|&t| std::ptr::eq(t, conversation));
+        let count = counts.format(conversation.message_count() as u64);
+        let share = counts.share(conversation.message_count() as u64, view.share_total);
+        let suffix_width = format!(" [{} messages, {}]", count, share).width();
+        let name = match conversation {
+            Conversation::Guild { channels, .. } => {
+                let channel = channels.first().map_or("", |channel| channel.name.as_str());
+                let full = format!("{} / {}", conversation.name(), channel);
+                match (is_top, names.fit(&full, suffix_width)) {
+                    (true, name) => palette.top(&name),
+                    (false, Cow::Borrowed(_)) => format!(
+                        "{} / {}",
+                        palette.guild(conversation.name()),
+                        palette.channel(channel)
+                    ),
+                    // Cut short, the line no longer tells guild from channel
+                    (false, Cow::Owned(name)) => palette.guild(&name),
+                }
+            }
+            // Notes belong to conversations, which for channels is their guild
+            Conversation::DmOrGc { .. } => {
+                let note = view
+                    .report
+                    .note(conversation.id())
+                    .map(|note| format!(" — {}", note))
+                    .unwrap_or_default();
+                let name = names.fit(conversation.name(), note.width() + suffix_width);
+                let note = dimmed(palette, &note);
+                match is_top {
+                    true => format!("{}{}", palette.top(&name), note),
+                    false => format!("{}{}", name, note),
+                }
+            }
+        };
+        writeln!(
+            out,
+            "{} [{} messages, {}]",
+            name,
+            palette.count(&count),
+            share
+        )?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+/// TSV cannot quote, so tabs and line breaks inside names become spaces.
+fn render_tsv(view: &ResultsView, out: &mut dyn Write) -> Result<(), MyError> {
+    fn field(value: &str) -> String {
+        value.replace(['\t', '\n', '\r'], " ")
+    }
+
+    let count_column = match view.counts {
+        CountStyle::Exact => "message_count",
+        CountStyle::Bucketed => "count_bucket",
+    };
+    writeln!(out, "kind\tid\tname\tparent\t{}\tnote", count_column)?;
+    for row in view.rows() {
+        writeln!(
+            out,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            row.kind,
+            field(row.id),
+            field(row.name),
+            field(row.parent.unwrap_or("")),
+            view.counts.format(row.message_count as u64),
+            field(row.note.unwrap_or(""))
+        )?;
+    }
+    Ok(())
+}
+
+/// A Markdown table of the rows, channels indented below their guild.
+fn render_markdown(view: &ResultsView, out: &mut dyn Write) -> Result<(), MyError> {
+    // Pipes would end the cell and backticks open code spans across cells
+    fn cell(value: &str) -> String {
+        value
+            .replace(['\n', '\r'], " ")
+            .replace('|', "\\|")
+            .replace('`', "\\`")
+    }
+
+    writeln!(out, "| Conversation | Type | Messages |")?;
+    writeln!(out, "| --- | --- | ---: |")?;
+    for row in view.rows() {
+        let (name, kind) = match row.kind {
+            "channel" => (format!("↳ #{}", cell(row.name)), "Channel"),
+            "guild" => (cell(row.name), "Server"),
+            "group_dm" => (cell(row.name), "Group DM"),
+            _ => (cell(row.name), "DM"),
+        };
+        writeln!(
+            out,
+            "| {} | {} | {} |",
+            name,
+            kind,
+            view.counts.format(row.message_count as u64)
+        )?;
+    }
+    Ok(())
+}
+
+/// Guild channels always get rows of their own in CSV, so a spreadsheet can
+/// sum the count column; guild totals would be counted twice and are opt-in.
+fn render_csv(view: &ResultsView, out: &mut dyn Write) -> Result<(), MyError> {
+    let count_column = match view.counts {
+        CountStyle::Exact => "message_count",
+        CountStyle::Bucketed => "count_bucket",
+    };
+    let mut writer = csv::Writer::from_writer(out);
+    writer.write_record([
+        "type",
+        "guild_name",
+        "channel_or_conversation_name",
+        count_column,
+    ])?;
+    for &conversation in &view.conversations {
+        let Conversation::Guild { name, channels, .. } = conversation else {
+            writer.write_record([
+                conversation.effective_kind(view.group_dms_as).label(),
+                "",
+                conversation.name(),
+                &view.counts.format(conversation.message_count() as u64),
+            ])?;
+            continue;
+        };
+
+        if view.guild_totals && !conversation.is_unknown_guild() {
+            writer.write_record([
+                "guild",
+                name,
+                "",
+                &view.counts.format(conversation.message_count() as u64),
+            ])?;
+        }
+        for channel in channels {
+            writer.write_record([
+                "channel",
+                name,
+                &channel.name,
+                &view.counts.format(channel.message_count as u64),
+            ])?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn render_nuon(view: &ResultsView, out: &mut dyn Write) -> Result<(), MyError> {
+    fn string(value: &str) -> String {
+        let mut quoted = String::with_capacity(value.len() + 2);
+        quoted.push('"');
+        for c in value.chars() {
+            match c {
+                '"' => quoted.push_str("\\\""),
+                '\\' => quoted.push_str("\\\\"),
+                '\n' => quoted.push_str("\\n"),
+                '\r' => quoted.push_str("\\r"),
+                '\t' => quoted.push_str("\\t"),
+                c if c.is_control() => quoted.push_str(&format!("\\u{{{:04x}}}", c as u32)),
+                c => quoted.push(c),
+            }
+        }
+        quoted.push('"');
+        quoted
+    }
+
+    writeln!(out, "[")?;
+    for row in view.rows() {
+        let count = match view.counts {
+            CountStyle::Exact => format!("message_count: {}", row.message_count),
+            CountStyle::Bucketed => format!(
+                "count_bucket: {}",
+                string(&count_bucket(row.message_count as u64))
+            ),
+        };
+        writeln!(
+            out,
+            "  {{kind: {}, id: {}, name: {}, parent: {}, {}, note: {}}}",
+            string(row.kind),
+            string(row.id),
+            string(row.name),
+            row.parent.map_or_else(|| "null".to_string(), string),
+            count,
+            row.note.map_or_else(|| "null".to_string(), string)
+        )?;
+    }
+    writeln!(out, "]")?;
+    Ok(())
+}
+
+/// Ranked voice time per guild. Omitted when there were no voice sessions,
+/// which only gets a note in verbose mode.
+fn write_voice_stats(
+    voice: &VoiceStats,
+    report: &Report,
+    verbose: bool,
+    glyphs: TreeGlyphs,
+    out: &mut dyn Write,
+) -> Result<(), MyError> {
+    if voice.sessions == 0 {
+        if verbose {
+            writeln!(out, "No voice activity found in the analytics")?;
+            writeln!(out)?;
+        }
+        return Ok(());
+    }
+
+    writeln!(
+        out,
+        "Voice activity: {} in total",
+        fmt_duration(Duration::from_secs(voice.total_seconds), 2)
+    )?;
+    let mut guilds: Vec<(&String, &u64)> = voice.seconds_by_guild.iter().collect();
+    guilds.sort_by_key(|&(id, seconds)| (Reverse(*seconds), id));
+    for (index, (guild_id, seconds)) in guilds.iter().enumerate() {
+        let connector = glyphs.branch(index + 1 == guilds.len());
+        let name = report
+            .guild_name(guild_id)
+            .map_or_else(|| format!("Guild {}", guild_id), str::to_string);
+        writeln!(
+            out,
+            "{} {}: {}",
+            connector,
+            name,
+            fmt_duration(Duration::from_secs(**seconds), 2)
+        )?;
+    }
+    if verbose && voice.discarded_sessions > 0 {
+        writeln!(
+            out,
+            "    (discarded {} sessions longer than 24 hours as corrupt)",
+            voice.discarded_sessions
+        )?;
+    }
+    writeln!(out)?;
+
+    Ok(())
+}
+
+/// One line tracing how many conversations each filter left, e.g.
+/// "312 conversations found → 198 after min-messages → 25 shown (limit)".
+/// Omitted when nothing was filtered out.
+fn write_filter_stages(stages: &[FilterStage], out: &mut dyn Write) -> Result<(), MyError> {
+    let (Some(first), Some(last)) = (stages.first(), stages.last()) else {
+        return Ok(());
+    };
+    if first.remaining == last.remaining {
+        return Ok(());
+    }
+
+    let line = stages
+        .iter()
+        .map(|stage| match stage.stage {
+            FilterStageKind::Found => format!("{} conversations found", stage.remaining),
+            kind => format!("{} {}", stage.remaining, kind.label()),
+        })
+        .collect::<Vec<_>>()
+        .join(" → ");
+    writeln!(out, "{}", line)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Final line on a terminal when the default limit cut conversations off.
+pub fn write_hidden_hint(
+    hidden: &[&Conversation],
+    view: &ResultsView,
+    out: &mut dyn Write,
+) -> Result<(), MyError> {
+    let messages: usize = hidden
+        .iter()
+        .map(|conversation| conversation.message_count())
+        .sum();
+    if view.accessible {
+        writeln!(
+            out,
+            "{} more conversations with {} messages are hidden. Pass --all to show everything.",
+            hidden.len(),
+            view.counts.format(messages as u64)
+        )?;
+        return Ok(());
+    }
+    writeln!(
+        out,
+        "… {} more conversations hidden ({} messages); pass --all to show everything",
+        format_thousands(hidden.len() as u64),
+        view.counts.grouped(messages as u64)
+    )?;
+    Ok(())
+}
+
+/// Write a conversation and, for guilds, its channels, followed by a blank
+/// line. `top` highlights one of the largest conversations.
+pub fn write_tree(
+    conversation: &Conversation,
+    report: &Report,
+    format: TreeFormat,
+    top: bool,
+    out: &mut dyn Write,
+) -> Result<(), MyError> {
+    let TreeFormat {
+        counts, palette, ..
+    } = format;
+    let discrepancy = report
+        .discrepancy(conversation.id())
+        .map(|d| {
+            format!(
+                " (~{} more sent historically, likely deleted or missing)",
+                counts.format(d.missing())
+            )
+        })
+        .unwrap_or_default();
+    let note = report
+        .note(conversation.id())
+        .map(|note| format!(" — {}", note))
+        .unwrap_or_default();
+    let count = counts.format(conversation.message_count() as u64);
+    let share = counts.share(conversation.message_count() as u64, format.share_total);
+    let rest = format!("{} [{} messages, {}]{}", note, count, share, discrepancy).width();
+    let name = format.names.fit(conversation.name(), rest);
+    let name = match conversation {
+        _ if top => palette.top(&name),
+        Conversation::Guild { .. } => palette.guild(&name),
+        Conversation::DmOrGc { .. } => name.into_owned(),
+    };
+    writeln!(
+        out,
+        "{}{} [{} messages, {}]{}",
+        name,
+        dimmed(palette, &note),
+        palette.count(&count),
+        share,
+        discrepancy
+    )?;
+    if format.verbose && conversation.excluded_system() > 0 {
+        writeln!(
+            out,
+            "{}({} system messages excluded)",
+            format.indent(),
+            conversation.excluded_system()
+        )?;
+    }
+    if format.empty_messages && conversation.empty_messages() > 0 {
+        writeln!(
+            out,
+            "{}({} empty messages, {})",
+            format.indent(),
+            counts.format(conversation.empty_messages() as u64),
+            empty_share(
+                conversation.empty_messages(),
+                conversation.message_count(),
+                format.excluded_empty,
+                counts
+            )
+        )?;
+    }
+    if let Some(peak) = report.peak_day(conversation.id()) {
+        writeln!(
+            out,
+            "{}(peaked at {} messages on {})",
+            format.indent(),
+            counts.format(peak.count as u64),
+            peak.day
+        )?;
+    }
+
+    if let Conversation::Guild { channels, .. } = conversation {
+        let channels: Vec<&Channel> = channels.iter().collect();
+        let guild_total = conversation.message_count() as u64;
+        write_channels(&channels, report, format, guild_total, out)?;
+    }
+    writeln!(out)?;
+
+    Ok(())
+}
+
+/// Channels in the order given, which is the order of `Conversation::sort_channels`,
+/// each with its share of `total`.
+fn write_channels(
+    channels: &[&Channel],
+    report: &Report,
+    format: TreeFormat,
+    total: u64,
+    out: &mut dyn Write,
+) -> Result<(), MyError> {
+    let TreeFormat {
+        counts, palette, ..
+    } = format;
+    for (i, channel) in channels.iter().enumerate() {
+        let merged = match channel.merged_ids.len() {
+            0 => String::new(),
+            n => format!(", merged from {} channels", n),
+        };
+        let previous = match report.previous_names(&channel.id, &channel.name)[..] {
+            [] => String::new(),
+            ref names => format!(" (previously: {})", names.join(", ")),
+        };
+        let branch = format.glyphs.branch(i == channels.len() - 1);
+        let count = counts.format(channel.message_count as u64);
+        let share = counts.share(channel.message_count as u64, total);
+        let rest = format!(
+            "{}{} {} [{} messages, {}{}]",
+            format.indent(),
+            branch,
+            previous,
+            count,
+            share,
+            merged
+        )
+        .width();
+        writeln!(
+            out,
+            "{}{} {}{} [{} messages, {}{}]",
+            format.indent(),
+            branch,
+            palette.channel(&format.names.fit(&channel.name, rest)),
+            previous,
+            palette.count(&count),
+            share,
+            merged
+        )?;
+    }
+    Ok(())
+}
+
+fn write_reference_counts(
+    reference_counts: &ReferenceCounts,
+    counts: CountStyle,
+    out: &mut dyn Write,
+) -> Result<(), MyError> {
+    writeln!(
+        out,
+        "Counted {}; Discord reports {} ({}); {}% difference, {}",
+        counts.grouped(reference_counts.counted),
+        counts.grouped(reference_counts.reported.count),
+        reference_counts.reported.source,
+        counts.percent(
+            reference_counts.difference(),
+            reference_counts.reported.count
+        ),
+        reference_counts.explanation()
+    )?;
+    Ok(())
+}
+
+pub fn format_thousands(n: u64) -> String {
+    let digits = n.to_string();
+    let mut formatted = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{channel, dm, guild, rendered, report, view};
+    use std::collections::HashMap;
+    use std::fs;
+
+    #[test]
+    fn unknown_server_channels_are_listed_under_one_heading() {
+        let report = report(vec![
+            guild(Some("10"), "Srv", vec![channel("11", "general", 5)]),
+            guild(None, UNKNOWN_GUILD_NAME, vec![channel("21", "lost", 3)]),
+            dm("30", "Alice", 2),
+            guild(None, UNKNOWN_GUILD_NAME, vec![channel("22", "gone", 1)]),
+        ]);
+        let text = rendered(OutputFormat::Text, &view(&report));
+
+        assert_eq!(text.matches(UNKNOWN_GUILD_NAME).count(), 1);
+        let heading = text.find(UNKNOWN_GUILD_NAME).unwrap();
+        // After every other conversation, with each channel on its own line
+        assert!(text.find("Alice").unwrap() < heading);
+        assert!(text[heading..].contains("├── lost [3 messages"));
+        assert!(text[heading..].contains("└── gone [1 messages"));
+    }
+
+    #[test]
+    fn output_targets_parse_as_format_and_path() {
+        let target: OutputTarget = "csv:out/counts.csv".parse().unwrap();
+        assert_eq!(target.format, OutputFormat::Csv);
+        assert_eq!(target.path, Path::new("out/counts.csv"));
+        // Only the first colon separates, so Windows drive letters survive
+        let target: OutputTarget = "json:C:\\counts.json".parse().unwrap();
+        assert_eq!(target.path, Path::new("C:\\counts.json"));
+
+        assert!("counts.csv".parse::<OutputTarget>().is_err());
+        assert!("csv:".parse::<OutputTarget>().is_err());
+        assert!("word:counts.doc".parse::<OutputTarget>().is_err());
+    }
+
+    #[test]
+    fn every_target_is_written_past_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let report = report(vec![dm("1", "Alice", 4)]);
+        let targets = [
+            OutputTarget {
+                format: OutputFormat::Csv,
+                path: dir.path().join("missing/counts.csv"),
+            },
+            OutputTarget {
+                format: OutputFormat::Json,
+                path: dir.path().join("counts.json"),
+            },
+        ];
+
+        let failures = render_to_targets(&targets, &view(&report), false);
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0.path, targets[0].path);
+        let json = fs::read_to_string(&targets[1].path).unwrap();
+        assert_eq!(json, rendered(OutputFormat::Json, &view(&report)));
+    }
+
+    #[test]
+    fn existing_targets_are_only_replaced_with_force() {
+        let dir = tempfile::tempdir().unwrap();
+        let targets = [OutputTarget {
+            format: OutputFormat::Json,
+            path: dir.path().join("counts.json"),
+        }];
+        fs::write(&targets[0].path, "old").unwrap();
+        let report = report(vec![dm("1", "Alice", 4)]);
+
+        let failures = render_to_targets(&targets, &view(&report), false);
+        assert!(matches!(failures[..], [(_, MyError::OutputExists(_))]));
+        assert_eq!(fs::read_to_string(&targets[0].path).unwrap(), "old");
+
+        assert!(render_to_targets(&targets, &view(&report), true).is_empty());
+        let json = fs::read_to_string(&targets[0].path).unwrap();
+        assert_eq!(json, rendered(OutputFormat::Json, &view(&report)));
+    }
+
+    #[test]
+    fn tsv_flattens_tabs_and_line_breaks() {
+        let report = report(vec![
+            guild(Some("10"), "Srv\tOne", vec![channel("11", "gen\neral", 2)]),
+            dm("20", "Al\rice", 1),
+        ]);
+        let mut view = view(&report);
+        view.channels = true;
+
+        assert_eq!(
+            rendered(OutputFormat::Tsv, &view),
+            "kind\tid\tname\tparent\tmessage_count\tnote\n\
+             guild\t10\tSrv One\t\t2\t\n\
+             channel\t11\tgen eral\tSrv One\t2\t\n\
+             dm\t20\tAl ice\t\t1\t\n"
+        );
+    }
+
+    #[test]
+    fn nuon_quotes_strings_and_leaves_out_channels_by_default() {
+        let report = report(vec![
+            guild(Some("10"), "Srv", vec![channel("11", "general", 2)]),
+            dm("20", "\"Al\\ice\"\u{1}", 1),
+        ]);
+
+        assert_eq!(
+            rendered(OutputFormat::Nuon, &view(&report)),
+            "[\n  \
+             {kind: \"guild\", id: \"10\", name: \"Srv\", parent: null, message_count: 2, note: null}\n  \
+             {kind: \"dm\", id: \"20\", name: \"\\\"Al\\\\ice\\\"\\u{0001}\", parent: null, message_count: 1, note: null}\n\
+             ]\n"
+        );
+    }
+
+    #[test]
+    fn empty_results_are_still_valid_documents() {
+        let report = report(Vec::new());
+        let view = view(&report);
+
+        let json: serde_json::Value =
+            serde_json::from_str(&rendered(OutputFormat::Json, &view)).unwrap();
+        assert_eq!(json["conversations"], serde_json::json!([]));
+        assert!(json["meta"].is_object());
+
+        let yaml: Vec<serde_yaml::Value> =
+            serde_yaml::from_str(&rendered(OutputFormat::Yaml, &view)).unwrap();
+        assert!(yaml.is_empty());
+        let toml: toml::Table = toml::from_str(&rendered(OutputFormat::Toml, &view)).unwrap();
+        assert_eq!(toml["conversation"], toml::Value::Array(Vec::new()));
+
+        assert_eq!(rendered(OutputFormat::Csv, &view).lines().count(), 1);
+        assert_eq!(rendered(OutputFormat::Tsv, &view).lines().count(), 1);
+        assert_eq!(rendered(OutputFormat::Ndjson, &view), "");
+        assert_eq!(rendered(OutputFormat::Nuon, &view), "[\n]\n");
+    }
+
+    #[test]
+    fn excluded_system_messages_are_reported() {
+        let mut alice = dm("1", "Alice", 5);
+        if let Conversation::DmOrGc {
+            excluded_system, ..
+        } = &mut alice
+        {
+            *excluded_system = 3;
+        }
+        let report = report(vec![alice, dm("2", "Bob", 2)]);
+        let mut view = view(&report);
+        view.quiet = false;
+        view.excluded_system = true;
+        let text = rendered(OutputFormat::Text, &view);
+        assert!(
+            text.contains("Excluded 3 system messages from the counts"),
+            "{}",
+            text
+        );
+        assert!(!text.contains("system messages excluded)"), "{}", text);
+
+        view.verbose = true;
+        let text = rendered(OutputFormat::Text, &view);
+        assert_eq!(
+            text.matches("(3 system messages excluded)").count(),
+            1,
+            "{}",
+            text
+        );
+    }
+
+    #[test]
+    fn empty_shares_read_the_same_with_stubs_excluded() {
+        let with_stubs = |message_count: usize, excluded: bool| {
+            let mut alice = dm("1", "Alice", message_count);
+            if let Conversation::DmOrGc { empty_messages, .. } = &mut alice {
+                *empty_messages = 2;
+            }
+            let report = report(vec![alice]);
+            let mut view = view(&report);
+            view.quiet = false;
+            view.empty_message_stats = true;
+            view.excluded_empty = excluded;
+            rendered(OutputFormat::Text, &view)
+        };
+
+        let counted = with_stubs(8, false);
+        let excluded = with_stubs(6, true);
+        for text in [&counted, &excluded] {
+            assert!(text.contains("(2 empty messages, 25.00%)"), "{}", text);
+            assert!(
+                text.contains("Empty messages (no contents or attachments): 2, 25.00%"),
+                "{}",
+                text
+            );
+        }
+        assert!(
+            !counted.contains("Excluded 2 empty messages"),
+            "{}",
+            counted
+        );
+        assert!(
+            excluded.contains("Excluded 2 empty messages from the counts"),
+            "{}",
+            excluded
+        );
+    }
+
+    #[test]
+    fn the_summary_counts_excluded_bot_dms() {
+        let mut bot = dm("1", "Helper", 3);
+        if let Conversation::DmOrGc { ref mut is_bot, .. } = bot {
+            *is_bot = Some(true);
+        }
+        let report = report(vec![bot, dm("2", "Alice", 1)]);
+        let mut view = view(&report);
+        view.conversations.remove(0);
+        view.excluded_bots = true;
+
+        assert!(rendered(OutputFormat::Text, &view).contains("Excluded 1 DMs with bots\n"));
+        let json: serde_json::Value =
+            serde_json::from_str(&rendered(OutputFormat::Json, &view)).unwrap();
+        assert_eq!(json["meta"]["excluded_bot_dms"], 1);
+    }
+
+    #[test]
+    fn the_summary_counts_hidden_departed_servers() {
+        let mut departed = guild(Some("10"), "Old Server", vec![channel("11", "general", 4)]);
+        if let Conversation::Guild {
+            departed: ref mut flag,
+            ..
+        } = departed
+        {
+            *flag = true;
+        }
+        let report = report(vec![departed, dm("2", "Alice", 1)]);
+        let mut view = view(&report);
+        view.conversations.remove(0);
+        view.summary = true;
+        view.quiet = false;
+
+        let hint = "Hid 1 servers you're no longer in (--include-departed lists them)\n";
+        assert!(!rendered(OutputFormat::Text, &view).contains(hint));
+        view.excluded_departed = true;
+        assert!(rendered(OutputFormat::Text, &view).contains(hint));
+        let json: serde_json::Value =
+            serde_json::from_str(&rendered(OutputFormat::Json, &view)).unwrap();
+        assert_eq!(json["meta"]["excluded_departed_guilds"], 1);
+
+        // Nothing was hidden once the departed server is listed
+        let listed = report.conversations.iter().collect();
+        view.conversations = listed;
+        view.excluded_departed = false;
+        assert!(!rendered(OutputFormat::Text, &view).contains("Hid "));
+    }
+
+    #[test]
+    fn voice_stats_draw_with_the_tree_style() {
+        let mut report = report(vec![guild(
+            Some("10"),
+            "Srv",
+            vec![channel("11", "general", 1)],
+        )]);
+        report.voice = Some(VoiceStats {
+            total_seconds: 5400,
+            seconds_by_guild: HashMap::from([("10".to_string(), 3600), ("20".to_string(), 1800)]),
+            sessions: 2,
+            discarded_sessions: 0,
+        });
+        let mut view = view(&report);
+        view.quiet = false;
+
+        let text = rendered(OutputFormat::Text, &view);
+        assert!(text.contains("├── Srv: 1h\n└── Guild 20: 30m\n"));
+        view.tree_glyphs = TreeGlyphs::Ascii;
+        let text = rendered(OutputFormat::Text, &view);
+        assert!(text.contains("|-- Srv: 1h\n`-- Guild 20: 30m\n"));
+        assert!(!text.contains('─'));
+    }
+}
@@ -0,0 +1,128 @@
+use crate::analytics::{Discrepancy, ReferenceCounts, VoiceStats};
+use crate::breadth::YearBreadth;
+use crate::budget::PartialRun;
+use crate::inventory::Coverage;
+use crate::peaks::PeakDay;
+use crate::sessions::SessionSummary;
+use crate::vintage::PackageVintage;
+use crate::volume::ProcessingStats;
+use crate::warnings::Warning;
+use crate::Conversation;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Everything a single counting run produces, before any filtering.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Report {
+    pub conversations: Vec<Conversation>,
+    pub reference_counts: Option<ReferenceCounts>,
+    /// Conversations with more messages sent according to the analytics
+    #[serde(default)]
+    pub discrepancies: Vec<Discrepancy>,
+    /// Start of the analytics coverage the discrepancies are based on
+    #[serde(default)]
+    pub analytics_since: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub warnings: Vec<Warning>,
+    /// Set when a budget or cancellation stopped processing early
+    #[serde(default)]
+    pub partial: Option<PartialRun>,
+    /// Voice activity, only collected when requested
+    #[serde(default)]
+    pub voice: Option<VoiceStats>,
+    /// Distinct servers, channels and DMs per year, only collected when requested
+    #[serde(default)]
+    pub breadth_by_year: Option<Vec<YearBreadth>>,
+    /// The package owner's user ID, to tell them apart from DM recipients
+    #[serde(default)]
+    pub account_id: Option<String>,
+    /// Earliest and latest counted message across the package
+    #[serde(default)]
+    pub first_message: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub last_message: Option<DateTime<Utc>>,
+    /// Messages grouped into sessions per conversation, only collected when requested
+    #[serde(default)]
+    pub sessions: Option<SessionSummary>,
+    /// Every name the analytics recorded per channel ID, most recent first,
+    /// from `--historical-names`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub previous_names: HashMap<String, Vec<String>>,
+    /// Package format generation the counts were read with
+    #[serde(default)]
+    pub vintage: Option<PackageVintage>,
+    /// The user's own annotations by conversation ID, from `--notes`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub notes: HashMap<String, String>,
+    /// Counted messages per conversation ID and calendar year, only
+    /// collected for `--threshold-timeline`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub messages_by_year: HashMap<String, BTreeMap<i32, usize>>,
+    /// Busiest day per conversation ID, only collected for `--activity-stats`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub peak_days: HashMap<String, PeakDay>,
+    /// Data read while counting, absent for reports read from stdin
+    #[serde(default)]
+    pub processing_stats: Option<ProcessingStats>,
+    /// The channels listed, present and counted, compared for `--coverage`
+    #[serde(default)]
+    pub coverage: Option<Coverage>,
+}
+
+impl Report {
+    pub fn discrepancy(&self, conversation_id: &str) -> Option<&Discrepancy> {
+        self.discrepancies
+            .iter()
+            .find(|discrepancy| discrepancy.conversation_id == conversation_id)
+    }
+
+    /// Up to two earlier names of a channel, most recent first.
+    pub fn previous_names(&self, channel_id: &str, current_name: &str) -> Vec<&str> {
+        self.previous_names
+            .get(channel_id)
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .filter(|name| *name != current_name)
+            .take(2)
+            .collect()
+    }
+
+    pub fn note(&self, conversation_id: &str) -> Option<&str> {
+        self.notes.get(conversation_id).map(String::as_str)
+    }
+
+    pub fn peak_day(&self, conversation_id: &str) -> Option<PeakDay> {
+        self.peak_days.get(conversation_id).copied()
+    }
+
+    /// DMs flagged as being with a bot, which --exclude-bots leaves out.
+    pub fn bot_dms(&self) -> usize {
+        self.conversations
+            .iter()
+            .filter(|conversation| conversation.is_bot())
+            .count()
+    }
+
+    /// Servers missing from `servers/index.json`, which are left out unless
+    /// --include-departed or --only-departed is given.
+    pub fn departed_guilds(&self) -> usize {
+        self.conversations
+            .iter()
+            .filter(|conversation| conversation.is_departed())
+            .count()
+    }
+
+    /// Name of a guild as counted, if the package contains any of its channels.
+    pub fn guild_name(&self, guild_id: &str) -> Option<&str> {
+        self.conversations
+            .iter()
+            .find_map(|conversation| match conversation {
+                Conversation::Guild {
+                    id: Some(id), name, ..
+                } if id == guild_id => Some(name.as_str()),
+                _ => None,
+            })
+    }
+}
@@ -0,0 +1,424 @@
+use std::collections::HashMap;
+
+/// Turns Discord IDs into display names.
+pub trait NameResolver {
+    fn guild_name(&mut self, guild_id: &str) -> Option<String>;
+    fn channel_name(&mut self, channel_id: &str) -> Option<String>;
+
+    /// Called once after the last lookup, e.g. to persist caches.
+    fn finish(&mut self) {}
+}
+
+impl<R: NameResolver + ?Sized> NameResolver for Box<R> {
+    fn guild_name(&mut self, guild_id: &str) -> Option<String> {
+        (**self).guild_name(guild_id)
+    }
+
+    fn channel_name(&mut self, channel_id: &str) -> Option<String> {
+        (**self).channel_name(channel_id)
+    }
+
+    fn finish(&mut self) {
+        (**self).finish()
+    }
+}
+
+/// Resolves names from the package's own `index.json` mappings.
+pub struct MappingResolver {
+    channel_mapping: Option<HashMap<String, String>>,
+    guild_mapping: Option<HashMap<String, String>>,
+}
+
+impl MappingResolver {
+    pub fn new(
+        channel_mapping: Option<HashMap<String, String>>,
+        guild_mapping: Option<HashMap<String, String>>,
+    ) -> Self {
+        Self {
+            channel_mapping,
+            guild_mapping,
+        }
+    }
+}
+
+/// Some exports map IDs to empty names, which are as good as no mapping.
+fn present(name: Option<&String>) -> Option<String> {
+    name.filter(|name| !name.trim().is_empty()).cloned()
+}
+
+impl NameResolver for MappingResolver {
+    fn guild_name(&mut self, guild_id: &str) -> Option<String> {
+        present(self.guild_mapping.as_ref()?.get(guild_id))
+    }
+
+    fn channel_name(&mut self, channel_id: &str) -> Option<String> {
+        present(self.channel_mapping.as_ref()?.get(channel_id))
+    }
+}
+
+#[cfg(feature = "net")]
+pub use online::OnlineResolver;
+
+#[cfg(feature = "net")]
+mod online {
+    use super::NameResolver;
+    use crate::errors::MyError;
+    use serde::Deserialize;
+    use std::collections::HashMap;
+    use std::fs::File;
+    use std::io::BufReader;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    /// Environment variable holding an optional bot token for guild lookups.
+    pub const TOKEN_VAR: &str = "DISCORD_BOT_TOKEN";
+
+    const API_BASE: &str = "https://discord.com/api/v10";
+    const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[derive(Deserialize)]
+    struct GuildResponse {
+        name: String,
+    }
+
+    /// Falls back to Discord's API for guilds the offline resolver cannot
+    /// name. Every answer, including failures, is cached so repeated runs
+    /// don't repeat requests; network errors only ever yield `None`.
+    pub struct OnlineResolver<R> {
+        offline: R,
+        cache_path: PathBuf,
+        cache: HashMap<String, Option<String>>,
+        requests_left: usize,
+        token: Option<String>,
+        agent: ureq::Agent,
+        api_base: String,
+    }
+
+    impl<R: NameResolver> OnlineResolver<R> {
+        pub fn new(offline: R, cache_path: PathBuf, max_requests: usize) -> Self {
+            let cache = File::open(&cache_path)
+                .ok()
+                .and_then(|file| serde_json::from_reader(BufReader::new(file)).ok())
+                .unwrap_or_default();
+
+            Self {
+                offline,
+                cache_path,
+                cache,
+                requests_left: max_requests,
+                token: std::env::var(TOKEN_VAR).ok().filter(|t| !t.is_empty()),
+                agent: ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build(),
+                api_base: API_BASE.to_string(),
+            }
+        }
+
+        /// Persist the lookup cache for the next run.
+        fn save(&self) -> Result<(), MyError> {
+            let file = File::create(&self.cache_path)?;
+            serde_json::to_writer(file, &self.cache)?;
+            Ok(())
+        }
+
+        fn fetch_guild_name(&self, guild_id: &str) -> Option<String> {
+            let request = match &self.token {
+                Some(token) => self
+                    .agent
+                    .get(&format!("{}/guilds/{}", self.api_base, guild_id))
+                    .set("Authorization", &format!("Bot {}", token)),
+                // The public widget only answers for guilds that enabled it
+                None => self.agent.get(&format!(
+                    "{}/guilds/{}/widget.json",
+                    self.api_base, guild_id
+                )),
+            };
+
+            let response: GuildResponse = request.call().ok()?.into_json().ok()?;
+            Some(response.name)
+        }
+    }
+
+    impl<R: NameResolver> NameResolver for OnlineResolver<R> {
+        fn guild_name(&mut self, guild_id: &str) -> Option<String> {
+            if let Some(name) = self.offline.guild_name(guild_id) {
+                return Some(name);
+            }
+            if let Some(cached) = self.cache.get(guild_id) {
+                return cached.clone();
+            }
+            if self.requests_left == 0 {
+                return None;
+            }
+
+            self.requests_left -= 1;
+            let name = self.fetch_guild_name(guild_id);
+            self.cache.insert(guild_id.to_string(), name.clone());
+            name
+        }
+
+        fn channel_name(&mut self, channel_id: &str) -> Option<String> {
+            // Channel names need the recipient's permissions, so only the package knows them
+            self.offline.channel_name(channel_id)
+        }
+
+        fn finish(&mut self) {
+            self.offline.finish();
+            // A failed cache write only costs requests on the next run
+            let _ = self.save();
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::resolve::tests::Recording;
+        use std::io::{BufRead, Write};
+        use std::net::TcpListener;
+        use std::sync::mpsc;
+        use std::thread;
+
+        /// A resolver asking the API at `api_base` after `offline`, with
+        /// its cache in `dir` and no token.
+        fn online(
+            offline: Recording,
+            dir: &tempfile::TempDir,
+            api_base: &str,
+            max_requests: usize,
+        ) -> OnlineResolver<Recording> {
+            let mut resolver =
+                OnlineResolver::new(offline, dir.path().join("names.json"), max_requests);
+            resolver.token = None;
+            resolver.api_base = api_base.to_string();
+            resolver
+        }
+
+        /// An API answering every request with `name`, passing on the
+        /// request lines it receives.
+        fn api(name: &'static str) -> (String, mpsc::Receiver<String>) {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let base = format!("http://{}", listener.local_addr().unwrap());
+            let (requests, received) = mpsc::channel();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let mut stream = stream.unwrap();
+                    let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let _ = requests.send(line.trim_end().to_string());
+                    // The rest of the request head
+                    loop {
+                        let mut header = String::new();
+                        if reader.read_line(&mut header).unwrap() <= 2 {
+                            break;
+                        }
+                    }
+                    let body = format!("{{\"name\": \"{}\"}}", name);
+                    write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                    .unwrap();
+                }
+            });
+            (base, received)
+        }
+
+        #[test]
+        fn offline_names_come_first() {
+            let dir = tempfile::tempdir().unwrap();
+            let offline = Recording::naming(&[("10", "Known")]);
+            // No server listens here, so any request would fail
+            let mut resolver = online(offline, &dir, "http://127.0.0.1:1", 5);
+            assert_eq!(resolver.guild_name("10").as_deref(), Some("Known"));
+            assert_eq!(resolver.requests_left, 5);
+        }
+
+        #[test]
+        fn unknown_guilds_are_looked_up_once_and_cached() {
+            let dir = tempfile::tempdir().unwrap();
+            let (base, requests) = api("Looked Up");
+            let mut resolver = online(Recording::default(), &dir, &base, 5);
+            assert_eq!(resolver.guild_name("20").as_deref(), Some("Looked Up"));
+            assert_eq!(resolver.guild_name("20").as_deref(), Some("Looked Up"));
+            assert_eq!(
+                requests.try_iter().collect::<Vec<_>>(),
+                ["GET /guilds/20/widget.json HTTP/1.1"]
+            );
+            resolver.finish();
+            assert_eq!(resolver.offline.finished, 1);
+
+            // The next run answers from the cache without asking
+            let mut resolver = online(Recording::default(), &dir, "http://127.0.0.1:1", 0);
+            assert_eq!(resolver.guild_name("20").as_deref(), Some("Looked Up"));
+        }
+
+        #[test]
+        fn network_failures_degrade_to_no_name() {
+            let dir = tempfile::tempdir().unwrap();
+            let mut resolver = online(Recording::default(), &dir, "http://127.0.0.1:1", 5);
+            assert_eq!(resolver.guild_name("30"), None);
+            // A failure is cached too, so it costs a single request
+            assert_eq!(resolver.guild_name("30"), None);
+            assert_eq!(resolver.requests_left, 4);
+        }
+
+        #[test]
+        fn requests_stop_at_the_cap() {
+            let dir = tempfile::tempdir().unwrap();
+            let (base, requests) = api("Named");
+            let mut resolver = online(Recording::default(), &dir, &base, 2);
+            let names: Vec<Option<String>> = ["1", "2", "3"]
+                .iter()
+                .map(|id| resolver.guild_name(id))
+                .collect();
+            assert_eq!(
+                names,
+                [Some("Named".to_string()), Some("Named".to_string()), None]
+            );
+            assert_eq!(requests.try_iter().count(), 2);
+        }
+
+        #[test]
+        fn channel_names_are_only_offline() {
+            let dir = tempfile::tempdir().unwrap();
+            let offline = Recording::naming(&[("5", "general")]);
+            let mut resolver = online(offline, &dir, "http://127.0.0.1:1", 5);
+            assert_eq!(resolver.channel_name("5").as_deref(), Some("general"));
+            assert_eq!(resolver.channel_name("6"), None);
+            assert_eq!(resolver.requests_left, 5);
+        }
+    }
+}
+
+/// The name without a legacy `#1234` discriminator, or `None` if it has none.
+/// Only exactly four digits directly after a non-blank name count, so names
+/// like `Room #1234`, `user#0` or `#12345` are left alone.
+pub fn strip_discriminator(name: &str) -> Option<&str> {
+    let (rest, digits) = name.rsplit_once('#')?;
+    let is_discriminator = digits.len() == 4 && digits.bytes().all(|b| b.is_ascii_digit());
+    let ends_in_username = rest.chars().last().is_some_and(|c| !c.is_whitespace());
+    (is_discriminator && ends_in_username).then_some(rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Package;
+    use crate::warnings::Warning;
+    use serde_json::json;
+
+    /// A resolver naming guilds and channels from a fixed list, counting
+    /// how often it was finished.
+    #[derive(Default)]
+    pub struct Recording {
+        names: HashMap<String, String>,
+        pub finished: usize,
+    }
+
+    impl Recording {
+        pub fn naming(names: &[(&str, &str)]) -> Self {
+            Self {
+                names: names
+                    .iter()
+                    .map(|&(id, name)| (id.to_string(), name.to_string()))
+                    .collect(),
+                finished: 0,
+            }
+        }
+    }
+
+    impl NameResolver for Recording {
+        fn guild_name(&mut self, guild_id: &str) -> Option<String> {
+            self.names.get(guild_id).cloned()
+        }
+
+        fn channel_name(&mut self, channel_id: &str) -> Option<String> {
+            self.names.get(channel_id).cloned()
+        }
+
+        fn finish(&mut self) {
+            self.finished += 1;
+        }
+    }
+
+    #[test]
+    fn mappings_name_what_they_list() {
+        let mut resolver = MappingResolver::new(
+            Some(HashMap::from([
+                ("1".to_string(), "general".to_string()),
+                ("2".to_string(), "  ".to_string()),
+            ])),
+            None,
+        );
+        assert_eq!(resolver.channel_name("1").as_deref(), Some("general"));
+        assert_eq!(resolver.channel_name("2"), None);
+        assert_eq!(resolver.channel_name("3"), None);
+        assert_eq!(resolver.guild_name("1"), None);
+    }
+
+    #[test]
+    fn boxed_resolvers_forward_every_call() {
+        let mut boxed = Box::new(Recording::naming(&[("1", "One")]));
+        assert_eq!(
+            NameResolver::guild_name(&mut boxed, "1").as_deref(),
+            Some("One")
+        );
+        assert_eq!(
+            NameResolver::channel_name(&mut boxed, "1").as_deref(),
+            Some("One")
+        );
+        NameResolver::finish(&mut boxed);
+        assert_eq!(boxed.finished, 1);
+    }
+
+    #[test]
+    fn strips_only_trailing_four_digit_discriminators() {
+        for (name, stripped) in [
+            ("friend#1234", Some("friend")),
+            ("two#tags#0001", Some("two#tags")),
+            ("Ünïcode#9999", Some("Ünïcode")),
+            ("user#0", None),
+            ("user#123", None),
+            ("user#12345", None),
+            ("user#12a4", None),
+            ("user#１２３４", None),
+            ("mid#1234string", None),
+            ("Room #1234", None),
+            ("#1234", None),
+            ("no tag", None),
+            ("", None),
+        ] {
+            assert_eq!(strip_discriminator(name), stripped, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn legacy_names_are_stripped_and_counted() {
+        let package = Package::new();
+        package
+            .file(
+                "messages/index.json",
+                &json!({"1": "friend#1234", "2": "Room #1234", "3": "pal#0042"}).to_string(),
+            )
+            .channel("1", json!({"id": "1", "type": 1}), 1)
+            .channel("2", json!({"id": "2", "type": 1}), 1)
+            .channel("3", json!({"id": "3", "type": 1}), 1);
+
+        let report = package.analyze();
+        let mut names: Vec<&str> = report.conversations.iter().map(|c| c.name()).collect();
+        names.sort_unstable();
+        assert_eq!(names, ["Room #1234", "friend", "pal"]);
+        assert!(report
+            .warnings
+            .contains(&Warning::LegacyUsernames { count: 2 }));
+
+        let mut options = package.options();
+        options.process.keep_discriminators = true;
+        let report = crate::analysis::analyze(&options).unwrap();
+        let mut names: Vec<&str> = report.conversations.iter().map(|c| c.name()).collect();
+        names.sort_unstable();
+        assert_eq!(names, ["Room #1234", "friend#1234", "pal#0042"]);
+    }
+}
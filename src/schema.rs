@@ -0,0 +1,375 @@
+//! `--validate-strict`: every `channel.json` and a sample of messages read
+//! against strict copies of the structures counting uses, to find the fields
+//! Discord added that this tool does not read yet. Counting itself stays
+//! tolerant; nothing here runs unless asked for.
+//!
+//! The strict structures list the same fields as `index::ChannelInfo` and
+//! `messages::Message`, plus the IDs those skip, with `deny_unknown_fields`
+//! and every field optional. A field read by one of those belongs here too,
+//! or every package will report it.
+
+use crate::errors::MyError;
+use crate::file_operations::prepare_data_root;
+use crate::index::build_index;
+use crate::vintage::{MessageFormat, PackageVintage};
+use serde::de::{DeserializeOwned, IgnoredAny, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::path::Path;
+
+/// Channels whose messages are sampled, the largest first.
+const SAMPLE_CHANNELS: usize = 20;
+/// Messages read from the start of each sampled channel.
+const SAMPLE_MESSAGES: usize = 50;
+/// Characters of an example value shown.
+const EXAMPLE_LENGTH: usize = 40;
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[allow(dead_code)]
+struct StrictChannel {
+    id: IgnoredAny,
+    #[serde(rename = "type")]
+    channel_type: IgnoredAny,
+    name: IgnoredAny,
+    /// Checked against `StrictRecipient` one by one
+    recipients: IgnoredAny,
+    /// Checked against `StrictGuild`
+    guild: IgnoredAny,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[allow(dead_code)]
+struct StrictGuild {
+    id: IgnoredAny,
+    name: IgnoredAny,
+}
+
+/// The object form of a recipient; bare IDs have no fields to check.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[allow(dead_code)]
+struct StrictRecipient {
+    id: IgnoredAny,
+    bot: IgnoredAny,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[allow(dead_code)]
+struct StrictMessage {
+    #[serde(rename = "ID")]
+    id: IgnoredAny,
+    #[serde(rename = "Timestamp")]
+    timestamp: IgnoredAny,
+    #[serde(rename = "Contents")]
+    contents: IgnoredAny,
+    #[serde(rename = "Attachments")]
+    attachments: IgnoredAny,
+    #[serde(rename = "Type", alias = "type")]
+    message_type: IgnoredAny,
+}
+
+/// Where an unknown field was found and how often, with its first value.
+#[derive(Debug, Default)]
+struct UnknownField {
+    occurrences: usize,
+    example: String,
+}
+
+#[derive(Debug, Default)]
+struct StrictCheck {
+    channel_files: usize,
+    message_channels: usize,
+    messages: usize,
+    /// By `channel.json` or `messages` and the field's path within
+    unknown: BTreeMap<(&'static str, String), UnknownField>,
+}
+
+impl StrictCheck {
+    fn record(&mut self, file: &'static str, prefix: &str, unknown: Vec<(String, Value)>) {
+        for (field, value) in unknown {
+            let entry = self
+                .unknown
+                .entry((file, format!("{}{}", prefix, field)))
+                .or_default();
+            if entry.occurrences == 0 {
+                entry.example = example(&value);
+            }
+            entry.occurrences += 1;
+        }
+    }
+
+    fn check_channel(&mut self, object: Map<String, Value>) {
+        if let Some(Value::Object(guild)) = object.get("guild") {
+            self.record(
+                "channel.json",
+                "guild.",
+                unknown_fields::<StrictGuild>(guild),
+            );
+        }
+        if let Some(Value::Array(recipients)) = object.get("recipients") {
+            for recipient in recipients {
+                if let Value::Object(recipient) = recipient {
+                    let unknown = unknown_fields::<StrictRecipient>(recipient);
+                    self.record("channel.json", "recipients[].", unknown);
+                }
+            }
+        }
+        self.record("channel.json", "", unknown_fields::<StrictChannel>(&object));
+    }
+}
+
+/// Strictly read every `channel.json` of the package and the first messages
+/// of its largest channels, and list the fields nothing reads.
+pub fn run(input_path: &Path, out: &mut dyn Write) -> Result<(), MyError> {
+    let data_root = prepare_data_root(input_path, None)?;
+    let index = build_index(&data_root)?;
+    let mut check = StrictCheck::default();
+
+    for channel in &index.channels {
+        let Some(folder) = channel.messages_file.parent() else {
+            continue;
+        };
+        let path = folder.join("channel.json");
+        if !path.exists() {
+            continue;
+        }
+        let value: Value = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        if let Value::Object(object) = value {
+            check.check_channel(object);
+            check.channel_files += 1;
+        }
+    }
+
+    let mut largest: Vec<_> = index.channels.iter().collect();
+    largest.sort_by_key(|channel| (std::cmp::Reverse(channel.messages_size), &channel.id));
+    for channel in largest.into_iter().take(SAMPLE_CHANNELS) {
+        if !channel.messages_file.exists() {
+            continue;
+        }
+        let messages = sample_messages(&channel.messages_file)?;
+        check.messages += messages.len();
+        check.message_channels += 1;
+        for message in messages {
+            check.record("messages", "", unknown_fields::<StrictMessage>(&message));
+        }
+    }
+
+    write_check(&check, index.vintage, out)
+}
+
+/// Every field of `object` that `T` rejects as unknown, with its value. Serde
+/// names one unknown field per attempt, so each is removed and the rest tried
+/// again until `T` accepts what is left or fails for another reason.
+fn unknown_fields<T: DeserializeOwned>(object: &Map<String, Value>) -> Vec<(String, Value)> {
+    let mut object = object.clone();
+    let mut unknown = Vec::new();
+    while let Err(error) = serde_json::from_value::<T>(Value::Object(object.clone())) {
+        let message = error.to_string();
+        let Some(field) = message
+            .strip_prefix("unknown field `")
+            .and_then(|rest| rest.split_once('`'))
+            .map(|(field, _)| field.to_string())
+        else {
+            break;
+        };
+        match object.remove(&field) {
+            Some(value) => unknown.push((field, value)),
+            None => break,
+        }
+    }
+    unknown
+}
+
+/// The first `SAMPLE_MESSAGES` messages of a messages file, as read.
+fn sample_messages(path: &Path) -> Result<Vec<Map<String, Value>>, MyError> {
+    let file = BufReader::new(File::open(path)?);
+    if MessageFormat::of(path) == MessageFormat::Csv {
+        let mut reader = csv::Reader::from_reader(file);
+        let mut messages = Vec::new();
+        for row in reader.deserialize::<BTreeMap<String, String>>() {
+            if messages.len() == SAMPLE_MESSAGES {
+                break;
+            }
+            messages.push(
+                row?.into_iter()
+                    .map(|(k, v)| (k, Value::String(v)))
+                    .collect(),
+            );
+        }
+        return Ok(messages);
+    }
+    let mut deserializer = serde_json::Deserializer::from_reader(file);
+    let messages = deserializer.deserialize_seq(SampleVisitor)?;
+    deserializer.end()?;
+    Ok(messages)
+}
+
+/// Keeps the first messages of an array and skips the rest unparsed.
+struct SampleVisitor;
+
+impl<'de> Visitor<'de> for SampleVisitor {
+    type Value = Vec<Map<String, Value>>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an array of messages")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut messages = Vec::new();
+        while messages.len() < SAMPLE_MESSAGES {
+            match seq.next_element::<Map<String, Value>>()? {
+                Some(message) => messages.push(message),
+                None => return Ok(messages),
+            }
+        }
+        while seq.next_element::<IgnoredAny>()?.is_some() {}
+        Ok(messages)
+    }
+}
+
+fn example(value: &Value) -> String {
+    let text = value.to_string();
+    match text.chars().count() > EXAMPLE_LENGTH {
+        true => text.chars().take(EXAMPLE_LENGTH - 1).chain(['…']).collect(),
+        false => text,
+    }
+}
+
+fn write_check(
+    check: &StrictCheck,
+    vintage: PackageVintage,
+    out: &mut dyn Write,
+) -> Result<(), MyError> {
+    writeln!(out, "Package vintage: {}", vintage)?;
+    writeln!(
+        out,
+        "Read {} channel.json files and {} messages from {} channels strictly",
+        check.channel_files, check.messages, check.message_channels
+    )?;
+    if check.unknown.is_empty() {
+        writeln!(out, "No unknown fields")?;
+        return Ok(());
+    }
+    writeln!(out, "Unknown fields:")?;
+    let width = check
+        .unknown
+        .keys()
+        .map(|(_, field)| field.chars().count())
+        .max()
+        .unwrap_or(0);
+    for ((file, field), unknown) in &check.unknown {
+        writeln!(
+            out,
+            "    {:<12} {:<width$}  {:>6}×  e.g. {}",
+            file,
+            field,
+            unknown.occurrences,
+            unknown.example,
+            width = width
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Package;
+    use serde_json::json;
+
+    fn checked(package: &Package) -> String {
+        let mut out = Vec::new();
+        run(package.path(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn fields_counting_reads_are_known() {
+        let package = Package::new();
+        package
+            .channel(
+                "1",
+                json!({
+                    "id": "1",
+                    "type": 0,
+                    "name": "general",
+                    "guild": {"id": "9", "name": "Server"},
+                }),
+                2,
+            )
+            .channel(
+                "2",
+                json!({"id": "2", "type": 1, "recipients": ["3", {"id": "4", "bot": true}]}),
+                1,
+            );
+
+        let text = checked(&package);
+        assert!(text.contains("Read 2 channel.json files and 3 messages from 2 channels"));
+        assert!(text.ends_with("No unknown fields\n"));
+    }
+
+    #[test]
+    fn unknown_fields_are_listed_with_an_example() {
+        let package = Package::new();
+        package
+            .channel(
+                "1",
+                json!({
+                    "id": "1",
+                    "topic": "x".repeat(100),
+                    "nsfw": false,
+                    "guild": {"id": "9", "icon": "abc"},
+                    "recipients": [{"id": "2", "avatar": null}],
+                }),
+                0,
+            )
+            .channel("2", json!({"id": "2", "nsfw": false}), 0)
+            .file(
+                "messages/c1/messages.json",
+                r#"[{"ID": "1", "Timestamp": "2021-01-01 12:00:00", "Reactions": [1, 2]}]"#,
+            );
+
+        let text = checked(&package);
+        let unknown: Vec<Vec<&str>> = text
+            .lines()
+            .skip_while(|line| *line != "Unknown fields:")
+            .skip(1)
+            .map(|line| line.split_whitespace().take(3).collect())
+            .collect();
+        assert_eq!(
+            unknown,
+            [
+                ["channel.json", "guild.icon", "1×"],
+                ["channel.json", "nsfw", "2×"],
+                ["channel.json", "recipients[].avatar", "1×"],
+                ["channel.json", "topic", "1×"],
+                ["messages", "Reactions", "1×"],
+            ]
+        );
+        // Long examples are cut to their length
+        let topic = format!("\"{}…", "x".repeat(EXAMPLE_LENGTH - 2));
+        assert!(text.contains(&format!("e.g. {}\n", topic)));
+        assert!(text.contains("e.g. false\n"));
+    }
+
+    #[test]
+    fn every_unknown_field_of_an_object_is_found() {
+        let object = json!({"ID": "1", "b": 2, "a": 1, "Contents": ""});
+        let Value::Object(object) = object else {
+            unreachable!()
+        };
+        let mut fields: Vec<String> = unknown_fields::<StrictMessage>(&object)
+            .into_iter()
+            .map(|(field, _)| field)
+            .collect();
+        fields.sort();
+        assert_eq!(fields, ["a", "b"]);
+    }
+}
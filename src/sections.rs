@@ -0,0 +1,181 @@
+//! Sections of grouped text output. Every grouping goes through
+//! `sections`, so section order and the place of pseudo-entries stay the
+//! same from run to run whatever the sort key.
+
+use crate::{Conversation, ConversationType, GroupDmPolicy};
+use clap::ValueEnum;
+
+/// What `--group-by` splits the listed conversations by.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GroupBy {
+    /// Servers, then group DMs, then DMs
+    Type,
+}
+
+/// Heading of the servers missing from `servers/index.json`, listed after
+/// the others of their section.
+pub const DEPARTED_SECTION_TITLE: &str = "Servers you're no longer in";
+
+/// A titled run of conversations, in display order.
+pub struct Section<'a> {
+    pub title: &'static str,
+    pub conversations: Vec<&'a Conversation>,
+}
+
+/// Split `conversations` into the sections of `group_by`, in the fixed order
+/// its documentation gives. Within a section conversations keep their order,
+/// except pseudo-entries such as channels of unknown servers, which always
+/// come last. Empty sections are left out unless `show_empty` is set;
+/// sections the group DM policy rules out are never listed.
+pub fn sections<'a>(
+    conversations: &[&'a Conversation],
+    group_by: GroupBy,
+    group_dms_as: GroupDmPolicy,
+    show_empty: bool,
+) -> Vec<Section<'a>> {
+    let order: &[(ConversationType, &'static str)] = match (group_by, group_dms_as) {
+        (GroupBy::Type, GroupDmPolicy::Separate) => &[
+            (ConversationType::Guild, "Servers"),
+            (ConversationType::GroupDm, "Group DMs"),
+            (ConversationType::Dm, "DMs"),
+        ],
+        (GroupBy::Type, _) => &[
+            (ConversationType::Guild, "Servers"),
+            (ConversationType::Dm, "DMs"),
+        ],
+    };
+
+    order
+        .iter()
+        .map(|&(kind, title)| {
+            let (pseudo, mut members): (Vec<&Conversation>, Vec<&Conversation>) = conversations
+                .iter()
+                .filter(|conversation| conversation.effective_kind(group_dms_as) == kind)
+                .partition(|conversation| conversation.is_unknown_guild());
+            members.extend(pseudo);
+            Section {
+                title,
+                conversations: members,
+            }
+        })
+        .filter(|section| show_empty || !section.conversations.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::OutputFormat;
+    use crate::testing::{channel, dm, guild, rendered, report, view};
+
+    fn group_dm(id: &str, message_count: usize) -> Conversation {
+        let mut conversation = dm(id, "Group", message_count);
+        if let Conversation::DmOrGc { group, .. } = &mut conversation {
+            *group = true;
+        }
+        conversation
+    }
+
+    fn section_totals(
+        conversations: &[Conversation],
+        policy: GroupDmPolicy,
+    ) -> Vec<(&'static str, usize)> {
+        let listed: Vec<&Conversation> = conversations.iter().collect();
+        sections(&listed, GroupBy::Type, policy, true)
+            .into_iter()
+            .map(|section| {
+                let total = section
+                    .conversations
+                    .iter()
+                    .map(|conversation| conversation.message_count())
+                    .sum();
+                (section.title, total)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_policy_places_group_dms_once() {
+        let conversations = [
+            guild(Some("10"), "Server", vec![channel("1", "general", 100)]),
+            group_dm("2", 20),
+            dm("3", "Alice", 3),
+        ];
+        assert_eq!(
+            section_totals(&conversations, GroupDmPolicy::Separate),
+            [("Servers", 100), ("Group DMs", 20), ("DMs", 3)]
+        );
+        assert_eq!(
+            section_totals(&conversations, GroupDmPolicy::Dms),
+            [("Servers", 100), ("DMs", 23)]
+        );
+        assert_eq!(
+            section_totals(&conversations, GroupDmPolicy::Guilds),
+            [("Servers", 120), ("DMs", 3)]
+        );
+
+        for policy in [
+            GroupDmPolicy::Dms,
+            GroupDmPolicy::Separate,
+            GroupDmPolicy::Guilds,
+        ] {
+            let totals = section_totals(&conversations, policy);
+            assert_eq!(totals.iter().map(|(_, total)| total).sum::<usize>(), 123);
+        }
+    }
+
+    #[test]
+    fn unknown_servers_come_last_in_their_section() {
+        let conversations = [
+            guild(None, "(unknown server)", vec![channel("1", "lost", 50)]),
+            guild(Some("10"), "Server", vec![channel("2", "general", 5)]),
+        ];
+        let listed: Vec<&Conversation> = conversations.iter().collect();
+        let sections = sections(&listed, GroupBy::Type, GroupDmPolicy::Separate, false);
+        assert_eq!(sections.len(), 1);
+        let ids: Vec<&str> = sections[0].conversations.iter().map(|c| c.id()).collect();
+        assert_eq!(ids, ["10", "1"]);
+    }
+
+    #[test]
+    fn sections_keep_their_order_whatever_the_input_order() {
+        let conversations = [
+            dm("3", "Alice", 300),
+            group_dm("2", 20),
+            guild(Some("10"), "Server", vec![channel("1", "general", 1)]),
+        ];
+        let titles = |listed: Vec<&Conversation>, show_empty| -> Vec<&'static str> {
+            sections(&listed, GroupBy::Type, GroupDmPolicy::Separate, show_empty)
+                .iter()
+                .map(|section| section.title)
+                .collect()
+        };
+        assert_eq!(
+            titles(conversations.iter().collect(), false),
+            ["Servers", "Group DMs", "DMs"]
+        );
+        assert_eq!(
+            titles(conversations.iter().rev().collect(), false),
+            ["Servers", "Group DMs", "DMs"]
+        );
+
+        // Empty sections are left out, or kept in their place when asked for
+        assert_eq!(titles(vec![&conversations[0]], false), ["DMs"]);
+        assert_eq!(
+            titles(vec![&conversations[0]], true),
+            ["Servers", "Group DMs", "DMs"]
+        );
+    }
+
+    #[test]
+    fn empty_sections_read_none_when_shown() {
+        let report = report(vec![dm("3", "Alice", 3)]);
+        let mut view = view(&report);
+        view.group_by = Some(GroupBy::Type);
+        assert!(rendered(OutputFormat::Text, &view).starts_with("DMs:\n"));
+
+        view.show_empty_sections = true;
+        let text = rendered(OutputFormat::Text, &view);
+        assert!(text.starts_with("Servers:\nnone\n\nGroup DMs:\nnone\n\nDMs:\n"));
+    }
+}
@@ -0,0 +1,42 @@
+use crate::errors::MyError;
+use crate::file_operations::normalize_channel_id;
+use crate::Conversation;
+
+/// Selects a single conversation by ID (with or without the `c` prefix) or by
+/// its case-insensitive display name.
+pub struct Selector {
+    query: String,
+}
+
+impl Selector {
+    pub fn new(query: &str) -> Self {
+        Self {
+            query: query.trim().to_string(),
+        }
+    }
+
+    pub fn matches(&self, conversation: &Conversation) -> bool {
+        let id = normalize_channel_id(&self.query);
+        conversation.id() == id || conversation.name().eq_ignore_ascii_case(&self.query)
+    }
+
+    pub fn select<'a>(
+        &self,
+        conversations: &'a [Conversation],
+    ) -> Result<&'a Conversation, MyError> {
+        let mut matches = conversations.iter().filter(|conv| self.matches(conv));
+        let first = matches
+            .next()
+            .ok_or_else(|| MyError::NoMatchingConversation(self.query.clone()))?;
+
+        let remaining = matches.count();
+        if remaining > 0 {
+            return Err(MyError::AmbiguousSelection(
+                self.query.clone(),
+                remaining + 1,
+            ));
+        }
+
+        Ok(first)
+    }
+}
@@ -0,0 +1,170 @@
+use crate::errors::MyError;
+use crate::humanize::{fmt_count, fmt_duration};
+use crate::metrics::Metric;
+use crate::numbers::fmt_ratio;
+use crate::render::ResultsView;
+use crate::{Conversation, UNKNOWN_GUILD_NAME};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+
+pub const METRIC: Metric = Metric {
+    name: "sessions",
+    definition: "Runs of a conversation's messages in which consecutive messages are less than \
+        --session-gap minutes apart. Per conversation: the number of sessions, messages per \
+        session, and the average time from a session's first to its last message.",
+    affected_by: "Collected with --session-stats or --sort sessions; --session-gap sets the \
+        gap. Channels of one guild are merged into one timeline first. --exclude-system and \
+        --dedupe-messages remove messages before sessions are formed.",
+    edge_cases: "Messages without a usable timestamp are left out. A single message is a \
+        session of zero length. The longest session is the first found among equally long ones.",
+};
+
+/// Runs of messages in one conversation without a gap of `--session-gap`.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SessionStats {
+    pub sessions: usize,
+    pub messages: usize,
+    /// Summed from each session's first to its last message
+    pub total_seconds: i64,
+}
+
+impl SessionStats {
+    pub fn messages_per_session(&self) -> String {
+        fmt_ratio(self.messages as u64, self.sessions as u64, 1)
+    }
+
+    /// From a session's first to its last message, to the second.
+    pub fn average_duration(&self) -> std::time::Duration {
+        let seconds = self.total_seconds.max(0) as u64;
+        let sessions = self.sessions.max(1) as u64;
+        std::time::Duration::from_secs((seconds + sessions / 2) / sessions)
+    }
+}
+
+/// A single session, kept for the longest one across all conversations.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub conversation_id: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub messages: usize,
+}
+
+impl Session {
+    fn duration(&self) -> Duration {
+        self.end - self.start
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SessionSummary {
+    /// Gap that ends a session, in minutes
+    pub gap_minutes: i64,
+    pub by_conversation: HashMap<String, SessionStats>,
+    pub longest: Option<Session>,
+}
+
+/// Split each conversation's messages into sessions. Timestamps need not be
+/// sorted; channels of one guild are merged chronologically here.
+pub fn summarize(timestamps: HashMap<String, Vec<DateTime<Utc>>>, gap: Duration) -> SessionSummary {
+    let mut summary = SessionSummary {
+        gap_minutes: gap.num_minutes(),
+        ..Default::default()
+    };
+
+    // Visit conversations in a fixed order so ties for the longest are stable
+    let mut timestamps: Vec<_> = timestamps.into_iter().collect();
+    timestamps.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+    for (conversation_id, mut timestamps) in timestamps {
+        timestamps.sort_unstable();
+        let mut stats = SessionStats::default();
+        for session in split(&timestamps, gap) {
+            stats.sessions += 1;
+            stats.messages += session.len();
+            let (start, end) = (session[0], session[session.len() - 1]);
+            stats.total_seconds += (end - start).num_seconds();
+
+            let longer = summary
+                .longest
+                .as_ref()
+                .is_none_or(|longest| end - start > longest.duration());
+            if longer {
+                summary.longest = Some(Session {
+                    conversation_id: conversation_id.clone(),
+                    start,
+                    end,
+                    messages: session.len(),
+                });
+            }
+        }
+        summary.by_conversation.insert(conversation_id, stats);
+    }
+
+    summary
+}
+
+/// Split sorted timestamps wherever consecutive ones are `gap` or more apart.
+fn split(timestamps: &[DateTime<Utc>], gap: Duration) -> impl Iterator<Item = &[DateTime<Utc>]> {
+    timestamps.chunk_by(move |a, b| *b - *a < gap)
+}
+
+pub fn write_session_stats(
+    summary: &SessionSummary,
+    view: &ResultsView,
+    out: &mut dyn Write,
+) -> Result<(), MyError> {
+    writeln!(
+        out,
+        "Sessions (messages less than {} apart):",
+        fmt_duration(
+            std::time::Duration::from_secs(summary.gap_minutes.max(0) as u64 * 60),
+            2
+        )
+    )?;
+    for conversation in &view.conversations {
+        let Some(stats) = summary.by_conversation.get(conversation.id()) else {
+            continue;
+        };
+        let name = match conversation {
+            Conversation::Guild {
+                id: None, channels, ..
+            } => format!(
+                "{} in {}",
+                channels.first().map_or("", |channel| channel.name.as_str()),
+                UNKNOWN_GUILD_NAME
+            ),
+            conversation => conversation.name().to_string(),
+        };
+        writeln!(
+            out,
+            "    {}: {}, {} messages and {} on average",
+            name,
+            fmt_count(stats.sessions as u64, "session"),
+            stats.messages_per_session(),
+            fmt_duration(stats.average_duration(), 2)
+        )?;
+    }
+
+    if let Some(ref longest) = summary.longest {
+        let name = view
+            .report
+            .conversations
+            .iter()
+            .find(|conversation| conversation.id() == longest.conversation_id)
+            .map_or(longest.conversation_id.as_str(), |conversation| {
+                conversation.name()
+            });
+        writeln!(
+            out,
+            "Longest session: {} over {} in {} on {}",
+            fmt_count(longest.messages as u64, "message"),
+            fmt_duration(longest.duration().to_std().unwrap_or_default(), 2),
+            name,
+            view.timezone.local_date(&longest.start)
+        )?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
@@ -0,0 +1,218 @@
+//! Static JSON layout for web viewers: an `index.json` listing every
+//! conversation, plus one detail file per conversation that viewers load lazily.
+
+use crate::errors::MyError;
+use crate::messages::message_datetimes;
+use crate::output::Output;
+use crate::render::ResultsView;
+use crate::zone::Zone;
+use crate::{Channel, Conversation};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+pub const SITE_FORMAT: &str = "discord-gdpr-counter/site";
+
+/// Bumped whenever the layout of the emitted files changes incompatibly.
+pub const SITE_SCHEMA_VERSION: u32 = 1;
+
+const DETAIL_FOLDER: &str = "conversations";
+
+#[derive(Serialize)]
+struct Index<'a> {
+    format: &'a str,
+    schema_version: u32,
+    total_messages: usize,
+    conversations: Vec<IndexEntry<'a>>,
+}
+
+#[derive(Serialize)]
+struct IndexEntry<'a> {
+    id: &'a str,
+    kind: &'static str,
+    name: &'a str,
+    message_count: usize,
+    /// Path of the detail file, relative to `index.json`
+    detail: String,
+}
+
+#[derive(Serialize)]
+struct Detail<'a> {
+    id: &'a str,
+    kind: &'static str,
+    name: &'a str,
+    message_count: usize,
+    first_message: Option<DateTime<Utc>>,
+    last_message: Option<DateTime<Utc>>,
+    /// Messages per month, keyed "YYYY-MM", before deduplication and system
+    /// message exclusion; empty for piped reports
+    monthly: BTreeMap<String, usize>,
+    channels: &'a [Channel],
+}
+
+/// Write the viewer layout for the conversations of `view` into `dir`.
+/// Files already there are only replaced with `force`.
+pub fn export_site(view: &ResultsView, dir: &Path, force: bool) -> Result<(), MyError> {
+    fs::create_dir_all(dir.join(DETAIL_FOLDER))?;
+
+    let mut entries = Vec::new();
+    for &conversation in &view.conversations {
+        let kind = conversation.effective_kind(view.group_dms_as).label();
+        let detail_path = format!(
+            "{}/{}-{}.json",
+            DETAIL_FOLDER,
+            kind,
+            file_stem(conversation.id())
+        );
+
+        let activity = monthly_activity(conversation, view.timezone)?;
+        let channels = match conversation {
+            Conversation::Guild { channels, .. } => channels.as_slice(),
+            Conversation::DmOrGc { .. } => &[],
+        };
+
+        let detail = Detail {
+            id: conversation.id(),
+            kind,
+            name: conversation.name(),
+            message_count: conversation.message_count(),
+            first_message: activity.first_message,
+            last_message: activity.last_message,
+            monthly: activity.monthly,
+            channels,
+        };
+        write_json(&dir.join(&detail_path), &detail, force)?;
+
+        entries.push(IndexEntry {
+            id: conversation.id(),
+            kind,
+            name: conversation.name(),
+            message_count: conversation.message_count(),
+            detail: detail_path,
+        });
+    }
+
+    let index = Index {
+        format: SITE_FORMAT,
+        schema_version: SITE_SCHEMA_VERSION,
+        total_messages: entries.iter().map(|entry| entry.message_count).sum(),
+        conversations: entries,
+    };
+    write_json(&dir.join("index.json"), &index, force)
+}
+
+/// A conversation's messages per month, read again from its messages files.
+pub struct MonthlyActivity {
+    /// Keyed "YYYY-MM" in `timezone`'s calendar
+    pub monthly: BTreeMap<String, usize>,
+    pub first_message: Option<DateTime<Utc>>,
+    pub last_message: Option<DateTime<Utc>>,
+}
+
+/// Messages per month, before deduplication and system message exclusion;
+/// empty for piped reports, which have no messages files to read.
+pub fn monthly_activity(
+    conversation: &Conversation,
+    timezone: Zone,
+) -> Result<MonthlyActivity, MyError> {
+    let datetimes = message_datetimes(&conversation.message_files())?;
+    let mut monthly = BTreeMap::new();
+    for datetime in &datetimes {
+        *monthly
+            .entry(timezone.local_date(datetime).format("%Y-%m").to_string())
+            .or_default() += 1;
+    }
+    Ok(MonthlyActivity {
+        monthly,
+        first_message: datetimes.iter().min().copied(),
+        last_message: datetimes.iter().max().copied(),
+    })
+}
+
+/// IDs are snowflakes, but anything else that ends up here must not escape
+/// the detail folder.
+fn file_stem(id: &str) -> String {
+    id.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T, force: bool) -> Result<(), MyError> {
+    let mut out = Output::open(Some(path), force)?;
+    serde_json::to_writer_pretty(&mut out, value)?;
+    out.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{dm, report, view, Package};
+    use serde_json::{json, Value};
+
+    fn read_json(path: &Path) -> Value {
+        serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn every_listed_conversation_has_its_detail_file() {
+        let package = Package::new();
+        package
+            .channel("1", json!({"id": "1", "type": 1, "recipients": ["2"]}), 3)
+            .channel(
+                "3",
+                json!({"id": "3", "name": "general", "guild": {"id": "10", "name": "Server"}}),
+                2,
+            );
+        let report = package.analyze();
+        let dir = tempfile::tempdir().unwrap();
+        export_site(&view(&report), dir.path(), false).unwrap();
+
+        let index = read_json(&dir.path().join("index.json"));
+        assert_eq!(index["format"], SITE_FORMAT);
+        assert_eq!(index["schema_version"], SITE_SCHEMA_VERSION);
+        assert_eq!(index["total_messages"], 5);
+        let entries = index["conversations"].as_array().unwrap();
+        assert_eq!(entries.len(), 2);
+        for entry in entries {
+            let detail = read_json(&dir.path().join(entry["detail"].as_str().unwrap()));
+            for field in ["id", "kind", "name", "message_count"] {
+                assert_eq!(detail[field], entry[field]);
+            }
+            assert_eq!(
+                detail["monthly"],
+                json!({"2021-01": entry["message_count"]})
+            );
+        }
+    }
+
+    #[test]
+    fn an_earlier_export_is_only_replaced_with_force() {
+        let report = report(vec![dm("1", "Alice", 3)]);
+        let dir = tempfile::tempdir().unwrap();
+        export_site(&view(&report), dir.path(), false).unwrap();
+        assert!(matches!(
+            export_site(&view(&report), dir.path(), false),
+            Err(MyError::OutputExists(_))
+        ));
+        export_site(&view(&report), dir.path(), true).unwrap();
+        assert_eq!(
+            read_json(&dir.path().join("index.json"))["total_messages"],
+            3
+        );
+    }
+
+    #[test]
+    fn detail_files_stay_in_their_folder() {
+        assert_eq!(file_stem("123"), "123");
+        assert_eq!(file_stem("../etc/passwd"), "___etc_passwd");
+        assert_eq!(file_stem("a b\\c"), "a_b_c");
+    }
+}
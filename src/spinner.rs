@@ -0,0 +1,50 @@
+//! The progress spinner of `--progress bar`. Builds without the `progress`
+//! feature count silently, and `--progress bar` shows nothing.
+
+use crate::errors::MyError;
+#[cfg(feature = "progress")]
+use indicatif::{ProgressBar, ProgressStyle};
+#[cfg(feature = "progress")]
+use std::time::Duration;
+
+pub struct Spinner {
+    #[cfg(feature = "progress")]
+    bar: ProgressBar,
+}
+
+#[cfg(feature = "progress")]
+impl Spinner {
+    /// Start spinning on stderr, or a spinner that shows nothing.
+    pub fn start(show: bool) -> Result<Self, MyError> {
+        let bar = match show {
+            true => ProgressBar::new_spinner(),
+            false => ProgressBar::hidden(),
+        };
+        bar.set_style(
+            ProgressStyle::default_spinner()
+                .template("{spinner} {msg}")
+                .map_err(|e| MyError::ProgressBar(e.to_string()))?,
+        );
+        bar.enable_steady_tick(Duration::from_millis(100));
+        Ok(Self { bar })
+    }
+
+    pub fn set_message(&self, message: String) {
+        self.bar.set_message(message);
+    }
+
+    pub fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+}
+
+#[cfg(not(feature = "progress"))]
+impl Spinner {
+    pub fn start(_show: bool) -> Result<Self, MyError> {
+        Ok(Self {})
+    }
+
+    pub fn set_message(&self, _message: String) {}
+
+    pub fn finish(&self) {}
+}
@@ -0,0 +1,300 @@
+//! `--split-output`: the results as one file per guild plus one for all DMs,
+//! for archiving. Each file is rendered like stdout, with the run's header
+//! and footer around its own conversations, and `manifest.json` maps every
+//! conversation ID to the file it ended up in.
+
+use crate::errors::MyError;
+use crate::output::Output;
+use crate::render::{render, OutputFormat, ResultsView};
+use crate::Conversation;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+pub const SPLIT_FORMAT: &str = "discord-gdpr-counter/split";
+
+/// Bumped whenever the layout of the manifest changes incompatibly.
+pub const SPLIT_SCHEMA_VERSION: u32 = 1;
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// Characters of a conversation name kept in its file name.
+const NAME_LIMIT: usize = 60;
+
+/// Characters of the ID appended to every guild's file name.
+const ID_SUFFIX_LENGTH: usize = 6;
+
+/// Device names Windows reserves in any directory, with or without extension.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+#[derive(Serialize)]
+struct Manifest<'a> {
+    format: &'a str,
+    schema_version: u32,
+    conversations: Vec<ManifestEntry<'a>>,
+}
+
+#[derive(Serialize)]
+struct ManifestEntry<'a> {
+    id: &'a str,
+    kind: &'static str,
+    name: &'a str,
+    /// Path of the file holding the conversation, relative to the manifest
+    file: String,
+}
+
+/// Write the conversations of `view` in `format` into `dir`: one file per
+/// guild, one for all DMs and group DMs, and one for channels of unknown
+/// servers, which have no guild to be filed under. Files already there are
+/// only replaced with `force`.
+pub fn split_output(
+    view: &ResultsView,
+    format: OutputFormat,
+    dir: &Path,
+    force: bool,
+) -> Result<(), MyError> {
+    if format == OutputFormat::Internal {
+        return Err(MyError::Split(
+            "the internal format always holds the whole report".to_string(),
+        ));
+    }
+    fs::create_dir_all(dir)?;
+
+    let mut files: Vec<(String, Vec<&Conversation>)> = Vec::new();
+    let mut dms = Vec::new();
+    let mut unknown = Vec::new();
+    for &conversation in &view.conversations {
+        match conversation {
+            Conversation::DmOrGc { .. } => dms.push(conversation),
+            Conversation::Guild { .. } if conversation.is_unknown_guild() => {
+                unknown.push(conversation)
+            }
+            Conversation::Guild { .. } => files.push((
+                format!(
+                    "{}-{}",
+                    sanitize(conversation.name()),
+                    id_suffix(conversation.id())
+                ),
+                vec![conversation],
+            )),
+        }
+    }
+    for (stem, conversations) in [("dms", dms), ("unknown-servers", unknown)] {
+        if !conversations.is_empty() {
+            files.push((stem.to_string(), conversations));
+        }
+    }
+
+    let mut entries = Vec::new();
+    for (stem, conversations) in files {
+        let file = format!("{}.{}", stem, format.extension());
+        let part = ResultsView {
+            conversations: conversations.clone(),
+            ..view.clone()
+        };
+        let mut out = Output::open(Some(&dir.join(&file)), force)?;
+        render(format, &part, &mut out)?;
+        out.finish()?;
+
+        entries.extend(conversations.into_iter().map(|conversation| ManifestEntry {
+            id: conversation.id(),
+            kind: conversation.effective_kind(view.group_dms_as).label(),
+            name: conversation.name(),
+            file: file.clone(),
+        }));
+    }
+
+    let manifest = Manifest {
+        format: SPLIT_FORMAT,
+        schema_version: SPLIT_SCHEMA_VERSION,
+        conversations: entries,
+    };
+    let mut out = Output::open(Some(&dir.join(MANIFEST_FILE)), force)?;
+    serde_json::to_writer_pretty(&mut out, &manifest)?;
+    out.finish()
+}
+
+/// A file name stem from a conversation name that is valid on every common
+/// file system: path separators, characters Windows forbids and control
+/// characters become `_`, reserved device names get a `_` before any
+/// extension, and leading and trailing dots and spaces are dropped. Other
+/// Unicode is kept.
+fn sanitize(name: &str) -> String {
+    let stem: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .take(NAME_LIMIT)
+        .collect();
+    let stem = stem.trim_matches(['.', ' ']).to_string();
+
+    let device = stem.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(device.trim_end()))
+    {
+        // Windows goes by the part before the first dot alone
+        return format!("{}_{}", device, &stem[device.len()..]);
+    }
+    match stem.is_empty() {
+        true => "conversation".to_string(),
+        false => stem,
+    }
+}
+
+/// The end of a snowflake, enough to tell guilds with the same name apart.
+fn id_suffix(id: &str) -> &str {
+    let start = id
+        .char_indices()
+        .rev()
+        .nth(ID_SUFFIX_LENGTH - 1)
+        .map_or(0, |(index, _)| index);
+    &id[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{channel, dm, guild, report, view};
+    use serde_json::Value;
+
+    #[test]
+    fn reserved_windows_names_are_escaped() {
+        for (name, stem) in [
+            ("CON", "CON_"),
+            ("con", "con_"),
+            ("Com1", "Com1_"),
+            ("NUL ", "NUL_"),
+            ("LPT9.log", "LPT9_.log"),
+            ("aux.tar.gz", "aux_.tar.gz"),
+            ("PRN .txt", "PRN _.txt"),
+        ] {
+            assert_eq!(sanitize(name), stem, "{:?}", name);
+        }
+        // Only whole device names are reserved
+        assert_eq!(sanitize("CONSOLE"), "CONSOLE");
+        assert_eq!(sanitize("COM10"), "COM10");
+        assert_eq!(sanitize("my con"), "my con");
+    }
+
+    #[test]
+    fn separators_and_forbidden_characters_are_replaced() {
+        assert_eq!(sanitize("a/b\\c"), "a_b_c");
+        assert_eq!(
+            sanitize("what? <this> | \"that\": *"),
+            "what_ _this_ _ _that__ _"
+        );
+        assert_eq!(sanitize("line\nbreak\t"), "line_break_");
+        assert_eq!(sanitize("../../etc"), "_.._etc");
+        assert_eq!(sanitize(" .hidden. "), "hidden");
+    }
+
+    #[test]
+    fn unicode_is_kept_and_cut_by_characters() {
+        assert_eq!(sanitize("Café ☕ 日本"), "Café ☕ 日本");
+        let long = "日".repeat(NAME_LIMIT + 10);
+        assert_eq!(sanitize(&long).chars().count(), NAME_LIMIT);
+        for empty in ["", "...", "  ", ". ."] {
+            assert_eq!(sanitize(empty), "conversation", "{:?}", empty);
+        }
+    }
+
+    #[test]
+    fn id_suffixes_are_the_end_of_the_id() {
+        assert_eq!(id_suffix("81384788765712384"), "712384");
+        assert_eq!(id_suffix("123"), "123");
+    }
+
+    #[test]
+    fn the_manifest_matches_the_written_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let report = report(vec![
+            guild(Some("100001"), "Srv/One", vec![channel("1", "general", 5)]),
+            guild(Some("200001"), "Srv/One", vec![channel("2", "general", 4)]),
+            guild(None, "(unknown server)", vec![channel("3", "lost", 3)]),
+            dm("4", "Alice", 2),
+            dm("5", "CON", 1),
+        ]);
+        split_output(&view(&report), OutputFormat::Json, dir.path(), false).unwrap();
+
+        let manifest: Value =
+            serde_json::from_str(&fs::read_to_string(dir.path().join(MANIFEST_FILE)).unwrap())
+                .unwrap();
+        assert_eq!(manifest["format"], SPLIT_FORMAT);
+        let mut by_file: Vec<(String, Vec<String>)> = Vec::new();
+        for entry in manifest["conversations"].as_array().unwrap() {
+            let file = entry["file"].as_str().unwrap().to_string();
+            let id = entry["id"].as_str().unwrap().to_string();
+            match by_file.iter_mut().find(|(known, _)| *known == file) {
+                Some((_, ids)) => ids.push(id),
+                None => by_file.push((file, vec![id])),
+            }
+        }
+        assert_eq!(
+            by_file,
+            [
+                (
+                    "Srv_One-100001.json".to_string(),
+                    vec!["100001".to_string()]
+                ),
+                (
+                    "Srv_One-200001.json".to_string(),
+                    vec!["200001".to_string()]
+                ),
+                (
+                    "dms.json".to_string(),
+                    vec!["4".to_string(), "5".to_string()]
+                ),
+                ("unknown-servers.json".to_string(), vec!["3".to_string()]),
+            ]
+        );
+
+        let mut written: Vec<String> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name != MANIFEST_FILE)
+            .collect();
+        written.sort();
+        let mut listed: Vec<&String> = by_file.iter().map(|(file, _)| file).collect();
+        listed.sort();
+        assert_eq!(written.iter().collect::<Vec<_>>(), listed);
+        // Each file holds the conversations the manifest files under it
+        for (file, ids) in &by_file {
+            let document: Value =
+                serde_json::from_str(&fs::read_to_string(dir.path().join(file)).unwrap()).unwrap();
+            let held: Vec<&str> = document["conversations"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|conversation| conversation["id"].as_str().unwrap())
+                .collect();
+            assert_eq!(held, *ids);
+        }
+    }
+
+    #[test]
+    fn earlier_files_are_only_replaced_with_force() {
+        let dir = tempfile::tempdir().unwrap();
+        let report = report(vec![dm("1", "Alice", 2)]);
+        fs::write(dir.path().join("dms.json"), "mine").unwrap();
+        assert!(matches!(
+            split_output(&view(&report), OutputFormat::Json, dir.path(), false),
+            Err(MyError::OutputExists(_))
+        ));
+        assert_eq!(
+            fs::read_to_string(dir.path().join("dms.json")).unwrap(),
+            "mine"
+        );
+
+        split_output(&view(&report), OutputFormat::Json, dir.path(), true).unwrap();
+        assert!(fs::read_to_string(dir.path().join("dms.json"))
+            .unwrap()
+            .contains("Alice"));
+    }
+}
@@ -0,0 +1,110 @@
+//! Results as rows of a SQLite database that accumulates exports over time.
+//! Rows are keyed by ID and export date, so each re-export adds a snapshot
+//! and re-running one replaces that day's rows.
+
+use crate::errors::MyError;
+use crate::render::ResultsView;
+use crate::Conversation;
+use chrono::NaiveDate;
+use rusqlite::{params, Connection};
+use std::path::Path;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS guilds (
+    id TEXT NOT NULL,
+    export_date TEXT NOT NULL,
+    name TEXT NOT NULL,
+    message_count INTEGER NOT NULL,
+    PRIMARY KEY (id, export_date)
+);
+CREATE TABLE IF NOT EXISTS channels (
+    id TEXT NOT NULL,
+    export_date TEXT NOT NULL,
+    -- NULL for channels of servers the package does not identify
+    guild_id TEXT,
+    name TEXT NOT NULL,
+    message_count INTEGER NOT NULL,
+    PRIMARY KEY (id, export_date),
+    FOREIGN KEY (guild_id, export_date) REFERENCES guilds (id, export_date)
+);
+CREATE TABLE IF NOT EXISTS dms (
+    id TEXT NOT NULL,
+    export_date TEXT NOT NULL,
+    name TEXT NOT NULL,
+    message_count INTEGER NOT NULL,
+    group_dm INTEGER NOT NULL,
+    PRIMARY KEY (id, export_date)
+);
+";
+
+/// Upsert the conversations of `view` into the database at `path`, creating
+/// it and its tables as needed. Everything is written in one transaction.
+pub fn export_sqlite(
+    view: &ResultsView,
+    path: &Path,
+    export_date: NaiveDate,
+) -> Result<(), MyError> {
+    let mut connection = Connection::open(path)?;
+    connection.execute_batch("PRAGMA foreign_keys = ON;")?;
+    connection.execute_batch(SCHEMA)?;
+
+    let export_date = export_date.format("%Y-%m-%d").to_string();
+    let transaction = connection.transaction()?;
+    {
+        let mut upsert_guild = transaction.prepare(
+            "INSERT INTO guilds (id, export_date, name, message_count) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (id, export_date)
+             DO UPDATE SET name = excluded.name, message_count = excluded.message_count",
+        )?;
+        let mut upsert_channel = transaction.prepare(
+            "INSERT INTO channels (id, export_date, guild_id, name, message_count)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT (id, export_date)
+             DO UPDATE SET guild_id = excluded.guild_id, name = excluded.name,
+                 message_count = excluded.message_count",
+        )?;
+        let mut upsert_dm = transaction.prepare(
+            "INSERT INTO dms (id, export_date, name, message_count, group_dm)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT (id, export_date)
+             DO UPDATE SET name = excluded.name, message_count = excluded.message_count,
+                 group_dm = excluded.group_dm",
+        )?;
+
+        for &conversation in &view.conversations {
+            match conversation {
+                Conversation::DmOrGc {
+                    id,
+                    name,
+                    message_count,
+                    group,
+                    ..
+                } => {
+                    upsert_dm.execute(params![id, export_date, name, message_count, group])?;
+                }
+                Conversation::Guild {
+                    id,
+                    name,
+                    message_count,
+                    channels,
+                    ..
+                } => {
+                    if let Some(id) = id {
+                        upsert_guild.execute(params![id, export_date, name, message_count])?;
+                    }
+                    for channel in channels {
+                        upsert_channel.execute(params![
+                            channel.id,
+                            export_date,
+                            id,
+                            channel.name,
+                            channel.message_count
+                        ])?;
+                    }
+                }
+            }
+        }
+    }
+    transaction.commit()?;
+    Ok(())
+}
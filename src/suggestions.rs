@@ -0,0 +1,184 @@
+//! Hints at flags worth trying next, picked from what a plain run found.
+
+use std::fmt;
+
+/// Most suggestions shown after a run.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Share of all messages above which guilds are said to dominate, in percent.
+const GUILD_DOMINANCE_PERCENT: u64 = 60;
+
+/// Conversations hidden by the default limit before `--all` is suggested.
+const MANY_HIDDEN: usize = 50;
+
+/// Years between first and last message before `--breadth` is suggested.
+const LONG_SPAN_YEARS: i32 = 3;
+
+/// What the suggestions are based on.
+#[derive(Debug, Default, Clone)]
+pub struct RunSummary {
+    pub guild_messages: u64,
+    pub total_messages: u64,
+    /// Conversations cut off by the default terminal limit
+    pub hidden: usize,
+    /// Calendar years of the first and last counted message
+    pub years: Option<(i32, i32)>,
+    /// Whether the run already filtered by conversation type
+    pub type_filtered: bool,
+    /// Whether the run already showed the per-year breadth table
+    pub breadth_shown: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Suggestion {
+    /// Guilds hold most messages, hiding the personal chats
+    DirectMessages {
+        guild_percent: u64,
+    },
+    ShowAll {
+        hidden: usize,
+    },
+    Breadth {
+        years: i32,
+    },
+}
+
+impl fmt::Display for Suggestion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::DirectMessages { guild_percent } => write!(
+                f,
+                "Servers hold {}% of your messages; try --conversation-type dm for personal chats",
+                guild_percent
+            ),
+            Self::ShowAll { hidden } => write!(
+                f,
+                "{} conversations were hidden; try --all to list every one",
+                hidden
+            ),
+            Self::Breadth { years } => write!(
+                f,
+                "Your messages span {} years; try --breadth to see how active you were each year",
+                years
+            ),
+        }
+    }
+}
+
+/// Suggestions that apply to the run, most useful first.
+pub fn suggest(summary: &RunSummary) -> Vec<Suggestion> {
+    let mut suggestions = Vec::new();
+
+    if !summary.type_filtered && summary.total_messages > 0 {
+        let guild_percent = summary.guild_messages * 100 / summary.total_messages;
+        if guild_percent > GUILD_DOMINANCE_PERCENT {
+            suggestions.push(Suggestion::DirectMessages { guild_percent });
+        }
+    }
+    if summary.hidden > MANY_HIDDEN {
+        suggestions.push(Suggestion::ShowAll {
+            hidden: summary.hidden,
+        });
+    }
+    if let (false, Some((first, last))) = (summary.breadth_shown, summary.years) {
+        let years = last - first + 1;
+        if years > LONG_SPAN_YEARS {
+            suggestions.push(Suggestion::Breadth { years });
+        }
+    }
+
+    suggestions.truncate(MAX_SUGGESTIONS);
+    suggestions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A run that triggers no rule.
+    fn quiet_run() -> RunSummary {
+        RunSummary {
+            guild_messages: 50,
+            total_messages: 100,
+            hidden: 0,
+            years: Some((2020, 2021)),
+            type_filtered: false,
+            breadth_shown: false,
+        }
+    }
+
+    #[test]
+    fn a_run_without_findings_gets_no_suggestions() {
+        assert_eq!(suggest(&quiet_run()), []);
+        assert_eq!(suggest(&RunSummary::default()), []);
+    }
+
+    #[test]
+    fn dominating_guilds_suggest_direct_messages() {
+        let dominated = RunSummary {
+            guild_messages: 61,
+            ..quiet_run()
+        };
+        assert_eq!(
+            suggest(&dominated),
+            [Suggestion::DirectMessages { guild_percent: 61 }]
+        );
+        let even = RunSummary {
+            guild_messages: 60,
+            ..quiet_run()
+        };
+        assert_eq!(suggest(&even), []);
+        let filtered = RunSummary {
+            type_filtered: true,
+            ..dominated
+        };
+        assert_eq!(suggest(&filtered), []);
+    }
+
+    #[test]
+    fn many_hidden_conversations_suggest_all() {
+        let hidden = |hidden| RunSummary {
+            hidden,
+            ..quiet_run()
+        };
+        assert_eq!(suggest(&hidden(51)), [Suggestion::ShowAll { hidden: 51 }]);
+        assert_eq!(suggest(&hidden(50)), []);
+    }
+
+    #[test]
+    fn a_long_span_suggests_breadth() {
+        let span = |years| RunSummary {
+            years: Some(years),
+            ..quiet_run()
+        };
+        assert_eq!(
+            suggest(&span((2018, 2021))),
+            [Suggestion::Breadth { years: 4 }]
+        );
+        assert_eq!(suggest(&span((2019, 2021))), []);
+        let shown = RunSummary {
+            breadth_shown: true,
+            ..span((2010, 2021))
+        };
+        assert_eq!(suggest(&shown), []);
+    }
+
+    #[test]
+    fn every_rule_at_once_keeps_their_order() {
+        let everything = RunSummary {
+            guild_messages: 90,
+            total_messages: 100,
+            hidden: 80,
+            years: Some((2016, 2021)),
+            ..quiet_run()
+        };
+        assert_eq!(
+            suggest(&everything),
+            [
+                Suggestion::DirectMessages { guild_percent: 90 },
+                Suggestion::ShowAll { hidden: 80 },
+                Suggestion::Breadth { years: 6 },
+            ]
+        );
+    }
+}
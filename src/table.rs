@@ -0,0 +1,131 @@
+//! Conversations as an aligned table. Column widths are measured in
+//! terminal cells, so names with emoji or CJK characters stay aligned.
+
+use crate::color::{Style, TOP_CONVERSATIONS};
+use crate::errors::MyError;
+use crate::render::{display_name, ResultsView};
+use crate::{Conversation, ConversationType};
+use comfy_table::presets::UTF8_FULL_CONDENSED;
+use comfy_table::{Cell, CellAlignment, Table};
+use std::io::Write;
+
+/// Indent of channel rows below their guild.
+const CHANNEL_INDENT: &str = "  ";
+
+pub fn render_table(view: &ResultsView, out: &mut dyn Write) -> Result<(), MyError> {
+    let count = |n: usize| view.counts.grouped(n as u64);
+    // As in the text listing: conversations of the total, channels of their guild
+    let share = |n: usize, total: u64| view.counts.share(n as u64, total);
+
+    // Notes get a column of their own, as a cell is styled all alike
+    let notes = !view.report.notes.is_empty();
+    let mut header = vec!["Type", "Name", "Messages", "Share"];
+    if notes {
+        header.push("Note");
+    }
+
+    let mut table = Table::new();
+    table.load_preset(UTF8_FULL_CONDENSED).set_header(header);
+    // Styled by the palette alone, not by whether stdout is a terminal
+    match view.palette.enabled {
+        true => table.enforce_styling(),
+        false => table.force_no_tty(),
+    };
+
+    for (index, &conversation) in view.conversations.iter().enumerate() {
+        let kind = match conversation.effective_kind(view.group_dms_as) {
+            ConversationType::Dm => "DM",
+            ConversationType::GroupDm => "Group DM",
+            ConversationType::Guild => "Server",
+        };
+        let message_count = conversation.message_count();
+        let name = display_name(conversation);
+        let name = match conversation {
+            _ if index < TOP_CONVERSATIONS => view.palette.cell(Style::Top, name),
+            Conversation::Guild { .. } => view.palette.cell(Style::Guild, name),
+            Conversation::DmOrGc { .. } => Cell::new(name),
+        };
+        let mut row = vec![
+            Cell::new(kind),
+            name,
+            view.palette.cell(Style::Count, count(message_count)),
+            Cell::new(share(message_count, view.share_total)),
+        ];
+        if notes {
+            let note = view.report.note(conversation.id()).unwrap_or_default();
+            row.push(view.palette.cell(Style::Dim, note));
+        }
+        table.add_row(row);
+
+        if let (true, Conversation::Guild { channels, .. }) = (view.channels, conversation) {
+            if conversation.is_unknown_guild() {
+                continue;
+            }
+            for channel in channels {
+                table.add_row([
+                    Cell::new("Channel"),
+                    view.palette.cell(
+                        Style::Channel,
+                        format!("{}#{}", CHANNEL_INDENT, channel.name),
+                    ),
+                    view.palette
+                        .cell(Style::Count, count(channel.message_count)),
+                    Cell::new(share(channel.message_count, message_count as u64)),
+                ]);
+            }
+        }
+    }
+
+    for index in [2, 3] {
+        if let Some(column) = table.column_mut(index) {
+            column.set_cell_alignment(CellAlignment::Right);
+        }
+    }
+    writeln!(out, "{}", table)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::color::Palette;
+    use crate::render::OutputFormat;
+    use crate::testing::{channel, dm, guild, rendered, report, view};
+
+    #[test]
+    fn channel_shares_are_of_their_guild() {
+        let report = report(vec![
+            dm("1", "Alice", 60),
+            guild(
+                Some("10"),
+                "Server",
+                vec![channel("11", "general", 30), channel("12", "memes", 10)],
+            ),
+        ]);
+        let mut view = view(&report);
+        view.channels = true;
+        let table = rendered(OutputFormat::Table, &view);
+        let share = |name: &str| -> String {
+            let row = table.lines().find(|line| line.contains(name)).unwrap();
+            let cells = row.trim_end_matches('│');
+            cells.rsplit('┆').next().unwrap().trim().to_string()
+        };
+        assert_eq!(share("Alice"), "60.0%");
+        assert_eq!(share("Server"), "40.0%");
+        assert_eq!(share("general"), "75.0%");
+        assert_eq!(share("memes"), "25.0%");
+    }
+
+    #[test]
+    fn colors_follow_the_palette() {
+        let report = report(vec![guild(
+            Some("10"),
+            "Server",
+            vec![channel("11", "general", 3)],
+        )]);
+        let mut view = view(&report);
+        view.channels = true;
+        assert!(!rendered(OutputFormat::Table, &view).contains('\x1b'));
+        view.palette = Palette { enabled: true };
+        assert!(rendered(OutputFormat::Table, &view).contains('\x1b'));
+    }
+}
@@ -0,0 +1,288 @@
+//! User-supplied output: `--template` writes one line per conversation from a
+//! pattern such as `{name}\t{count}`, and `--template-file` renders a whole
+//! document with MiniJinja, whose syntax is that of Jinja2 and Tera. Both
+//! are checked before the package is read, so a typo costs no counting.
+
+use crate::errors::MyError;
+use crate::render::ResultsView;
+use crate::Conversation;
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Clone, Copy)]
+enum Field {
+    Name,
+    Id,
+    Kind,
+    Count,
+    Rank,
+    Percent,
+    /// How many channels a guild has
+    Channels,
+}
+
+/// Names of the fields a `--template` line can hold.
+const FIELDS: &[(&str, Field)] = &[
+    ("name", Field::Name),
+    ("id", Field::Id),
+    ("kind", Field::Kind),
+    ("count", Field::Count),
+    ("rank", Field::Rank),
+    ("percent", Field::Percent),
+    ("channels", Field::Channels),
+];
+
+pub enum Template {
+    Line(LineTemplate),
+    #[cfg(feature = "templates")]
+    File(FileTemplate),
+}
+
+impl Template {
+    pub fn render(&self, view: &ResultsView, out: &mut dyn Write) -> Result<(), MyError> {
+        let conversations = template_conversations(view);
+        match self {
+            Self::Line(template) => {
+                for conversation in &conversations {
+                    writeln!(out, "{}", template.fill(conversation))?;
+                }
+                Ok(())
+            }
+            #[cfg(feature = "templates")]
+            Self::File(template) => template.render(&conversations, view, out),
+        }
+    }
+}
+
+/// A conversation as templates see it. Counts and percentages are text, so
+/// --bucket-counts applies to them as to every other output.
+#[derive(Serialize)]
+struct TemplateConversation<'a> {
+    /// Position in display order, from 1
+    rank: usize,
+    id: &'a str,
+    name: &'a str,
+    kind: &'static str,
+    count: String,
+    /// Share of every counted message, without the sign
+    percent: String,
+    /// Guild channels, most messages first; empty for DMs
+    channels: Vec<TemplateChannel<'a>>,
+}
+
+#[derive(Serialize)]
+struct TemplateChannel<'a> {
+    id: &'a str,
+    name: &'a str,
+    count: String,
+    percent: String,
+}
+
+fn template_conversations<'a>(view: &ResultsView<'a>) -> Vec<TemplateConversation<'a>> {
+    let total = total_messages(view);
+    let counts = view.counts;
+    view.conversations
+        .iter()
+        .enumerate()
+        .map(|(index, &conversation)| TemplateConversation {
+            rank: index + 1,
+            id: conversation.id(),
+            name: conversation.name(),
+            kind: conversation.effective_kind(view.group_dms_as).label(),
+            count: counts.format(conversation.message_count() as u64),
+            percent: counts.percent(conversation.message_count() as u64, total),
+            channels: match conversation {
+                Conversation::Guild { channels, .. } => channels
+                    .iter()
+                    .map(|channel| TemplateChannel {
+                        id: &channel.id,
+                        name: &channel.name,
+                        count: counts.format(channel.message_count as u64),
+                        percent: counts.percent(channel.message_count as u64, total),
+                    })
+                    .collect(),
+                Conversation::DmOrGc { .. } => Vec::new(),
+            },
+        })
+        .collect()
+}
+
+/// Messages across every counted conversation, before filtering.
+fn total_messages(view: &ResultsView) -> u64 {
+    view.report
+        .conversations
+        .iter()
+        .map(|conversation| conversation.message_count() as u64)
+        .sum()
+}
+
+enum Segment {
+    Text(String),
+    Field(Field),
+}
+
+/// A `--template` pattern: text with `{field}` placeholders, where `{{` and
+/// `}}` stand for braces and `\t`, `\n` and `\\` for a tab, a line break and
+/// a backslash.
+pub struct LineTemplate {
+    segments: Vec<Segment>,
+}
+
+impl LineTemplate {
+    pub fn parse(pattern: &str) -> Result<Self, MyError> {
+        let error = |column: usize, message: &str| {
+            MyError::Template(format!("--template column {}: {}", column + 1, message))
+        };
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut chars = pattern.chars().enumerate().peekable();
+        while let Some((column, c)) = chars.next() {
+            let next = chars.peek().map(|&(_, next)| next);
+            match (c, next) {
+                ('\\', Some('t')) => text.push('\t'),
+                ('\\', Some('n')) => text.push('\n'),
+                ('\\', Some('\\')) => text.push('\\'),
+                ('{', Some('{')) => text.push('{'),
+                ('}', Some('}')) => text.push('}'),
+                ('{', _) => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some((_, '}')) => break,
+                            Some((_, c)) => name.push(c),
+                            None => return Err(error(column, "'{' is never closed")),
+                        }
+                    }
+                    let field = FIELDS
+                        .iter()
+                        .find(|(field, _)| *field == name.trim())
+                        .map(|&(_, field)| field)
+                        .ok_or_else(|| {
+                            let names: Vec<&str> = FIELDS.iter().map(|(name, _)| *name).collect();
+                            error(
+                                column + 1,
+                                &format!(
+                                    "unknown field '{}'; expected one of {}",
+                                    name,
+                                    names.join(", ")
+                                ),
+                            )
+                        })?;
+                    segments.push(Segment::Text(std::mem::take(&mut text)));
+                    segments.push(Segment::Field(field));
+                    continue;
+                }
+                ('}', _) => {
+                    return Err(error(
+                        column,
+                        "'}' without an opening '{'; write '}}' for a brace",
+                    ))
+                }
+                (c, _) => {
+                    text.push(c);
+                    continue;
+                }
+            }
+            // The second character of an escape or a doubled brace
+            chars.next();
+        }
+        segments.push(Segment::Text(text));
+        Ok(Self { segments })
+    }
+
+    fn fill(&self, conversation: &TemplateConversation) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => text.clone(),
+                Segment::Field(Field::Name) => conversation.name.to_string(),
+                Segment::Field(Field::Id) => conversation.id.to_string(),
+                Segment::Field(Field::Kind) => conversation.kind.to_string(),
+                Segment::Field(Field::Count) => conversation.count.clone(),
+                Segment::Field(Field::Rank) => conversation.rank.to_string(),
+                Segment::Field(Field::Percent) => conversation.percent.clone(),
+                Segment::Field(Field::Channels) => conversation.channels.len().to_string(),
+            })
+            .collect()
+    }
+}
+
+/// A `--template-file`, rendered once with `conversations` and `total`.
+#[cfg(feature = "templates")]
+pub struct FileTemplate {
+    name: String,
+    source: String,
+}
+
+#[cfg(feature = "templates")]
+impl FileTemplate {
+    pub fn load(path: &Path) -> Result<Self, MyError> {
+        let template = Self {
+            name: path.display().to_string(),
+            source: std::fs::read_to_string(path)?,
+        };
+        template.environment()?;
+        Ok(template)
+    }
+
+    fn environment(&self) -> Result<minijinja::Environment<'_>, MyError> {
+        let mut environment = minijinja::Environment::new();
+        environment
+            .add_template(&self.name, &self.source)
+            .map_err(|error| self.error(error))?;
+        Ok(environment)
+    }
+
+    fn render(
+        &self,
+        conversations: &[TemplateConversation],
+        view: &ResultsView,
+        out: &mut dyn Write,
+    ) -> Result<(), MyError> {
+        let environment = self.environment()?;
+        let template = environment
+            .get_template(&self.name)
+            .map_err(|error| self.error(error))?;
+        let context = minijinja::context! {
+            conversations => conversations,
+            total => view.counts.format(total_messages(view)),
+        };
+        let rendered = template
+            .render(context)
+            .map_err(|error| self.error(error))?;
+        out.write_all(rendered.as_bytes())?;
+        Ok(())
+    }
+
+    /// The error with the line and column it points at, where known.
+    fn error(&self, error: minijinja::Error) -> MyError {
+        let detail = error
+            .detail()
+            .map_or_else(|| error.kind().to_string(), str::to_string);
+        let position = match (error.line(), error.range()) {
+            (Some(line), Some(range)) => {
+                let line_start = self.source[..range.start].rfind('\n').map_or(0, |i| i + 1);
+                let column = self.source[line_start..range.start].chars().count() + 1;
+                format!(" line {}, column {}", line, column)
+            }
+            (Some(line), None) => format!(" line {}", line),
+            _ => String::new(),
+        };
+        MyError::Template(format!("{}{}: {}", self.name, position, detail))
+    }
+}
+
+/// The template of `--template` or `--template-file`, checked, or `None`
+/// for the built-in formats.
+pub fn load(pattern: Option<&str>, file: Option<&Path>) -> Result<Option<Template>, MyError> {
+    #[cfg(feature = "templates")]
+    if let Some(path) = file {
+        return FileTemplate::load(path).map(|template| Some(Template::File(template)));
+    }
+    #[cfg(not(feature = "templates"))]
+    let _ = file;
+    pattern
+        .map(|pattern| LineTemplate::parse(pattern).map(Template::Line))
+        .transpose()
+}
@@ -0,0 +1,182 @@
+//! Fixtures for unit tests: conversations, reports and views built in
+//! memory, and data packages written to a temporary folder.
+
+use crate::analysis::{analyze, AnalysisOptions};
+use crate::buckets::CountStyle;
+use crate::color::Palette;
+use crate::render::{render, OutputFormat, ResultsView, TreeGlyphs};
+use crate::report::Report;
+use crate::width::NameWidth;
+use crate::zone::Zone;
+use crate::{Channel, Conversation, GroupDmPolicy};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+pub fn dm(id: &str, name: &str, message_count: usize) -> Conversation {
+    Conversation::DmOrGc {
+        id: id.to_string(),
+        name: name.to_string(),
+        message_count,
+        excluded_system: 0,
+        empty_messages: 0,
+        group: false,
+        recipients: Vec::new(),
+        is_bot: None,
+        messages_file: PathBuf::new(),
+    }
+}
+
+pub fn channel(id: &str, name: &str, message_count: usize) -> Channel {
+    Channel {
+        id: id.to_string(),
+        name: name.to_string(),
+        message_count,
+        first_message: None,
+        last_message: None,
+        merged_ids: Vec::new(),
+        message_files: Vec::new(),
+    }
+}
+
+/// A guild holding `channels`, sorted as counting leaves them; `id: None`
+/// makes it a channel of an unidentifiable server.
+pub fn guild(id: Option<&str>, name: &str, channels: Vec<Channel>) -> Conversation {
+    let mut guild = Conversation::Guild {
+        id: id.map(str::to_string),
+        name: name.to_string(),
+        message_count: channels.iter().map(|channel| channel.message_count).sum(),
+        excluded_system: 0,
+        empty_messages: 0,
+        channels,
+        departed: false,
+    };
+    guild.sort_channels();
+    guild
+}
+
+pub fn report(conversations: Vec<Conversation>) -> Report {
+    let mut report: Report = serde_json::from_value(json!({
+        "conversations": [],
+        "reference_counts": null,
+    }))
+    .expect("an empty report");
+    report.conversations = conversations;
+    report
+}
+
+/// Every conversation of `report` in its order, with the defaults of a run
+/// without options.
+pub fn view(report: &Report) -> ResultsView<'_> {
+    ResultsView {
+        report,
+        conversations: report.conversations.iter().collect(),
+        filter_stages: Vec::new(),
+        quiet: true,
+        verbose: false,
+        excluded_system: false,
+        excluded_empty: false,
+        empty_message_stats: false,
+        summary: false,
+        share_total: report
+            .conversations
+            .iter()
+            .map(|conversation| conversation.message_count() as u64)
+            .sum(),
+        excluded_bots: false,
+        excluded_departed: false,
+        channels: false,
+        guild_totals: false,
+        group_dms_as: GroupDmPolicy::default(),
+        display_window: None,
+        accessible: false,
+        timezone: Zone::default(),
+        thousands_separators: false,
+        counts: CountStyle::Exact,
+        concentration: None,
+        threshold_timeline: None,
+        min_messages: 0,
+        fenced: false,
+        group_by: None,
+        show_empty_sections: false,
+        palette: Palette::default(),
+        tree_glyphs: TreeGlyphs::Unicode,
+        tree_indent: 4,
+        flat: false,
+        names: NameWidth::Full,
+        options: &[],
+    }
+}
+
+pub fn rendered(format: OutputFormat, view: &ResultsView) -> String {
+    let mut out = Vec::new();
+    render(format, view, &mut out).expect("rendering into memory");
+    String::from_utf8(out).expect("UTF-8 output")
+}
+
+/// An extracted data package in a temporary folder, removed when dropped.
+pub struct Package {
+    dir: TempDir,
+}
+
+impl Package {
+    pub fn new() -> Self {
+        let package = Self {
+            dir: tempfile::tempdir().expect("a temporary folder"),
+        };
+        fs::create_dir(package.path().join("messages")).expect("the messages folder");
+        package
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// How a run without options analyses the package.
+    pub fn options(&self) -> AnalysisOptions {
+        AnalysisOptions {
+            input_path: self.path().to_path_buf(),
+            process: Default::default(),
+            discrepancy_threshold: 0,
+            voice_stats: false,
+            coverage: false,
+            historical_names: false,
+            cache_index: false,
+            wait_for_locks: false,
+            #[cfg(feature = "zip")]
+            temp_dir: None,
+            #[cfg(feature = "zip")]
+            verify_against: None,
+            #[cfg(feature = "net")]
+            resolve_online: false,
+            #[cfg(feature = "net")]
+            resolve_limit: 0,
+        }
+    }
+
+    pub fn analyze(&self) -> Report {
+        analyze(&self.options()).expect("a countable package")
+    }
+
+    /// Write `contents` to `relative`, creating its folders.
+    pub fn file(&self, relative: &str, contents: &str) -> &Self {
+        let path = self.path().join(relative);
+        fs::create_dir_all(path.parent().expect("a file in a folder")).expect("the folders");
+        fs::write(path, contents).expect("the file");
+        self
+    }
+
+    /// A channel folder `c<id>` with its `channel.json` and a `messages.json`
+    /// holding `messages` messages.
+    pub fn channel(&self, id: &str, info: Value, messages: usize) -> &Self {
+        let messages: Vec<Value> = (0..messages)
+            .map(|i| json!({"ID": (i + 1).to_string(), "Timestamp": "2021-01-01 12:00:00"}))
+            .collect();
+        self.file(&format!("messages/c{}/channel.json", id), &info.to_string())
+            .file(
+                &format!("messages/c{}/messages.json", id),
+                &Value::from(messages).to_string(),
+            )
+    }
+}
@@ -0,0 +1,216 @@
+//! How many conversations got at least N messages in each year: a rough
+//! count of the friendships and communities kept up over time.
+
+use crate::errors::MyError;
+use crate::metrics::Metric;
+use crate::window::DisplayWindow;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+
+pub const METRIC: Metric = Metric {
+    name: "threshold_timeline",
+    definition: "Per calendar year, the conversations with at least --threshold-timeline \
+        counted messages in that year. A guild's messages of a year are those of all its \
+        channels.",
+    affected_by: "--timezone decides where years begin; --conversation-type, --participant \
+        and --min-messages decide which conversations take part, --limit does not; \
+        --display-window limits the years shown. --exclude-system and --dedupe-messages \
+        remove messages first.",
+    edge_cases: "Every year from the first to the last counted message is listed, with zero \
+        for years no conversation reached the threshold in. Messages without a usable \
+        timestamp belong to no year.",
+};
+
+/// Bars of the sparkline, from the lowest non-zero value to the highest.
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ThresholdTimeline {
+    /// Messages a conversation needs in a year to count for it
+    pub threshold: usize,
+    /// Every year from the first to the last counted message, ascending
+    pub years: Vec<ThresholdYear>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ThresholdYear {
+    pub year: i32,
+    pub conversations: usize,
+}
+
+/// Parse the threshold of `--threshold-timeline`, a message count of at least 1.
+pub fn parse_threshold(s: &str) -> Result<usize, String> {
+    match s.parse() {
+        Ok(threshold @ 1..) => Ok(threshold),
+        _ => Err(format!("'{}' is not a message count of at least 1", s)),
+    }
+}
+
+/// Count, per year, the conversations among `conversation_ids` with at least
+/// `threshold` messages in that year.
+pub fn threshold_timeline<'a>(
+    conversation_ids: impl IntoIterator<Item = &'a str>,
+    messages_by_year: &HashMap<String, BTreeMap<i32, usize>>,
+    threshold: usize,
+) -> ThresholdTimeline {
+    let mut active: BTreeMap<i32, usize> = BTreeMap::new();
+    for years in conversation_ids
+        .into_iter()
+        .filter_map(|id| messages_by_year.get(id))
+    {
+        for (&year, &count) in years {
+            let conversations = active.entry(year).or_default();
+            if count >= threshold {
+                *conversations += 1;
+            }
+        }
+    }
+
+    let years = match (active.keys().next(), active.keys().next_back()) {
+        (Some(&first), Some(&last)) => (first..=last)
+            .map(|year| ThresholdYear {
+                year,
+                conversations: active.get(&year).copied().unwrap_or(0),
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    ThresholdTimeline { threshold, years }
+}
+
+impl ThresholdTimeline {
+    /// The years within `window`, or all of them without one.
+    pub fn shown_years(&self, window: Option<DisplayWindow>) -> Vec<&ThresholdYear> {
+        self.years
+            .iter()
+            .filter(|year| window.is_none_or(|w| w.overlaps_year(year.year)))
+            .collect()
+    }
+}
+
+/// One character per year, scaled to the busiest one. Years without any
+/// conversation over the threshold are blank.
+fn sparkline(years: &[&ThresholdYear]) -> String {
+    let highest = years
+        .iter()
+        .map(|year| year.conversations)
+        .max()
+        .unwrap_or(0);
+    years
+        .iter()
+        .map(|year| match year.conversations {
+            0 => ' ',
+            n => SPARKS[(n * SPARKS.len()).div_ceil(highest) - 1],
+        })
+        .collect()
+}
+
+pub fn write_threshold_timeline(
+    timeline: &ThresholdTimeline,
+    window: Option<DisplayWindow>,
+    out: &mut dyn Write,
+) -> Result<(), MyError> {
+    let years = timeline.shown_years(window);
+    writeln!(
+        out,
+        "Conversations with at least {} messages per year:",
+        timeline.threshold
+    )?;
+    writeln!(out, "{:<6}{:>14}", "Year", "Conversations")?;
+    for year in &years {
+        writeln!(out, "{:<6}{:>14}", year.year, year.conversations)?;
+    }
+    if let (Some(first), Some(last)) = (years.first(), years.last()) {
+        writeln!(out, "{}–{}  {}", first.year, last.year, sparkline(&years))?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::analyze;
+    use crate::testing::Package;
+    use serde_json::json;
+
+    fn counts(years: &[(i32, usize)]) -> BTreeMap<i32, usize> {
+        years.iter().copied().collect()
+    }
+
+    fn active(timeline: &ThresholdTimeline) -> Vec<(i32, usize)> {
+        timeline
+            .years
+            .iter()
+            .map(|year| (year.year, year.conversations))
+            .collect()
+    }
+
+    #[test]
+    fn thresholds_are_message_counts_of_at_least_one() {
+        assert_eq!(parse_threshold("1"), Ok(1));
+        assert_eq!(parse_threshold("250"), Ok(250));
+        for invalid in ["0", "-3", "many", ""] {
+            assert!(parse_threshold(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn a_conversation_counts_only_in_years_it_reached_the_threshold() {
+        let by_year = HashMap::from([
+            // Straddles the threshold: below it, at it, above it
+            (
+                "1".to_string(),
+                counts(&[(2019, 9), (2020, 10), (2021, 11)]),
+            ),
+            ("2".to_string(), counts(&[(2020, 50), (2023, 10)])),
+        ]);
+        let timeline = threshold_timeline(["1", "2"], &by_year, 10);
+        // Years between are listed with zero
+        assert_eq!(
+            active(&timeline),
+            [(2019, 0), (2020, 2), (2021, 1), (2022, 0), (2023, 1)]
+        );
+
+        // Only the conversations passed in take part, as --conversation-type leaves them
+        let timeline = threshold_timeline(["1"], &by_year, 10);
+        assert_eq!(active(&timeline), [(2019, 0), (2020, 1), (2021, 1)]);
+        assert!(threshold_timeline([], &by_year, 10).years.is_empty());
+    }
+
+    #[test]
+    fn the_sparkline_scales_to_the_busiest_year() {
+        let years: Vec<ThresholdYear> = [0, 1, 4, 8]
+            .into_iter()
+            .zip(2020..)
+            .map(|(conversations, year)| ThresholdYear {
+                year,
+                conversations,
+            })
+            .collect();
+        assert_eq!(sparkline(&years.iter().collect::<Vec<_>>()), " ▁▄█");
+    }
+
+    #[test]
+    fn years_come_from_a_counted_package() {
+        let package = Package::new();
+        package.channel("1", json!({"id": "1"}), 0).file(
+            "messages/c1/messages.json",
+            &json!([
+                {"ID": "1", "Timestamp": "2020-06-01 12:00:00"},
+                {"ID": "2", "Timestamp": "2020-07-01 12:00:00"},
+                {"ID": "3", "Timestamp": "2022-01-01 12:00:00"},
+            ])
+            .to_string(),
+        );
+        let mut options = package.options();
+        options.process.messages_by_year = true;
+        let report = analyze(&options).unwrap();
+
+        let timeline = threshold_timeline(["1"], &report.messages_by_year, 2);
+        assert_eq!(active(&timeline), [(2020, 1), (2021, 0), (2022, 0)]);
+        let json = serde_json::to_value(&timeline).unwrap();
+        assert_eq!(json["years"][0], json!({"year": 2020, "conversations": 1}));
+    }
+}
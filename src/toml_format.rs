@@ -0,0 +1,29 @@
+//! `--format toml`: the conversations of the JSON format as an array of
+//! `[[conversation]]` tables, with `[[conversation.channels]]` below each
+//! guild, for dashboards configured in TOML. Entries keep the listing's
+//! order, so exports of the same package diff cleanly.
+
+use crate::errors::MyError;
+use crate::json::{json_conversation, JsonContext, JsonConversation};
+use crate::render::ResultsView;
+use serde::Serialize;
+use std::io::Write;
+
+#[derive(Serialize)]
+struct TomlDocument<'a> {
+    conversation: Vec<JsonConversation<'a>>,
+}
+
+/// Without conversations this is `conversation = []`, still a valid document.
+pub fn render_toml(view: &ResultsView, out: &mut dyn Write) -> Result<(), MyError> {
+    let context = JsonContext::of(view);
+    let document = TomlDocument {
+        conversation: view
+            .conversations
+            .iter()
+            .map(|&conversation| json_conversation(conversation, context))
+            .collect(),
+    };
+    out.write_all(toml::to_string_pretty(&document)?.as_bytes())?;
+    Ok(())
+}
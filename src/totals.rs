@@ -0,0 +1,113 @@
+//! The summary after the conversation list: how many of all counted messages
+//! are shown, what kinds of conversations hold them and which one is the
+//! largest. `--no-summary` leaves it out, for output piped into other tools.
+
+use crate::buckets::CountStyle;
+use crate::errors::MyError;
+use crate::humanize::fmt_count;
+use crate::render::{display_name, ResultsView};
+use crate::Conversation;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::io::Write;
+
+#[derive(Debug, Serialize)]
+pub struct Totals {
+    /// Messages across every counted conversation, before filtering
+    pub total_messages: u64,
+    /// Messages of the conversations shown
+    pub shown_messages: u64,
+    /// DMs shown, group DMs included
+    pub dms: usize,
+    /// Servers shown, each counted once even when --flat splits it up
+    pub guilds: usize,
+    /// Server channels shown, including those of unknown servers
+    pub channels: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub largest: Option<Largest>,
+}
+
+/// The shown conversation with the most messages.
+#[derive(Debug, Serialize)]
+pub struct Largest {
+    pub id: String,
+    pub name: String,
+    pub message_count: u64,
+}
+
+pub fn totals(view: &ResultsView) -> Totals {
+    let count = |conversation: &Conversation| conversation.message_count() as u64;
+    let mut guilds = HashSet::new();
+    let mut channels = 0;
+    for conversation in &view.conversations {
+        if let Conversation::Guild {
+            id,
+            channels: guild_channels,
+            ..
+        } = conversation
+        {
+            if let Some(id) = id {
+                guilds.insert(id);
+            }
+            channels += guild_channels.len();
+        }
+    }
+    // The first of equally large conversations in display order
+    let largest = view
+        .conversations
+        .iter()
+        .rev()
+        .max_by_key(|conversation| count(conversation))
+        .map(|&conversation| Largest {
+            id: conversation.id().to_string(),
+            name: display_name(conversation),
+            message_count: count(conversation),
+        });
+    Totals {
+        total_messages: view.report.conversations.iter().map(count).sum(),
+        shown_messages: view.conversations.iter().map(|&c| count(c)).sum(),
+        dms: view
+            .conversations
+            .iter()
+            .filter(|conversation| matches!(conversation, Conversation::DmOrGc { .. }))
+            .count(),
+        guilds: guilds.len(),
+        channels,
+        largest,
+    }
+}
+
+pub fn write_totals(
+    totals: &Totals,
+    counts: CountStyle,
+    out: &mut dyn Write,
+) -> Result<(), MyError> {
+    let messages = |n: u64| counts.grouped(n);
+    writeln!(out, "Summary:")?;
+    match totals.shown_messages == totals.total_messages {
+        true => writeln!(out, "    {} messages", messages(totals.total_messages))?,
+        false => writeln!(
+            out,
+            "    showing {} of {} messages",
+            messages(totals.shown_messages),
+            messages(totals.total_messages)
+        )?,
+    }
+    writeln!(
+        out,
+        "    {}, {}, {}",
+        fmt_count(totals.dms as u64, "DM"),
+        fmt_count(totals.guilds as u64, "server"),
+        fmt_count(totals.channels as u64, "channel")
+    )?;
+    if let Some(ref largest) = totals.largest {
+        writeln!(
+            out,
+            "    largest: {} [{} messages]",
+            largest.name,
+            messages(largest.message_count)
+        )?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
@@ -0,0 +1,289 @@
+use crate::errors::MyError;
+use crate::file_operations::DataRoot;
+use crate::index::ChannelFolder;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+/// Channel folders looked at when fingerprinting a package.
+const PROBE_FOLDERS: usize = 16;
+
+/// Generation of the data package format, each with its own quirks.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PackageVintage {
+    /// Bare `<ID>` or `<ID> - <name>` folders holding `messages.csv`
+    V2019,
+    /// `c<ID>` folders holding `messages.csv`; `index.json` may map to null
+    V2021,
+    /// `c<ID>` folders holding `messages.json`
+    V2023,
+    /// Anything else, handled by the tolerant fallbacks
+    #[default]
+    Unknown,
+}
+
+/// How a channel's messages are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageFormat {
+    Json,
+    Csv,
+}
+
+impl MessageFormat {
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Self::Json => "messages.json",
+            Self::Csv => "messages.csv",
+        }
+    }
+
+    /// The format a folder falls back to when it lacks a file of this one.
+    pub fn other(self) -> Self {
+        match self {
+            Self::Json => Self::Csv,
+            Self::Csv => Self::Json,
+        }
+    }
+
+    /// Format of a messages file, judged by its name.
+    pub fn of(path: &Path) -> Self {
+        match path.extension().is_some_and(|ext| ext == "csv") {
+            true => Self::Csv,
+            false => Self::Json,
+        }
+    }
+}
+
+impl PackageVintage {
+    /// The one message format of this vintage; `None` when either may appear.
+    pub fn message_format(self) -> Option<MessageFormat> {
+        match self {
+            Self::V2019 | Self::V2021 => Some(MessageFormat::Csv),
+            Self::V2023 => Some(MessageFormat::Json),
+            Self::Unknown => None,
+        }
+    }
+
+    /// The messages file of a channel folder, if it has one. A file in the
+    /// other format is taken when the vintage's own is missing: detection
+    /// only looks at `PROBE_FOLDERS` folders, and a package may hold folders
+    /// of another export among them.
+    pub fn messages_file(self, folder: &Path) -> Option<PathBuf> {
+        let preferred = self.message_format().unwrap_or(MessageFormat::Json);
+        [preferred, preferred.other()]
+            .into_iter()
+            .map(|format| folder.join(format.file_name()))
+            .find(|file| file.exists())
+    }
+}
+
+impl fmt::Display for PackageVintage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::V2019 => write!(f, "2019 (bare folder IDs, CSV messages)"),
+            Self::V2021 => write!(f, "2021 (c-prefixed folders, CSV messages)"),
+            Self::V2023 => write!(f, "2023 (c-prefixed folders, JSON messages)"),
+            Self::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// What a quick look at the package found, without reading any messages.
+#[derive(Debug, Default, Clone)]
+pub struct PackageProbe {
+    /// Channel folders named `c<ID>`
+    pub prefixed_folders: usize,
+    /// Channel folders named `<ID>` or `<ID> - <name>`
+    pub bare_folders: usize,
+    pub json_messages: usize,
+    pub csv_messages: usize,
+    /// Whether `messages/index.json` maps any channel to null
+    pub index_has_nulls: bool,
+}
+
+impl PackageProbe {
+    pub fn collect(data_root: &DataRoot, folders: &[ChannelFolder]) -> Result<Self, MyError> {
+        let mut probe = Self::default();
+        let mut folders: Vec<&ChannelFolder> = folders.iter().collect();
+        folders.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+        for folder in folders.into_iter().take(PROBE_FOLDERS) {
+            match folder.prefixed {
+                true => probe.prefixed_folders += 1,
+                false => probe.bare_folders += 1,
+            }
+            if folder.path.join(MessageFormat::Json.file_name()).exists() {
+                probe.json_messages += 1;
+            }
+            if folder.path.join(MessageFormat::Csv.file_name()).exists() {
+                probe.csv_messages += 1;
+            }
+        }
+
+        probe.index_has_nulls =
+            index_has_nulls(&data_root.path.join("messages").join("index.json"))?;
+        Ok(probe)
+    }
+}
+
+/// Fingerprint a package from its probe. Mixed or empty packages are unknown.
+pub fn detect(probe: &PackageProbe) -> PackageVintage {
+    let format = match (probe.json_messages, probe.csv_messages) {
+        (0, 0) => return PackageVintage::Unknown,
+        (_, 0) => MessageFormat::Json,
+        (0, _) => MessageFormat::Csv,
+        _ => return PackageVintage::Unknown,
+    };
+
+    match (probe.prefixed_folders, probe.bare_folders, format) {
+        (0, _, MessageFormat::Csv) if !probe.index_has_nulls => PackageVintage::V2019,
+        (_, 0, MessageFormat::Csv) => PackageVintage::V2021,
+        (_, 0, MessageFormat::Json) => PackageVintage::V2023,
+        _ => PackageVintage::Unknown,
+    }
+}
+
+fn index_has_nulls(path: &Path) -> Result<bool, MyError> {
+    if !path.exists() {
+        return Ok(false);
+    }
+    let file = File::open(path)?;
+    // A malformed index is reported when the mappings are loaded
+    let index: HashMap<String, Value> = match serde_json::from_reader(BufReader::new(file)) {
+        Ok(index) => index,
+        Err(_) => return Ok(false),
+    };
+    Ok(index.values().any(Value::is_null))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_operations::prepare_data_root;
+    use crate::index::{build_index, ChannelIndex};
+    use crate::testing::Package;
+    use crate::warnings::Warning;
+
+    const CSV: &str = "ID,Timestamp,Contents,Attachments\n1,2019-01-01 12:00:00,hi,\n";
+    const JSON: &str = r#"[{"ID": "1", "Timestamp": "2023-01-01 12:00:00"}]"#;
+
+    /// A package with a `channel.json` and the given messages file in each
+    /// of `folders`, and `index` as its `messages/index.json`.
+    fn package(folders: &[(&str, &str, &str)], index: &str) -> Package {
+        let package = Package::new();
+        package.file("messages/index.json", index);
+        for &(folder, file, contents) in folders {
+            package
+                .file(&format!("messages/{}/channel.json", folder), "{}")
+                .file(&format!("messages/{}/{}", folder, file), contents);
+        }
+        package
+    }
+
+    fn index(package: &Package) -> ChannelIndex {
+        build_index(&prepare_data_root(package.path(), None).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn detects_each_vintage_from_its_fixture() {
+        let v2019 = package(
+            &[
+                ("123", "messages.csv", CSV),
+                ("456 - general", "messages.csv", CSV),
+            ],
+            r#"{"123": "Alice", "456": "general"}"#,
+        );
+        let v2021 = package(
+            &[("c123", "messages.csv", CSV), ("c456", "messages.csv", CSV)],
+            r#"{"123": "Alice", "456": null}"#,
+        );
+        let v2023 = package(
+            &[
+                ("c123", "messages.json", JSON),
+                ("c456", "messages.json", JSON),
+            ],
+            r#"{"123": "Alice"}"#,
+        );
+        let mixed = package(
+            &[
+                ("c123", "messages.json", JSON),
+                ("c456", "messages.csv", CSV),
+            ],
+            "{}",
+        );
+
+        for (package, vintage) in [
+            (v2019, PackageVintage::V2019),
+            (v2021, PackageVintage::V2021),
+            (v2023, PackageVintage::V2023),
+            (mixed, PackageVintage::Unknown),
+        ] {
+            let index = index(&package);
+            assert_eq!(index.vintage, vintage);
+            // Every vintage, the unknown one included, still finds every channel
+            assert_eq!(index.channels.len(), 2, "{}", vintage);
+            assert!(index.warnings.is_empty(), "{}", vintage);
+        }
+    }
+
+    #[test]
+    fn detection_needs_one_folder_style_and_one_format() {
+        let probe = |prefixed, bare, json, csv, index_has_nulls| PackageProbe {
+            prefixed_folders: prefixed,
+            bare_folders: bare,
+            json_messages: json,
+            csv_messages: csv,
+            index_has_nulls,
+        };
+        assert_eq!(detect(&probe(0, 2, 0, 2, false)), PackageVintage::V2019);
+        assert_eq!(detect(&probe(2, 0, 0, 2, true)), PackageVintage::V2021);
+        assert_eq!(detect(&probe(2, 0, 0, 2, false)), PackageVintage::V2021);
+        assert_eq!(detect(&probe(2, 0, 2, 0, false)), PackageVintage::V2023);
+        assert_eq!(detect(&probe(0, 2, 0, 2, true)), PackageVintage::Unknown);
+        assert_eq!(detect(&probe(1, 1, 2, 0, false)), PackageVintage::Unknown);
+        assert_eq!(detect(&probe(2, 0, 1, 1, false)), PackageVintage::Unknown);
+        assert_eq!(detect(&probe(0, 0, 0, 0, false)), PackageVintage::Unknown);
+    }
+
+    #[test]
+    fn folders_beyond_the_probe_fall_back_to_the_other_format() {
+        let package = package(&[], "{}");
+        for id in 10..10 + PROBE_FOLDERS {
+            package
+                .file(&format!("messages/c{}/channel.json", id), "{}")
+                .file(&format!("messages/c{}/messages.json", id), JSON);
+        }
+        // Sorted after every probed folder
+        package
+            .file("messages/c99/channel.json", "{}")
+            .file("messages/c99/messages.csv", CSV);
+
+        let index = index(&package);
+        assert_eq!(index.vintage, PackageVintage::V2023);
+        let fallback = index.channels.iter().find(|channel| channel.id == "99");
+        assert_eq!(
+            fallback.map(|channel| MessageFormat::of(&channel.messages_file)),
+            Some(MessageFormat::Csv)
+        );
+        assert_eq!(index.channels.len(), PROBE_FOLDERS + 1);
+    }
+
+    #[test]
+    fn a_folder_without_messages_is_skipped_with_a_warning() {
+        let package = package(&[("c123", "messages.json", JSON)], "{}");
+        package.file("messages/c456/channel.json", "{}");
+
+        let index = index(&package);
+        assert_eq!(index.channels.len(), 1);
+        assert_eq!(
+            index.warnings,
+            [Warning::MissingMessagesFile {
+                channel_id: "c456".to_string()
+            }]
+        );
+    }
+}
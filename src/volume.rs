@@ -0,0 +1,149 @@
+//! How much data a run read, for `--timing` and the JSON metadata. Bytes are
+//! counted where messages files are read, so the figures hold for any source
+//! a reader can be opened on.
+
+use crate::humanize::{fmt_bytes, fmt_rate};
+use crate::render::format_thousands;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read};
+use std::time::Duration;
+
+/// A reader that counts the bytes read through it.
+pub struct CountingReader<R> {
+    inner: R,
+    bytes_read: u64,
+}
+
+impl<R: Read> CountingReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            bytes_read: 0,
+        }
+    }
+
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.bytes_read += read as u64;
+        Ok(read)
+    }
+}
+
+/// Data read while counting messages, and how fast.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ProcessingStats {
+    pub channel_folders: usize,
+    /// Bytes of messages files parsed
+    pub bytes_read: u64,
+    pub messages: usize,
+    pub seconds: f64,
+    /// Decimal megabytes; left out when counting took no measurable time
+    pub megabytes_per_second: Option<f64>,
+    pub messages_per_second: Option<f64>,
+}
+
+impl ProcessingStats {
+    pub fn new(
+        channel_folders: usize,
+        bytes_read: u64,
+        messages: usize,
+        elapsed: Duration,
+    ) -> Self {
+        let seconds = elapsed.as_secs_f64();
+        let rate = |amount: f64| (seconds > 0.0).then(|| amount / seconds);
+        Self {
+            channel_folders,
+            bytes_read,
+            messages,
+            seconds,
+            megabytes_per_second: rate(bytes_read as f64 / 1_000_000.0),
+            messages_per_second: rate(messages as f64),
+        }
+    }
+
+    pub fn print(&self) {
+        eprintln!(
+            "Read {} channel folders, {} of messages files and {} messages",
+            format_thousands(self.channel_folders as u64),
+            fmt_bytes(self.bytes_read),
+            format_thousands(self.messages as u64)
+        );
+        if let (Some(megabytes), Some(messages)) =
+            (self.megabytes_per_second, self.messages_per_second)
+        {
+            eprintln!(
+                "Throughput: {}/s, messages {}",
+                fmt_bytes((megabytes * 1_000_000.0).round() as u64),
+                fmt_rate(messages, "s")
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::OutputFormat;
+    use crate::testing::{rendered, view, Package};
+    use serde_json::json;
+    use std::fs;
+
+    #[test]
+    fn counting_reader_counts_every_byte_however_it_is_read() {
+        let data = vec![7u8; 10_000];
+        let mut reader = CountingReader::new(&data[..]);
+        let mut buf = [0; 3];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(reader.bytes_read(), 3);
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        assert_eq!(reader.bytes_read(), 10_000);
+        // Reading past the end adds nothing
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+        assert_eq!(reader.bytes_read(), 10_000);
+    }
+
+    #[test]
+    fn stats_match_the_fixture_sizes() {
+        let package = Package::new();
+        package
+            .channel("1", json!({"id": "1"}), 3)
+            .channel("2", json!({"id": "2"}), 0)
+            .file("messages/c2/messages.json", "[]");
+        let size = |id: &str| {
+            let path = package
+                .path()
+                .join(format!("messages/c{}/messages.json", id));
+            fs::metadata(path).unwrap().len()
+        };
+
+        let report = package.analyze();
+        let stats = report.processing_stats.unwrap();
+        assert_eq!(stats.channel_folders, 2);
+        assert_eq!(stats.bytes_read, size("1") + size("2"));
+        assert_eq!(stats.messages, 3);
+
+        let json: serde_json::Value =
+            serde_json::from_str(&rendered(OutputFormat::Json, &view(&report))).unwrap();
+        assert_eq!(
+            json["meta"]["processing_stats"]["bytes_read"],
+            stats.bytes_read
+        );
+    }
+
+    #[test]
+    fn rates_need_measurable_time() {
+        let stats = ProcessingStats::new(1, 2_000_000, 500, Duration::ZERO);
+        assert_eq!(stats.megabytes_per_second, None);
+        assert_eq!(stats.messages_per_second, None);
+
+        let stats = ProcessingStats::new(1, 2_000_000, 500, Duration::from_millis(500));
+        assert_eq!(stats.megabytes_per_second, Some(4.0));
+        assert_eq!(stats.messages_per_second, Some(1000.0));
+    }
+}
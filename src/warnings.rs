@@ -0,0 +1,326 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{self, Write};
+
+/// Non-fatal findings collected while counting, shown after the results.
+/// Ordered by kind, then by the channel, folder or IDs concerned, so the
+/// displayed list does not depend on the order channels were processed in.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Warning {
+    /// First, so it leads the list however many warnings there are
+    StillExtracting {
+        signs: Vec<String>,
+    },
+    DuplicateMessages {
+        channel_id: String,
+        duplicates: usize,
+    },
+    UnknownGuild {
+        channel_id: String,
+    },
+    UnreadableChannelInfo {
+        channel_id: String,
+        reason: String,
+    },
+    UnrecognizedFolder {
+        folder: String,
+    },
+    MissingMessagesFile {
+        channel_id: String,
+    },
+    ChannelKeyCollision {
+        channel_id: String,
+        prefixed_id: String,
+        /// Whether the name under the prefixed key was used, as the
+        /// channel's folder is named that way
+        #[serde(default)]
+        prefixed_used: bool,
+    },
+    UnknownNoteIds {
+        ids: Vec<String>,
+    },
+    LegacyUsernames {
+        count: usize,
+    },
+    SkippedChannel {
+        channel_id: String,
+        reason: String,
+    },
+    GuildNameConflict {
+        guild_id: String,
+        channel_id: String,
+    },
+    MessagesAfterExport {
+        channel_ids: Vec<String>,
+        /// Day the newest message of most channels falls before
+        others_end: String,
+    },
+    #[cfg(feature = "zip")]
+    MirrorMismatch {
+        file: String,
+        problem: String,
+    },
+    #[cfg(feature = "zip")]
+    StaleSiblingFolder {
+        folder: String,
+    },
+}
+
+impl Warning {
+    /// Fields holding a channel or server ID, for redaction.
+    pub fn ids_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            Self::DuplicateMessages { channel_id, .. }
+            | Self::UnknownGuild { channel_id }
+            | Self::UnreadableChannelInfo { channel_id, .. }
+            | Self::MissingMessagesFile { channel_id }
+            | Self::ChannelKeyCollision { channel_id, .. }
+            | Self::SkippedChannel { channel_id, .. } => vec![channel_id],
+            Self::GuildNameConflict {
+                guild_id,
+                channel_id,
+            } => vec![guild_id, channel_id],
+            Self::UnknownNoteIds { ids: channel_ids }
+            | Self::MessagesAfterExport { channel_ids, .. } => channel_ids.iter_mut().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Fields holding a file or folder name, which may contain IDs.
+    pub fn paths_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            Self::UnrecognizedFolder { folder } => vec![folder],
+            Self::ChannelKeyCollision { prefixed_id, .. } => vec![prefixed_id],
+            #[cfg(feature = "zip")]
+            Self::MirrorMismatch { file, .. } => vec![file],
+            #[cfg(feature = "zip")]
+            Self::StaleSiblingFolder { folder } => vec![folder],
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::StillExtracting { signs } => write!(
+                f,
+                "the package appears to be still extracting; results are likely incomplete ({})",
+                signs.join("; ")
+            ),
+            Self::DuplicateMessages {
+                channel_id,
+                duplicates,
+            } => write!(
+                f,
+                "channel {} contains {} duplicated messages, counted once",
+                channel_id, duplicates
+            ),
+            Self::UnknownGuild { channel_id } => write!(
+                f,
+                "channel {} belongs to a server without an ID in channel.json; \
+                 it is listed on its own under {}",
+                channel_id,
+                crate::UNKNOWN_GUILD_NAME
+            ),
+            Self::UnreadableChannelInfo { channel_id, reason } => write!(
+                f,
+                "channel {} has an unreadable channel.json ({}); \
+                 it is listed as a conversation of its own",
+                channel_id, reason
+            ),
+            Self::UnrecognizedFolder { folder } => write!(
+                f,
+                "folder messages/{} is not named after a channel ID and was skipped",
+                folder
+            ),
+            Self::MissingMessagesFile { channel_id } => write!(
+                f,
+                "channel {} has neither a messages.json nor a messages.csv and was skipped",
+                channel_id
+            ),
+            Self::ChannelKeyCollision {
+                channel_id,
+                prefixed_id,
+                prefixed_used,
+            } => write!(
+                f,
+                "messages/index.json names channel {} differently under the keys {} and {}; \
+                 the name under {}, as its folder is named, is used",
+                channel_id,
+                channel_id,
+                prefixed_id,
+                match prefixed_used {
+                    true => prefixed_id,
+                    false => channel_id,
+                }
+            ),
+            Self::UnknownNoteIds { ids } => write!(
+                f,
+                "the notes file mentions conversations not in the package: {}",
+                ids.join(", ")
+            ),
+            Self::SkippedChannel { channel_id, reason } => write!(
+                f,
+                "channel {} was skipped: {}; its messages are not counted",
+                channel_id, reason
+            ),
+            Self::MessagesAfterExport {
+                channel_ids,
+                others_end,
+            } => write!(
+                f,
+                "channels {} hold messages from months after most channels end \
+                 (around {}); their folders may come from a newer export",
+                channel_ids.join(", "),
+                others_end
+            ),
+            #[cfg(feature = "zip")]
+            Self::MirrorMismatch { file, problem } => write!(
+                f,
+                "{} {}, so the counts of this copy may differ from the original's",
+                file, problem
+            ),
+            #[cfg(feature = "zip")]
+            Self::StaleSiblingFolder { folder } => write!(
+                f,
+                "the folder {} next to the archive does not hold the same messages, \
+                 so the archive was extracted instead",
+                folder
+            ),
+            Self::GuildNameConflict {
+                guild_id,
+                channel_id,
+            } => write!(
+                f,
+                "server {} is named differently in servers/index.json and in the \
+                 channel.json of channel {}; the name from servers/index.json is used",
+                guild_id, channel_id
+            ),
+            Self::LegacyUsernames { count } => write!(
+                f,
+                "{} conversation names use the pre-2023 name#1234 username format, \
+                 so the package's names are outdated",
+                count
+            ),
+        }
+    }
+}
+
+/// Sort warnings and collapse identical ones into a single entry with a count.
+pub fn summarize(warnings: &[Warning]) -> Vec<(&Warning, usize)> {
+    let mut sorted: Vec<&Warning> = warnings.iter().collect();
+    sorted.sort();
+
+    let mut summary: Vec<(&Warning, usize)> = Vec::new();
+    for warning in sorted {
+        match summary.last_mut() {
+            Some((last, count)) if *last == warning => *count += 1,
+            _ => summary.push((warning, 1)),
+        }
+    }
+    summary
+}
+
+/// Print the warnings to stderr in a stable order, listing at most `limit`
+/// of them (0 lists all) and summarizing the rest.
+pub fn print_warnings(warnings: &[Warning], limit: usize) {
+    // Nothing sensible is left to do when stderr cannot be written
+    let _ = write_warnings(warnings, limit, &mut io::stderr().lock());
+}
+
+pub fn write_warnings(warnings: &[Warning], limit: usize, out: &mut dyn Write) -> io::Result<()> {
+    if warnings.is_empty() {
+        return Ok(());
+    }
+
+    let summary = summarize(warnings);
+    let shown = match limit {
+        0 => summary.len(),
+        limit => limit.min(summary.len()),
+    };
+
+    writeln!(out, "Warnings:")?;
+    for (warning, count) in &summary[..shown] {
+        match count {
+            1 => writeln!(out, "    {}", warning)?,
+            count => writeln!(out, "    {} (×{})", warning, count)?,
+        }
+    }
+    let hidden: usize = summary[shown..].iter().map(|(_, count)| count).sum();
+    if hidden > 0 {
+        writeln!(
+            out,
+            "    ... and {} more; use --warnings-limit 0 to list all",
+            hidden
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::SeedableRng;
+
+    fn written(warnings: &[Warning], limit: usize) -> String {
+        let mut out = Vec::new();
+        write_warnings(warnings, limit, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn warnings() -> Vec<Warning> {
+        let unknown = |id: &str| Warning::UnknownGuild {
+            channel_id: id.to_string(),
+        };
+        vec![
+            unknown("c3"),
+            Warning::LegacyUsernames { count: 2 },
+            unknown("c1"),
+            Warning::UnrecognizedFolder {
+                folder: "backup".to_string(),
+            },
+            unknown("c3"),
+            Warning::DuplicateMessages {
+                channel_id: "c2".to_string(),
+                duplicates: 4,
+            },
+            unknown("c3"),
+        ]
+    }
+
+    #[test]
+    fn shuffled_warnings_render_identically() {
+        let warnings = warnings();
+        let expected = written(&warnings, 0);
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..20 {
+            let mut shuffled = warnings.clone();
+            shuffled.shuffle(&mut rng);
+            assert_eq!(written(&shuffled, 0), expected);
+            assert_eq!(written(&shuffled, 2), written(&warnings, 2));
+        }
+    }
+
+    #[test]
+    fn identical_warnings_are_counted_once() {
+        let warnings = warnings();
+        let counts: Vec<usize> = summarize(&warnings)
+            .iter()
+            .map(|(_, count)| *count)
+            .collect();
+        assert_eq!(counts, [1, 1, 3, 1, 1]);
+        assert!(written(&warnings, 0).contains("listed on its own under (unknown server) (×3)\n"));
+    }
+
+    #[test]
+    fn the_limit_summarizes_the_rest() {
+        let output = written(&warnings(), 2);
+        assert_eq!(output.lines().count(), 4);
+        assert!(output.ends_with("    ... and 5 more; use --warnings-limit 0 to list all\n"));
+        assert_eq!(written(&[], 2), "");
+    }
+}
@@ -0,0 +1,53 @@
+//! Names shortened to fit a terminal line. Widths are counted in terminal
+//! cells, so emoji and other double-width characters take the room they are
+//! drawn with. Only what is displayed is shortened; filtering, sorting and
+//! every export see the full names.
+
+use std::borrow::Cow;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Cells a name keeps however little room a line leaves.
+const MIN_NAME_WIDTH: usize = 10;
+
+/// How long names in the text listing may be.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum NameWidth {
+    /// Names are never shortened
+    #[default]
+    Full,
+    /// Names take at most this many cells, with --max-name-len
+    Fixed(usize),
+    /// Lines take at most this many cells, the terminal's width
+    Line(usize),
+}
+
+impl NameWidth {
+    /// `name` shortened for a line holding `rest` cells besides it.
+    pub fn fit(self, name: &str, rest: usize) -> Cow<'_, str> {
+        let limit = match self {
+            Self::Full => return Cow::Borrowed(name),
+            Self::Fixed(width) => width,
+            Self::Line(width) => width.saturating_sub(rest).max(MIN_NAME_WIDTH),
+        };
+        truncate(name, limit)
+    }
+}
+
+/// `name` cut short with `…` to at most `width` cells, the ellipsis included.
+pub fn truncate(name: &str, width: usize) -> Cow<'_, str> {
+    if name.width() <= width {
+        return Cow::Borrowed(name);
+    }
+    let mut truncated = String::new();
+    let mut used = 0;
+    for c in name.chars() {
+        let cell = c.width().unwrap_or(0);
+        if used + cell + 1 > width {
+            break;
+        }
+        truncated.push(c);
+        used += cell;
+    }
+    truncated.push('…');
+    Cow::Owned(truncated)
+}
@@ -0,0 +1,196 @@
+use chrono::{Datelike, NaiveDate};
+use std::fmt;
+use std::str::FromStr;
+
+/// A date range that restricts what charts and breakdowns show without
+/// changing any counts. Parsed from `START..END`, where END is exclusive and
+/// either side may be left open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayWindow {
+    pub start: Option<NaiveDate>,
+    pub end: Option<NaiveDate>,
+}
+
+impl DisplayWindow {
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.start.is_none_or(|start| date >= start) && self.end.is_none_or(|end| date < end)
+    }
+
+    /// Whether any day of `year` falls inside the window.
+    pub fn overlaps_year(&self, year: i32) -> bool {
+        self.start.is_none_or(|start| start.year() <= year)
+            && self
+                .end
+                .is_none_or(|end| (end.year(), end.ordinal()) > (year, 1))
+    }
+}
+
+impl FromStr for DisplayWindow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once("..")
+            .ok_or_else(|| format!("expected START..END, got '{}'", s))?;
+        let parse = |date: &str| -> Result<Option<NaiveDate>, String> {
+            match date.trim() {
+                "" => Ok(None),
+                date => NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                    .map(Some)
+                    .map_err(|e| format!("invalid date '{}': {}", date, e)),
+            }
+        };
+        let window = Self {
+            start: parse(start)?,
+            end: parse(end)?,
+        };
+        if let (Some(start), Some(end)) = (window.start, window.end) {
+            if start >= end {
+                return Err(format!(
+                    "window start {} is not before its end {}",
+                    start, end
+                ));
+            }
+        }
+        Ok(window)
+    }
+}
+
+impl fmt::Display for DisplayWindow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let date = |date: Option<NaiveDate>| date.map(|d| d.to_string()).unwrap_or_default();
+        write!(f, "{}..{}", date(self.start), date(self.end))
+    }
+}
+
+/// Header line keeping the clipped charts from being read as clipped counts.
+pub fn window_note(window: &DisplayWindow) -> String {
+    format!(
+        "Display window {}: charts and breakdowns show only this period; \
+         message counts cover the whole package",
+        window
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::breadth::YearBreadth;
+    use crate::render::OutputFormat;
+    use crate::testing::{dm, rendered, report, view};
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn window(s: &str) -> DisplayWindow {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn either_side_may_be_left_open() {
+        assert_eq!(
+            window("2020-01-01..2024-01-01"),
+            DisplayWindow {
+                start: Some(date(2020, 1, 1)),
+                end: Some(date(2024, 1, 1)),
+            }
+        );
+        assert_eq!(window("..2024-01-01").start, None);
+        assert_eq!(window("2020-01-01..").end, None);
+        assert_eq!(
+            window(".."),
+            DisplayWindow {
+                start: None,
+                end: None
+            }
+        );
+        for s in [
+            "2020-01-01..2024-01-01",
+            "..2024-01-01",
+            "2020-01-01..",
+            "..",
+        ] {
+            assert_eq!(window(s).to_string(), s);
+        }
+    }
+
+    #[test]
+    fn empty_reversed_and_malformed_windows_are_rejected() {
+        for s in [
+            "2020-01-01..2020-01-01",
+            "2024-01-01..2020-01-01",
+            "2020-01-01",
+            "2020-13-01..",
+        ] {
+            assert!(s.parse::<DisplayWindow>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn the_start_is_inside_and_the_end_is_not() {
+        let window = window("2020-03-01..2022-01-01");
+        assert!(!window.contains(date(2020, 2, 29)));
+        assert!(window.contains(date(2020, 3, 1)));
+        assert!(window.contains(date(2021, 12, 31)));
+        assert!(!window.contains(date(2022, 1, 1)));
+
+        let years: Vec<i32> = (2019..=2022)
+            .filter(|&year| window.overlaps_year(year))
+            .collect();
+        assert_eq!(years, [2020, 2021]);
+        assert!(self::window("2020-03-01..2022-01-02").overlaps_year(2022));
+    }
+
+    /// The breadth table's years, and the text output's total line.
+    fn shown(window: Option<DisplayWindow>) -> (Vec<String>, String) {
+        let mut report = report(vec![dm("1", "Alice", 7)]);
+        report.breadth_by_year = Some(
+            (2019..=2021)
+                .map(|year| YearBreadth {
+                    year,
+                    servers: 1,
+                    channels: 2,
+                    dms: 3,
+                })
+                .collect(),
+        );
+        let mut view = view(&report);
+        view.quiet = false;
+        view.display_window = window;
+        let text = rendered(OutputFormat::Text, &view);
+        let years = text
+            .lines()
+            .skip_while(|line| !line.starts_with("Year"))
+            .skip(1)
+            .take_while(|line| !line.is_empty())
+            .map(|line| line[..4].to_string())
+            .collect();
+        let total = text
+            .lines()
+            .find(|line| line.contains("Alice"))
+            .unwrap()
+            .to_string();
+        (years, total)
+    }
+
+    #[test]
+    fn windows_clip_the_charts_but_not_the_counts() {
+        let (all, total) = shown(None);
+        assert_eq!(all, ["2019", "2020", "2021"]);
+
+        // A window larger than the data shows all of it
+        assert_eq!(
+            shown(Some(window("2000-01-01..2100-01-01"))),
+            (all, total.clone())
+        );
+
+        let (clipped, clipped_total) = shown(Some(window("2020-01-01..2021-01-01")));
+        assert_eq!(clipped, ["2020"]);
+        assert_eq!(clipped_total, total);
+
+        // A window without any data leaves the table empty
+        let (none, _) = shown(Some(window("2030-01-01..")));
+        assert!(none.is_empty());
+    }
+}
@@ -0,0 +1,159 @@
+use crate::errors::MyError;
+use crate::report::Report;
+use crate::Conversation;
+use serde::Serialize;
+use serde_json::Value;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+
+/// Identifies the internal wire format used to pipe reports between runs.
+pub const WIRE_FORMAT: &str = "discord-gdpr-counter/internal";
+
+/// Bumped whenever the serialized report changes incompatibly.
+pub const SCHEMA_VERSION: u32 = 1;
+
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+#[derive(Serialize)]
+struct EnvelopeRef<'a> {
+    format: &'a str,
+    schema_version: u32,
+    #[serde(flatten)]
+    report: &'a Report,
+}
+
+pub fn write_report<W: Write>(report: &Report, writer: W) -> Result<(), MyError> {
+    let envelope = EnvelopeRef {
+        format: WIRE_FORMAT,
+        schema_version: SCHEMA_VERSION,
+        report,
+    };
+    serde_json::to_writer(writer, &envelope)?;
+    Ok(())
+}
+
+/// Read the report at `path`, or from stdin when it is `-`.
+pub fn read_report_at(path: &Path) -> Result<Report, MyError> {
+    if path == Path::new("-") {
+        return read_report(io::stdin().lock());
+    }
+    if path.is_dir() {
+        return Err(MyError::InvalidWireInput(format!(
+            "{} is a folder, not a report; count data packages with 'count'",
+            path.display()
+        )));
+    }
+    read_report(BufReader::new(File::open(path)?))
+}
+
+/// Read a report produced by `--emit internal`, rejecting anything else with
+/// an explanation of what was received instead. The envelope is checked
+/// before the report is, so another JSON document is named as such rather
+/// than failing on the first field it lacks.
+pub fn read_report<R: Read>(mut reader: R) -> Result<Report, MyError> {
+    let mut input = Vec::new();
+    reader.read_to_end(&mut input)?;
+
+    if input.starts_with(ZIP_MAGIC) {
+        return Err(MyError::InvalidWireInput(
+            "received a ZIP archive, not a report; count data packages with 'count'".to_string(),
+        ));
+    }
+
+    let trimmed = input.trim_ascii_start();
+    if trimmed.is_empty() {
+        return Err(MyError::InvalidWireInput("input is empty".to_string()));
+    }
+    if !trimmed.starts_with(b"{") {
+        return Err(MyError::InvalidWireInput(
+            "input is not JSON; produce it with '--emit internal'".to_string(),
+        ));
+    }
+
+    let value: Value = serde_json::from_slice(trimmed).map_err(|error| {
+        MyError::InvalidWireInput(format!("input is not valid JSON ({})", error))
+    })?;
+    let format = value.get("format").and_then(Value::as_str);
+    let version = value.get("schema_version").and_then(Value::as_u64);
+    match (format, version) {
+        (Some(WIRE_FORMAT), Some(version)) if version == u64::from(SCHEMA_VERSION) => {}
+        (Some(WIRE_FORMAT), Some(version)) => {
+            return Err(MyError::InvalidWireInput(format!(
+                "schema version {} is not supported (expected {})",
+                version, SCHEMA_VERSION
+            )))
+        }
+        (Some(WIRE_FORMAT), None) => {
+            return Err(MyError::InvalidWireInput(
+                "wire-format report without a schema version".to_string(),
+            ))
+        }
+        (Some(other), _) => {
+            return Err(MyError::InvalidWireInput(format!(
+                "not a wire-format report but a '{}' document; produce one with '--emit internal'",
+                other
+            )))
+        }
+        (None, _) => return Err(MyError::InvalidWireInput(
+            "not a wire-format report, as it names no format; produce one with '--emit internal'"
+                .to_string(),
+        )),
+    }
+
+    let mut report: Report = serde_json::from_value(value)?;
+    report
+        .conversations
+        .iter_mut()
+        .for_each(Conversation::sort_channels);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(input: &str) -> String {
+        match read_report(input.as_bytes()) {
+            Err(MyError::InvalidWireInput(message)) => message,
+            other => panic!("expected invalid input, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn reads_what_it_writes() {
+        let input = format!(
+            r#"{{"format": "{}", "schema_version": {}, "conversations": [],
+                "reference_counts": null}}"#,
+            WIRE_FORMAT, SCHEMA_VERSION
+        );
+        let report = read_report(input.as_bytes()).unwrap();
+        let mut written = Vec::new();
+        write_report(&report, &mut written).unwrap();
+        assert!(read_report(written.as_slice())
+            .unwrap()
+            .conversations
+            .is_empty());
+    }
+
+    #[test]
+    fn names_other_documents() {
+        assert!(error("PK\x03\x04rest").contains("ZIP archive"));
+        assert!(error("  ").contains("empty"));
+        assert!(error("[1, 2]").contains("not JSON"));
+        assert!(error("{").contains("not valid JSON"));
+        assert!(error(r#"{"conversations": []}"#).contains("names no format"));
+        assert!(
+            error(r#"{"format": "discord-gdpr-counter/json", "schema_version": 2}"#)
+                .contains("'discord-gdpr-counter/json' document")
+        );
+    }
+
+    #[test]
+    fn checks_the_version_before_the_report() {
+        let newer = format!(r#"{{"format": "{}", "schema_version": 99}}"#, WIRE_FORMAT);
+        assert!(error(&newer).contains("schema version 99"));
+        let unversioned = format!(r#"{{"format": "{}"}}"#, WIRE_FORMAT);
+        assert!(error(&unversioned).contains("without a schema version"));
+    }
+}
@@ -0,0 +1,75 @@
+//! `--format xlsx`: the results as an Excel workbook, for people who only
+//! open spreadsheets. The first sheet lists the conversations in display
+//! order, the second each guild's channels; both keep their header row in
+//! view and show counts with thousands separators.
+
+use crate::errors::MyError;
+use crate::render::{display_name, ResultsView};
+use crate::Conversation;
+use rust_xlsxwriter::{Format, Workbook, Worksheet, XlsxError};
+use std::io::Write;
+
+const COUNT_FORMAT: &str = "#,##0";
+
+pub fn render_xlsx(view: &ResultsView, out: &mut dyn Write) -> Result<(), MyError> {
+    let header = Format::new().set_bold();
+    let count = Format::new().set_num_format(COUNT_FORMAT);
+    let mut workbook = Workbook::new();
+
+    let conversations = workbook.add_worksheet();
+    conversations.set_name("Conversations")?;
+    write_header(
+        conversations,
+        &["Conversation", "Type", "Messages"],
+        &header,
+    )?;
+    for (row, &conversation) in (1..).zip(&view.conversations) {
+        conversations.write_string(row, 0, display_name(conversation))?;
+        conversations.write_string(
+            row,
+            1,
+            conversation.effective_kind(view.group_dms_as).label(),
+        )?;
+        conversations.write_number_with_format(
+            row,
+            2,
+            conversation.message_count() as f64,
+            &count,
+        )?;
+    }
+    conversations.autofit();
+
+    let channels = workbook.add_worksheet();
+    channels.set_name("Channels")?;
+    write_header(channels, &["Server", "Channel", "Messages"], &header)?;
+    let mut row = 1;
+    for &conversation in &view.conversations {
+        let Conversation::Guild {
+            name,
+            channels: guild_channels,
+            ..
+        } = conversation
+        else {
+            continue;
+        };
+        for channel in guild_channels {
+            channels.write_string(row, 0, name)?;
+            channels.write_string(row, 1, &channel.name)?;
+            channels.write_number_with_format(row, 2, channel.message_count as f64, &count)?;
+            row += 1;
+        }
+    }
+    channels.autofit();
+
+    out.write_all(&workbook.save_to_buffer()?)?;
+    Ok(())
+}
+
+/// Bold column titles in the first row, which stays in view when scrolling.
+fn write_header(sheet: &mut Worksheet, titles: &[&str], format: &Format) -> Result<(), XlsxError> {
+    for (column, title) in (0..).zip(titles) {
+        sheet.write_string_with_format(0, column, *title, format)?;
+    }
+    sheet.set_freeze_panes(1, 0)?;
+    Ok(())
+}
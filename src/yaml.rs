@@ -0,0 +1,20 @@
+//! `--format yaml`: the conversations of the JSON format as a YAML list,
+//! for static site generators that keep their data in YAML. Names are
+//! quoted by the serializer wherever YAML would read them differently.
+
+use crate::errors::MyError;
+use crate::json::{json_conversation, JsonContext, JsonConversation};
+use crate::render::ResultsView;
+use std::io::Write;
+
+/// Without conversations this is `[]`, still a valid document.
+pub fn render_yaml(view: &ResultsView, out: &mut dyn Write) -> Result<(), MyError> {
+    let context = JsonContext::of(view);
+    let conversations: Vec<JsonConversation> = view
+        .conversations
+        .iter()
+        .map(|&conversation| json_conversation(conversation, context))
+        .collect();
+    serde_yaml::to_writer(out, &conversations)?;
+    Ok(())
+}
@@ -0,0 +1,143 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use std::str::FromStr;
+
+/// Time zone that decides where one day ends and the next begins. Every
+/// bucketing by day, week, month or year converts through `local_date`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Zone {
+    #[default]
+    Utc,
+    Fixed(FixedOffset),
+    Named(Tz),
+}
+
+impl Zone {
+    pub fn local_datetime(&self, datetime: &DateTime<Utc>) -> NaiveDateTime {
+        match self {
+            Self::Utc => datetime.naive_utc(),
+            Self::Fixed(offset) => datetime.with_timezone(offset).naive_local(),
+            Self::Named(tz) => datetime.with_timezone(tz).naive_local(),
+        }
+    }
+
+    pub fn local_date(&self, datetime: &DateTime<Utc>) -> NaiveDate {
+        self.local_datetime(datetime).date()
+    }
+}
+
+impl FromStr for Zone {
+    type Err = String;
+
+    /// Accepts "UTC", fixed offsets such as "+05:30" and IANA names such as
+    /// "Europe/Berlin".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("utc") || s == "Z" {
+            return Ok(Self::Utc);
+        }
+        if s.starts_with(['+', '-']) {
+            return format!("2000-01-01T00:00:00{}", s)
+                .parse::<DateTime<FixedOffset>>()
+                .map(|datetime| Self::Fixed(*datetime.offset()))
+                .map_err(|_| format!("invalid UTC offset '{}', expected e.g. +05:30", s));
+        }
+        s.parse::<Tz>()
+            .map(Self::Named)
+            .map_err(|_| format!("unknown time zone '{}', expected e.g. Europe/Berlin", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Package;
+    use chrono::{Duration, TimeZone};
+    use serde_json::json;
+
+    fn utc(year: i32, month: u32, day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, 0, 0).unwrap()
+    }
+
+    fn zone(s: &str) -> Zone {
+        s.parse().unwrap()
+    }
+
+    /// Local dates of every half hour from `start` on for two days.
+    fn dates(zone: Zone, start: DateTime<Utc>) -> Vec<NaiveDate> {
+        (0..96)
+            .map(|i| zone.local_date(&(start + Duration::minutes(30 * i))))
+            .collect()
+    }
+
+    #[test]
+    fn dates_never_go_backwards_across_transitions() {
+        for (zone, start) in [
+            // Europe/Berlin springs forward on 2021-03-28 and falls back on 2021-10-31
+            (zone("Europe/Berlin"), utc(2021, 3, 27, 12)),
+            (zone("Europe/Berlin"), utc(2021, 10, 30, 12)),
+            // Samoa skipped 2011-12-30 entirely
+            (zone("Pacific/Apia"), utc(2011, 12, 29, 0)),
+            (zone("+13:00"), utc(2021, 1, 1, 0)),
+            (zone("-12:00"), utc(2021, 1, 1, 0)),
+        ] {
+            let dates = dates(zone, start);
+            assert!(
+                dates.windows(2).all(|pair| pair[0] <= pair[1]),
+                "{:?}",
+                zone
+            );
+            // A date once left is never returned to
+            let mut runs = dates.clone();
+            runs.dedup();
+            let mut distinct = runs.clone();
+            distinct.sort_unstable();
+            distinct.dedup();
+            assert_eq!(runs, distinct, "{:?}", zone);
+        }
+    }
+
+    #[test]
+    fn extreme_offsets_move_the_day() {
+        let noon = utc(2021, 1, 1, 12);
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        assert_eq!(zone("UTC").local_date(&noon), date(2021, 1, 1));
+        assert_eq!(zone("+13:00").local_date(&noon), date(2021, 1, 2));
+        assert_eq!(zone("-12:00").local_date(&noon), date(2021, 1, 1));
+        assert_eq!(
+            zone("-12:00").local_date(&utc(2021, 1, 1, 11)),
+            date(2020, 12, 31)
+        );
+        assert_eq!(
+            zone("Pacific/Kiritimati").local_date(&noon),
+            date(2021, 1, 2)
+        );
+    }
+
+    #[test]
+    fn peak_days_follow_the_time_zone() {
+        let package = Package::new();
+        // Every fixture message is sent at 12:00 UTC on 2021-01-01
+        package.channel("1", json!({"id": "1"}), 3);
+        let mut options = package.options();
+        options.process.peak_days = true;
+        options.process.timezone = zone("+13:00");
+
+        let report = crate::analysis::analyze(&options).unwrap();
+        let peak = report.peak_day("1").unwrap();
+        assert_eq!(peak.day, NaiveDate::from_ymd_opt(2021, 1, 2).unwrap());
+        assert_eq!(peak.count, 3);
+    }
+
+    #[test]
+    fn parses_utc_offsets_and_names() {
+        assert_eq!(zone("utc"), Zone::Utc);
+        assert_eq!(zone("Z"), Zone::Utc);
+        assert_eq!(
+            zone("+05:30"),
+            Zone::Fixed(FixedOffset::east_opt(5 * 3600 + 30 * 60).unwrap())
+        );
+        assert_eq!(zone("Europe/Berlin"), Zone::Named(Tz::Europe__Berlin));
+        assert!("+25:00".parse::<Zone>().is_err());
+        assert!("Mars/Olympus".parse::<Zone>().is_err());
+    }
+}